@@ -0,0 +1,55 @@
+//! Times `bulk_insert_leaves` on random dirty leaf sets of several densities
+//! under several level rebuild thresholds, the first of which never rebuilds
+//! whole levels, to pick `DEFAULT_LEVEL_REBUILD_THRESHOLD`. Alongside each
+//! time is the number of parents hashed per update. Run with
+//! `cargo bench --bench bulk_update`.
+
+use std::time::{Duration, Instant};
+
+use merkle_tree::binary_merkle_tree::{chunk_output, BinaryMerkleTree};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::SeedableRng;
+
+const LEAF_COUNT: usize = 1 << 15;
+const ROUNDS: u32 = 30;
+const THRESHOLDS: [f64; 5] = [f64::INFINITY, 1.0, 0.9, 0.75, 0.5];
+
+fn time_updates(tree: &BinaryMerkleTree, indices: &[usize], threshold: f64) -> (Duration, u64) {
+    let mut total = Duration::ZERO;
+    let mut parents = 0;
+    for round in 0..ROUNDS {
+        let mut tree = tree.clone();
+        tree.set_level_rebuild_threshold(threshold);
+        // Copy the shared node array before timing, so only the update is measured
+        tree.insert_leaf(0, *tree.get_leaf(0).unwrap()).unwrap();
+        let leaves: Vec<_> = indices.iter().map(|&i| chunk_output(i as u64, &round.to_le_bytes())).collect();
+        let before = tree.parent_hash_count();
+        let start = Instant::now();
+        tree.bulk_insert_leaves(indices.iter().copied(), leaves.into_iter()).unwrap();
+        total += start.elapsed();
+        parents = tree.parent_hash_count() - before;
+    }
+    (total / ROUNDS, parents)
+}
+
+fn main() {
+    let tree = BinaryMerkleTree::new_from_leaves((0..LEAF_COUNT).map(|i| chunk_output(i as u64, b"leaf")).collect());
+    let mut rng = StdRng::seed_from_u64(2641);
+    println!("{LEAF_COUNT} leaves; time and parents hashed per bulk update by dirty share and threshold");
+    print!("{:>8}", "dirty");
+    for threshold in THRESHOLDS {
+        print!(" {:>20}", if threshold.is_finite() { format!("{threshold}") } else { "per-path".to_string() });
+    }
+    println!();
+    for density in [0.01, 0.05, 0.1, 0.2, 0.3, 0.5, 0.7, 0.9, 0.95, 1.0] {
+        let mut indices = sample(&mut rng, LEAF_COUNT, (LEAF_COUNT as f64 * density) as usize).into_vec();
+        indices.sort_unstable();
+        print!("{density:>8.2}");
+        for threshold in THRESHOLDS {
+            let (time, parents) = time_updates(&tree, &indices, threshold);
+            print!(" {:>12} {:>7}", format!("{time:.2?}"), parents);
+        }
+        println!();
+    }
+}
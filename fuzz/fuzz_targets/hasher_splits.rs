@@ -0,0 +1,58 @@
+#![no_main]
+
+//! Feed an arbitrary input to `Blake3Hasher` in arbitrary pieces, in all three
+//! modes, and check every output against the official `blake3` crate.
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, Blake3Hasher};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    data: Vec<u8>,
+    splits: Vec<u16>,
+    key: [u8; 32],
+    output_len: u16,
+}
+
+fn feed(mut hasher: Blake3Hasher, data: &[u8], splits: &[u16], output_len: usize) -> Vec<u8> {
+    let mut rest = data;
+    for &split in splits {
+        let (piece, tail) = rest.split_at((split as usize).min(rest.len()));
+        hasher.update(piece);
+        rest = tail;
+    }
+    hasher.update(rest);
+    let mut out = vec![0; output_len];
+    hasher.finalize(&mut out);
+    out
+}
+
+fn expected(mut hasher: blake3::Hasher, data: &[u8], output_len: usize) -> Vec<u8> {
+    hasher.update(data);
+    let mut out = vec![0; output_len];
+    hasher.finalize_xof().fill(&mut out);
+    out
+}
+
+fuzz_target!(|input: Input| {
+    let output_len = input.output_len as usize % 1024 + 1;
+    let data = &input.data;
+
+    assert_eq!(
+        feed(Blake3Hasher::new(), data, &input.splits, output_len),
+        expected(blake3::Hasher::new(), data, output_len)
+    );
+    assert_eq!(
+        feed(Blake3Hasher::new_keyed(&input.key), data, &input.splits, output_len),
+        expected(blake3::Hasher::new_keyed(&input.key), data, output_len)
+    );
+    let context = "merkle_tree fuzz 2024 context";
+    assert_eq!(
+        feed(Blake3Hasher::new_derive_key(context), data, &input.splits, output_len),
+        expected(blake3::Hasher::new_derive_key(context), data, output_len)
+    );
+
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(data));
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(data).as_bytes());
+});
@@ -0,0 +1,60 @@
+#![no_main]
+
+//! Apply an arbitrary sequence of writes, truncations and extensions to an
+//! arbitrary input through the incremental update path, and check the root
+//! against the official `blake3` crate after every step.
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree};
+
+// Keeps each run fast while still spanning many chunks
+const MAX_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Arbitrary)]
+enum Mutation {
+    /// Overwrite bytes starting at `offset` (wrapped to the current length),
+    /// growing the input if the write runs past its end.
+    Write { offset: u32, bytes: Vec<u8> },
+    /// Truncate or zero-extend to `len` (wrapped to MAX_LEN).
+    SetLen { len: u32 },
+    /// Replace a single leaf with the rehash of its chunk.
+    InsertLeaf { chunk: u16 },
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    initial: Vec<u8>,
+    mutations: Vec<Mutation>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut content = input.initial;
+    content.truncate(MAX_LEN);
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&content));
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&content).as_bytes());
+
+    for mutation in input.mutations {
+        match mutation {
+            Mutation::Write { offset, bytes } => {
+                let start = offset as usize % (content.len() + 1);
+                let end = (start + bytes.len()).min(MAX_LEN);
+                if end > content.len() {
+                    content.resize(end, 0);
+                }
+                content[start..end].copy_from_slice(&bytes[..end - start]);
+                tree.update_bytes(&content, start as u64..end as u64);
+            }
+            Mutation::SetLen { len } => {
+                content.resize(len as usize % (MAX_LEN + 1), 0);
+                tree.set_len(&content);
+            }
+            Mutation::InsertLeaf { chunk } => {
+                let leaves = process_input_to_chunks(&content);
+                let index = chunk as usize % leaves.len();
+                tree.insert_leaf(index, leaves[index]).unwrap();
+            }
+        }
+        assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&content).as_bytes());
+    }
+});
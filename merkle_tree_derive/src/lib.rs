@@ -0,0 +1,85 @@
+//! `#[derive(MerkleLeaf)]` for `merkle_tree::record::MerkleLeaf`. Enable the
+//! `derive` feature of `merkle_tree` rather than depending on this crate
+//! directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Index};
+
+/// Encode a struct's fields in declaration order, or an enum's variant index
+/// as a little-endian u32 followed by that variant's fields. Every field type
+/// must implement `MerkleLeaf`.
+#[proc_macro_derive(MerkleLeaf)]
+pub fn derive_merkle_leaf(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, encode) = destructure(&data.fields);
+            quote! {
+                let Self #pattern = self;
+                #encode
+            }
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+                let name = &variant.ident;
+                let index = index as u32;
+                let (pattern, encode) = destructure(&variant.fields);
+                quote! {
+                    Self::#name #pattern => {
+                        out.extend_from_slice(&#index.to_le_bytes());
+                        #encode
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new(Span::call_site(), "MerkleLeaf cannot be derived for unions"));
+        }
+    };
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::merkle_tree::record::MerkleLeaf));
+    }
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::merkle_tree::record::MerkleLeaf for #name #type_generics #where_clause {
+            #[allow(unused_variables)]
+            fn encode_canonical(&self, out: &mut ::std::vec::Vec<u8>) {
+                #body
+            }
+        }
+    })
+}
+
+// A pattern binding every field of a struct or variant, and the statements
+// encoding those bindings in order
+fn destructure(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    let bindings: Vec<_> = (0..fields.len()).map(|i| format_ident!("field_{}", i)).collect();
+    let pattern = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| field.ident.as_ref().unwrap());
+            quote!({ #(#names: #bindings),* })
+        }
+        Fields::Unnamed(_) => {
+            let indices = (0..fields.len()).map(Index::from);
+            quote!({ #(#indices: #bindings),* })
+        }
+        Fields::Unit => quote!(),
+    };
+    let encode = quote! {
+        #(::merkle_tree::record::MerkleLeaf::encode_canonical(#bindings, out);)*
+    };
+    (pattern, encode)
+}
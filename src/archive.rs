@@ -0,0 +1,106 @@
+use std::ops::Range;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::codec::Decoder;
+use crate::directory::{DirectoryEntry, DirectoryManifest};
+use crate::hash::Hash;
+use crate::proof::{verify_range, ByteRangeProof, RangeProof};
+
+const ARCHIVE_PROOF_MAGIC: &[u8; 4] = b"B3AP";
+const ARCHIVE_PROOF_VERSION: u8 = 1;
+
+/// Proof that some bytes of one file belong to an archive, a tree of trees
+/// whose leaves are the files' `DirectoryEntry`s: the entry with its
+/// position, proven against the archive root, and a byte range proof against
+/// the root recorded in the entry. A release bundle can then be verified by
+/// its one top root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveProof {
+    pub entry: DirectoryEntry,
+    /// Position of the entry among the archive's leaves.
+    pub entry_index: usize,
+    pub entry_proof: RangeProof,
+    pub bytes: ByteRangeProof,
+}
+
+impl DirectoryManifest {
+    /// Prove `byte_range` of the file at `path` against this manifest's root.
+    /// `file_tree` and `file_data` are the file's tree and contents. Returns
+    /// None if there is no such entry, the tree is not the one the entry
+    /// records, or the range cannot be proven.
+    pub fn prove_file_bytes(
+        &self,
+        path: &str,
+        file_tree: &BinaryMerkleTree,
+        file_data: &[u8],
+        byte_range: Range<u64>,
+    ) -> Option<ArchiveProof> {
+        let entry_index = self.position(path)?;
+        let entry = &self.entries()[entry_index];
+        if !file_tree.root_hash().ct_eq(&entry.root) {
+            return None;
+        }
+        Some(ArchiveProof {
+            entry: entry.clone(),
+            entry_index,
+            entry_proof: self.tree().prove_range(entry_index..entry_index + 1)?,
+            bytes: file_tree.prove_bytes(file_data, byte_range)?,
+        })
+    }
+}
+
+impl ArchiveProof {
+    /// Check that `data` is exactly the bytes at `byte_range` of the file
+    /// named by `entry.path` in an archive with root `archive_root`.
+    pub fn verify(&self, archive_root: &Hash, byte_range: Range<u64>, data: &[u8]) -> bool {
+        if self.entry_proof.chunks != (self.entry_index..self.entry_index + 1) {
+            return false;
+        }
+        let leaf = self.entry.leaf(self.entry_index);
+        let entry_proven = self
+            .entry_proof
+            .compute_root_from_leaves(&[leaf])
+            .is_some_and(|root| root.ct_eq(archive_root));
+        entry_proven && verify_range(&self.entry.root, byte_range, data, &self.bytes)
+    }
+
+    /// Serialize as: magic, version, the entry index (u64 LE), the entry in
+    /// its `DirectoryEntry::encode` form, the encoded entry proof and byte
+    /// range chunk proof, and the byte proof's prefix and suffix, with every
+    /// variable-length part preceded by its length as u64 LE.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = ARCHIVE_PROOF_MAGIC.to_vec();
+        bytes.push(ARCHIVE_PROOF_VERSION);
+        bytes.extend_from_slice(&(self.entry_index as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.entry.encode());
+        for part in [&self.entry_proof.encode(), &self.bytes.chunks.encode(), &self.bytes.prefix, &self.bytes.suffix] {
+            bytes.extend_from_slice(&(part.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(part);
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<ArchiveProof> {
+        fn take_part<'a>(decoder: &mut Decoder<'a>) -> Option<&'a [u8]> {
+            let len = decoder.take_usize()?;
+            decoder.take(len)
+        }
+
+        let mut decoder = Decoder::with_header(bytes, ARCHIVE_PROOF_MAGIC, ARCHIVE_PROOF_VERSION)?;
+        let entry_index = decoder.take_usize()?;
+        let path = String::from_utf8(take_part(&mut decoder)?.to_vec()).ok()?;
+        let mode = decoder.take_u32()?;
+        let len = decoder.take_u64()?;
+        let root = decoder.take_hash()?;
+        let entry_proof = RangeProof::decode(take_part(&mut decoder)?)?;
+        let chunks = RangeProof::decode(take_part(&mut decoder)?)?;
+        let prefix = take_part(&mut decoder)?.to_vec();
+        let suffix = take_part(&mut decoder)?.to_vec();
+        decoder.is_empty().then_some(ArchiveProof {
+            entry: DirectoryEntry { path, mode, len, root },
+            entry_index,
+            entry_proof,
+            bytes: ByteRangeProof { chunks, prefix, suffix },
+        })
+    }
+}
@@ -1,19 +1,37 @@
-use std::collections::VecDeque;
-use std::iter::FromIterator;
+use std::sync::Arc;
 use core::cmp::min;
 
+use crate::blocks::BlockCvs;
+use crate::error::{check_bulk_update, Error};
+use crate::hash::Hash;
+use crate::hasher::{Blake3TreeHasher, TreeHasher};
+use crate::journal::Journal;
+use crate::node_index::NodeIndex;
+use crate::oplog::{Operation, OperationLog};
+#[cfg(feature = "metrics")]
+use crate::metrics::{Metrics, MetricsState};
+
 pub const OUT_LEN: usize = 32;
 pub const KEY_LEN: usize = 32;
 pub const BLOCK_LEN: usize = 64;
 pub const CHUNK_LEN: usize = 1024;
 
-const CHUNK_START: u32 = 1 << 0;
-const CHUNK_END: u32 = 1 << 1;
-const PARENT: u32 = 1 << 2;
+pub(crate) const CHUNK_START: u32 = 1 << 0;
+pub(crate) const CHUNK_END: u32 = 1 << 1;
+pub(crate) const PARENT: u32 = 1 << 2;
 pub const ROOT: u32 = 1 << 3;
-const KEYED_HASH: u32 = 1 << 4;
-const DERIVE_KEY_CONTEXT: u32 = 1 << 5;
-const DERIVE_KEY_MATERIAL: u32 = 1 << 6;
+pub const KEYED_HASH: u32 = 1 << 4;
+pub const DERIVE_KEY_CONTEXT: u32 = 1 << 5;
+pub const DERIVE_KEY_MATERIAL: u32 = 1 << 6;
+
+/// Default `BinaryMerkleTree::level_rebuild_threshold`, a share of dirty
+/// leaves. Per-path updates never hash more parents than a full rebuild, so
+/// rebuilding only pays once nearly every parent is dirty anyway, by saving
+/// the frontier bookkeeping. In the `bulk_update` benchmark (2^15 leaves),
+/// rebuilding at 90% dirty leaves hashes 0.5% more parents than per-path
+/// updates in the same time, while a threshold of 0.5 hashes 16% more
+/// parents at half the leaves dirty and is about 10% slower.
+pub const DEFAULT_LEVEL_REBUILD_THRESHOLD: f64 = 0.9;
 
 pub const IV: [u32; 8] = [
     0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
@@ -22,7 +40,7 @@ pub const IV: [u32; 8] = [
 // Each chunk or parent node can produce either an 8-word chaining value or, by
 // setting the ROOT flag, any number of final output bytes. The Output struct
 // captures the state just prior to choosing between those two possibilities.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Output {
     pub input_chaining_value: [u32; 8],
     pub block_words: [u32; 16],
@@ -40,26 +58,28 @@ impl Output {
             self.block_len,
             self.flags,
         ));
-        println!("Output chaining_value: input_cv={:?}, block_words={:?}, counter={}, block_len={}, flags={:b} => cv={:?}",
-            self.input_chaining_value, self.block_words, self.counter, self.block_len, self.flags, cv);
+        #[cfg(feature = "trace")]
+        tracing::trace!(counter = self.counter, block_len = self.block_len, flags = self.flags, cv = ?cv, "chaining value");
+        #[cfg(feature = "metrics")]
+        crate::metrics::count_compressions(1);
         cv
     }
 
     pub fn root_output_bytes(&self, out_slice: &mut [u8]) {
-        let mut output_block_counter = 0;
-        for out_block in out_slice.chunks_mut(2 * OUT_LEN) {
+        for (output_block_counter, out_block) in out_slice.chunks_mut(2 * OUT_LEN).enumerate() {
             let words = compress(
                 &self.input_chaining_value,
                 &self.block_words,
-                output_block_counter,
+                output_block_counter as u64,
                 self.block_len,
                 self.flags | ROOT,
             );
+            #[cfg(feature = "metrics")]
+            crate::metrics::count_compressions(1);
             // The output length might not be a multiple of 4.
             for (word, out_word) in words.iter().zip(out_block.chunks_mut(4)) {
                 out_word.copy_from_slice(&word.to_le_bytes()[..out_word.len()]);
             }
-            output_block_counter += 1;
         }
     }
 }
@@ -70,8 +90,8 @@ pub fn parent_output(
     key_words: [u32; 8],
     flags: u32,
 ) -> Output {
-    println!("Creating parent node: left_cv={:?}, right_cv={:?}, key={:?}, flags={:b}",
-        left_child_cv, right_child_cv, key_words, flags);
+    #[cfg(feature = "trace")]
+    tracing::trace!(left_cv = ?left_child_cv, right_cv = ?right_child_cv, flags, "parent node");
     let mut block_words = [0; 16];
     block_words[..8].copy_from_slice(&left_child_cv);
     block_words[8..].copy_from_slice(&right_child_cv);
@@ -84,11 +104,17 @@ pub fn parent_output(
     }
 }
 
-fn first_8_words(compression_output: [u32; 16]) -> [u32; 8] {
-    compression_output[0..8].try_into().unwrap()
+const fn first_8_words(compression_output: [u32; 16]) -> [u32; 8] {
+    let mut words = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+        words[i] = compression_output[i];
+        i += 1;
+    }
+    words
 }
 
-fn compress(
+pub(crate) const fn compress(
     chaining_value: &[u32; 8],
     block_words: &[u32; 16],
     counter: u64,
@@ -120,24 +146,28 @@ fn compress(
     permute(&mut block);
     round(&mut state, &block); // round 7
 
-    for i in 0..8 {
+    let mut i = 0;
+    while i < 8 {
         state[i] ^= state[i + 8];
         state[i + 8] ^= chaining_value[i];
+        i += 1;
     }
     state
 }
 
 const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
 
-fn permute(m: &mut [u32; 16]) {
+const fn permute(m: &mut [u32; 16]) {
     let mut permuted = [0; 16];
-    for i in 0..16 {
+    let mut i = 0;
+    while i < 16 {
         permuted[i] = m[MSG_PERMUTATION[i]];
+        i += 1;
     }
     *m = permuted;
 }
 
-fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+const fn round(state: &mut [u32; 16], m: &[u32; 16]) {
     // Mix the columns.
     g(state, 0, 4, 8, 12, m[0], m[1]);
     g(state, 1, 5, 9, 13, m[2], m[3]);
@@ -150,7 +180,7 @@ fn round(state: &mut [u32; 16], m: &[u32; 16]) {
     g(state, 3, 4, 9, 14, m[14], m[15]);
 }
 
-fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+const fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
     state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
     state[d] = (state[d] ^ state[a]).rotate_right(16);
     state[c] = state[c].wrapping_add(state[d]);
@@ -161,13 +191,147 @@ fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my:
     state[b] = (state[b] ^ state[c]).rotate_right(7);
 }
 
-fn words_from_little_endian_bytes(bytes: &[u8], words: &mut [u32]) {
+pub(crate) fn words_from_little_endian_bytes(bytes: &[u8], words: &mut [u32]) {
     debug_assert_eq!(bytes.len(), 4 * words.len());
     for (four_bytes, word) in bytes.chunks_exact(4).zip(words) {
         *word = u32::from_le_bytes(four_bytes.try_into().unwrap());
     }
 }
 
+// Compile-time hashing. These mirror ChunkState and the chaining value stack of
+// Blake3Hasher one block at a time, using only what a const fn can do.
+
+/// Hash `input` in a `const` context, with the same result as `blake3::hash`.
+/// It is portable code without SIMD or threads, meant for short static inputs
+/// such as protocol labels:
+///
+/// ```
+/// use merkle_tree::binary_merkle_tree::const_hash;
+///
+/// const LABEL: [u8; 32] = const_hash(b"my-protocol v1");
+/// assert_eq!(LABEL, *blake3::hash(b"my-protocol v1").as_bytes());
+/// ```
+pub const fn const_hash(input: &[u8]) -> [u8; OUT_LEN] {
+    const_hash_inner(IV, 0, input)
+}
+
+/// `const` equivalent of `blake3::keyed_hash`.
+pub const fn const_keyed_hash(key: &[u8; KEY_LEN], input: &[u8]) -> [u8; OUT_LEN] {
+    const_hash_inner(first_8_words(const_block_words(key)), KEYED_HASH, input)
+}
+
+/// `const` equivalent of `blake3::derive_key`.
+pub const fn const_derive_key(context: &str, key_material: &[u8]) -> [u8; OUT_LEN] {
+    let context_key = const_hash_inner(IV, DERIVE_KEY_CONTEXT, context.as_bytes());
+    const_hash_inner(first_8_words(const_block_words(&context_key)), DERIVE_KEY_MATERIAL, key_material)
+}
+
+const fn const_hash_inner(key_words: [u32; 8], flags: u32, mut input: &[u8]) -> [u8; OUT_LEN] {
+    let mut cv_stack = [[0; 8]; 54];
+    let mut stack_len = 0;
+    let mut chunk_counter = 0;
+    // Every chunk but the last is merged into the stack as soon as it completes
+    // a subtree, exactly like Blake3Hasher::add_chunk_chaining_value
+    while input.len() > CHUNK_LEN {
+        let (chunk, rest) = input.split_at(CHUNK_LEN);
+        let mut cv = const_output_cv(&const_chunk_output(key_words, chunk_counter, flags, chunk));
+        chunk_counter += 1;
+        let mut total_chunks = chunk_counter;
+        while total_chunks & 1 == 0 {
+            stack_len -= 1;
+            cv = const_output_cv(&const_parent_output(cv_stack[stack_len], cv, key_words, flags));
+            total_chunks >>= 1;
+        }
+        cv_stack[stack_len] = cv;
+        stack_len += 1;
+        input = rest;
+    }
+
+    let mut output = const_chunk_output(key_words, chunk_counter, flags, input);
+    while stack_len > 0 {
+        stack_len -= 1;
+        output = const_parent_output(cv_stack[stack_len], const_output_cv(&output), key_words, flags);
+    }
+    let words = compress(
+        &output.input_chaining_value,
+        &output.block_words,
+        0,
+        output.block_len,
+        output.flags | ROOT,
+    );
+    let mut bytes = [0; OUT_LEN];
+    let mut i = 0;
+    while i < OUT_LEN {
+        bytes[i] = (words[i / 4] >> (8 * (i % 4))) as u8;
+        i += 1;
+    }
+    bytes
+}
+
+// The output of one chunk of at most CHUNK_LEN bytes
+const fn const_chunk_output(key_words: [u32; 8], chunk_counter: u64, flags: u32, mut chunk: &[u8]) -> Output {
+    let mut chaining_value = key_words;
+    let mut block_flags = flags | CHUNK_START;
+    while chunk.len() > BLOCK_LEN {
+        let (block, rest) = chunk.split_at(BLOCK_LEN);
+        let block_words = const_block_words(block);
+        chaining_value = first_8_words(compress(
+            &chaining_value,
+            &block_words,
+            chunk_counter,
+            BLOCK_LEN as u32,
+            block_flags,
+        ));
+        block_flags = flags;
+        chunk = rest;
+    }
+    Output {
+        input_chaining_value: chaining_value,
+        block_words: const_block_words(chunk),
+        counter: chunk_counter,
+        block_len: chunk.len() as u32,
+        flags: block_flags | CHUNK_END,
+    }
+}
+
+const fn const_parent_output(left_child_cv: [u32; 8], right_child_cv: [u32; 8], key_words: [u32; 8], flags: u32) -> Output {
+    let mut block_words = [0; 16];
+    let mut i = 0;
+    while i < 8 {
+        block_words[i] = left_child_cv[i];
+        block_words[i + 8] = right_child_cv[i];
+        i += 1;
+    }
+    Output {
+        input_chaining_value: key_words,
+        block_words,
+        counter: 0,
+        block_len: BLOCK_LEN as u32,
+        flags: PARENT | flags,
+    }
+}
+
+const fn const_output_cv(output: &Output) -> [u32; 8] {
+    first_8_words(compress(
+        &output.input_chaining_value,
+        &output.block_words,
+        output.counter,
+        output.block_len,
+        output.flags,
+    ))
+}
+
+// Little-endian words of a block of at most BLOCK_LEN bytes, zero padded
+const fn const_block_words(block: &[u8]) -> [u32; 16] {
+    let mut words = [0; 16];
+    let mut i = 0;
+    while i < block.len() {
+        words[i / 4] |= (block[i] as u32) << (8 * (i % 4));
+        i += 1;
+    }
+    words
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ChunkState {
     pub chaining_value: [u32; 8],
@@ -194,6 +358,10 @@ impl ChunkState {
         BLOCK_LEN * self.blocks_compressed as usize + self.block_len as usize
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn start_flag(&self) -> u32 {
         if self.blocks_compressed == 0 {
             CHUNK_START
@@ -214,8 +382,10 @@ impl ChunkState {
                     &block_words,
                     self.chunk_counter,
                     BLOCK_LEN as u32,
-                    self.flags | self.start_flag() as u32,
+                    self.flags | self.start_flag(),
                 ));
+                #[cfg(feature = "metrics")]
+                crate::metrics::count_compressions(1);
                 self.blocks_compressed += 1;
                 self.block = [0; BLOCK_LEN];
                 self.block_len = 0;
@@ -233,16 +403,20 @@ impl ChunkState {
     pub fn output(&self) -> Output {
         let mut block_words = [0; 16];
         words_from_little_endian_bytes(&self.block, &mut block_words);
-        println!("ChunkState output: cv={:?}, counter={}, block={:?}, block_len={}, blocks_compressed={}, flags={:b}",
-            self.chaining_value, self.chunk_counter, self.block, self.block_len, self.blocks_compressed, self.flags);
-        let output = Output {
+        #[cfg(feature = "trace")]
+        tracing::trace!(
+            counter = self.chunk_counter,
+            len = self.len(),
+            flags = self.flags,
+            "chunk output"
+        );
+        Output {
             input_chaining_value: self.chaining_value,
             block_words,
             counter: self.chunk_counter,
             block_len: self.block_len as u32,
-            flags: self.flags | self.start_flag() as u32 | CHUNK_END as u32,
-        };
-        output
+            flags: self.flags | self.start_flag() | CHUNK_END,
+        }
     }
 }
 
@@ -255,6 +429,44 @@ pub fn parent_cv(
     parent_output(left_child_cv, right_child_cv, key_words, flags).chaining_value()
 }
 
+/// The key words and flags every chunk and parent of a tree is hashed with:
+/// the IV and no flags for the regular hash function, the key and
+/// KEYED_HASH for the keyed hash, and the context key and
+/// DERIVE_KEY_MATERIAL for key derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashMode {
+    pub key_words: [u32; 8],
+    pub flags: u32,
+}
+
+impl Default for HashMode {
+    fn default() -> Self {
+        HashMode::HASH
+    }
+}
+
+impl HashMode {
+    pub const HASH: HashMode = HashMode { key_words: IV, flags: 0 };
+
+    pub fn keyed(key: &[u8; KEY_LEN]) -> Self {
+        let mut key_words = [0; 8];
+        words_from_little_endian_bytes(key, &mut key_words);
+        HashMode { key_words, flags: KEYED_HASH }
+    }
+
+    /// The mode for deriving keys under `context`, which should be hardcoded,
+    /// globally unique, and application-specific.
+    pub fn derive_key(context: &str) -> Self {
+        let mut context_hasher = Blake3Hasher::new_internal(IV, DERIVE_KEY_CONTEXT);
+        context_hasher.update(context.as_bytes());
+        let mut context_key = [0; KEY_LEN];
+        context_hasher.finalize(&mut context_key);
+        let mut key_words = [0; 8];
+        words_from_little_endian_bytes(&context_key, &mut key_words);
+        HashMode { key_words, flags: DERIVE_KEY_MATERIAL }
+    }
+}
+
 /// An incremental hasher that can accept any number of writes.
 pub struct Blake3Hasher {
     chunk_state: ChunkState,
@@ -264,6 +476,12 @@ pub struct Blake3Hasher {
     flags: u32,
 }
 
+impl Default for Blake3Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Blake3Hasher {
     fn new_internal(key_words: [u32; 8], flags: u32) -> Self {
         Self {
@@ -282,21 +500,15 @@ impl Blake3Hasher {
 
     /// Construct a new `Hasher` for the keyed hash function.
     pub fn new_keyed(key: &[u8; KEY_LEN]) -> Self {
-        let mut key_words = [0; 8];
-        words_from_little_endian_bytes(key, &mut key_words);
-        Self::new_internal(key_words, KEYED_HASH)
+        let mode = HashMode::keyed(key);
+        Self::new_internal(mode.key_words, mode.flags)
     }
 
     /// Construct a new `Hasher` for the key derivation function. The context
     /// string should be hardcoded, globally unique, and application-specific.
     pub fn new_derive_key(context: &str) -> Self {
-        let mut context_hasher = Self::new_internal(IV, DERIVE_KEY_CONTEXT);
-        context_hasher.update(context.as_bytes());
-        let mut context_key = [0; KEY_LEN];
-        context_hasher.finalize(&mut context_key);
-        let mut context_key_words = [0; 8];
-        words_from_little_endian_bytes(&context_key, &mut context_key_words);
-        Self::new_internal(context_key_words, DERIVE_KEY_MATERIAL)
+        let mode = HashMode::derive_key(context);
+        Self::new_internal(mode.key_words, mode.flags)
     }
 
     fn push_stack(&mut self, cv: [u32; 8]) {
@@ -350,50 +562,469 @@ impl Blake3Hasher {
         // Starting with the Output from the current chunk, compute all the
         // parent chaining values along the right edge of the tree, until we
         // have the root Output.
-        println!("\nBLAKE3 Finalization:");
+        #[cfg(feature = "trace")]
+        let _span = tracing::debug_span!("finalize", total_len = self.total_len(), stack_len = self.cv_stack_len).entered();
         let mut output = self.chunk_state.output();
-        println!("Initial chunk output cv: {:?}", output.chaining_value());
         let mut parent_nodes_remaining = self.cv_stack_len as usize;
         while parent_nodes_remaining > 0 {
             parent_nodes_remaining -= 1;
-            let stack_cv = self.cv_stack[parent_nodes_remaining];
-            println!("Combining with stack cv[{}]: {:?}", parent_nodes_remaining, stack_cv);
             output = parent_output(
                 self.cv_stack[parent_nodes_remaining],
                 output.chaining_value(),
                 self.key_words,
                 self.flags,
             );
-            println!("New output cv: {:?}", output.chaining_value());
         }
         output.root_output_bytes(out_slice);
     }
+
+    /// Number of input bytes hashed so far.
+    pub fn total_len(&self) -> u64 {
+        self.chunk_state.chunk_counter * CHUNK_LEN as u64 + self.chunk_state.len() as u64
+    }
+
+    /// Capture the hasher's state so hashing can resume after a restart with
+    /// `resume`. The state is under 2 KiB: the key, flags, the partial chunk,
+    /// and the chaining values of the completed subtrees on the CV stack.
+    ///
+    /// Layout: magic, version, key words, flags, chunk chaining value, chunk
+    /// counter (u64), block buffer, block length, blocks compressed, chunk flags,
+    /// stack length, then the stack; all integers LE.
+    pub fn serialize_state(&self) -> Vec<u8> {
+        let stack = &self.cv_stack[..self.cv_stack_len as usize];
+        let mut state = Vec::with_capacity(HASHER_STATE_FIXED_LEN + 32 * stack.len());
+        state.extend_from_slice(HASHER_STATE_MAGIC);
+        state.push(HASHER_STATE_VERSION);
+        let push_words = |state: &mut Vec<u8>, words: &[u32; 8]| {
+            for word in words {
+                state.extend_from_slice(&word.to_le_bytes());
+            }
+        };
+        push_words(&mut state, &self.key_words);
+        state.extend_from_slice(&self.flags.to_le_bytes());
+        push_words(&mut state, &self.chunk_state.chaining_value);
+        state.extend_from_slice(&self.chunk_state.chunk_counter.to_le_bytes());
+        state.extend_from_slice(&self.chunk_state.block);
+        state.push(self.chunk_state.block_len);
+        state.push(self.chunk_state.blocks_compressed);
+        state.extend_from_slice(&self.chunk_state.flags.to_le_bytes());
+        state.push(self.cv_stack_len);
+        for cv in stack {
+            push_words(&mut state, cv);
+        }
+        state
+    }
+
+    /// Rebuild a hasher from `serialize_state` output. Returns None if the
+    /// state is malformed or internally inconsistent.
+    pub fn resume(state: &[u8]) -> Option<Self> {
+        let rest = state.strip_prefix(HASHER_STATE_MAGIC)?;
+        let (&version, mut rest) = rest.split_first()?;
+        if version != HASHER_STATE_VERSION {
+            return None;
+        }
+        let mut take = |len: usize| -> Option<&[u8]> {
+            let (taken, remaining) = rest.split_at_checked(len)?;
+            rest = remaining;
+            Some(taken)
+        };
+        fn words(bytes: &[u8]) -> [u32; 8] {
+            let mut words = [0; 8];
+            words_from_little_endian_bytes(bytes, &mut words);
+            words
+        }
+        let key_words = words(take(32)?);
+        let flags = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let chunk_chaining_value = words(take(32)?);
+        let chunk_counter = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let block: [u8; BLOCK_LEN] = take(BLOCK_LEN)?.try_into().unwrap();
+        let block_len = take(1)?[0];
+        let blocks_compressed = take(1)?[0];
+        let chunk_flags = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let cv_stack_len = take(1)?[0];
+
+        let mut cv_stack = [[0; 8]; 54];
+        for cv in cv_stack.iter_mut().take(cv_stack_len as usize) {
+            *cv = words(take(32)?);
+        }
+        // The stack holds one subtree per set bit of the completed chunk count
+        let consistent = take(1).is_none()
+            && block_len as usize <= BLOCK_LEN
+            && (blocks_compressed as usize) < CHUNK_LEN / BLOCK_LEN
+            && cv_stack_len as u32 == chunk_counter.count_ones()
+            && chunk_flags == flags;
+        if !consistent {
+            return None;
+        }
+        Some(Self {
+            chunk_state: ChunkState {
+                chaining_value: chunk_chaining_value,
+                chunk_counter,
+                block,
+                block_len,
+                blocks_compressed,
+                flags: chunk_flags,
+            },
+            key_words,
+            cv_stack,
+            cv_stack_len,
+            flags,
+        })
+    }
 }
 
+const HASHER_STATE_MAGIC: &[u8; 4] = b"B3HS";
+const HASHER_STATE_VERSION: u8 = 1;
+// Everything but the CV stack, including the magic and version
+const HASHER_STATE_FIXED_LEN: usize = 4 + 1 + 32 + 4 + 32 + 8 + BLOCK_LEN + 1 + 1 + 4 + 1;
+
+// Below this many bytes a subtree is hashed on the calling thread; splitting
+// further costs more in task overhead than it saves
+#[cfg(feature = "rayon")]
+const RAYON_MIN_SUBTREE_LEN: usize = 16 * CHUNK_LEN;
+
+#[cfg(feature = "rayon")]
+impl Blake3Hasher {
+    /// Like `update`, but hashes large inputs on the rayon thread pool. The
+    /// input is split into the largest complete subtrees that fit at the
+    /// current position, each subtree's halves are hashed in parallel, and the
+    /// resulting chaining values join the CV stack as if every chunk had been
+    /// added one at a time. The result is identical to `update`.
+    pub fn update_rayon(&mut self, mut input: &[u8]) {
+        // Fill up the chunk in progress first, so the rest starts on a chunk
+        // boundary
+        if !self.chunk_state.is_empty() {
+            let take = min(CHUNK_LEN - self.chunk_state.len(), input.len());
+            self.update(&input[..take]);
+            input = &input[take..];
+        }
+        // The final chunk must stay in the chunk state, since it may turn out to
+        // be the root, so only input past it is split off
+        while input.len() > CHUNK_LEN {
+            if self.chunk_state.len() == CHUNK_LEN {
+                let chunk_cv = self.chunk_state.output().chaining_value();
+                let total_chunks = self.chunk_state.chunk_counter + 1;
+                self.add_chunk_chaining_value(chunk_cv, total_chunks);
+                self.chunk_state = ChunkState::new(self.key_words, total_chunks, self.flags);
+            }
+            let counter = self.chunk_state.chunk_counter;
+            // A subtree must start at a multiple of its own size
+            let aligned_chunks = if counter == 0 { u64::MAX } else { 1 << counter.trailing_zeros() };
+            let available_chunks = ((input.len() - 1) / CHUNK_LEN) as u64;
+            let subtree_chunks = 1 << (u64::BITS - 1 - aligned_chunks.min(available_chunks).leading_zeros());
+            let (subtree, rest) = input.split_at(subtree_chunks as usize * CHUNK_LEN);
+
+            let subtree_cv = self.subtree_cv_rayon(subtree, counter);
+            let total_chunks = counter + subtree_chunks;
+            // The subtree counts as one entry at its own level of the stack
+            self.add_chunk_chaining_value(subtree_cv, total_chunks >> subtree_chunks.trailing_zeros());
+            self.chunk_state = ChunkState::new(self.key_words, total_chunks, self.flags);
+            input = rest;
+        }
+        self.update(input);
+    }
+
+    /// Like `update_rayon`, running on `pool` rather than the global pool, so
+    /// hashing does not starve the caller's other parallel work.
+    pub fn update_rayon_in(&mut self, input: &[u8], pool: &rayon::ThreadPool) {
+        pool.install(|| self.update_rayon(input))
+    }
+
+    // Chaining value of a complete subtree of whole chunks starting at chunk
+    // `counter`
+    fn subtree_cv_rayon(&self, input: &[u8], counter: u64) -> [u32; 8] {
+        if input.len() == CHUNK_LEN {
+            let mut chunk_state = ChunkState::new(self.key_words, counter, self.flags);
+            chunk_state.update(input);
+            return chunk_state.output().chaining_value();
+        }
+        let (left, right) = input.split_at(input.len() / 2);
+        let right_counter = counter + (left.len() / CHUNK_LEN) as u64;
+        let (left_cv, right_cv) = if input.len() > RAYON_MIN_SUBTREE_LEN {
+            rayon::join(|| self.subtree_cv_rayon(left, counter), || self.subtree_cv_rayon(right, right_counter))
+        } else {
+            (self.subtree_cv_rayon(left, counter), self.subtree_cv_rayon(right, right_counter))
+        };
+        parent_cv(left_cv, right_cv, self.key_words, self.flags)
+    }
+}
+
+// ChunkState is Copy, so it cannot clear itself on drop; Blake3Hasher, which
+// owns the key, wipes its chunk state when it is dropped.
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for ChunkState {
+    fn zeroize(&mut self) {
+        self.chaining_value.zeroize();
+        self.chunk_counter.zeroize();
+        self.block.zeroize();
+        self.block_len.zeroize();
+        self.blocks_compressed.zeroize();
+        self.flags.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Blake3Hasher {
+    fn zeroize(&mut self) {
+        self.chunk_state.zeroize();
+        self.key_words.zeroize();
+        self.cv_stack.zeroize();
+        self.cv_stack_len.zeroize();
+        self.flags.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Blake3Hasher {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(self);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for Blake3Hasher {}
+
+/// Hash a single chunk of at most CHUNK_LEN bytes at position `chunk_counter`.
+pub fn chunk_output(chunk_counter: u64, chunk: &[u8]) -> Output {
+    chunk_output_with_mode(chunk_counter, chunk, &HashMode::HASH)
+}
+
+/// `chunk_output` for a keyed or key derivation tree.
+pub fn chunk_output_with_mode(chunk_counter: u64, chunk: &[u8], mode: &HashMode) -> Output {
+    debug_assert!(chunk.len() <= CHUNK_LEN);
+    let mut chunk_state = ChunkState::new(mode.key_words, chunk_counter, mode.flags);
+    chunk_state.update(chunk);
+    chunk_state.output()
+}
+
+/// Number of leaves in the left subtree of a BLAKE3 subtree with `leaf_count`
+/// leaves: the largest power of two strictly less than `leaf_count`.
+pub(crate) fn left_subtree_len(leaf_count: usize) -> usize {
+    debug_assert!(leaf_count > 1);
+    1 << (usize::BITS - 1 - (leaf_count - 1).leading_zeros())
+}
+
+/// Returns the leaf position (0-indexed) of the leftmost leaf below `index` in a
+/// 1-indexed heap layout whose leaves start at `leaf_offset`.
+pub(crate) fn first_leaf_below(index: usize, leaf_offset: usize) -> usize {
+    let height = leaf_offset.trailing_zeros() - index.ilog2();
+    (index << height) - leaf_offset
+}
+
+/// A Merkle tree laid out as a 1-indexed heap. Nodes are combined by `H`,
+/// which defaults to BLAKE3's parent compression; with that hasher and chunk
+/// outputs as leaves, the root matches `Blake3Hasher::finalize` over the input.
 #[derive(Debug, Clone)]
-pub struct BinaryMerkleTree {
-    pub tree: Vec<Output>,
+pub struct BinaryMerkleTree<H: TreeHasher = Blake3TreeHasher> {
+    // Shared with snapshots; writes copy the array first if a snapshot holds it
+    pub(crate) tree: Arc<Vec<H::Node>>,
+    pub(crate) leaf_count: usize,
+    pub(crate) journal: Option<Journal<H::Node>>,
+    pub(crate) recording: Option<OperationLog<H::Node>>,
+    // Parent nodes hashed since construction, including building the tree
+    pub(crate) parent_hashes: u64,
+    pub(crate) level_rebuild_threshold: f64,
+    // Key and flags parents are hashed with; the leaves must use the same
+    pub(crate) mode: HashMode,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: MetricsState,
+    // Per-block chaining values of every chunk, from `cache_block_cvs`
+    pub(crate) block_cvs: Option<BlockCvs>,
 }
 
 impl BinaryMerkleTree {
-    pub fn new_from_leaves(leaves: Vec<Output>) -> BinaryMerkleTree {
+    /// Build a tree over a segment of a larger input starting at chunk
+    /// `start_chunk_counter`, with leaves from `process_input_to_chunks_from`.
+    /// The segment's `root_node().chaining_value()` is the chaining value of
+    /// the corresponding subtree of the whole input, provided the segment is
+    /// one: it starts at a multiple of a power of two number of chunks and is
+    /// that many chunks long, or is the tail of the input. `root` and
+    /// `root_hash` only mean the hash of the input when the segment is all of
+    /// it.
+    ///
+    /// `update_bytes`, `set_len` and the other byte-level helpers number chunks
+    /// from 0, so leaves of a segment tree must be replaced with `insert_leaf`.
+    pub fn from_segment(input: &[u8], start_chunk_counter: u64) -> Self {
+        BinaryMerkleTree::new_from_leaves(process_input_to_chunks_from(input, start_chunk_counter))
+    }
+
+    /// Join `left` and `right`, trees over consecutive segments of one input,
+    /// into the tree over both. `left` must be a complete subtree, a power of
+    /// two number of chunks, and `right` must continue its chunk counters and
+    /// be no larger, which is exactly when the two are the children of the
+    /// root in BLAKE3. Every node of both trees is reused as is; only the new
+    /// root is hashed.
+    pub fn merge(left: BinaryMerkleTree, right: BinaryMerkleTree) -> Result<BinaryMerkleTree, Error> {
+        let left_count = left.num_leaves();
+        let right_count = right.num_leaves();
+        let continues = right_count > 0 && right.leaf(0).counter == left.leaf(0).counter + left_count as u64;
+        if !left_count.is_power_of_two() || right_count > left_count || !continues {
+            return Err(Error::MisalignedSegment {
+                first_chunk: right.get_leaf(0).map_or(0, |leaf| leaf.counter),
+                chunk_count: right_count as u64,
+            });
+        }
+
+        let mut merged = Self::new_empty(2 * left_count as u64);
+        merged.leaf_count = left_count + right_count;
+        merged.parent_hashes = left.parent_hashes + right.parent_hashes;
+        merged.mode = left.mode;
+        #[cfg(feature = "metrics")]
+        {
+            let (left, right) = (left.metrics(), right.metrics());
+            merged.metrics.totals = Metrics {
+                compressions: left.compressions + right.compressions,
+                leaves_updated: left.leaves_updated + right.leaves_updated,
+                ancestors_recomputed: left.ancestors_recomputed + right.ancestors_recomputed,
+                bytes_hashed: left.bytes_hashed + right.bytes_hashed,
+            };
+        }
+        let nodes = Arc::make_mut(&mut merged.tree);
+        // Level d of `left` becomes the left half of level d + 1
+        let left_height = left_count.trailing_zeros();
+        for depth in 0..=left_height {
+            let level = 1 << depth;
+            nodes[2 * level..3 * level].copy_from_slice(&left.tree[level..2 * level]);
+        }
+        // `right` may be shallower. Its root is promoted up the left edge of the
+        // right half until the levels line up, then its levels are copied in.
+        let right_height = right.leaf_offset().trailing_zeros();
+        let promoted_levels = left_height - right_height;
+        for depth in 1..=promoted_levels {
+            nodes[3 << (depth - 1)] = right.tree[1];
+        }
+        for depth in 0..=right_height {
+            let level = 1 << depth;
+            let start = 3 << (promoted_levels + depth);
+            nodes[start..start + level].copy_from_slice(&right.tree[level..2 * level]);
+        }
+        let root = merged.parent_of(2, 3);
+        merged.set_node(1, root);
+        Ok(merged)
+    }
+
+    /// Split into the trees over the chunks before and from `chunk_index`. The
+    /// boundary must be aligned so that the right part is a subtree of this
+    /// tree: `chunk_index` is a multiple of the right part's chunk count rounded
+    /// up to a power of two. The right tree keeps its chunk counters, so it can
+    /// be served as a segment on its own, its root node is the matching node of
+    /// this tree, and `merge` puts the pair back together when the left part is
+    /// the root's left subtree. Returns None for an unaligned or out of range
+    /// index.
+    pub fn split_at(&self, chunk_index: usize) -> Option<(BinaryMerkleTree, BinaryMerkleTree)> {
+        let right_count = self.num_leaves().checked_sub(chunk_index)?;
+        if chunk_index == 0 || right_count == 0 || !chunk_index.is_multiple_of(right_count.next_power_of_two()) {
+            return None;
+        }
+        let leaves = &self.tree[self.leaf_offset()..self.leaf_offset() + self.num_leaves()];
+        Some((
+            BinaryMerkleTree::new_from_leaves_with_mode(leaves[..chunk_index].to_vec(), self.mode),
+            BinaryMerkleTree::new_from_leaves_with_mode(leaves[chunk_index..].to_vec(), self.mode),
+        ))
+    }
+
+    /// The root output with the ROOT flag applied. A tree with one leaf is just
+    /// that chunk, and a tree with no leaves hashes like empty input, so the
+    /// root always matches `Blake3Hasher::finalize` over the same input.
+    pub fn root(&self) -> Output {
+        let mut root = if self.leaf_count == 0 { chunk_output_with_mode(0, &[], &self.mode) } else { self.tree[1] };
+        // Apply ROOT flag to the final root output
+        root.flags |= ROOT;
+        root
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        Hash::from_root(&self.root())
+    }
+
+    /// Chaining value of the leaf at `leaf_index`, or None past the last leaf.
+    pub fn leaf_cv(&self, leaf_index: usize) -> Option<[u32; 8]> {
+        self.get_leaf(leaf_index).map(Output::chaining_value)
+    }
+
+    /// Chaining value committing to the subtree of `leaf_count` leaves starting
+    /// at `first_leaf`, as BLAKE3 would compute it (without the ROOT flag).
+    /// Returns None unless the range is a subtree of this tree: `first_leaf`
+    /// must be a multiple of `leaf_count.next_power_of_two()`, and a count that
+    /// is not a power of two is only allowed for the last subtree on the right
+    /// edge.
+    pub fn subtree_cv(&self, first_leaf: usize, leaf_count: usize) -> Option<[u32; 8]> {
+        let end = first_leaf.checked_add(leaf_count)?;
+        let aligned = leaf_count > 0 && first_leaf.is_multiple_of(leaf_count.next_power_of_two());
+        let in_tree = (end < self.leaf_count && leaf_count.is_power_of_two()) || end == self.leaf_count;
+        (aligned && in_tree).then(|| self.subtree_output(first_leaf, leaf_count).chaining_value())
+    }
+
+    /// Chaining value of the node at `node_index` in the heap layout (root 1,
+    /// children of i at 2i and 2i + 1), or None outside the node array.
+    pub fn node_cv(&self, node_index: usize) -> Option<[u32; 8]> {
+        self.get_node(node_index).map(Output::chaining_value)
+    }
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    pub fn new_from_leaves(leaves: Vec<H::Node>) -> Self {
+        Self::new_from_leaves_with_mode(leaves, HashMode::HASH)
+    }
+
+    /// Build a keyed or key derivation tree, hashing parents under `mode`.
+    /// The leaves must have been hashed under the same mode, as with
+    /// `chunk_output_with_mode`, for the root to match `Blake3Hasher`'s.
+    /// Later updates keep hashing under it.
+    pub fn new_from_leaves_with_mode(leaves: Vec<H::Node>, mode: HashMode) -> Self {
         // Initialize a zero vector with the correct number of nodes
         let number_of_leaves = leaves.len().next_power_of_two();
         let mut tree = Self::new_empty(number_of_leaves as u64);
+        tree.leaf_count = leaves.len();
+        tree.mode = mode;
 
         tree.create_tree_from_leaves(leaves);
         tree
     }
 
-    pub fn root(&self) -> Output {
-        let mut root = self.tree[1];
-        // Apply ROOT flag to the final root output
-        root.flags |= ROOT;
-        root
+    /// The key and flags parents are hashed with.
+    pub fn mode(&self) -> HashMode {
+        self.mode
+    }
+
+    /// The top node as stored. For BLAKE3 trees, `root` additionally applies
+    /// the ROOT flag.
+    pub fn root_node(&self) -> H::Node {
+        self.tree[1]
     }
 
     pub fn num_leaves(&self) -> usize {
-        self.tree.len() / 2
+        self.leaf_count
+    }
+
+    /// The stored leaf at `leaf_index`, or None past the last leaf.
+    pub fn get_leaf(&self, leaf_index: usize) -> Option<&H::Node> {
+        (leaf_index < self.leaf_count).then(|| self.leaf(leaf_index))
+    }
+
+    /// The stored node at `node_index` in the heap layout, or None outside the
+    /// node array. Slots that cover no real leaf hold filler values.
+    pub fn get_node(&self, node_index: usize) -> Option<&H::Node> {
+        (node_index >= 1).then(|| self.tree.get(node_index)).flatten()
+    }
+
+    pub(crate) fn leaf(&self, leaf_index: usize) -> &H::Node {
+        &self.tree[self.leaf_offset() + leaf_index]
+    }
+
+    /// The stored output of the subtree covering `leaf_count` leaves starting at
+    /// `first_leaf`. The subtree must be one BLAKE3 would build: `first_leaf` is a
+    /// multiple of `leaf_count.next_power_of_two()`, and only the last subtree of
+    /// a level may be partial. Partial subtrees were promoted, so their node holds
+    /// the same output as their populated part.
+    pub(crate) fn subtree_output(&self, first_leaf: usize, leaf_count: usize) -> H::Node {
+        self.tree[self.subtree_node_index(first_leaf, leaf_count)]
+    }
+
+    /// Heap index of the node holding `subtree_output(first_leaf, leaf_count)`.
+    pub(crate) fn subtree_node_index(&self, first_leaf: usize, leaf_count: usize) -> usize {
+        let height = leaf_count.next_power_of_two().trailing_zeros();
+        (self.leaf_offset() + first_leaf) >> height
     }
 
     pub fn get_tree_length(&self) -> usize {
@@ -402,158 +1033,358 @@ impl BinaryMerkleTree {
 
     pub fn new_empty(number_of_leaves: u64) -> Self {
         assert!(number_of_leaves.is_power_of_two());
-        let empty_output = Output {
-            input_chaining_value: IV,
-            block_words: [0; 16],
-            counter: 0,
-            block_len: 64,
-            flags: 0,
-        };
-        let tree: Vec<Output> = vec![empty_output; 2 * number_of_leaves as usize];
-        BinaryMerkleTree { tree }
+        let tree: Vec<H::Node> = vec![H::empty_node(); 2 * number_of_leaves as usize];
+        BinaryMerkleTree {
+            tree: Arc::new(tree),
+            leaf_count: number_of_leaves as usize,
+            journal: None,
+            recording: None,
+            parent_hashes: 0,
+            level_rebuild_threshold: DEFAULT_LEVEL_REBUILD_THRESHOLD,
+            mode: HashMode::HASH,
+            #[cfg(feature = "metrics")]
+            metrics: MetricsState::default(),
+            block_cvs: None,
+        }
     }
 
-    // The parent of a node is always at node_index / 2
-    fn get_parent_index(index: usize) -> usize {
-        index >> 1
+    // Leaves are stored after all interior nodes, so the first leaf sits at the
+    // midpoint of the node array
+    pub(crate) fn leaf_offset(&self) -> usize {
+        self.tree.len() / 2
     }
 
-    fn create_tree_from_leaves(&mut self, leaves: Vec<Output>) {
+    // Every write goes through here so the node array is copied, once, before
+    // the first write after a snapshot was taken, so an open journal sees the
+    // value being overwritten, and so cached block chaining values are dropped
+    // once a leaf changes under them
+    pub(crate) fn set_node(&mut self, index: usize, output: H::Node) {
+        if let Some(journal) = &mut self.journal {
+            journal.record(index, self.tree[index]);
+        }
+        if index >= self.leaf_offset() {
+            self.block_cvs = None;
+        }
+        Arc::make_mut(&mut self.tree)[index] = output;
+    }
+
+    // Recompute the node at `parent` from its two children
+    fn rehash_node(&mut self, parent: NodeIndex) {
+        let output = self.parent_of(parent.left_child().get(), parent.right_child().get());
+        self.set_node(parent.get(), output);
+        self.count_ancestor_recomputed();
+    }
+
+    fn create_tree_from_leaves(&mut self, leaves: Vec<H::Node>) {
         // Copy the leaves into the end of the tree
+        let leaf_start_index = self.leaf_offset();
         let number_of_leaves = leaves.len();
-        self.tree
-            .splice(self.tree.capacity() - number_of_leaves.., leaves);
-        // If there is only one leaf (plus the filler first node), the tree is simply that leaf
-        if number_of_leaves == 1 {
-            return;
+        Arc::make_mut(&mut self.tree)
+            .splice(leaf_start_index..leaf_start_index + number_of_leaves, leaves);
+
+        // Build ancestors level by level, from bottom to top. If there is only one
+        // leaf (plus the filler first node), the tree is simply that leaf.
+        let mut level_start = leaf_start_index;
+        let mut nodes_in_level = number_of_leaves;
+        while level_start > 1 {
+            let parent_level_start = level_start / 2;
+            let nodes_in_parent_level = nodes_in_level.div_ceil(2);
+            for i in 0..nodes_in_parent_level {
+                let left_index = level_start + 2 * i;
+                let parent = self.parent_of(left_index, left_index + 1);
+                self.set_node(parent_level_start + i, parent);
+            }
+            level_start = parent_level_start;
+            nodes_in_level = nodes_in_parent_level;
         }
+    }
 
-        // Build ancestors
-        let leaf_start_index = self.get_tree_length() / 2 + 1;
-        let leaves_with_indices = self.tree[leaf_start_index..]
-            .iter()
-            .copied()
-            .zip(leaf_start_index..leaf_start_index + number_of_leaves);
-        let mut hash_queue = VecDeque::from_iter(leaves_with_indices);
-        while hash_queue.len() > 1 {
-            let (left_child, left_index) = hash_queue.pop_front().unwrap();
-            let (right_child, _right_index) = hash_queue.pop_front().unwrap();
-            let parent_output = parent_output(left_child.chaining_value(), right_child.chaining_value(), IV, 0);
-            let parent_index = BinaryMerkleTree::get_parent_index(left_index);
-            self.tree[parent_index] = parent_output;
-            hash_queue.push_back((parent_output, parent_index));
+    /// Combine two sibling nodes into their parent. A right sibling that covers no
+    /// real leaves is only padding, so the left node is promoted unchanged. This is
+    /// how BLAKE3 shapes the right edge of a tree whose chunk count is not a power
+    /// of two.
+    pub(crate) fn parent_of(&mut self, left_node_index: usize, right_node_index: usize) -> H::Node {
+        if first_leaf_below(right_node_index, self.leaf_offset()) >= self.leaf_count {
+            return self.tree[left_node_index];
+        }
+        self.parent_hashes += 1;
+        #[cfg(feature = "metrics")]
+        let compressions_before = crate::metrics::thread_compressions();
+        let parent = H::parent_with_mode(&self.tree[left_node_index], &self.tree[right_node_index], &self.mode);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.totals.compressions += crate::metrics::thread_compressions() - compressions_before;
         }
+        parent
     }
 
-    pub fn insert_leaf(&mut self, leaf_index: usize, leaf_output: Output) {
-        let real_leaf_index = leaf_index + self.num_leaves();
-        self.tree[real_leaf_index] = leaf_output;
+    // Record input bytes a byte-level helper hashed into this tree's chunks
+    pub(crate) fn count_bytes_hashed(&mut self, _bytes: u64) {
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.totals.bytes_hashed += _bytes;
+        }
+    }
 
-        let mut current_index = real_leaf_index;
-        while current_index > 1 {
-            // Update parent
-            let parent_index = BinaryMerkleTree::get_parent_index(current_index);
-            let (left_node_index, right_node_index) =
-                self.get_left_and_right_node_indices_from_index(current_index);
-            let left_node = &self.tree[left_node_index];
-            let right_node = &self.tree[right_node_index];
-
-            let parent_output = parent_output(left_node.chaining_value(), right_node.chaining_value(), IV, 0);
-            self.tree[parent_index] = parent_output;
-            current_index = parent_index;
+    // Hand the counters to the metrics callback, if one is installed
+    pub(crate) fn report_metrics(&self) {
+        #[cfg(feature = "metrics")]
+        self.metrics.report();
+    }
+
+    fn count_ancestor_recomputed(&mut self) {
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.totals.ancestors_recomputed += 1;
         }
     }
 
+    /// Replace the leaf at `leaf_index` and update its ancestors. Fails if the
+    /// index is past the last leaf.
+    pub fn insert_leaf(&mut self, leaf_index: usize, leaf_output: H::Node) -> Result<(), Error> {
+        if leaf_index >= self.leaf_count {
+            return Err(Error::IndexOutOfRange { index: leaf_index, leaf_count: self.leaf_count });
+        }
+        self.insert_leaf_unchecked(leaf_index, leaf_output);
+        Ok(())
+    }
+
+    /// Like `insert_leaf`, for hot paths where the index is known to be valid.
+    /// Panics if it is not.
+    pub fn insert_leaf_unchecked(&mut self, leaf_index: usize, leaf_output: H::Node) {
+        assert!(leaf_index < self.leaf_count, "leaf index {} out of range", leaf_index);
+        let mut node = NodeIndex(self.set_leaf_only(leaf_index, leaf_output));
+        while let Some(parent) = node.parent() {
+            self.rehash_node(parent);
+            node = parent;
+        }
+        self.report_metrics();
+    }
+
     /// Bulk insert leaves and propogate hash updates to all ancestors.
-    /// This method avoid updating shared parents if given two direct siblings to update.
+    /// Ancestors shared by several updated leaves are only recomputed once.
     /// Leaf_index input should be 0-indexed where the first leaf would be entered as index 0
+    ///
+    /// Fails without changing the tree if the indices are not strictly
+    /// increasing, run past the last leaf, or do not match the leaves in number.
     pub fn bulk_insert_leaves<I, J>(
         &mut self,
         leaf_indices_iter: I,
         leaf_hashes_iter: J,
-    ) -> Option<()>
+    ) -> Result<(), Error>
     where
         I: Iterator<Item = usize>,
-        J: Iterator<Item = Output>,
+        J: Iterator<Item = H::Node>,
     {
-        // Check if sorted
-        let leaf_offset = self.num_leaves();
-        let leaf_indices = leaf_indices_iter
-            .map(|input_index| input_index + leaf_offset)
-            .collect::<Vec<_>>();
-
-        // In-line our own sort checker because Rust's is_sorted is not yet stable.
-        fn is_sorted(leaf_indices: &[usize]) -> bool {
-            (0..leaf_indices.len() - 1).all(|i| leaf_indices[i] < leaf_indices[i + 1])
-        }
-        if !is_sorted(&leaf_indices) {
-            return None;
+        let input_indices: Vec<usize> = leaf_indices_iter.collect();
+        let leaf_hashes: Vec<H::Node> = leaf_hashes_iter.collect();
+        check_bulk_update(&input_indices, &leaf_hashes)?;
+        if let Some(&index) = input_indices.last().filter(|&&index| index >= self.leaf_count) {
+            return Err(Error::IndexOutOfRange { index, leaf_count: self.leaf_count });
         }
 
+        let leaf_offset = self.leaf_offset();
+        let leaf_indices: Vec<usize> = input_indices.iter().map(|input_index| input_index + leaf_offset).collect();
+
         // Insert all leaf nodes
-        for (leaf_index, updated_leaf_hash) in leaf_indices.iter().zip(leaf_hashes_iter) {
-            self.tree[*leaf_index] = updated_leaf_hash;
+        for (index, updated_leaf_hash) in input_indices.into_iter().zip(leaf_hashes) {
+            self.set_leaf_only(index, updated_leaf_hash);
+        }
+
+        self.update_ancestors(leaf_indices);
+        Ok(())
+    }
+
+    /// Write a leaf without touching its ancestors. The caller must later pass
+    /// its node index to `update_ancestors`.
+    pub(crate) fn set_leaf_only(&mut self, leaf_index: usize, leaf_output: H::Node) -> usize {
+        if let Some(recording) = &mut self.recording {
+            recording.record(Operation::SetLeaf { index: leaf_index, leaf: leaf_output });
+        }
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.totals.leaves_updated += 1;
         }
+        let node_index = self.leaf_offset() + leaf_index;
+        self.set_node(node_index, leaf_output);
+        node_index
+    }
 
-        // Update ancestors based on sorted leaf indices
-        let mut update_queue = VecDeque::from(leaf_indices);
-        while let Some(current_index) = update_queue.pop_front() {
-            // Break if the root is reached
-            if current_index == 1 {
-                break;
+    /// Recompute every ancestor of the given leaf node indices, which must be
+    /// strictly increasing. The ancestors are walked one level at a time as a
+    /// sorted frontier, so each dirty parent is hashed exactly once no matter
+    /// how the dirty leaves are spread out: k dirty leaves cost at most
+    /// k * depth parent hashes, and far fewer when they share ancestors.
+    ///
+    /// Once the dirty share of the leaves reaches `level_rebuild_threshold`,
+    /// every parent is rehashed instead, as a plain sweep over the node array.
+    /// That sweep hashes n - 1 parents, at most k / threshold, so the bound
+    /// above still holds. The decision is made on the leaves rather than on
+    /// each parent level, since the dirty share of parent levels climbs
+    /// quickly (half the leaves dirty at random leaves three quarters of
+    /// their parents dirty) and switching there rehashes clean subtrees.
+    pub(crate) fn update_ancestors(&mut self, leaf_indices: Vec<usize>) {
+        let dirty_share = leaf_indices.len() as f64 / self.leaf_count as f64;
+        if !leaf_indices.is_empty() && dirty_share >= self.level_rebuild_threshold {
+            self.rebuild_levels_from(self.leaf_offset() / 2);
+            self.report_metrics();
+            return;
+        }
+        // Every node in the frontier is on the same level, so mapping it to its
+        // parents keeps it sorted, and siblings collapse into adjacent duplicates
+        let mut frontier: Vec<NodeIndex> = leaf_indices.into_iter().map(NodeIndex).collect();
+        while frontier.first().is_some_and(|&node| node != NodeIndex::ROOT) {
+            for node in frontier.iter_mut() {
+                *node = node.parent().expect("the frontier is below the root");
+            }
+            frontier.dedup();
+            for &parent in &frontier {
+                self.rehash_node(parent);
             }
+        }
+        self.report_metrics();
+    }
 
-            // If the next ancestor to update is the sibling's, pop it from the queue
-            // since it will have the same parent as the current node
-            let sibling_index = BinaryMerkleTree::get_sibling_index(current_index);
-            if let Some(&next_index) = update_queue.front() {
-                if next_index == sibling_index {
-                    update_queue.pop_front();
-                }
+    // Number of nodes on the level starting at heap index `level_start` that
+    // cover at least one leaf
+    fn nodes_covering_leaves(&self, level_start: usize) -> usize {
+        let leaves_per_node = self.leaf_offset() / level_start;
+        self.leaf_count.div_ceil(leaves_per_node)
+    }
+
+    // Rehash every node that covers leaves, from the level starting at heap
+    // index `level_start` up to the root
+    fn rebuild_levels_from(&mut self, mut level_start: usize) {
+        while level_start >= 1 {
+            for index in level_start..level_start + self.nodes_covering_leaves(level_start) {
+                self.rehash_node(NodeIndex(index));
             }
+            level_start /= 2;
+        }
+    }
 
-            let (left_node_index, right_node_index) =
-                self.get_left_and_right_node_indices_from_index(current_index);
-            let left_node = self.tree[left_node_index];
-            let right_node = self.tree[right_node_index];
+    /// Dirty share of the leaves, from 0 to 1, at which bulk updates switch
+    /// from rehashing dirty ancestors one by one to rehashing every parent.
+    /// The default is `DEFAULT_LEVEL_REBUILD_THRESHOLD`; anything above 1
+    /// never switches.
+    pub fn level_rebuild_threshold(&self) -> f64 {
+        self.level_rebuild_threshold
+    }
+
+    pub fn set_level_rebuild_threshold(&mut self, threshold: f64) {
+        self.level_rebuild_threshold = threshold;
+    }
+
+    /// Number of parent nodes hashed since the tree was constructed, including
+    /// building it. Promoting a lone left child is free and not counted; every
+    /// counted BLAKE3 parent costs one compression per child chaining value.
+    pub fn parent_hash_count(&self) -> u64 {
+        self.parent_hashes
+    }
+
+    /// Like `bulk_insert_leaves`, but takes `(index, leaf)` pairs in any order.
+    /// When an index appears more than once, the last pair for it wins.
+    pub fn bulk_insert_leaves_unordered<I>(&mut self, updates: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (usize, H::Node)>,
+    {
+        let (indices, leaves) = sort_and_dedup_updates(updates);
+        self.bulk_insert_leaves(indices.into_iter(), leaves.into_iter())
+    }
+}
 
-            let parent_output = parent_output(left_node.chaining_value(), right_node.chaining_value(), IV, 0);
-            let parent_index = BinaryMerkleTree::get_parent_index(current_index);
-            self.tree[parent_index] = parent_output;
-            update_queue.push_back(parent_index);
+// Sort updates by leaf index, keeping only the last update for each index.
+// The sort is stable, so among equal indices the input order is preserved and
+// the last one is the latest write.
+fn sort_and_dedup_updates<N>(updates: impl IntoIterator<Item = (usize, N)>) -> (Vec<usize>, Vec<N>) {
+    let mut updates: Vec<(usize, N)> = updates.into_iter().collect();
+    updates.sort_by_key(|(index, _)| *index);
+    let mut indices: Vec<usize> = Vec::with_capacity(updates.len());
+    let mut leaves: Vec<N> = Vec::with_capacity(updates.len());
+    for (index, leaf) in updates {
+        if indices.last() == Some(&index) {
+            *leaves.last_mut().unwrap() = leaf;
+        } else {
+            indices.push(index);
+            leaves.push(leaf);
         }
+    }
+    (indices, leaves)
+}
 
-        Some(())
+/// Incrementally splits a byte stream into chunk outputs. This is the
+/// streaming counterpart of `process_input_to_chunks`: bytes can be fed in
+/// any number of writes, and the outputs are identical to chunking the
+/// concatenated input in one call.
+#[derive(Debug, Clone)]
+pub struct ChunkSplitter {
+    chunk_state: ChunkState,
+    mode: HashMode,
+    outputs: Vec<Output>,
+    total_len: u64,
+}
+
+impl Default for ChunkSplitter {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn get_sibling_index(index: usize) -> usize {
-        // Bit-wise XOR to get the sibling index
-        // Example: Sibling of index 4(0b100) is 5(0b101) and sibling of index 5(0b101) is 4(0b100)
-        index ^ 1
+impl ChunkSplitter {
+    pub fn new() -> Self {
+        Self::with_start_chunk_counter(0)
     }
 
-    fn is_left(index: usize) -> bool {
-        // All left-children have an even node index
-        index % 2 == 0
+    /// A splitter for a segment of a larger input that starts at chunk
+    /// `start_chunk_counter`, so its chunks get the counters they have in the
+    /// whole input.
+    pub fn with_start_chunk_counter(start_chunk_counter: u64) -> Self {
+        Self::with_mode(start_chunk_counter, HashMode::HASH)
     }
 
-    /// Given an index of the current node, identify its direct sibling,
-    /// identify which node is left, which is right, and return them.
-    fn get_left_and_right_node_indices_from_index(&self, current_index: usize) -> (usize, usize) {
-        let sibling_index = BinaryMerkleTree::get_sibling_index(current_index);
+    /// `with_start_chunk_counter` for a keyed or key derivation tree.
+    pub fn with_mode(start_chunk_counter: u64, mode: HashMode) -> Self {
+        Self {
+            chunk_state: ChunkState::new(mode.key_words, start_chunk_counter, mode.flags),
+            mode,
+            outputs: Vec::new(),
+            total_len: 0,
+        }
+    }
 
-        // Use boolean indexing to avoid if statement branching
-        let node_pair = [current_index, sibling_index]; // Stack allocation
+    /// Add input bytes. This can be called any number of times.
+    pub fn update(&mut self, mut input: &[u8]) {
+        self.total_len += input.len() as u64;
+        while !input.is_empty() {
+            // If the current chunk is complete, finalize it and reset the
+            // chunk state. More input is coming, so this chunk is not ROOT.
+            if self.chunk_state.len() == CHUNK_LEN {
+                self.outputs.push(self.chunk_state.output());
+                let total_chunks = self.chunk_state.chunk_counter + 1;
+                self.chunk_state = ChunkState::new(self.mode.key_words, total_chunks, self.mode.flags);
+            }
 
-        // If the sibling is the left child, is_left returns 1 and gets the sibling
-        // If the sibling is the right child, is_left returns 0 and gets the node to update (the left child)
-        let left_node_index = node_pair[BinaryMerkleTree::is_left(sibling_index) as usize];
+            // Compress input bytes into the current chunk state.
+            let want = CHUNK_LEN - self.chunk_state.len();
+            let take = min(want, input.len());
+            self.chunk_state.update(&input[..take]);
+            input = &input[take..];
+        }
+    }
 
-        // If the node to update is the left child, is_left returns 1 and gets the sibling (the right child)
-        // If the node to update is the right child, is_left returns 0 and gets the node to update
-        let right_node_index = node_pair[BinaryMerkleTree::is_left(current_index) as usize];
+    /// Total number of bytes fed so far.
+    pub fn total_len(&self) -> u64 {
+        self.total_len
+    }
 
-        (left_node_index, right_node_index)
+    /// Finish the stream and return one Output per chunk.
+    pub fn finalize(mut self) -> Vec<Output> {
+        // Add the final chunk if it's not empty. Empty input still has one
+        // chunk, as in BLAKE3.
+        if !self.chunk_state.is_empty() || self.outputs.is_empty() {
+            self.outputs.push(self.chunk_state.output());
+        }
+        self.outputs
     }
 }
 
@@ -563,37 +1394,21 @@ impl BinaryMerkleTree {
 /// 2. For each chunk, splits into blocks of 64 bytes
 /// 3. Creates a ChunkState for each chunk and processes its blocks
 /// 4. Returns a vector of Output structs ready for Merkle tree construction
+///
+/// Empty input yields a single empty chunk, so there is always at least one leaf.
 pub fn process_input_to_chunks(input: &[u8]) -> Vec<Output> {
-    const CHUNK_LEN: usize = 1024;
-    const BLOCK_LEN: usize = 64;
-    let mut outputs = Vec::new();
-    let mut chunk_state = ChunkState::new(IV, 0, 0);
-    let mut input = input;
-
-    while !input.is_empty() {
-        // If the current chunk is complete, finalize it and reset the
-        // chunk state. More input is coming, so this chunk is not ROOT.
-        if chunk_state.len() == CHUNK_LEN {
-            let chunk_output = chunk_state.output();
-            outputs.push(chunk_output);
-            let total_chunks = chunk_state.chunk_counter + 1;
-            chunk_state = ChunkState::new(IV, total_chunks, 0);
-        }
-
-        // Compress input bytes into the current chunk state.
-        let want = CHUNK_LEN - chunk_state.len();
-        let take = min(want, input.len());
-        chunk_state.update(&input[..take]);
-        input = &input[take..];
-    }
+    process_input_to_chunks_from(input, 0)
+}
 
-    // Add the final chunk if it's not empty
-    if chunk_state.len() > 0 {
-        let chunk_output = chunk_state.output();
-        outputs.push(chunk_output);
-    }
-    
-    outputs
+/// Like `process_input_to_chunks`, for a segment of a larger input whose first
+/// chunk is chunk `start_chunk_counter` of the whole. Segments can then be
+/// chunk-hashed independently, on different machines or out of order, and the
+/// outputs are the same as those for the corresponding chunks of the whole
+/// input. The segment must start on a chunk boundary.
+pub fn process_input_to_chunks_from(input: &[u8], start_chunk_counter: u64) -> Vec<Output> {
+    let mut splitter = ChunkSplitter::with_start_chunk_counter(start_chunk_counter);
+    splitter.update(input);
+    splitter.finalize()
 }
 
 #[derive(Debug, Clone)]
@@ -607,7 +1422,7 @@ impl UnbalancedMerkleTree {
         let actual_leaves = leaves.len();
         // Calculate the next power of two to allocate enough space
         let number_of_leaves = leaves.len().next_power_of_two();
-        let mut tree = vec![Output {
+        let tree = vec![Output {
             input_chaining_value: IV,
             block_words: [0; 16],
             counter: 0,
@@ -624,13 +1439,20 @@ impl UnbalancedMerkleTree {
         binary_tree
     }
 
+    /// The root output with the ROOT flag applied. As with
+    /// `BinaryMerkleTree::root`, a tree with no leaves hashes like empty
+    /// input.
     pub fn root(&self) -> Output {
-        let mut root = self.tree[1];
+        let mut root = if self.actual_leaves == 0 { chunk_output(0, &[]) } else { self.tree[1] };
         // Apply ROOT flag to the final root output
         root.flags |= ROOT;
         root
     }
 
+    pub fn root_hash(&self) -> Hash {
+        Hash::from_root(&self.root())
+    }
+
     pub fn num_leaves(&self) -> usize {
         self.actual_leaves
     }
@@ -654,7 +1476,7 @@ impl UnbalancedMerkleTree {
         
         while current_level_start > 1 {
             let parent_level_start = current_level_start / 2;
-            let nodes_in_parent_level = (nodes_at_current_level + 1) / 2;
+            let nodes_in_parent_level = nodes_at_current_level.div_ceil(2);
 
             for i in 0..nodes_in_parent_level {
                 let left_index = current_level_start + 2 * i;
@@ -680,143 +1502,125 @@ impl UnbalancedMerkleTree {
         }
     }
 
+    /// Grow the tree so it holds `new_actual_leaves` leaves. When the node array
+    /// has to be reallocated the leaf level moves, so the existing leaves are
+    /// copied into the new layout and the ancestors rebuilt.
+    fn grow_to(&mut self, new_actual_leaves: usize) {
+        let new_size = new_actual_leaves.next_power_of_two() * 2;
+        #[cfg(feature = "trace")]
+        tracing::debug!(
+            from_leaves = self.actual_leaves,
+            to_leaves = new_actual_leaves,
+            from_len = self.tree.len(),
+            to_len = new_size,
+            "resizing unbalanced tree"
+        );
+        if new_size > self.tree.len() {
+            let leaf_start = self.tree.len() / 2;
+            let leaves = self.tree[leaf_start..leaf_start + self.actual_leaves].to_vec();
+            self.tree = vec![self.tree[0]; new_size];
+            self.create_tree_from_leaves(leaves);
+        }
+        self.actual_leaves = new_actual_leaves;
+    }
+
+    /// Recompute the node at `parent_index` from its children, promoting the left
+    /// child when the right subtree holds no leaves yet.
+    fn update_parent(&mut self, parent_index: usize) {
+        let leaf_start = self.tree.len() / 2;
+        let left_index = parent_index * 2;
+        let right_index = left_index + 1;
+
+        // Check if there is a valid right sibling
+        let right_leaf_index = first_leaf_below(right_index, leaf_start);
+        let has_right_sibling = right_leaf_index < self.actual_leaves;
+
+        if has_right_sibling {
+            // Create a parent node combining both children
+            self.tree[parent_index] = parent_output(
+                self.tree[left_index].chaining_value(),
+                self.tree[right_index].chaining_value(),
+                IV,
+                0,
+            );
+        } else {
+            // No right sibling, promote the left node directly
+            self.tree[parent_index] = self.tree[left_index];
+        }
+    }
+
     pub fn insert_leaf(&mut self, leaf_index: usize, leaf_output: Output) {
-        println!("\nInserting leaf {} into unbalanced tree:", leaf_index);
-        println!("Leaf output cv: {:?}", leaf_output.chaining_value());
-        
+        #[cfg(feature = "trace")]
+        let _span = tracing::debug_span!("insert_leaf", leaf_index, actual_leaves = self.actual_leaves).entered();
+
         if leaf_index >= self.actual_leaves {
             // Extend the tree if inserting beyond current leaves
-            let new_actual_leaves = leaf_index + 1;
-            let new_size = new_actual_leaves.next_power_of_two() * 2;
-            println!("Resizing tree: actual_leaves {} -> {}, size {} -> {}", 
-                self.actual_leaves, new_actual_leaves, self.tree.len(), new_size);
-            if new_size > self.tree.len() {
-                self.tree.resize(new_size, self.tree[0]);
-            }
-            self.actual_leaves = new_actual_leaves;
+            self.grow_to(leaf_index + 1);
         }
 
         let leaf_start = self.tree.len() / 2;
         let real_leaf_index = leaf_index + leaf_start;
-        println!("Real leaf index: {} (leaf_start={})", real_leaf_index, leaf_start);
         self.tree[real_leaf_index] = leaf_output;
 
         let mut current_index = real_leaf_index;
         while current_index > 1 {
             let parent_index = current_index / 2;
-            let left_index = parent_index * 2;
-            let right_index = left_index + 1;
-
-            println!("\nProcessing node {}: parent={}, left={}, right={}", 
-                current_index, parent_index, left_index, right_index);
-
-            // Check if there is a valid right sibling
-            let right_leaf_index = right_index - leaf_start;
-            let has_right_sibling = right_leaf_index < self.actual_leaves;
-            println!("Right sibling check: right_leaf_index={}, has_right_sibling={}", 
-                right_leaf_index, has_right_sibling);
-
-            if has_right_sibling {
-                // Create a parent node combining both children
-                println!("Creating parent node with both children:");
-                println!("  Left  node cv: {:?}", self.tree[left_index].chaining_value());
-                println!("  Right node cv: {:?}", self.tree[right_index].chaining_value());
-                self.tree[parent_index] = parent_output(
-                    self.tree[left_index].chaining_value(),
-                    self.tree[right_index].chaining_value(),
-                    IV,
-                    0,
-                );
-                println!("  Parent node cv: {:?}", self.tree[parent_index].chaining_value());
-            } else {
-                // No right sibling, promote the left node directly
-                println!("No right sibling, promoting left node:");
-                println!("  Left node cv: {:?}", self.tree[left_index].chaining_value());
-                self.tree[parent_index] = self.tree[left_index];
-                println!("  Parent node cv: {:?}", self.tree[parent_index].chaining_value());
-            }
+            self.update_parent(parent_index);
             current_index = parent_index;
         }
-        println!("Final root cv: {:?}", self.tree[1].chaining_value());
     }
 
     pub fn bulk_insert_leaves<I, J>(
         &mut self,
         leaf_indices_iter: I,
         leaf_hashes_iter: J,
-    ) -> Option<()>
+    ) -> Result<(), Error>
     where
         I: Iterator<Item = usize>,
         J: Iterator<Item = Output>,
     {
-        // Helper function to check if indices are sorted
-        fn is_sorted(indices: &[usize]) -> bool {
-            indices.windows(2).all(|w| w[0] < w[1])
-        }
-
-        // Collect indices and check if sorted
+        // Collect indices and leaves and check they line up
         let leaf_indices: Vec<_> = leaf_indices_iter.collect();
-        if !is_sorted(&leaf_indices) {
-            return None;
-        }
+        let leaf_hashes: Vec<_> = leaf_hashes_iter.collect();
+        check_bulk_update(&leaf_indices, &leaf_hashes)?;
 
         // Find maximum leaf index and resize if needed
-        if let Some(&max_index) = leaf_indices.iter().max() {
+        if let Some(&max_index) = leaf_indices.last() {
             if max_index >= self.actual_leaves {
-                let new_actual_leaves = max_index + 1;
-                let new_size = new_actual_leaves.next_power_of_two() * 2;
-                if new_size > self.tree.len() {
-                    self.tree.resize(new_size, self.tree[0]);
-                }
-                self.actual_leaves = new_actual_leaves;
+                self.grow_to(max_index + 1);
             }
         }
 
         // Insert all leaf nodes
         let leaf_start = self.tree.len() / 2;
-        for (leaf_index, updated_leaf_hash) in leaf_indices.iter().zip(leaf_hashes_iter) {
+        for (leaf_index, updated_leaf_hash) in leaf_indices.iter().zip(leaf_hashes) {
             self.tree[leaf_start + leaf_index] = updated_leaf_hash;
         }
 
-        // Update ancestors using a queue to avoid duplicate updates
-        let mut update_queue = VecDeque::from(leaf_indices);
-        while let Some(leaf_index) = update_queue.pop_front() {
-            let current_index = leaf_start + leaf_index;
-            if current_index <= 1 {
-                break;
+        // Update ancestors one level at a time, so each dirty parent is
+        // recomputed exactly once
+        let mut frontier: Vec<usize> =
+            leaf_indices.iter().map(|leaf_index| leaf_start + leaf_index).collect();
+        while frontier.first().is_some_and(|&index| index > 1) {
+            for index in frontier.iter_mut() {
+                *index /= 2;
             }
-
-            let parent_index = current_index / 2;
-            let left_index = parent_index * 2;
-            let right_index = left_index + 1;
-
-            // Skip if the next node is this node's sibling (they share a parent)
-            if let Some(&next_leaf_index) = update_queue.front() {
-                if leaf_start + next_leaf_index == right_index {
-                    update_queue.pop_front();
-                }
-            }
-
-            // Check if there is a valid right sibling
-            let right_leaf_index = right_index - leaf_start;
-            let has_right_sibling = right_leaf_index < self.actual_leaves;
-
-            if has_right_sibling {
-                // Create a parent node combining both children
-                self.tree[parent_index] = parent_output(
-                    self.tree[left_index].chaining_value(),
-                    self.tree[right_index].chaining_value(),
-                    IV,
-                    0,
-                );
-            } else {
-                // No right sibling, promote the left node directly
-                self.tree[parent_index] = self.tree[left_index];
+            frontier.dedup();
+            for &parent_index in &frontier {
+                self.update_parent(parent_index);
             }
-
-            update_queue.push_back(parent_index - leaf_start);
         }
 
-        Some(())
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Like `bulk_insert_leaves`, but takes `(index, leaf)` pairs in any order.
+    /// When an index appears more than once, the last pair for it wins.
+    pub fn bulk_insert_leaves_unordered<I>(&mut self, updates: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (usize, Output)>,
+    {
+        let (indices, leaves) = sort_and_dedup_updates(updates);
+        self.bulk_insert_leaves(indices.into_iter(), leaves.into_iter())
+    }
+}
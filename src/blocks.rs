@@ -0,0 +1,151 @@
+//! Chaining values entering each block of every chunk, kept beside a tree so
+//! a write that starts in block k of a chunk only recompresses blocks k to
+//! the end of the chunk instead of all 16. Writes clustered near the ends of
+//! chunks cost about half as many leaf compressions.
+//!
+//! The cache only follows the byte-level helpers `update_bytes` and
+//! `set_len`. Any other write to a leaf drops it, since the cache could no
+//! longer vouch for the bytes before the change. Snapshots never carry it.
+
+use std::mem::size_of;
+
+use crate::binary_merkle_tree::{
+    chunk_output_with_mode, compress, words_from_little_endian_bytes, BinaryMerkleTree, HashMode, Output, BLOCK_LEN,
+    CHUNK_END, CHUNK_LEN, CHUNK_START, IV,
+};
+use crate::error::Error;
+
+const BLOCKS_PER_CHUNK: usize = CHUNK_LEN / BLOCK_LEN;
+
+#[derive(Debug, Clone, Copy)]
+struct ChunkBlocks {
+    // cvs[k] is the chaining value block k was compressed with
+    cvs: [[u32; 8]; BLOCKS_PER_CHUNK],
+    // Blocks in the chunk when it was last hashed, so how many of `cvs` hold
+    // real values
+    blocks: u8,
+}
+
+// Stands in for chunks that were never hashed: with one block, rehashing
+// starts from the first, whose chaining value is always the key
+const UNHASHED: ChunkBlocks = ChunkBlocks {
+    cvs: [IV; BLOCKS_PER_CHUNK],
+    blocks: 1,
+};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlockCvs {
+    chunks: Vec<ChunkBlocks>,
+}
+
+impl BlockCvs {
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.chunks.capacity() * size_of::<ChunkBlocks>()
+    }
+
+    /// The output of chunk `index`, which now holds `chunk` and whose first
+    /// `unchanged` bytes are the ones it held when last hashed, together with
+    /// the number of bytes compressed to get it. `mode` must be the one the
+    /// cached chaining values were computed with.
+    pub(crate) fn rehash(&mut self, index: usize, chunk: &[u8], unchanged: usize, mode: &HashMode) -> (Output, usize) {
+        if index >= self.chunks.len() {
+            self.chunks.resize(index + 1, UNHASHED);
+        }
+        let entry = &mut self.chunks[index];
+        entry.cvs[0] = mode.key_words;
+        let blocks = chunk.len().div_ceil(BLOCK_LEN).max(1);
+        // The old final block was never compressed into the chain, so the
+        // chain can only be resumed at or before it
+        let first = (unchanged / BLOCK_LEN).min(entry.blocks as usize - 1).min(blocks - 1);
+        let last = blocks - 1;
+        for k in first..last {
+            let flags = mode.flags | if k == 0 { CHUNK_START } else { 0 };
+            let words = block_words(&chunk[k * BLOCK_LEN..(k + 1) * BLOCK_LEN]);
+            let output = compress(&entry.cvs[k], &words, index as u64, BLOCK_LEN as u32, flags);
+            #[cfg(feature = "metrics")]
+            crate::metrics::count_compressions(1);
+            entry.cvs[k + 1] = output[..8].try_into().unwrap();
+        }
+        entry.blocks = blocks as u8;
+        let output = Output {
+            input_chaining_value: entry.cvs[last],
+            block_words: block_words(&chunk[last * BLOCK_LEN..]),
+            counter: index as u64,
+            block_len: (chunk.len() - last * BLOCK_LEN) as u32,
+            flags: mode.flags | CHUNK_END | if last == 0 { CHUNK_START } else { 0 },
+        };
+        (output, chunk.len() - first * BLOCK_LEN)
+    }
+
+    pub(crate) fn truncate(&mut self, chunk_count: usize) {
+        self.chunks.truncate(chunk_count);
+    }
+}
+
+// A block's message words, zero padded as the final block of a chunk is
+fn block_words(block: &[u8]) -> [u32; 16] {
+    let mut padded = [0u8; BLOCK_LEN];
+    padded[..block.len()].copy_from_slice(block);
+    let mut words = [0; 16];
+    words_from_little_endian_bytes(&padded, &mut words);
+    words
+}
+
+impl BinaryMerkleTree {
+    /// Start keeping the chaining value entering every block of every chunk,
+    /// computed from `content`, the input the tree currently describes. Costs
+    /// 512 bytes per chunk; `memory_usage` reports it as `cached_cv_bytes`.
+    /// Fails with `HashMismatch` at the first chunk that does not match its
+    /// leaf, keeping no cache.
+    pub fn cache_block_cvs(&mut self, content: &[u8]) -> Result<(), Error> {
+        let chunk_count = content.len().div_ceil(CHUNK_LEN).max(1);
+        if chunk_count != self.num_leaves() {
+            return Err(Error::HashMismatch {
+                offset: (chunk_count.min(self.num_leaves()) * CHUNK_LEN) as u64,
+            });
+        }
+        let mut cvs = BlockCvs::default();
+        for index in 0..chunk_count {
+            let start = index * CHUNK_LEN;
+            let chunk = &content[start..content.len().min(start + CHUNK_LEN)];
+            let (output, _) = cvs.rehash(index, chunk, 0, &self.mode);
+            if output != *self.leaf(index) {
+                return Err(Error::HashMismatch { offset: start as u64 });
+            }
+        }
+        self.block_cvs = Some(cvs);
+        Ok(())
+    }
+
+    /// Whether block chaining values are cached, i.e. no write since
+    /// `cache_block_cvs` has dropped them.
+    pub fn has_block_cvs(&self) -> bool {
+        self.block_cvs.is_some()
+    }
+
+    /// Stop keeping block chaining values and free their memory.
+    pub fn drop_block_cvs(&mut self) {
+        self.block_cvs = None;
+    }
+}
+
+// Hash chunk `index` of `content`, resuming from the cached block chaining
+// values when there are some. Bytes before `first_changed` must be the
+// ones last hashed. Returns the output and the bytes compressed.
+pub(crate) fn rehash_chunk(
+    block_cvs: &mut Option<BlockCvs>,
+    content: &[u8],
+    index: usize,
+    first_changed: u64,
+    mode: &HashMode,
+) -> (Output, usize) {
+    let start = index * CHUNK_LEN;
+    let chunk = &content[start..content.len().min(start + CHUNK_LEN)];
+    match block_cvs {
+        Some(cvs) => {
+            let unchanged = first_changed.saturating_sub(start as u64).min(CHUNK_LEN as u64) as usize;
+            cvs.rehash(index, chunk, unchanged, mode)
+        }
+        None => (chunk_output_with_mode(index as u64, chunk, mode), chunk.len()),
+    }
+}
@@ -0,0 +1,135 @@
+use std::cmp::min;
+use std::io;
+#[cfg(feature = "rayon")]
+use std::sync::Arc;
+
+use crate::binary_merkle_tree::{chunk_output_with_mode, BinaryMerkleTree, HashMode, Output, CHUNK_LEN, KEY_LEN};
+use crate::grouped::{group_output_with_mode, GroupedMerkleTree};
+use crate::node_store::{NodeStore, StoredMerkleTree};
+
+/// One place to configure how a tree over an input is built, instead of a
+/// `new_*` constructor for every combination of options:
+///
+/// - `start_chunk_counter` builds a segment tree, as `from_segment` does.
+/// - `group_log` makes each leaf cover 2^`group_log` chunks, as in
+///   `GroupedMerkleTree`.
+/// - `parallel` hashes leaves on the rayon pool when the `rayon` feature is
+///   enabled, and is ignored otherwise. `thread_pool` picks the pool.
+/// - `keyed` and `derive_key` hash every chunk and parent like
+///   `Blake3Hasher::new_keyed` and `new_derive_key`, and `flags` overrides
+///   the mode flags they set.
+///
+/// The same configuration then builds an in-memory tree (`build`,
+/// `build_grouped`) or one in a node store (`build_stored`).
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTreeBuilder {
+    start_chunk_counter: u64,
+    group_log: u32,
+    parallel: bool,
+    mode: HashMode,
+    // None means the global pool
+    #[cfg(feature = "rayon")]
+    thread_pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+impl MerkleTreeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number the input's chunks from `start_chunk_counter` rather than 0.
+    pub fn start_chunk_counter(mut self, start_chunk_counter: u64) -> Self {
+        self.start_chunk_counter = start_chunk_counter;
+        self
+    }
+
+    /// Make each leaf the subtree over 2^`group_log` chunks.
+    pub fn group_log(mut self, group_log: u32) -> Self {
+        self.group_log = group_log;
+        self
+    }
+
+    /// Hash leaves in parallel.
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Hash like `blake3::keyed_hash` with `key`.
+    pub fn keyed(mut self, key: &[u8; KEY_LEN]) -> Self {
+        self.mode = HashMode::keyed(key);
+        self
+    }
+
+    /// Hash like `blake3::derive_key` with `context`, so the root is the key
+    /// derived from the input as key material.
+    pub fn derive_key(mut self, context: &str) -> Self {
+        self.mode = HashMode::derive_key(context);
+        self
+    }
+
+    /// Hash chunks and parents with `flags` in place of the mode flags set by
+    /// `keyed` or `derive_key`, keeping their key.
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.mode.flags = flags;
+        self
+    }
+
+    /// Hash leaves on `pool` instead of the global rayon pool, so building a
+    /// tree does not starve a service's other parallel work. Implies
+    /// `parallel(true)`.
+    #[cfg(feature = "rayon")]
+    pub fn thread_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.thread_pool = Some(pool);
+        self.parallel = true;
+        self
+    }
+
+    /// Build an in-memory tree over `input`.
+    ///
+    /// Panics if both a start chunk counter and a group size are set:
+    /// segments are always built chunk by chunk.
+    pub fn build(&self, input: &[u8]) -> BinaryMerkleTree {
+        BinaryMerkleTree::new_from_leaves_with_mode(self.leaves(input), self.mode)
+    }
+
+    /// Build a `GroupedMerkleTree` over `input` with the configured group
+    /// size.
+    ///
+    /// Panics if a start chunk counter is set.
+    pub fn build_grouped(&self, input: &[u8]) -> GroupedMerkleTree {
+        assert_eq!(self.start_chunk_counter, 0, "grouped trees cannot be segments");
+        GroupedMerkleTree::from_group_leaves(self.leaves(input), self.group_log, self.mode)
+    }
+
+    /// Build a tree over `input` in `store`.
+    pub fn build_stored<S: NodeStore>(&self, store: S, input: &[u8]) -> io::Result<StoredMerkleTree<S>> {
+        StoredMerkleTree::create_with_mode(store, self.leaves(input), self.mode)
+    }
+
+    fn leaves(&self, input: &[u8]) -> Vec<Output> {
+        assert!(
+            self.group_log == 0 || self.start_chunk_counter == 0,
+            "segment trees cannot have grouped leaves"
+        );
+        // Empty input is still one (empty) chunk
+        let leaf_count = input.len().div_ceil(CHUNK_LEN << self.group_log).max(1);
+        let leaf = |i: usize| {
+            if self.group_log > 0 {
+                return group_output_with_mode(input, self.group_log, i, &self.mode);
+            }
+            let chunk = &input[i * CHUNK_LEN..min((i + 1) * CHUNK_LEN, input.len())];
+            chunk_output_with_mode(self.start_chunk_counter + i as u64, chunk, &self.mode)
+        };
+        #[cfg(feature = "rayon")]
+        if self.parallel {
+            use rayon::prelude::*;
+            let hash_all = || (0..leaf_count).into_par_iter().map(leaf).collect();
+            return match &self.thread_pool {
+                Some(pool) => pool.install(hash_all),
+                None => hash_all(),
+            };
+        }
+        (0..leaf_count).map(leaf).collect()
+    }
+}
@@ -0,0 +1,491 @@
+//! Stable hashes and record trees for structured data, through a deterministic
+//! CBOR encoding of any `serde::Serialize` value.
+//!
+//! The encoding follows the core deterministic rules of RFC 8949 section 4.2:
+//! integers and lengths use their shortest head, every length is definite,
+//! floats use the shortest of half, single, or double precision that holds the
+//! value exactly (NaN is always `f9 7e00`), and map entries are sorted by the
+//! bytes of their encoded keys. A map with two equal keys is an error rather
+//! than an ambiguity.
+//!
+//! serde's data model is mapped onto CBOR the way JSON-like data expects:
+//! `None`, `()` and unit structs are `null`, `Some(x)` is just `x`, structs are
+//! maps keyed by field name, sequences and tuples are arrays, unit variants are
+//! their name as a text string, and other variants are a one-entry map from the
+//! name to the content. Integers outside the 64-bit CBOR range are rejected.
+
+use serde::ser::{self, Serialize};
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, OUT_LEN};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::record::record_leaf;
+
+/// The deterministic CBOR encoding of `value`.
+pub fn to_canonical_cbor<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    value.serialize(Encoder { out: &mut out })?;
+    Ok(out)
+}
+
+/// The BLAKE3 hash of `value`'s canonical encoding. Equal values hash equally
+/// whatever the platform, field order of a source document, or map iteration
+/// order.
+pub fn hash_canonical<T: Serialize + ?Sized>(value: &T) -> Result<Hash, Error> {
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&to_canonical_cbor(value)?);
+    let mut digest = [0; OUT_LEN];
+    hasher.finalize(&mut digest);
+    Ok(Hash::from_bytes(digest))
+}
+
+impl BinaryMerkleTree {
+    /// A record tree with one leaf per value, the same tree
+    /// `BinaryMerkleTree::from_records` builds over each value's canonical
+    /// encoding.
+    pub fn from_serialized_records<'a, I, T>(records: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: Serialize + 'a,
+    {
+        let leaves = records
+            .into_iter()
+            .enumerate()
+            .map(|(index, record)| Ok(record_leaf(index as u64, &to_canonical_cbor(record)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(BinaryMerkleTree::new_from_leaves(leaves))
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<M: std::fmt::Display>(message: M) -> Self {
+        Error::Unserializable(message.to_string())
+    }
+}
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+
+const FALSE: u8 = 0xf4;
+const TRUE: u8 = 0xf5;
+const NULL: u8 = 0xf6;
+
+fn write_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        out.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.extend_from_slice(&[major | 24, value as u8]);
+    } else if value <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn write_int(out: &mut Vec<u8>, value: i128) -> Result<(), Error> {
+    let (major, magnitude) = if value < 0 { (NEGATIVE, -1 - value) } else { (UNSIGNED, value) };
+    let magnitude = u64::try_from(magnitude)
+        .map_err(|_| Error::Unserializable(format!("integer {} does not fit in CBOR", value)))?;
+    write_head(out, major, magnitude);
+    Ok(())
+}
+
+fn write_float(out: &mut Vec<u8>, value: f64) {
+    if value.is_nan() {
+        out.extend_from_slice(&[0xf9, 0x7e, 0x00]);
+    } else if (value as f32) as f64 != value {
+        out.push(0xfb);
+        out.extend_from_slice(&value.to_be_bytes());
+    } else if let Some(half) = f32_to_exact_f16(value as f32) {
+        out.push(0xf9);
+        out.extend_from_slice(&half.to_be_bytes());
+    } else {
+        out.push(0xfa);
+        out.extend_from_slice(&(value as f32).to_be_bytes());
+    }
+}
+
+// The half precision bits of `value`, if it has an exact half precision form
+fn f32_to_exact_f16(value: f32) -> Option<u16> {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity; NaN is handled by the caller
+        return Some(sign | 0x7c00);
+    }
+    if exponent == 0 {
+        // Zero, or a single precision subnormal, far below half precision
+        return (mantissa == 0).then_some(sign);
+    }
+    let unbiased = exponent - 127;
+    if (-14..=15).contains(&unbiased) {
+        if mantissa & 0x1fff != 0 {
+            return None;
+        }
+        return Some(sign | (((unbiased + 15) as u16) << 10) | (mantissa >> 13) as u16);
+    }
+    if (-24..-14).contains(&unbiased) {
+        // A half precision subnormal: the implicit leading bit becomes explicit
+        let shift = 13 + (-14 - unbiased) as u32;
+        let significand = 0x80_0000 | mantissa;
+        if significand & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        return Some(sign | (significand >> shift) as u16);
+    }
+    None
+}
+
+struct Encoder<'a> {
+    out: &'a mut Vec<u8>,
+}
+
+impl<'a> Encoder<'a> {
+    // Open a one-entry map holding a variant's content under its name
+    fn variant(self, variant: &'static str) -> Self {
+        write_head(self.out, MAP, 1);
+        write_head(self.out, TEXT, variant.len() as u64);
+        self.out.extend_from_slice(variant.as_bytes());
+        self
+    }
+}
+
+impl<'a> ser::Serializer for Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = ArrayEncoder<'a>;
+    type SerializeTuple = ArrayEncoder<'a>;
+    type SerializeTupleStruct = ArrayEncoder<'a>;
+    type SerializeTupleVariant = ArrayEncoder<'a>;
+    type SerializeMap = MapEncoder<'a>;
+    type SerializeStruct = MapEncoder<'a>;
+    type SerializeStructVariant = MapEncoder<'a>;
+
+    fn serialize_bool(self, value: bool) -> Result<(), Error> {
+        self.out.push(if value { TRUE } else { FALSE });
+        Ok(())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_i128(self, value: i128) -> Result<(), Error> {
+        write_int(self.out, value)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), Error> {
+        write_int(self.out, value.into())
+    }
+
+    fn serialize_u128(self, value: u128) -> Result<(), Error> {
+        let value = i128::try_from(value)
+            .map_err(|_| Error::Unserializable(format!("integer {} does not fit in CBOR", value)))?;
+        write_int(self.out, value)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), Error> {
+        write_float(self.out, value.into());
+        Ok(())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), Error> {
+        write_float(self.out, value);
+        Ok(())
+    }
+
+    fn serialize_char(self, value: char) -> Result<(), Error> {
+        self.serialize_str(value.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), Error> {
+        write_head(self.out, TEXT, value.len() as u64);
+        self.out.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), Error> {
+        write_head(self.out, BYTES, value.len() as u64);
+        self.out.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.out.push(NULL);
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self.variant(variant))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<ArrayEncoder<'a>, Error> {
+        Ok(ArrayEncoder::new(self.out))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<ArrayEncoder<'a>, Error> {
+        Ok(ArrayEncoder::new(self.out))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<ArrayEncoder<'a>, Error> {
+        Ok(ArrayEncoder::new(self.out))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<ArrayEncoder<'a>, Error> {
+        Ok(ArrayEncoder::new(self.variant(variant).out))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapEncoder<'a>, Error> {
+        Ok(MapEncoder::new(self.out))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapEncoder<'a>, Error> {
+        Ok(MapEncoder::new(self.out))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapEncoder<'a>, Error> {
+        Ok(MapEncoder::new(self.variant(variant).out))
+    }
+}
+
+// Elements are buffered so the array head can carry the definite length
+struct ArrayEncoder<'a> {
+    out: &'a mut Vec<u8>,
+    items: Vec<u8>,
+    len: u64,
+}
+
+impl<'a> ArrayEncoder<'a> {
+    fn new(out: &'a mut Vec<u8>) -> Self {
+        ArrayEncoder {
+            out,
+            items: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(Encoder { out: &mut self.items })?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        write_head(self.out, ARRAY, self.len);
+        self.out.extend_from_slice(&self.items);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for ArrayEncoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for ArrayEncoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for ArrayEncoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for ArrayEncoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+// Entries are buffered as encoded (key, value) pairs and sorted by key bytes
+// when the map ends
+struct MapEncoder<'a> {
+    out: &'a mut Vec<u8>,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a> MapEncoder<'a> {
+    fn new(out: &'a mut Vec<u8>) -> Self {
+        MapEncoder {
+            out,
+            entries: Vec::new(),
+        }
+    }
+
+    fn entry<K: Serialize + ?Sized, V: Serialize + ?Sized>(&mut self, key: &K, value: &V) -> Result<(), Error> {
+        let key = to_canonical_cbor(key)?;
+        self.entries.push((key, to_canonical_cbor(value)?));
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), Error> {
+        self.entries.sort_by(|a, b| a.0.cmp(&b.0));
+        if self.entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::Unserializable("map has duplicate keys".to_string()));
+        }
+        write_head(self.out, MAP, self.entries.len() as u64);
+        for (key, value) in &self.entries {
+            self.out.extend_from_slice(key);
+            self.out.extend_from_slice(value);
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapEncoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.entries.push((to_canonical_cbor(key)?, Vec::new()));
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let entry = self
+            .entries
+            .last_mut()
+            .ok_or_else(|| Error::Unserializable("map value without a key".to_string()))?;
+        value.serialize(Encoder { out: &mut entry.1 })
+    }
+
+    fn serialize_entry<K: Serialize + ?Sized, V: Serialize + ?Sized>(&mut self, key: &K, value: &V) -> Result<(), Error> {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapEncoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapEncoder<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
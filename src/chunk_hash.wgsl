@@ -0,0 +1,101 @@
+// BLAKE3 chunk compression for the `wgpu` backend. Each invocation hashes one
+// full 1024-byte chunk up to, but not including, its final block, and writes
+// the resulting chaining value. The host turns that into the chunk's Output.
+
+struct Params {
+    counter_low: u32,
+    counter_high: u32,
+    chunk_count: u32,
+    padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read> input: array<u32>;
+@group(0) @binding(1) var<storage, read_write> output: array<u32>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+const CHUNK_START: u32 = 1u;
+const BLOCK_LEN: u32 = 64u;
+const BLOCKS_PER_CHUNK: u32 = 16u;
+
+fn rotr(x: u32, n: u32) -> u32 {
+    return (x >> n) | (x << (32u - n));
+}
+
+fn g(state: ptr<function, array<u32, 16>>, a: u32, b: u32, c: u32, d: u32, mx: u32, my: u32) {
+    (*state)[a] = (*state)[a] + (*state)[b] + mx;
+    (*state)[d] = rotr((*state)[d] ^ (*state)[a], 16u);
+    (*state)[c] = (*state)[c] + (*state)[d];
+    (*state)[b] = rotr((*state)[b] ^ (*state)[c], 12u);
+    (*state)[a] = (*state)[a] + (*state)[b] + my;
+    (*state)[d] = rotr((*state)[d] ^ (*state)[a], 8u);
+    (*state)[c] = (*state)[c] + (*state)[d];
+    (*state)[b] = rotr((*state)[b] ^ (*state)[c], 7u);
+}
+
+fn round(state: ptr<function, array<u32, 16>>, m: ptr<function, array<u32, 16>>) {
+    // Mix the columns.
+    g(state, 0u, 4u, 8u, 12u, (*m)[0], (*m)[1]);
+    g(state, 1u, 5u, 9u, 13u, (*m)[2], (*m)[3]);
+    g(state, 2u, 6u, 10u, 14u, (*m)[4], (*m)[5]);
+    g(state, 3u, 7u, 11u, 15u, (*m)[6], (*m)[7]);
+    // Mix the diagonals.
+    g(state, 0u, 5u, 10u, 15u, (*m)[8], (*m)[9]);
+    g(state, 1u, 6u, 11u, 12u, (*m)[10], (*m)[11]);
+    g(state, 2u, 7u, 8u, 13u, (*m)[12], (*m)[13]);
+    g(state, 3u, 4u, 9u, 14u, (*m)[14], (*m)[15]);
+}
+
+fn permute(m: ptr<function, array<u32, 16>>) {
+    var schedule = array<u32, 16>(2u, 6u, 3u, 10u, 7u, 0u, 4u, 13u, 1u, 11u, 12u, 5u, 9u, 14u, 15u, 8u);
+    var permuted: array<u32, 16>;
+    for (var i = 0u; i < 16u; i++) {
+        permuted[i] = (*m)[schedule[i]];
+    }
+    *m = permuted;
+}
+
+fn compress(cv: array<u32, 8>, block: array<u32, 16>, counter_low: u32, counter_high: u32, flags: u32) -> array<u32, 8> {
+    var state = array<u32, 16>(
+        cv[0], cv[1], cv[2], cv[3], cv[4], cv[5], cv[6], cv[7],
+        0x6A09E667u, 0xBB67AE85u, 0x3C6EF372u, 0xA54FF53Au,
+        counter_low, counter_high, BLOCK_LEN, flags,
+    );
+    var m = block;
+    for (var r = 0u; r < 7u; r++) {
+        round(&state, &m);
+        if (r < 6u) {
+            permute(&m);
+        }
+    }
+    var out: array<u32, 8>;
+    for (var i = 0u; i < 8u; i++) {
+        out[i] = state[i] ^ state[i + 8u];
+    }
+    return out;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let chunk = id.x;
+    if (chunk >= params.chunk_count) {
+        return;
+    }
+    let counter_low = params.counter_low + chunk;
+    let counter_high = params.counter_high + select(0u, 1u, counter_low < chunk);
+
+    var cv = array<u32, 8>(
+        0x6A09E667u, 0xBB67AE85u, 0x3C6EF372u, 0xA54FF53Au,
+        0x510E527Fu, 0x9B05688Cu, 0x1F83D9ABu, 0x5BE0CD19u,
+    );
+    let first_word = chunk * BLOCKS_PER_CHUNK * 16u;
+    for (var b = 0u; b < BLOCKS_PER_CHUNK - 1u; b++) {
+        var block: array<u32, 16>;
+        for (var j = 0u; j < 16u; j++) {
+            block[j] = input[first_word + b * 16u + j];
+        }
+        cv = compress(cv, block, counter_low, counter_high, select(0u, CHUNK_START, b == 0u));
+    }
+    for (var j = 0u; j < 8u; j++) {
+        output[chunk * 8u + j] = cv[j];
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::binary_merkle_tree::{chunk_output, BinaryMerkleTree, ChunkSplitter, CHUNK_LEN};
+use crate::hash::Hash;
+
+/// Storage for raw chunks keyed by their chaining value.
+///
+/// A chunk's chaining value also commits to its position (the chunk counter),
+/// so identical bytes at different offsets are stored separately. Across
+/// versions of a file, every chunk that did not change is shared.
+pub trait ChunkStore {
+    fn put(&mut self, cv: Hash, chunk: &[u8]) -> io::Result<()>;
+
+    fn get(&self, cv: &Hash) -> io::Result<Option<Vec<u8>>>;
+
+    fn has(&self, cv: &Hash) -> io::Result<bool> {
+        Ok(self.get(cv)?.is_some())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryChunkStore {
+    chunks: HashMap<Hash, Vec<u8>>,
+}
+
+impl MemoryChunkStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+impl ChunkStore for MemoryChunkStore {
+    fn put(&mut self, cv: Hash, chunk: &[u8]) -> io::Result<()> {
+        self.chunks.entry(cv).or_insert_with(|| chunk.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, cv: &Hash) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.chunks.get(cv).cloned())
+    }
+
+    fn has(&self, cv: &Hash) -> io::Result<bool> {
+        Ok(self.chunks.contains_key(cv))
+    }
+}
+
+/// Stores each chunk as a file named after its hex chaining value.
+#[derive(Debug, Clone)]
+pub struct DirectoryChunkStore {
+    dir: PathBuf,
+}
+
+impl DirectoryChunkStore {
+    pub fn open<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, cv: &Hash) -> PathBuf {
+        self.dir.join(cv.to_hex())
+    }
+}
+
+impl ChunkStore for DirectoryChunkStore {
+    fn put(&mut self, cv: Hash, chunk: &[u8]) -> io::Result<()> {
+        let path = self.path(&cv);
+        if path.exists() {
+            return Ok(());
+        }
+        // Write under a temporary name first so a crash never leaves a torn chunk
+        let temp = self.dir.join(format!("{}.tmp", cv.to_hex()));
+        fs::write(&temp, chunk)?;
+        fs::rename(temp, path)
+    }
+
+    fn get(&self, cv: &Hash) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(cv)) {
+            Ok(chunk) => Ok(Some(chunk)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn has(&self, cv: &Hash) -> io::Result<bool> {
+        Ok(self.path(cv).exists())
+    }
+}
+
+/// Chunk `input`, put every chunk into `store`, and return the tree over it.
+pub fn store_input<S: ChunkStore>(store: &mut S, input: &[u8]) -> io::Result<BinaryMerkleTree> {
+    let mut splitter = ChunkSplitter::new();
+    splitter.update(input);
+    let leaves = splitter.finalize();
+    for (leaf, chunk) in leaves.iter().zip(input.chunks(CHUNK_LEN)) {
+        store.put(Hash::from_chaining_value(leaf.chaining_value()), chunk)?;
+    }
+    Ok(BinaryMerkleTree::new_from_leaves(leaves))
+}
+
+/// Write the input `tree` was built over to `writer`, fetching each chunk from
+/// `store`. Every chunk is re-hashed and checked against its leaf, so a store
+/// returning wrong data fails with InvalidData instead of producing a corrupt
+/// file. Returns the number of bytes written.
+pub fn rebuild<S: ChunkStore, W: Write>(tree: &BinaryMerkleTree, store: &S, mut writer: W) -> io::Result<u64> {
+    let mut written = 0;
+    for index in 0..tree.num_leaves() {
+        let cv = tree.leaf(index).chaining_value();
+        let key = Hash::from_chaining_value(cv);
+        let chunk = store.get(&key)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("chunk {} ({}) missing from store", index, key))
+        })?;
+        if chunk.len() > CHUNK_LEN || chunk_output(index as u64, &chunk).chaining_value() != cv {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk {} ({}) does not match its leaf", index, key),
+            ));
+        }
+        writer.write_all(&chunk)?;
+        written += chunk.len() as u64;
+    }
+    writer.flush()?;
+    Ok(written)
+}
@@ -0,0 +1,51 @@
+//! Cursor shared by the decoders of the binary formats (patches, deltas,
+//! archive proofs). Every format starts with a magic and a version byte and
+//! writes integers little-endian; every read is bounds checked and gives None
+//! on truncated input instead of panicking.
+
+use crate::hash::Hash;
+
+pub(crate) struct Decoder<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// A decoder positioned after `magic` and `version`, or None if `bytes`
+    /// does not start with them.
+    pub(crate) fn with_header(bytes: &'a [u8], magic: &[u8], version: u8) -> Option<Self> {
+        let mut decoder = Decoder { rest: bytes.strip_prefix(magic)? };
+        (decoder.take(1)? == [version]).then_some(decoder)
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (taken, remaining) = self.rest.split_at_checked(len)?;
+        self.rest = remaining;
+        Some(taken)
+    }
+
+    pub(crate) fn take_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        Some(self.take(N)?.try_into().unwrap())
+    }
+
+    pub(crate) fn take_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take_array()?))
+    }
+
+    pub(crate) fn take_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take_array()?))
+    }
+
+    /// A u64 that must also fit in a usize, such as an index or a length.
+    pub(crate) fn take_usize(&mut self) -> Option<usize> {
+        usize::try_from(self.take_u64()?).ok()
+    }
+
+    pub(crate) fn take_hash(&mut self) -> Option<Hash> {
+        Some(Hash::from_bytes(self.take_array()?))
+    }
+
+    /// Whether every byte has been read. Decoders reject trailing bytes.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+}
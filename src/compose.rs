@@ -0,0 +1,90 @@
+//! Trees whose leaves are the roots of existing subtrees, such as trees of
+//! separately hashed segments or of whole smaller trees. With
+//! `Composition::Blake3` the subtrees are checked to be the aligned pieces
+//! BLAKE3 itself would build, so the composed root is the BLAKE3 hash of the
+//! concatenated input; with `Composition::Opaque` any subtrees may be
+//! combined, and the root is only meaningful to this crate.
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, BLOCK_LEN, CHUNK_END, CHUNK_START, IV, PARENT};
+use crate::distributed::SegmentOutput;
+use crate::error::Error;
+
+/// How strictly `compose_subtrees` checks the subtrees it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Composition {
+    /// The subtrees must cover consecutive chunks from 0, every one but the
+    /// last the same power of two number of chunks and the last no more, and
+    /// their outputs must be unkeyed, non-root chunk or parent outputs for
+    /// those chunks. The root is then the BLAKE3 hash of the whole input.
+    Blake3,
+    /// Any subtrees, combined with BLAKE3 parent compression in the order
+    /// given. The root is not the BLAKE3 hash of anything.
+    Opaque,
+}
+
+impl SegmentOutput {
+    /// `tree` as a subtree covering its chunks from `first_chunk`. The tree
+    /// must have been built with that chunk counter base, as by
+    /// `BinaryMerkleTree::from_segment`, for the result to compose as BLAKE3.
+    pub fn from_tree(tree: &BinaryMerkleTree, first_chunk: u64) -> Self {
+        SegmentOutput { first_chunk, chunk_count: tree.num_leaves() as u64, output: tree.root_node() }
+    }
+}
+
+/// Build a tree whose leaves are the outputs of `subtrees`, checked according
+/// to `composition`. Under `Composition::Blake3` a subtree that does not fit
+/// is reported as `Error::MisalignedSegment`, and a gap as
+/// `Error::MissingChunks`.
+pub fn compose_subtrees(subtrees: &[SegmentOutput], composition: Composition) -> Result<BinaryMerkleTree, Error> {
+    if subtrees.is_empty() {
+        return Err(Error::MissingChunks { first_chunk: 0 });
+    }
+    if composition == Composition::Blake3 {
+        check_alignment(subtrees)?;
+    }
+    Ok(BinaryMerkleTree::new_from_leaves(subtrees.iter().map(|subtree| subtree.output).collect()))
+}
+
+// The grouping rule under which a binary tree over the subtrees splits
+// exactly where BLAKE3 splits the chunks, as for `GroupedMerkleTree`
+fn check_alignment(subtrees: &[SegmentOutput]) -> Result<(), Error> {
+    // A single subtree is the whole tree, whatever its size
+    let group = subtrees[0].chunk_count;
+    if !group.is_power_of_two() && subtrees.len() > 1 {
+        return Err(misaligned(&subtrees[0]));
+    }
+    let mut next_chunk = 0;
+    for (i, subtree) in subtrees.iter().enumerate() {
+        if subtree.first_chunk > next_chunk {
+            return Err(Error::MissingChunks { first_chunk: next_chunk });
+        }
+        let is_last = i == subtrees.len() - 1;
+        let sized = if is_last { (1..=group).contains(&subtree.chunk_count) } else { subtree.chunk_count == group };
+        if subtree.first_chunk < next_chunk || !sized || !has_subtree_flags(subtree) {
+            return Err(misaligned(subtree));
+        }
+        next_chunk += subtree.chunk_count;
+    }
+    Ok(())
+}
+
+// A single chunk's output carries its counter and CHUNK_END; a larger
+// subtree's is an unkeyed parent. Neither may be keyed or already finalized
+// as a root.
+fn has_subtree_flags(subtree: &SegmentOutput) -> bool {
+    let output = &subtree.output;
+    if subtree.chunk_count == 1 {
+        output.flags & CHUNK_END != 0
+            && output.flags & !(CHUNK_START | CHUNK_END) == 0
+            && output.counter == subtree.first_chunk
+    } else {
+        output.flags == PARENT
+            && output.input_chaining_value == IV
+            && output.counter == 0
+            && output.block_len == BLOCK_LEN as u32
+    }
+}
+
+fn misaligned(subtree: &SegmentOutput) -> Error {
+    Error::MisalignedSegment { first_chunk: subtree.first_chunk, chunk_count: subtree.chunk_count }
+}
@@ -0,0 +1,36 @@
+//! The flag byte that marks how the body of a persisted tree or manifest is
+//! stored, and zstd compression of those bodies behind the `compression`
+//! feature. Interior node arrays are never persisted, since they compress
+//! poorly and are recomputed on load anyway; leaf lists and manifests
+//! compress well.
+
+use std::io::{self, Read};
+
+/// The body follows the header as is.
+pub const COMPRESSION_NONE: u8 = 0;
+/// The body is a single zstd frame.
+pub const COMPRESSION_ZSTD: u8 = 1;
+
+/// The zstd level used when none is given.
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Wrap `reader` to decode a body stored with `flag`. A zstd body fails to
+/// read without the `compression` feature.
+pub(crate) fn decoder<'a, R: Read + 'a>(reader: R, flag: u8) -> io::Result<Box<dyn Read + 'a>> {
+    match flag {
+        COMPRESSION_NONE => Ok(Box::new(reader)),
+        #[cfg(feature = "compression")]
+        COMPRESSION_ZSTD => Ok(Box::new(zstd::Decoder::new(reader)?)),
+        #[cfg(not(feature = "compression"))]
+        COMPRESSION_ZSTD => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zstd-compressed data needs the compression feature",
+        )),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown compression flag")),
+    }
+}
+
+#[cfg(feature = "compression")]
+pub(crate) fn compress(body: &[u8], level: i32) -> io::Result<Vec<u8>> {
+    zstd::encode_all(body, level)
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use crate::binary_merkle_tree::{first_leaf_below, parent_output, BinaryMerkleTree, Output, IV};
+use crate::codec::Decoder;
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::serialize::{decode_output, encode_output, OUTPUT_ENCODED_LEN};
+
+const DELTA_MAGIC: &[u8; 4] = b"B3DL";
+const DELTA_VERSION: u8 = 1;
+
+/// The nodes a batch of updates changed, for bringing a replica of the tree
+/// as it was before the batch up to date without resending the whole tree.
+/// Changed leaves carry their full output; changed parents only carry their
+/// new chaining value, which the replica recomputes and checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeDelta {
+    leaf_count: usize,
+    // (leaf index, new leaf), ascending
+    leaves: Vec<(usize, Output)>,
+    // (heap index, new chaining value), ascending
+    parents: Vec<(usize, [u32; 8])>,
+    root: Hash,
+}
+
+impl TreeDelta {
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The root the replica must reach.
+    pub fn root_hash(&self) -> Hash {
+        self.root
+    }
+
+    /// Number of changed leaves.
+    pub fn changed_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Serialize as: magic, version, leaf count as u64 LE, the number of
+    /// changed leaves as u64 LE and each as its index (u64 LE) and Output
+    /// record, the number of changed parents as u64 LE and each as its heap
+    /// index (u64 LE) and chaining value, then the 32-byte root.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            4 + 1 + 24 + self.leaves.len() * (8 + OUTPUT_ENCODED_LEN) + self.parents.len() * 40 + 32,
+        );
+        bytes.extend_from_slice(DELTA_MAGIC);
+        bytes.push(DELTA_VERSION);
+        bytes.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.leaves.len() as u64).to_le_bytes());
+        for (index, leaf) in &self.leaves {
+            bytes.extend_from_slice(&(*index as u64).to_le_bytes());
+            encode_output(leaf, &mut bytes);
+        }
+        bytes.extend_from_slice(&(self.parents.len() as u64).to_le_bytes());
+        for (index, cv) in &self.parents {
+            bytes.extend_from_slice(&(*index as u64).to_le_bytes());
+            bytes.extend_from_slice(Hash::from_chaining_value(*cv).as_bytes());
+        }
+        bytes.extend_from_slice(self.root.as_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<TreeDelta> {
+        let mut decoder = Decoder::with_header(bytes, DELTA_MAGIC, DELTA_VERSION)?;
+        let leaf_count = decoder.take_usize()?;
+        let leaf_entries = decoder.take_usize()?;
+        let mut leaves = Vec::new();
+        for _ in 0..leaf_entries {
+            let index = decoder.take_usize()?;
+            leaves.push((index, decode_output(&decoder.take_array::<OUTPUT_ENCODED_LEN>()?)));
+        }
+        let parent_entries = decoder.take_usize()?;
+        let mut parents = Vec::new();
+        for _ in 0..parent_entries {
+            let index = decoder.take_usize()?;
+            parents.push((index, decoder.take_hash()?.to_chaining_value()));
+        }
+        let root = decoder.take_hash()?;
+        decoder.is_empty().then_some(TreeDelta {
+            leaf_count,
+            leaves,
+            parents,
+            root,
+        })
+    }
+}
+
+impl BinaryMerkleTree {
+    /// The delta from the tree as it was at `begin_journal` to its current
+    /// state, or None if no journal is open. The journal stays open.
+    pub fn journal_delta(&self) -> Option<TreeDelta> {
+        let node_indices = self.journal()?.node_indices();
+        let leaf_offset = self.leaf_offset();
+        let (parent_indices, leaf_indices) = node_indices.split_at(node_indices.partition_point(|&i| i < leaf_offset));
+        Some(TreeDelta {
+            leaf_count: self.num_leaves(),
+            leaves: leaf_indices.iter().map(|&i| (i - leaf_offset, self.tree[i])).collect(),
+            parents: parent_indices.iter().map(|&i| (i, self.tree[i].chaining_value())).collect(),
+            root: self.root_hash(),
+        })
+    }
+
+    /// Apply a delta made from a tree equal to this one. Every changed parent
+    /// is recomputed from its children rather than trusted, its chaining value
+    /// checked against the delta, and the result checked against the delta's
+    /// root; the tree is only modified once all of that holds. Fails with
+    /// `InvalidProof` if the delta does not fit this tree or does not lead to
+    /// its root.
+    pub fn apply_delta(&mut self, delta: &TreeDelta) -> Result<(), Error> {
+        let leaf_offset = self.leaf_offset();
+        if delta.leaf_count != self.num_leaves() {
+            return Err(Error::InvalidProof);
+        }
+        let mut updated: HashMap<usize, Output> = HashMap::new();
+        for &(index, leaf) in &delta.leaves {
+            if index >= self.num_leaves() {
+                return Err(Error::InvalidProof);
+            }
+            updated.insert(leaf_offset + index, leaf);
+        }
+
+        // Children sit at higher heap indices, so descending order recomputes
+        // every changed node before its parent
+        for &(index, cv) in delta.parents.iter().rev() {
+            if index == 0 || index >= leaf_offset {
+                return Err(Error::InvalidProof);
+            }
+            let node = |i: usize| updated.get(&i).copied().unwrap_or(self.tree[i]);
+            let parent = if first_leaf_below(2 * index + 1, leaf_offset) >= self.num_leaves() {
+                node(2 * index)
+            } else {
+                parent_output(node(2 * index).chaining_value(), node(2 * index + 1).chaining_value(), IV, 0)
+            };
+            if parent.chaining_value() != cv {
+                return Err(Error::InvalidProof);
+            }
+            updated.insert(index, parent);
+        }
+        let root = updated.get(&1).copied().unwrap_or(self.tree[1]);
+        if !Hash::from_root(&root).ct_eq(&delta.root) {
+            return Err(Error::InvalidProof);
+        }
+
+        for (index, leaf) in &delta.leaves {
+            self.set_leaf_only(*index, *leaf);
+        }
+        for (index, _) in &delta.parents {
+            self.set_node(*index, updated[index]);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use crate::binary_merkle_tree::BinaryMerkleTree;
+
+/// Anything that can report the chaining value of an aligned BLAKE3 subtree,
+/// such as a full tree or a summary of a remote tree's nodes.
+pub(crate) trait SubtreeCvs {
+    fn leaf_count(&self) -> usize;
+
+    /// Chaining value of the subtree of `leaf_count` leaves starting at
+    /// `first_leaf`, with the same alignment rules as
+    /// `BinaryMerkleTree::subtree_output`.
+    fn subtree_cv(&self, first_leaf: usize, leaf_count: usize) -> [u32; 8];
+}
+
+impl SubtreeCvs for BinaryMerkleTree {
+    fn leaf_count(&self) -> usize {
+        self.num_leaves()
+    }
+
+    fn subtree_cv(&self, first_leaf: usize, leaf_count: usize) -> [u32; 8] {
+        self.subtree_output(first_leaf, leaf_count).chaining_value()
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Leaf indices whose contents differ between `self` and `other`, in
+    /// ascending order. Leaves present in only one of the trees count as
+    /// different. Both trees are walked top-down and subtrees with equal
+    /// chaining values are skipped, so k differences cost O(k log n).
+    pub fn diff(&self, other: &BinaryMerkleTree) -> Vec<usize> {
+        diff_leaves(self, other)
+    }
+}
+
+pub(crate) fn diff_leaves<A: SubtreeCvs, B: SubtreeCvs>(a: &A, b: &B) -> Vec<usize> {
+    let mut differing = Vec::new();
+    let longest = a.leaf_count().max(b.leaf_count());
+    if longest > 0 {
+        diff_block(a, b, 0, longest.next_power_of_two(), &mut differing);
+    }
+    differing
+}
+
+// Compare the aligned block of `block_len` leaves starting at `first_leaf`.
+// Either tree may only populate part of the block, or none of it.
+fn diff_block<A: SubtreeCvs, B: SubtreeCvs>(
+    a: &A,
+    b: &B,
+    first_leaf: usize,
+    block_len: usize,
+    differing: &mut Vec<usize>,
+) {
+    let populated = |leaf_count: usize| leaf_count.saturating_sub(first_leaf).min(block_len);
+    let (a_count, b_count) = (populated(a.leaf_count()), populated(b.leaf_count()));
+    if a_count == 0 && b_count == 0 {
+        return;
+    }
+    if a_count == b_count && a.subtree_cv(first_leaf, a_count) == b.subtree_cv(first_leaf, b_count) {
+        return;
+    }
+    if block_len == 1 {
+        differing.push(first_leaf);
+        return;
+    }
+    let half = block_len / 2;
+    diff_block(a, b, first_leaf, half, differing);
+    diff_block(a, b, first_leaf + half, half, differing);
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, ChunkState, Output, IV, OUT_LEN};
+use crate::hash::Hash;
+
+// Domain separation for manifest leaves, so an entry can never be confused with
+// a file chunk
+const ENTRY_CONTEXT: &str = "blake3_merkle_tree 2024 directory entry v1";
+
+/// One file in a hashed directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    /// Path relative to the hashed directory, using `/` as the separator.
+    pub path: String,
+    /// Unix permission bits, or 0o644 (0o444 if read-only) on other platforms.
+    pub mode: u32,
+    pub len: u64,
+    /// Root hash of the file's own Merkle tree.
+    pub root: Hash,
+}
+
+impl DirectoryEntry {
+    /// The canonical byte encoding committed to by the manifest:
+    /// path length (u64 LE), path bytes, mode (u32 LE), length (u64 LE), root.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.path.len() + 4 + 8 + OUT_LEN);
+        bytes.extend_from_slice(&(self.path.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(self.path.as_bytes());
+        bytes.extend_from_slice(&self.mode.to_le_bytes());
+        bytes.extend_from_slice(&self.len.to_le_bytes());
+        bytes.extend_from_slice(self.root.as_bytes());
+        bytes
+    }
+
+    /// The manifest leaf for this entry at position `index`. The encoding is
+    /// first hashed with a dedicated derive-key context, then placed in a chunk
+    /// whose counter is the entry's position.
+    pub fn leaf(&self, index: usize) -> Output {
+        let mut hasher = Blake3Hasher::new_derive_key(ENTRY_CONTEXT);
+        hasher.update(&self.encode());
+        let mut digest = [0; OUT_LEN];
+        hasher.finalize(&mut digest);
+
+        let mut chunk_state = ChunkState::new(IV, index as u64, 0);
+        chunk_state.update(&digest);
+        chunk_state.output()
+    }
+}
+
+/// Walks a directory, hashes every regular file into its own Merkle tree, and
+/// combines the entries into one deterministic `DirectoryManifest`.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryHasher {
+    follow_symlinks: bool,
+}
+
+impl DirectoryHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash the targets of symlinks that point at files. Symlinks are skipped by
+    /// default, and symlinks to directories are always skipped to avoid cycles.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    pub fn hash<P: AsRef<Path>>(&self, dir: P) -> io::Result<DirectoryManifest> {
+        let mut entries = Vec::new();
+        self.walk(dir.as_ref(), "", &mut |entry, _| entries.push(entry))?;
+        Ok(DirectoryManifest::from_entries(entries))
+    }
+
+    /// Hash every file below `dir`, handing each entry and its tree to `visit`.
+    /// Files are visited in directory order, not sorted.
+    pub(crate) fn visit_files<F>(&self, dir: &Path, visit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(DirectoryEntry, BinaryMerkleTree),
+    {
+        self.walk(dir, "", visit)
+    }
+
+    fn walk<F>(&self, dir: &Path, prefix: &str, visit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(DirectoryEntry, BinaryMerkleTree),
+    {
+        for child in fs::read_dir(dir)? {
+            let child = child?;
+            let name = child.file_name().into_string().map_err(|name| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("non UTF-8 file name {:?}", name))
+            })?;
+            let relative = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+
+            let mut metadata = fs::symlink_metadata(child.path())?;
+            if metadata.file_type().is_symlink() {
+                if !self.follow_symlinks {
+                    continue;
+                }
+                metadata = fs::metadata(child.path())?;
+                if !metadata.is_file() {
+                    continue;
+                }
+            }
+
+            if metadata.is_dir() {
+                self.walk(&child.path(), &relative, visit)?;
+            } else if metadata.is_file() {
+                let (tree, len) = BinaryMerkleTree::from_file(child.path())?;
+                let entry = DirectoryEntry {
+                    path: relative,
+                    mode: file_mode(&metadata),
+                    len,
+                    root: tree.root_hash(),
+                };
+                visit(entry, tree);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+/// The per-file entries of a directory, sorted by path, and the Merkle tree
+/// built over their leaves. Entry `i` is leaf `i` of the tree, so a single
+/// file can later be proven against the manifest root.
+#[derive(Debug, Clone)]
+pub struct DirectoryManifest {
+    entries: Vec<DirectoryEntry>,
+    tree: BinaryMerkleTree,
+}
+
+impl DirectoryManifest {
+    pub fn from_entries(mut entries: Vec<DirectoryEntry>) -> Self {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let leaves = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| entry.leaf(index))
+            .collect();
+        let tree = BinaryMerkleTree::new_from_leaves(leaves);
+        Self { entries, tree }
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.tree.root_hash()
+    }
+
+    pub fn entries(&self) -> &[DirectoryEntry] {
+        &self.entries
+    }
+
+    pub fn tree(&self) -> &BinaryMerkleTree {
+        &self.tree
+    }
+
+    /// Leaf index of the entry for `path`, if present.
+    pub fn position(&self, path: &str) -> Option<usize> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_str().cmp(path))
+            .ok()
+    }
+}
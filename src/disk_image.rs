@@ -0,0 +1,123 @@
+use std::cmp::min;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::binary_merkle_tree::{chunk_output, BinaryMerkleTree, Output, CHUNK_LEN};
+use crate::file::read_full;
+use crate::hash::Hash;
+
+/// A tree over a block device or VM disk image that follows sector-granularity
+/// writes. Writes only mark the chunks they touch in a bitset; `flush` then
+/// reads those chunks back from the image, rehashes them and updates the tree
+/// in one pass. The root stays the BLAKE3 hash of the whole image.
+///
+/// The image size is fixed when the tree is built, as for a block device.
+#[derive(Debug, Clone)]
+pub struct DiskImageTree {
+    tree: BinaryMerkleTree,
+    sector_size: u64,
+    image_len: u64,
+    // Bit i is set if chunk i was written since the last flush
+    dirty: Vec<u64>,
+}
+
+impl DiskImageTree {
+    /// Hash the whole image read from `image`. `sector_size` is the unit of
+    /// `mark_sectors`, typically 512 or 4096.
+    pub fn from_reader<R: Read>(image: R, sector_size: u64) -> io::Result<Self> {
+        assert!(sector_size > 0, "sector size must be non-zero");
+        let (tree, image_len) = BinaryMerkleTree::from_reader(image)?;
+        let dirty = vec![0; tree.num_leaves().div_ceil(64)];
+        Ok(DiskImageTree {
+            tree,
+            sector_size,
+            image_len,
+            dirty,
+        })
+    }
+
+    pub fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+
+    pub fn image_len(&self) -> u64 {
+        self.image_len
+    }
+
+    pub fn tree(&self) -> &BinaryMerkleTree {
+        &self.tree
+    }
+
+    /// Root of the image as of the last flush.
+    pub fn root_hash(&self) -> Hash {
+        self.tree.root_hash()
+    }
+
+    /// Number of chunks waiting to be rehashed.
+    pub fn dirty_chunks(&self) -> usize {
+        self.dirty.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Record a write of `count` sectors starting at sector `first_sector`.
+    ///
+    /// Panics if the write runs past the end of the image.
+    pub fn mark_sectors(&mut self, first_sector: u64, count: u64) {
+        self.mark_bytes(first_sector * self.sector_size, count * self.sector_size);
+    }
+
+    /// Record a write of `len` bytes at byte `offset`, for writes that are not
+    /// sector aligned.
+    ///
+    /// Panics if the write runs past the end of the image.
+    pub fn mark_bytes(&mut self, offset: u64, len: u64) {
+        assert!(offset + len <= self.image_len, "write past the end of the image");
+        if len == 0 {
+            return;
+        }
+        let first = (offset / CHUNK_LEN as u64) as usize;
+        let end = (offset + len).div_ceil(CHUNK_LEN as u64) as usize;
+        for chunk in first..end {
+            self.dirty[chunk / 64] |= 1 << (chunk % 64);
+        }
+    }
+
+    /// Reread every dirty chunk from `image`, which must hold the image with
+    /// all marked writes applied, and update the tree. Returns the indices of
+    /// the chunks whose outputs changed. Chunks stay dirty if reading fails.
+    pub fn flush<R: Read + Seek>(&mut self, image: &mut R) -> io::Result<Vec<usize>> {
+        let mut updates: Vec<(usize, Output)> = Vec::new();
+        let mut buffer = vec![0; CHUNK_LEN];
+        let mut next_offset = None;
+        for chunk in self.dirty_chunk_indices() {
+            let offset = (chunk * CHUNK_LEN) as u64;
+            let len = min(CHUNK_LEN as u64, self.image_len - offset) as usize;
+            // Consecutive dirty chunks are read without seeking in between
+            if next_offset != Some(offset) {
+                image.seek(SeekFrom::Start(offset))?;
+            }
+            if read_full(image, &mut buffer[..len])? < len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "disk image is shorter than when it was hashed"));
+            }
+            next_offset = Some(offset + len as u64);
+            let output = chunk_output(chunk as u64, &buffer[..len]);
+            if *self.tree.leaf(chunk) != output {
+                updates.push((chunk, output));
+            }
+        }
+
+        self.tree
+            .bulk_insert_leaves(updates.iter().map(|(i, _)| *i), updates.iter().map(|(_, output)| *output))
+            .expect("dirty chunks are visited in sorted order");
+        self.dirty.fill(0);
+        Ok(updates.into_iter().map(|(i, _)| i).collect())
+    }
+
+    fn dirty_chunk_indices(&self) -> Vec<usize> {
+        self.dirty
+            .iter()
+            .enumerate()
+            .flat_map(|(word_index, &word)| {
+                (0..64).filter(move |bit| word >> bit & 1 == 1).map(move |bit| word_index * 64 + bit)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,124 @@
+//! Hashing one huge input across many workers. Each worker hashes an aligned
+//! segment with `SegmentOutput::hash` and sends the small result to a
+//! coordinator, which combines them with `merge_segments` into the root of
+//! the whole input without seeing any of its bytes.
+
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{left_subtree_len, parent_output, BinaryMerkleTree, Output, CHUNK_LEN, IV};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::serialize::{decode_output, encode_output, OUTPUT_ENCODED_LEN};
+
+/// The result of hashing one segment: which chunks it covers and the output
+/// of their subtree, before the ROOT flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentOutput {
+    pub first_chunk: u64,
+    pub chunk_count: u64,
+    pub output: Output,
+}
+
+/// Serialized size of a `SegmentOutput`.
+pub const SEGMENT_OUTPUT_ENCODED_LEN: usize = 8 + 8 + OUTPUT_ENCODED_LEN;
+
+impl SegmentOutput {
+    /// Hash `input`, the segment of the whole input starting at chunk
+    /// `first_chunk`. Empty input is one empty chunk, as for a whole input.
+    pub fn hash(input: &[u8], first_chunk: u64) -> Self {
+        let tree = BinaryMerkleTree::from_segment(input, first_chunk);
+        SegmentOutput {
+            first_chunk,
+            chunk_count: tree.num_leaves() as u64,
+            output: tree.root_node(),
+        }
+    }
+
+    /// Serialize as first chunk and chunk count (u64 LE), then the output as
+    /// in `encode_output`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SEGMENT_OUTPUT_ENCODED_LEN);
+        bytes.extend_from_slice(&self.first_chunk.to_le_bytes());
+        bytes.extend_from_slice(&self.chunk_count.to_le_bytes());
+        encode_output(&self.output, &mut bytes);
+        bytes
+    }
+
+    /// Decode `encode` output. Returns None if `bytes` is the wrong length.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != SEGMENT_OUTPUT_ENCODED_LEN {
+            return None;
+        }
+        Some(SegmentOutput {
+            first_chunk: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            chunk_count: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            output: decode_output(bytes[16..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Split an input of `total_len` bytes into at most `workers` byte ranges
+/// that can be hashed independently: every range but the last covers the same
+/// power of two number of chunks, so each is a subtree of the whole input.
+pub fn plan_segments(total_len: u64, workers: usize) -> Vec<Range<u64>> {
+    let chunk_count = total_len.div_ceil(CHUNK_LEN as u64).max(1);
+    let chunks_per_segment = chunk_count.div_ceil(workers.max(1) as u64).next_power_of_two();
+    let segment_len = chunks_per_segment * CHUNK_LEN as u64;
+    (0..chunk_count.div_ceil(chunks_per_segment))
+        .map(|i| i * segment_len..total_len.min((i + 1) * segment_len))
+        .collect()
+}
+
+/// Combine the outputs of segments covering a whole input into its root hash.
+/// The segments may arrive in any order, but together they must cover every
+/// chunk exactly once, and each must be a subtree of the whole input's tree,
+/// as the ranges from `plan_segments` are.
+pub fn merge_segments(segments: &[SegmentOutput]) -> Result<Hash, Error> {
+    let mut segments = segments.to_vec();
+    segments.sort_by_key(|segment| segment.first_chunk);
+    let mut next_chunk = 0;
+    for segment in &segments {
+        if segment.first_chunk > next_chunk {
+            return Err(Error::MissingChunks { first_chunk: next_chunk });
+        }
+        if segment.first_chunk < next_chunk || segment.chunk_count == 0 {
+            return Err(misaligned(segment));
+        }
+        next_chunk += segment.chunk_count;
+    }
+    if segments.is_empty() {
+        return Err(Error::MissingChunks { first_chunk: 0 });
+    }
+    let root = merge(&segments, 0, next_chunk)?;
+    Ok(Hash::from_root(&root))
+}
+
+// The output of the subtree over `chunk_count` chunks from `first_chunk`,
+// where `segments` are exactly the segments inside it
+fn merge(segments: &[SegmentOutput], first_chunk: u64, chunk_count: u64) -> Result<Output, Error> {
+    if let [segment] = segments {
+        if segment.first_chunk == first_chunk && segment.chunk_count == chunk_count {
+            return Ok(segment.output);
+        }
+    }
+    if chunk_count == 1 {
+        return Err(misaligned(&segments[0]));
+    }
+    let left_count = left_subtree_len(chunk_count as usize) as u64;
+    let split = first_chunk + left_count;
+    let left_segments = segments.partition_point(|segment| segment.first_chunk < split);
+    let straddling = &segments[left_segments - 1];
+    if straddling.first_chunk + straddling.chunk_count > split {
+        return Err(misaligned(straddling));
+    }
+    let left = merge(&segments[..left_segments], first_chunk, left_count)?;
+    let right = merge(&segments[left_segments..], split, chunk_count - left_count)?;
+    Ok(parent_output(left.chaining_value(), right.chaining_value(), IV, 0))
+}
+
+fn misaligned(segment: &SegmentOutput) -> Error {
+    Error::MisalignedSegment {
+        first_chunk: segment.first_chunk,
+        chunk_count: segment.chunk_count,
+    }
+}
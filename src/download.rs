@@ -0,0 +1,151 @@
+use std::cmp::min;
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{chunk_output, Output, CHUNK_LEN};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::partial::PartialTree;
+use crate::proof::RangeProof;
+
+/// Bookkeeping for a torrent-style verified fetch of an input known only by
+/// its root and length. `next_request` hands out ranges of chunks that are
+/// neither received nor in flight, `receive` checks the data for a range
+/// against its proof and the root before marking it received, and the loop
+/// ends when `is_complete` holds. Failed requests go back to the pool with
+/// `cancel`.
+///
+/// The manager only tracks and verifies chunks; writing the verified bytes
+/// at their offsets is left to the caller.
+#[derive(Debug, Clone)]
+pub struct Download {
+    partial: PartialTree,
+    total_len: u64,
+    // Bit i is set once chunk i has been verified
+    received: Vec<u64>,
+    // Bit i is set while chunk i is requested but not yet received
+    in_flight: Vec<u64>,
+}
+
+impl Download {
+    pub fn new(root: Hash, total_len: u64) -> Self {
+        let total_chunks = (total_len.div_ceil(CHUNK_LEN as u64) as usize).max(1);
+        Download {
+            partial: PartialTree::new(root, total_chunks),
+            total_len,
+            received: vec![0; total_chunks.div_ceil(64)],
+            in_flight: vec![0; total_chunks.div_ceil(64)],
+        }
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.partial.root_hash()
+    }
+
+    pub fn total_len(&self) -> u64 {
+        self.total_len
+    }
+
+    pub fn total_chunks(&self) -> usize {
+        self.partial.total_chunks()
+    }
+
+    /// Number of chunks verified so far.
+    pub fn received_chunks(&self) -> usize {
+        self.received.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn is_received(&self, chunk: usize) -> bool {
+        get_bit(&self.received, chunk)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received_chunks() == self.total_chunks()
+    }
+
+    /// The tree learned from the verified proofs, which can serve proofs for
+    /// the received chunks to other peers.
+    pub fn partial_tree(&self) -> &PartialTree {
+        &self.partial
+    }
+
+    /// Maximal runs of chunks not yet received, in order.
+    pub fn missing_ranges(&self) -> Vec<Range<usize>> {
+        runs(self.total_chunks(), |i| !self.is_received(i))
+    }
+
+    /// Pick the first run of at most `max_chunks` chunks that are neither
+    /// received nor in flight, and mark it in flight. Returns None when every
+    /// missing chunk is already requested.
+    pub fn next_request(&mut self, max_chunks: usize) -> Option<Range<usize>> {
+        assert!(max_chunks > 0, "requests must cover at least one chunk");
+        let free = |i: usize| !get_bit(&self.received, i) && !get_bit(&self.in_flight, i);
+        let start = (0..self.total_chunks()).find(|&i| free(i))?;
+        let end = (start..min(start + max_chunks, self.total_chunks()))
+            .find(|&i| !free(i))
+            .unwrap_or(min(start + max_chunks, self.total_chunks()));
+        for chunk in start..end {
+            set_bit(&mut self.in_flight, chunk, true);
+        }
+        Some(start..end)
+    }
+
+    /// Return the chunks of a failed request to the pool.
+    pub fn cancel(&mut self, chunks: Range<usize>) {
+        for chunk in chunks.start..min(chunks.end, self.total_chunks()) {
+            set_bit(&mut self.in_flight, chunk, false);
+        }
+    }
+
+    /// Verify `data`, the bytes of the chunks in `proof.chunks`, against the
+    /// proof and the root, and mark those chunks received. Returns the byte
+    /// offset at which the data belongs. Nothing is marked if verification
+    /// fails, and the chunks stay in flight so the caller can `cancel` them.
+    pub fn receive(&mut self, proof: &RangeProof, data: &[u8]) -> Result<u64, Error> {
+        let chunks = &proof.chunks;
+        if chunks.start >= chunks.end || chunks.end > self.total_chunks() {
+            return Err(Error::InvalidProof);
+        }
+        let offset = (chunks.start * CHUNK_LEN) as u64;
+        if data.len() as u64 != min(self.total_len, (chunks.end * CHUNK_LEN) as u64) - offset {
+            return Err(Error::InvalidProof);
+        }
+        let leaves: Vec<Output> = if data.is_empty() {
+            vec![chunk_output(0, &[])]
+        } else {
+            data.chunks(CHUNK_LEN)
+                .enumerate()
+                .map(|(i, chunk)| chunk_output((chunks.start + i) as u64, chunk))
+                .collect()
+        };
+        self.partial.ingest(proof, &leaves)?;
+        for chunk in chunks.clone() {
+            set_bit(&mut self.received, chunk, true);
+            set_bit(&mut self.in_flight, chunk, false);
+        }
+        Ok(offset)
+    }
+}
+
+fn get_bit(bits: &[u64], index: usize) -> bool {
+    bits[index / 64] >> (index % 64) & 1 == 1
+}
+
+fn set_bit(bits: &mut [u64], index: usize, value: bool) {
+    if value {
+        bits[index / 64] |= 1 << (index % 64);
+    } else {
+        bits[index / 64] &= !(1 << (index % 64));
+    }
+}
+
+// Maximal runs of indices below `len` for which `wanted` holds
+fn runs(len: usize, wanted: impl Fn(usize) -> bool) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for i in (0..len).filter(|&i| wanted(i)) {
+        match runs.last_mut() {
+            Some(run) if run.end == i => run.end += 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+    runs
+}
@@ -0,0 +1,113 @@
+use std::cmp::{max, min};
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{left_subtree_len, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::slice::{decode_slice, HEADER_LEN, PARENT_LEN};
+
+/// Encode `input` in the combined format: the input length as a u64 LE,
+/// followed by every parent node and chunk of the tree in pre-order, so the data
+/// and everything needed to verify it live in a single blob. Returns the
+/// encoding and the root it verifies against.
+pub fn encode_combined(input: &[u8]) -> (Vec<u8>, Hash) {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input));
+    let encoded = tree.encode_combined(input).expect("tree was built from input");
+    (encoded, tree.root_hash())
+}
+
+/// Check a combined encoding against `root` and return the original input.
+pub fn decode_combined(root: &Hash, encoded: &[u8]) -> Result<Vec<u8>, Error> {
+    decode_slice(root, encoded, 0..u64::MAX)
+}
+
+impl BinaryMerkleTree {
+    /// The combined encoding of `input`, the data this tree was built from.
+    /// This is the slice covering all of the input. Returns None if `input`
+    /// does not have as many chunks as the tree.
+    pub fn encode_combined(&self, input: &[u8]) -> Option<Vec<u8>> {
+        self.extract_slice(input, 0..input.len() as u64)
+    }
+}
+
+/// Cut the slice for `byte_range` out of a combined encoding without hashing
+/// anything, so a server holding only the blob can answer verified range
+/// requests. Fails if the encoding is shorter than its header says.
+pub fn slice_from_combined(encoded: &[u8], byte_range: Range<u64>) -> Result<Vec<u8>, Error> {
+    let header = encoded.get(..HEADER_LEN).ok_or(Error::UnexpectedEnd)?;
+    let len = u64::from_le_bytes(header.try_into().unwrap());
+    if (encoded.len() as u64) < combined_len(len) {
+        return Err(Error::UnexpectedEnd);
+    }
+    let mut slice = header.to_vec();
+    for range in combined_slice_ranges(len, byte_range) {
+        let range = usize::try_from(range.start).map_err(|_| Error::UnexpectedEnd)?
+            ..usize::try_from(range.end).map_err(|_| Error::UnexpectedEnd)?;
+        slice.extend_from_slice(encoded.get(range).ok_or(Error::UnexpectedEnd)?);
+    }
+    Ok(slice)
+}
+
+/// Length of the combined encoding of an input of `len` bytes.
+pub fn combined_len(len: u64) -> u64 {
+    HEADER_LEN as u64 + encoded_len(len, 0, max(1, len.div_ceil(CHUNK_LEN as u64)))
+}
+
+/// The byte ranges of the combined encoding of an input of `len` bytes that
+/// make up the slice for `byte_range`, after the header, in order and with
+/// adjacent ranges coalesced. Concatenating the header and these ranges gives
+/// what `slice_from_combined` returns, so a client can fetch a slice from a
+/// blob served as a plain file with range requests.
+pub fn combined_slice_ranges(len: u64, byte_range: Range<u64>) -> Vec<Range<u64>> {
+    let leaf_count = max(1, len.div_ceil(CHUNK_LEN as u64));
+    let first = min(byte_range.start / CHUNK_LEN as u64, leaf_count - 1);
+    let end = byte_range.end.div_ceil(CHUNK_LEN as u64).clamp(first + 1, leaf_count);
+    let mut collector = RangeCollector {
+        position: HEADER_LEN as u64,
+        len,
+        chunks: first..end,
+        ranges: Vec::new(),
+    };
+    collector.subtree(0, leaf_count);
+    collector.ranges
+}
+
+// Encoded size of a subtree: its data plus one parent per interior node
+fn encoded_len(len: u64, first_leaf: u64, leaf_count: u64) -> u64 {
+    let data_end = min((first_leaf + leaf_count) * CHUNK_LEN as u64, len);
+    data_end - first_leaf * CHUNK_LEN as u64 + (leaf_count - 1) * PARENT_LEN as u64
+}
+
+struct RangeCollector {
+    position: u64,
+    len: u64,
+    chunks: Range<u64>,
+    ranges: Vec<Range<u64>>,
+}
+
+impl RangeCollector {
+    fn take(&mut self, count: u64) {
+        let end = self.position + count;
+        match self.ranges.last_mut() {
+            Some(last) if last.end == self.position => last.end = end,
+            _ => self.ranges.push(self.position..end),
+        }
+        self.position = end;
+    }
+
+    fn subtree(&mut self, first_leaf: u64, leaf_count: u64) {
+        let subtree_end = first_leaf + leaf_count;
+        if subtree_end <= self.chunks.start || self.chunks.end <= first_leaf {
+            // Not needed: skip over it
+            self.position += encoded_len(self.len, first_leaf, leaf_count);
+            return;
+        }
+        if leaf_count == 1 {
+            return self.take(encoded_len(self.len, first_leaf, 1));
+        }
+        self.take(PARENT_LEN as u64);
+        let left_count = left_subtree_len(leaf_count as usize) as u64;
+        self.subtree(first_leaf, left_count);
+        self.subtree(first_leaf + left_count, leaf_count - left_count);
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+/// Errors from updating a tree's leaves or decoding verified data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Leaf indices for a bulk update must be strictly increasing. `position`
+    /// is where in the input the first out-of-order index was found.
+    UnsortedIndices {
+        position: usize,
+        previous: usize,
+        index: usize,
+    },
+    /// A leaf index past the last leaf of the tree.
+    IndexOutOfRange { index: usize, leaf_count: usize },
+    /// A bulk update was given a different number of indices and leaves.
+    LengthMismatch { indices: usize, leaves: usize },
+    /// Encoded data did not hash to what its parent or the root committed to.
+    /// `offset` is where the offending node or chunk starts in the encoding.
+    HashMismatch { offset: u64 },
+    /// Encoded data ended before everything it describes was read.
+    UnexpectedEnd,
+    /// A proof does not match the trusted root, or does not have the shape of
+    /// the tree it was applied to.
+    InvalidProof,
+    /// A segment of chunks is not a subtree of the whole input's tree, or
+    /// overlaps another segment.
+    MisalignedSegment { first_chunk: u64, chunk_count: u64 },
+    /// No segment covers the chunks starting at `first_chunk`.
+    MissingChunks { first_chunk: u64 },
+    /// A value has no canonical encoding, for example a map with duplicate
+    /// keys or an integer too large for CBOR.
+    Unserializable(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsortedIndices { position, previous, index } => write!(
+                f,
+                "leaf indices must be strictly increasing, but index {} at position {} follows {}",
+                index, position, previous
+            ),
+            Error::IndexOutOfRange { index, leaf_count } => {
+                write!(f, "leaf index {} is out of range for a tree of {} leaves", index, leaf_count)
+            }
+            Error::LengthMismatch { indices, leaves } => {
+                write!(f, "got {} leaf indices but {} leaves", indices, leaves)
+            }
+            Error::HashMismatch { offset } => write!(f, "hash mismatch at offset {}", offset),
+            Error::UnexpectedEnd => write!(f, "encoded data ended unexpectedly"),
+            Error::InvalidProof => write!(f, "proof does not match the trusted root"),
+            Error::MisalignedSegment { first_chunk, chunk_count } => write!(
+                f,
+                "segment of {} chunks at chunk {} is not a subtree of the input",
+                chunk_count, first_chunk
+            ),
+            Error::MissingChunks { first_chunk } => write!(f, "no segment covers chunk {}", first_chunk),
+            Error::Unserializable(message) => write!(f, "cannot encode canonically: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Check the inputs of a bulk update: the same number of indices and leaves,
+/// with the indices strictly increasing.
+pub(crate) fn check_bulk_update<T>(indices: &[usize], leaves: &[T]) -> Result<(), Error> {
+    if indices.len() != leaves.len() {
+        return Err(Error::LengthMismatch {
+            indices: indices.len(),
+            leaves: leaves.len(),
+        });
+    }
+    match indices.windows(2).position(|pair| pair[0] >= pair[1]) {
+        Some(i) => Err(Error::UnsortedIndices {
+            position: i + 1,
+            previous: indices[i],
+            index: indices[i + 1],
+        }),
+        None => Ok(()),
+    }
+}
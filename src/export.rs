@@ -0,0 +1,144 @@
+//! Tree exports with a choice of node order. Bao and other streaming
+//! verifiers want nodes in pre-order, while database-style consumers writing
+//! sequentially prefer level order; the order is recorded in the header so a
+//! reader knows where each node sits.
+//!
+//! An export is the magic `B3NX`, a version byte (1), the order byte (see
+//! `NodeOrder`), the leaf count as a u64 LE, and then one `Output` record (see
+//! `serialize`) per node of the BLAKE3 tree in that order: every leaf and
+//! every parent, 2n - 1 records for n leaves. Nodes promoted unchanged on the
+//! right edge are not parents in BLAKE3 and are not repeated.
+
+use std::io::{self, Read, Write};
+
+use crate::binary_merkle_tree::{first_leaf_below, BinaryMerkleTree, Output};
+use crate::node_index::NodeIndex;
+use crate::serialize::{decode_output, encode_output, OUTPUT_ENCODED_LEN};
+
+pub(crate) const EXPORT_MAGIC: &[u8; 4] = b"B3NX";
+pub(crate) const EXPORT_VERSION: u8 = 1;
+
+/// The order of the nodes in an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeOrder {
+    /// A parent, then its left subtree, then its right subtree, as in Bao.
+    PreOrder = 0,
+    /// Both subtrees, then their parent, so every node follows its children.
+    PostOrder = 1,
+    /// Level by level from the root, left to right: heap index order.
+    LevelOrder = 2,
+}
+
+impl NodeOrder {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(NodeOrder::PreOrder),
+            1 => Some(NodeOrder::PostOrder),
+            2 => Some(NodeOrder::LevelOrder),
+            _ => None,
+        }
+    }
+}
+
+/// Heap indices of the nodes of a tree over `leaf_count` leaves in `order`,
+/// as an export lays them out.
+pub fn ordered_nodes(leaf_count: usize, order: NodeOrder) -> Vec<NodeIndex> {
+    let leaf_offset = leaf_count.max(1).next_power_of_two();
+    let covers_leaves = |index: usize| first_leaf_below(index, leaf_offset) < leaf_count;
+    let mut nodes = Vec::with_capacity((2 * leaf_count).saturating_sub(1));
+    match order {
+        NodeOrder::LevelOrder => nodes.extend(
+            (1..2 * leaf_offset)
+                .filter(|&index| covers_leaves(index) && (index >= leaf_offset || covers_leaves(2 * index + 1)))
+                .map(NodeIndex),
+        ),
+        NodeOrder::PreOrder | NodeOrder::PostOrder if leaf_count > 0 => {
+            visit(1, leaf_offset, &covers_leaves, order, &mut nodes)
+        }
+        _ => {}
+    }
+    nodes
+}
+
+// Append the nodes of the subtree at `index` in pre- or post-order
+fn visit(
+    index: usize,
+    leaf_offset: usize,
+    covers_leaves: &dyn Fn(usize) -> bool,
+    order: NodeOrder,
+    nodes: &mut Vec<NodeIndex>,
+) {
+    if index >= leaf_offset {
+        return nodes.push(NodeIndex(index));
+    }
+    // A node whose right half is empty is its left child promoted
+    if !covers_leaves(2 * index + 1) {
+        return visit(2 * index, leaf_offset, covers_leaves, order, nodes);
+    }
+    if order == NodeOrder::PreOrder {
+        nodes.push(NodeIndex(index));
+    }
+    visit(2 * index, leaf_offset, covers_leaves, order, nodes);
+    visit(2 * index + 1, leaf_offset, covers_leaves, order, nodes);
+    if order == NodeOrder::PostOrder {
+        nodes.push(NodeIndex(index));
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Write every leaf and parent of the tree in `order`, with the order in
+    /// the header.
+    pub fn export_nodes<W: Write>(&self, mut writer: W, order: NodeOrder) -> io::Result<()> {
+        let nodes = ordered_nodes(self.num_leaves(), order);
+        let mut bytes = Vec::with_capacity(4 + 1 + 1 + 8 + OUTPUT_ENCODED_LEN * nodes.len());
+        bytes.extend_from_slice(EXPORT_MAGIC);
+        bytes.push(EXPORT_VERSION);
+        bytes.push(order as u8);
+        bytes.extend_from_slice(&(self.num_leaves() as u64).to_le_bytes());
+        for index in nodes {
+            encode_output(&self.tree[index.get()], &mut bytes);
+        }
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    /// Read an export in any order. The tree is rebuilt from the exported
+    /// leaves and every exported parent must match the rebuilt one, so a
+    /// corrupted export fails with `InvalidData` rather than loading.
+    pub fn import_nodes<R: Read>(mut reader: R) -> io::Result<(BinaryMerkleTree, NodeOrder)> {
+        fn invalid(message: String) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        }
+
+        let mut header = [0u8; 4 + 1 + 1 + 8];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != EXPORT_MAGIC {
+            return Err(invalid("not a node export".to_string()));
+        }
+        if header[4] != EXPORT_VERSION {
+            return Err(invalid("unsupported node export version".to_string()));
+        }
+        let order = NodeOrder::from_byte(header[5]).ok_or_else(|| invalid(format!("unknown node order {}", header[5])))?;
+        let leaf_count = usize::try_from(u64::from_le_bytes(header[6..].try_into().unwrap()))
+            .map_err(|_| invalid("leaf count too large".to_string()))?;
+
+        let leaf_offset = leaf_count.max(1).next_power_of_two();
+        let mut leaves = vec![None; leaf_count];
+        let mut parents: Vec<(usize, Output)> = Vec::new();
+        let mut record = [0u8; OUTPUT_ENCODED_LEN];
+        for index in ordered_nodes(leaf_count, order) {
+            reader.read_exact(&mut record)?;
+            let node = decode_output(&record);
+            match index.get().checked_sub(leaf_offset) {
+                Some(leaf_index) => leaves[leaf_index] = Some(node),
+                None => parents.push((index.get(), node)),
+            }
+        }
+        let leaves = leaves.into_iter().map(|leaf| leaf.expect("every leaf is exported")).collect();
+        let tree = BinaryMerkleTree::new_from_leaves(leaves);
+        if let Some((index, _)) = parents.iter().find(|(index, node)| tree.tree[*index] != *node) {
+            return Err(invalid(format!("node {} does not match its children", index)));
+        }
+        Ok((tree, order))
+    }
+}
@@ -0,0 +1,262 @@
+use std::cmp::{max, min};
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+
+#[cfg(feature = "memmap2")]
+use crate::binary_merkle_tree::chunk_output;
+use crate::binary_merkle_tree::{BinaryMerkleTree, ChunkSplitter, HashMode, Output, CHUNK_LEN};
+use crate::blocks::rehash_chunk;
+
+// Read files 64 chunks at a time so every buffer ends on a chunk boundary
+pub(crate) const READ_BUFFER_LEN: usize = 64 * CHUNK_LEN;
+
+impl BinaryMerkleTree {
+    /// Build a tree over the contents of the file at `path`.
+    /// Returns the tree together with the total number of bytes read.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<(BinaryMerkleTree, u64)> {
+        let file = File::open(path)?;
+        Self::from_reader(file)
+    }
+
+    /// Build a tree over everything read from `reader` until EOF.
+    /// Returns the tree together with the total number of bytes read.
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<(BinaryMerkleTree, u64)> {
+        Self::from_reader_with_mode(reader, HashMode::HASH)
+    }
+
+    /// `from_reader` for a keyed or key derivation tree.
+    pub fn from_reader_with_mode<R: Read>(mut reader: R, mode: HashMode) -> io::Result<(BinaryMerkleTree, u64)> {
+        let mut splitter = ChunkSplitter::with_mode(0, mode);
+        let mut buffer = vec![0u8; READ_BUFFER_LEN];
+        loop {
+            let filled = read_full(&mut reader, &mut buffer)?;
+            splitter.update(&buffer[..filled]);
+            if filled < buffer.len() {
+                break;
+            }
+        }
+        let total_len = splitter.total_len();
+        let mut tree = BinaryMerkleTree::new_from_leaves_with_mode(splitter.finalize(), mode);
+        tree.count_bytes_hashed(total_len);
+        Ok((tree, total_len))
+    }
+}
+
+// Empty input is still one (empty) chunk
+fn chunk_count(content: &[u8]) -> usize {
+    content.len().div_ceil(CHUNK_LEN).max(1)
+}
+
+// Bytes of an input of `len` bytes that fall in the chunks `chunks`
+pub(crate) fn bytes_in_chunks(len: u64, chunks: Range<usize>) -> u64 {
+    let offset = |chunk: usize| min((chunk * CHUNK_LEN) as u64, len);
+    offset(chunks.end) - offset(chunks.start)
+}
+
+/// Fill `buffer` as far as possible, only returning less than its length at EOF.
+pub(crate) fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(feature = "memmap2")]
+impl BinaryMerkleTree {
+    /// Build a tree over the file at `path` by hashing directly from a memory
+    /// mapping, which avoids copying the file through a read buffer. The mapping
+    /// is split into contiguous chunk ranges that are hashed on separate threads.
+    ///
+    /// The file must not be modified while it is being hashed, otherwise the
+    /// resulting tree is unspecified.
+    pub fn from_file_mmap<P: AsRef<Path>>(path: P) -> io::Result<(BinaryMerkleTree, u64)> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::from_file_mmap_with_threads(path, threads)
+    }
+
+    /// Like `from_file_mmap`, hashing on at most `max_threads` threads.
+    pub fn from_file_mmap_with_threads<P: AsRef<Path>>(
+        path: P,
+        max_threads: usize,
+    ) -> io::Result<(BinaryMerkleTree, u64)> {
+        let file = File::open(path)?;
+        let total_len = file.metadata()?.len();
+        if total_len == 0 {
+            // Mapping an empty file is an error on some platforms
+            return Ok((BinaryMerkleTree::new_from_leaves(vec![chunk_output(0, &[])]), 0));
+        }
+        // Safety: the mapping is only read, and callers are told not to modify the
+        // file concurrently.
+        let mapping = unsafe { memmap2::Mmap::map(&file)? };
+        let mut tree = BinaryMerkleTree::new_from_leaves(chunk_outputs_on_threads(&mapping, max_threads));
+        tree.count_bytes_hashed(total_len);
+        Ok((tree, total_len))
+    }
+}
+
+/// Hash every chunk of `input`, splitting the work into contiguous chunk ranges
+/// across up to `threads` threads. The outputs are the same as
+/// `process_input_to_chunks(input)`.
+#[cfg(feature = "memmap2")]
+fn chunk_outputs_on_threads(input: &[u8], threads: usize) -> Vec<Output> {
+    use crate::binary_merkle_tree::{ChunkState, IV};
+
+    let number_of_chunks = input.len().div_ceil(CHUNK_LEN);
+    let chunks_per_thread = number_of_chunks.div_ceil(threads.max(1)).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = input
+            .chunks(chunks_per_thread * CHUNK_LEN)
+            .enumerate()
+            .map(|(segment_index, segment)| {
+                let first_chunk = segment_index * chunks_per_thread;
+                scope.spawn(move || {
+                    segment
+                        .chunks(CHUNK_LEN)
+                        .enumerate()
+                        .map(|(i, chunk)| {
+                            let mut chunk_state = ChunkState::new(IV, (first_chunk + i) as u64, 0);
+                            chunk_state.update(chunk);
+                            chunk_state.output()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("chunk hashing thread panicked"))
+            .collect()
+    })
+}
+
+impl BinaryMerkleTree {
+    /// Re-hash `input` and update the tree to match it, returning the indices of
+    /// the chunks whose outputs changed. Only ancestors of changed chunks are
+    /// recomputed, so re-hashing a mostly unchanged input is dominated by the
+    /// chunk hashing itself.
+    ///
+    /// If the number of chunks changed, the tree shape changes with it and is
+    /// rebuilt; chunks that were added or removed are reported as changed.
+    pub fn refresh_from_bytes(&mut self, input: &[u8]) -> Vec<usize> {
+        let mut splitter = ChunkSplitter::with_mode(0, self.mode);
+        splitter.update(input);
+        self.count_bytes_hashed(input.len() as u64);
+        self.refresh_from_leaves(splitter.finalize())
+    }
+
+    /// Like `refresh_from_bytes`, reading the new contents from `reader`.
+    pub fn refresh_from_reader<R: Read>(&mut self, mut reader: R) -> io::Result<Vec<usize>> {
+        let mut splitter = ChunkSplitter::with_mode(0, self.mode);
+        let mut buffer = vec![0u8; READ_BUFFER_LEN];
+        loop {
+            let filled = read_full(&mut reader, &mut buffer)?;
+            splitter.update(&buffer[..filled]);
+            if filled < buffer.len() {
+                break;
+            }
+        }
+        self.count_bytes_hashed(splitter.total_len());
+        Ok(self.refresh_from_leaves(splitter.finalize()))
+    }
+
+    /// Like `refresh_from_bytes`, reading the new contents from the file at `path`.
+    pub fn refresh_from_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Vec<usize>> {
+        self.refresh_from_reader(File::open(path)?)
+    }
+
+    /// Update the tree after the bytes in `changed` were rewritten. `content` is
+    /// the whole input after the write, which may have made it longer. Only the
+    /// chunks overlapping `changed` are rehashed, plus, if the chunk count
+    /// changed, the old final chunk and everything after it. Returns the indices
+    /// of the leaves that changed.
+    pub fn update_bytes(&mut self, content: &[u8], changed: Range<u64>) -> Vec<usize> {
+        let new_leaf_count = chunk_count(content);
+        let first = min(changed.start as usize / CHUNK_LEN, new_leaf_count);
+        let end = (changed.end as usize).div_ceil(CHUNK_LEN).clamp(first, new_leaf_count);
+        if new_leaf_count == self.num_leaves() {
+            return self.rehash_chunks(content, first..end, changed.start);
+        }
+        let old_last = min(self.num_leaves(), new_leaf_count).saturating_sub(1);
+        self.rehash_chunks(content, min(first, old_last)..new_leaf_count, changed.start)
+    }
+
+    /// Update the tree after the input was truncated or extended to
+    /// `content.len()` bytes, where `content` is the whole input after the
+    /// change. The final chunk of the shorter length is the only surviving
+    /// chunk whose bytes can differ, so it is rehashed with its new length and
+    /// chunks past it are added or dropped. Returns the indices of the leaves
+    /// that changed.
+    pub fn set_len(&mut self, content: &[u8]) -> Vec<usize> {
+        let new_leaf_count = chunk_count(content);
+        let old_last = min(self.num_leaves(), new_leaf_count).saturating_sub(1);
+        self.rehash_chunks(content, old_last..new_leaf_count, content.len() as u64)
+    }
+
+    // Rehash the chunks of `content` in `dirty`, reusing the stored leaves for
+    // every other chunk. Bytes before `first_changed` are the ones last hashed,
+    // so cached block chaining values can be resumed up to there.
+    fn rehash_chunks(&mut self, content: &[u8], dirty: Range<usize>, first_changed: u64) -> Vec<usize> {
+        // Taken out while the leaves are written, which would otherwise drop it
+        let mut block_cvs = self.block_cvs.take();
+        let mode = self.mode;
+        let mut bytes_hashed = 0;
+        let mut chunk = |i: usize| {
+            let (output, hashed) = rehash_chunk(&mut block_cvs, content, i, first_changed, &mode);
+            bytes_hashed += hashed as u64;
+            output
+        };
+        let new_leaf_count = chunk_count(content);
+        let changed = if new_leaf_count != self.num_leaves() {
+            let leaves = (0..new_leaf_count)
+                .map(|i| if dirty.contains(&i) { chunk(i) } else { *self.leaf(i) })
+                .collect();
+            self.count_bytes_hashed(bytes_hashed);
+            self.refresh_from_leaves(leaves)
+        } else {
+            let updates: Vec<(usize, Output)> = dirty
+                .map(|i| (i, chunk(i)))
+                .filter(|(i, output)| self.leaf(*i) != output)
+                .collect();
+            self.count_bytes_hashed(bytes_hashed);
+            self.bulk_insert_leaves(updates.iter().map(|(i, _)| *i), updates.iter().map(|(_, output)| *output))
+                .expect("dirty indices are produced in sorted order");
+            updates.into_iter().map(|(i, _)| i).collect()
+        };
+        if let Some(cvs) = &mut block_cvs {
+            cvs.truncate(new_leaf_count);
+        }
+        self.block_cvs = block_cvs;
+        changed
+    }
+
+    pub(crate) fn refresh_from_leaves(&mut self, leaves: Vec<Output>) -> Vec<usize> {
+        let old_leaf_count = self.num_leaves();
+        let leaf_offset = self.leaf_offset();
+
+        // Comparing the stored outputs is equivalent to comparing their chaining
+        // values, without compressing every stored leaf.
+        let common = min(old_leaf_count, leaves.len());
+        let mut changed: Vec<usize> = (0..common)
+            .filter(|&i| self.tree[leaf_offset + i] != leaves[i])
+            .collect();
+
+        if leaves.len() != old_leaf_count {
+            changed.extend(common..max(old_leaf_count, leaves.len()));
+            self.rebuild_from_leaves(leaves);
+            return changed;
+        }
+
+        let changed_outputs: Vec<Output> = changed.iter().map(|&i| leaves[i]).collect();
+        self.bulk_insert_leaves(changed.iter().copied(), changed_outputs.into_iter())
+            .expect("changed indices are produced in sorted order");
+        changed
+    }
+}
@@ -0,0 +1,176 @@
+use std::borrow::Cow;
+use std::sync::mpsc;
+
+use wgpu::util::DeviceExt;
+
+use crate::binary_merkle_tree::{
+    chunk_output, process_input_to_chunks, words_from_little_endian_bytes, BinaryMerkleTree, Output, BLOCK_LEN,
+    CHUNK_END, CHUNK_LEN,
+};
+
+// Chunks per dispatch: 64 MiB of input, well under the default storage buffer
+// binding limit of 128 MiB
+const BATCH_CHUNKS: usize = 64 * 1024;
+const WORKGROUP_SIZE: usize = 64;
+
+/// Hashes the chunks of large inputs on a GPU through a compute shader. Only
+/// the leaves are computed on the device: every full chunk except the last is
+/// compressed up to its final block there, the final block and the tail chunk
+/// are finished on the CPU, and so are all parent nodes. The outputs are the
+/// same as `process_input_to_chunks`.
+#[derive(Debug)]
+pub struct GpuChunkHasher {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuChunkHasher {
+    /// Connect to the default GPU adapter. Returns None if there is no usable
+    /// adapter or device.
+    pub fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("blake3 chunk hash"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("chunk_hash.wgsl"))),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("blake3 chunk hash"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Some(GpuChunkHasher { device, queue, pipeline })
+    }
+
+    /// The output of every chunk of `input`.
+    pub fn chunk_outputs(&self, input: &[u8]) -> Vec<Output> {
+        // The last chunk may be partial, so it is always hashed on the CPU
+        let gpu_chunks = input.len().div_ceil(CHUNK_LEN).saturating_sub(1);
+        let mut outputs = Vec::with_capacity(gpu_chunks + 1);
+        for first_chunk in (0..gpu_chunks).step_by(BATCH_CHUNKS) {
+            let count = BATCH_CHUNKS.min(gpu_chunks - first_chunk);
+            let batch = &input[first_chunk * CHUNK_LEN..(first_chunk + count) * CHUNK_LEN];
+            match self.hash_batch(first_chunk as u64, batch) {
+                Some(cvs) => outputs.extend(
+                    cvs.into_iter()
+                        .zip(batch.chunks_exact(CHUNK_LEN))
+                        .enumerate()
+                        .map(|(i, (cv, chunk))| finish_chunk((first_chunk + i) as u64, cv, chunk)),
+                ),
+                // A failed readback only costs speed, not correctness
+                None => outputs.extend(
+                    batch
+                        .chunks_exact(CHUNK_LEN)
+                        .enumerate()
+                        .map(|(i, chunk)| chunk_output((first_chunk + i) as u64, chunk)),
+                ),
+            }
+        }
+        outputs.push(chunk_output(gpu_chunks as u64, &input[gpu_chunks * CHUNK_LEN..]));
+        outputs
+    }
+
+    // Chaining values of the full chunks in `batch` after all but their last
+    // block, or None if the device failed to return them
+    fn hash_batch(&self, first_chunk: u64, batch: &[u8]) -> Option<Vec<[u32; 8]>> {
+        let count = batch.len() / CHUNK_LEN;
+        let output_len = (count * 8 * 4) as u64;
+        let input = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("chunks"),
+            contents: batch,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("chaining values"),
+            size: output_len,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: output_len,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params: Vec<u8> = [first_chunk as u32, (first_chunk >> 32) as u32, count as u32, 0]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        let params = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: &params,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: input.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: output.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: params.as_entire_binding() },
+            ],
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(count.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, output_len);
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().ok()?.ok()?;
+        let mapped = readback.slice(..).get_mapped_range();
+        let cvs = mapped
+            .chunks_exact(32)
+            .map(|bytes| {
+                let mut cv = [0; 8];
+                words_from_little_endian_bytes(bytes, &mut cv);
+                cv
+            })
+            .collect();
+        drop(mapped);
+        readback.unmap();
+        Some(cvs)
+    }
+}
+
+// The output of a full chunk given its chaining value before the last block
+fn finish_chunk(counter: u64, cv: [u32; 8], chunk: &[u8]) -> Output {
+    let mut block_words = [0; 16];
+    words_from_little_endian_bytes(&chunk[CHUNK_LEN - BLOCK_LEN..], &mut block_words);
+    Output {
+        input_chaining_value: cv,
+        block_words,
+        counter,
+        block_len: BLOCK_LEN as u32,
+        flags: CHUNK_END,
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Build a tree over `input`, hashing its chunks on the GPU if one is
+    /// available and on the CPU otherwise. The tree is the same either way.
+    pub fn from_bytes_gpu(input: &[u8]) -> BinaryMerkleTree {
+        let leaves = match GpuChunkHasher::new() {
+            Some(hasher) => hasher.chunk_outputs(input),
+            None => process_input_to_chunks(input),
+        };
+        BinaryMerkleTree::new_from_leaves(leaves)
+    }
+}
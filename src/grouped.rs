@@ -0,0 +1,134 @@
+use std::cmp::min;
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{chunk_output_with_mode, left_subtree_len, parent_output, BinaryMerkleTree, HashMode, Output, CHUNK_LEN};
+use crate::hash::Hash;
+
+/// A tree whose leaves are groups of 2^`group_log` consecutive chunks rather
+/// than single chunks. Each leaf stores the output of its group's BLAKE3
+/// subtree, so the tree is `group_log` levels shallower and 2^`group_log`
+/// times smaller than a chunk-per-leaf tree, while its root is still the
+/// BLAKE3 hash of the input. The price is granularity: changing one byte
+/// rehashes its whole group.
+///
+/// Groups start at multiples of their size, so every group but the last is a
+/// complete BLAKE3 subtree, and the last is the subtree over whatever chunks
+/// remain.
+#[derive(Debug, Clone)]
+pub struct GroupedMerkleTree {
+    tree: BinaryMerkleTree,
+    group_log: u32,
+}
+
+impl GroupedMerkleTree {
+    pub fn from_bytes(input: &[u8], group_log: u32) -> Self {
+        let leaves = (0..group_count(input, group_log))
+            .map(|group| group_output(input, group_log, group))
+            .collect();
+        GroupedMerkleTree {
+            tree: BinaryMerkleTree::new_from_leaves(leaves),
+            group_log,
+        }
+    }
+
+    // Wrap group outputs computed elsewhere under `mode`, in group order
+    pub(crate) fn from_group_leaves(leaves: Vec<Output>, group_log: u32, mode: HashMode) -> Self {
+        GroupedMerkleTree {
+            tree: BinaryMerkleTree::new_from_leaves_with_mode(leaves, mode),
+            group_log,
+        }
+    }
+
+    pub fn group_log(&self) -> u32 {
+        self.group_log
+    }
+
+    /// Number of input bytes covered by each leaf.
+    pub fn group_len(&self) -> usize {
+        CHUNK_LEN << self.group_log
+    }
+
+    pub fn num_groups(&self) -> usize {
+        self.tree.num_leaves()
+    }
+
+    /// The underlying tree over group leaves.
+    pub fn tree(&self) -> &BinaryMerkleTree {
+        &self.tree
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.tree.root_hash()
+    }
+
+    /// Update the tree after the bytes in `changed` were rewritten, as with
+    /// `BinaryMerkleTree::update_bytes`. `content` is the whole input after the
+    /// write. Only the groups overlapping `changed` are rehashed, plus, if the
+    /// group count changed, the old final group and everything after it.
+    /// Returns the indices of the groups that changed.
+    pub fn update_bytes(&mut self, content: &[u8], changed: Range<u64>) -> Vec<usize> {
+        let group_len = self.group_len() as u64;
+        let new_group_count = group_count(content, self.group_log);
+        let first = min((changed.start / group_len) as usize, new_group_count);
+        let end = (changed.end.div_ceil(group_len) as usize).clamp(first, new_group_count);
+        let dirty = if new_group_count == self.num_groups() {
+            first..end
+        } else {
+            let old_last = min(self.num_groups(), new_group_count).saturating_sub(1);
+            min(first, old_last)..new_group_count
+        };
+
+        let mode = self.tree.mode();
+        let group = |i: usize| group_output_with_mode(content, self.group_log, i, &mode);
+        if new_group_count != self.num_groups() {
+            let leaves: Vec<Output> = (0..new_group_count)
+                .map(|i| if dirty.contains(&i) { group(i) } else { *self.tree.leaf(i) })
+                .collect();
+            let common = min(self.num_groups(), new_group_count);
+            let mut changed: Vec<usize> = (0..common).filter(|&i| *self.tree.leaf(i) != leaves[i]).collect();
+            changed.extend(common..self.num_groups().max(new_group_count));
+            self.tree.rebuild_from_leaves(leaves);
+            return changed;
+        }
+
+        let updates: Vec<(usize, Output)> = dirty
+            .map(|i| (i, group(i)))
+            .filter(|(i, output)| self.tree.leaf(*i) != output)
+            .collect();
+        self.tree
+            .bulk_insert_leaves(updates.iter().map(|(i, _)| *i), updates.iter().map(|(_, output)| *output))
+            .expect("dirty groups are produced in sorted order");
+        updates.into_iter().map(|(i, _)| i).collect()
+    }
+}
+
+// Empty input is still one group holding one empty chunk
+fn group_count(content: &[u8], group_log: u32) -> usize {
+    content.len().div_ceil(CHUNK_LEN << group_log).max(1)
+}
+
+/// The output of the BLAKE3 subtree over the chunks of group `group` of
+/// `content`, the leaf a `GroupedMerkleTree` stores for it.
+pub fn group_output(content: &[u8], group_log: u32, group: usize) -> Output {
+    group_output_with_mode(content, group_log, group, &HashMode::HASH)
+}
+
+/// `group_output` for a keyed or key derivation tree.
+pub fn group_output_with_mode(content: &[u8], group_log: u32, group: usize, mode: &HashMode) -> Output {
+    let chunk_count = content.len().div_ceil(CHUNK_LEN).max(1);
+    let first_chunk = group << group_log;
+    let end_chunk = min((group + 1) << group_log, chunk_count);
+    subtree_output(content, first_chunk, end_chunk - first_chunk, mode)
+}
+
+fn subtree_output(content: &[u8], first_chunk: usize, chunk_count: usize, mode: &HashMode) -> Output {
+    if chunk_count == 1 {
+        let start = first_chunk * CHUNK_LEN;
+        let end = min(start + CHUNK_LEN, content.len());
+        return chunk_output_with_mode(first_chunk as u64, &content[start..end], mode);
+    }
+    let left_count = left_subtree_len(chunk_count);
+    let left = subtree_output(content, first_chunk, left_count, mode);
+    let right = subtree_output(content, first_chunk + left_count, chunk_count - left_count, mode);
+    parent_output(left.chaining_value(), right.chaining_value(), mode.key_words, mode.flags)
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::str::FromStr;
+
+use subtle::ConstantTimeEq;
+
+use crate::binary_merkle_tree::{Output, OUT_LEN};
+
+/// A 32-byte BLAKE3 hash, such as a tree root or the output of
+/// `Blake3Hasher::finalize`.
+///
+/// `==` returns as soon as two bytes differ; use `ct_eq` wherever the result
+/// decides whether data is authentic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hash([u8; OUT_LEN]);
+
+impl Hash {
+    pub fn from_bytes(bytes: [u8; OUT_LEN]) -> Self {
+        Hash(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; OUT_LEN] {
+        &self.0
+    }
+
+    /// The hash committed to by a root output. The ROOT flag is applied here, so
+    /// this works for any node a caller wants to treat as the root.
+    pub fn from_root(root: &Output) -> Self {
+        let mut bytes = [0; OUT_LEN];
+        root.root_output_bytes(&mut bytes);
+        Hash(bytes)
+    }
+
+    /// Reinterpret little-endian chaining value words, as returned by
+    /// `tree.root().chaining_value()`, as a hash.
+    pub fn from_chaining_value(words: [u32; 8]) -> Self {
+        let mut bytes = [0; OUT_LEN];
+        for (word, out) in words.iter().zip(bytes.chunks_exact_mut(4)) {
+            out.copy_from_slice(&word.to_le_bytes());
+        }
+        Hash(bytes)
+    }
+
+    pub fn to_chaining_value(&self) -> [u32; 8] {
+        let mut words = [0; 8];
+        for (four_bytes, word) in self.0.chunks_exact(4).zip(words.iter_mut()) {
+            *word = u32::from_le_bytes(four_bytes.try_into().unwrap());
+        }
+        words
+    }
+
+    /// Compare in constant time, so the timing does not reveal how many leading
+    /// bytes of a forged root or MAC were right.
+    pub fn ct_eq(&self, other: &Hash) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+
+    /// Lowercase hex encoding, as printed by b3sum.
+    pub fn to_hex(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, ParseHashError> {
+        hex.parse()
+    }
+}
+
+/// Constant-time comparison of chaining values, for verification paths that
+/// check an interior node rather than a root.
+pub(crate) fn cv_ct_eq(a: &[u32; 8], b: &[u32; 8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returned when a string is not exactly 64 hex digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseHashError;
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} hex digits", 2 * OUT_LEN)
+    }
+}
+
+impl std::error::Error for ParseHashError {}
+
+impl FromStr for Hash {
+    type Err = ParseHashError;
+
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        let hex = hex.as_bytes();
+        if hex.len() != 2 * OUT_LEN {
+            return Err(ParseHashError);
+        }
+        fn nibble(digit: u8) -> Result<u8, ParseHashError> {
+            match digit {
+                b'0'..=b'9' => Ok(digit - b'0'),
+                b'a'..=b'f' => Ok(digit - b'a' + 10),
+                b'A'..=b'F' => Ok(digit - b'A' + 10),
+                _ => Err(ParseHashError),
+            }
+        }
+        let mut bytes = [0; OUT_LEN];
+        for (byte, pair) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+            *byte = (nibble(pair[0])? << 4) | nibble(pair[1])?;
+        }
+        Ok(Hash(bytes))
+    }
+}
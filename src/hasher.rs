@@ -0,0 +1,109 @@
+use std::fmt::Debug;
+
+use crate::binary_merkle_tree::{parent_output, HashMode, Output, IV};
+
+/// How a `BinaryMerkleTree` turns two child nodes into their parent. The
+/// default, `Blake3TreeHasher`, keeps the tree compatible with BLAKE3 itself;
+/// the others trade that compatibility for interop with existing verifiers
+/// that expect a different hash.
+pub trait TreeHasher {
+    type Node: Copy + PartialEq + Debug;
+
+    fn parent(left: &Self::Node, right: &Self::Node) -> Self::Node;
+
+    /// `parent` in a keyed or key derivation tree. Hashers with no notion of
+    /// a key ignore `mode`.
+    fn parent_with_mode(left: &Self::Node, right: &Self::Node, _mode: &HashMode) -> Self::Node {
+        Self::parent(left, right)
+    }
+
+    /// Placeholder held by nodes that cover no leaves.
+    fn empty_node() -> Self::Node;
+}
+
+/// BLAKE3 parent compression over chunk outputs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3TreeHasher;
+
+impl TreeHasher for Blake3TreeHasher {
+    type Node = Output;
+
+    fn parent(left: &Output, right: &Output) -> Output {
+        parent_output(left.chaining_value(), right.chaining_value(), IV, 0)
+    }
+
+    fn parent_with_mode(left: &Output, right: &Output, mode: &HashMode) -> Output {
+        parent_output(left.chaining_value(), right.chaining_value(), mode.key_words, mode.flags)
+    }
+
+    fn empty_node() -> Output {
+        Output {
+            input_chaining_value: IV,
+            block_words: [0; 16],
+            counter: 0,
+            block_len: 64,
+            flags: 0,
+        }
+    }
+}
+
+/// SHA-256 with RFC 6962 domain separation: leaves are `SHA-256(0x00 || data)`
+/// and parents `SHA-256(0x01 || left || right)`.
+#[cfg(feature = "sha2")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256TreeHasher;
+
+#[cfg(feature = "sha2")]
+impl Sha256TreeHasher {
+    pub fn leaf(data: &[u8]) -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+        Sha256::new().chain_update([0x00]).chain_update(data).finalize().into()
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl TreeHasher for Sha256TreeHasher {
+    type Node = [u8; 32];
+
+    fn parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+        Sha256::new()
+            .chain_update([0x01])
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .into()
+    }
+
+    fn empty_node() -> [u8; 32] {
+        [0; 32]
+    }
+}
+
+/// Keccak-256 as used on Ethereum: leaves are `keccak256(data)` and parents
+/// `keccak256(left || right)`, with no domain separation.
+#[cfg(feature = "sha3")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Keccak256TreeHasher;
+
+#[cfg(feature = "sha3")]
+impl Keccak256TreeHasher {
+    pub fn leaf(data: &[u8]) -> [u8; 32] {
+        use sha3::{Digest, Keccak256};
+        Keccak256::digest(data).into()
+    }
+}
+
+#[cfg(feature = "sha3")]
+impl TreeHasher for Keccak256TreeHasher {
+    type Node = [u8; 32];
+
+    fn parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        use sha3::{Digest, Keccak256};
+        Keccak256::new().chain_update(left).chain_update(right).finalize().into()
+    }
+
+    fn empty_node() -> [u8; 32] {
+        [0; 32]
+    }
+}
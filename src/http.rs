@@ -0,0 +1,92 @@
+use std::io;
+use std::ops::Range;
+
+use crate::encoding::combined_slice_ranges;
+use crate::hash::Hash;
+use crate::slice::{decode_slice, HEADER_LEN};
+
+/// The one operation a verified reader needs from an HTTP client: a GET with
+/// a `Range: bytes=start-(end - 1)` header, returning the response body. Any
+/// client library can be plugged in by implementing this, and closures with
+/// the same signature implement it directly.
+pub trait HttpClient {
+    fn get_range(&self, url: &str, range: Range<u64>) -> io::Result<Vec<u8>>;
+}
+
+impl<F: Fn(&str, Range<u64>) -> io::Result<Vec<u8>>> HttpClient for F {
+    fn get_range(&self, url: &str, range: Range<u64>) -> io::Result<Vec<u8>> {
+        self(url, range)
+    }
+}
+
+/// Partial reads of a combined encoding (see `encoding::encode_combined`)
+/// published at `url`, for example on a CDN, verified against a trusted root.
+/// Only the parents and chunks covering each requested range are fetched, and
+/// only bytes that check out against the root are returned.
+#[derive(Debug, Clone)]
+pub struct VerifiedHttpReader<C> {
+    client: C,
+    url: String,
+    root: Hash,
+    // Input length from the encoding's header, fetched on first use
+    len: Option<u64>,
+}
+
+impl<C: HttpClient> VerifiedHttpReader<C> {
+    pub fn new(client: C, url: impl Into<String>, root: Hash) -> Self {
+        VerifiedHttpReader {
+            client,
+            url: url.into(),
+            root,
+            len: None,
+        }
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.root
+    }
+
+    /// Length of the input as the server's header states it. It is only
+    /// authenticated once a read reaching the end of the input has succeeded.
+    pub fn len(&mut self) -> io::Result<u64> {
+        if let Some(len) = self.len {
+            return Ok(len);
+        }
+        let header = self.fetch(0..HEADER_LEN as u64)?;
+        let len = u64::from_le_bytes(header.try_into().unwrap());
+        // Keeps every offset into the encoding within u64
+        if len > u64::MAX / 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "encoding header is too large"));
+        }
+        self.len = Some(len);
+        Ok(len)
+    }
+
+    pub fn is_empty(&mut self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Fetch and verify the bytes of `byte_range`, clipped to the end of the
+    /// input. Fails with InvalidData if anything the server sent does not
+    /// match the root.
+    pub fn read_range(&mut self, byte_range: Range<u64>) -> io::Result<Vec<u8>> {
+        let len = self.len()?;
+        let mut slice = len.to_le_bytes().to_vec();
+        for range in combined_slice_ranges(len, byte_range.clone()) {
+            slice.extend_from_slice(&self.fetch(range)?);
+        }
+        decode_slice(&self.root, &slice, byte_range).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // GET one range, insisting on a body of exactly its length
+    fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let body = self.client.get_range(&self.url, range.clone())?;
+        if body.len() as u64 != range.end - range.start {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("server returned {} bytes for range {:?}", body.len(), range),
+            ));
+        }
+        Ok(body)
+    }
+}
@@ -0,0 +1,127 @@
+//! Building a tree's nodes in a single pass over its chunk outputs. Like the
+//! chaining value stack of `Blake3Hasher`, `IncrementalTreeEncoder` keeps
+//! only one pending subtree per level, but it hands every node to the caller
+//! as soon as it is known instead of discarding it, so the whole tree can be
+//! written out while holding O(log n) nodes in memory.
+//!
+//! Complete subtrees are emitted as soon as their last chunk arrives, and the
+//! parents on the right edge when the encoder finishes. Every leaf and parent
+//! of the BLAKE3 tree is emitted once, in post-order, which is the
+//! `NodeOrder::PostOrder` layout of an export.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::binary_merkle_tree::{chunk_output, parent_output, Output, IV, ROOT};
+use crate::distributed::SegmentOutput;
+use crate::export::{NodeOrder, EXPORT_MAGIC, EXPORT_VERSION};
+use crate::hash::Hash;
+use crate::serialize::encode_output;
+
+/// Consumes chunk outputs in order and emits each node, as the chunks it
+/// covers and its output, through `emit`.
+pub struct IncrementalTreeEncoder<F> {
+    // Complete subtrees not yet merged, largest first
+    stack: Vec<SegmentOutput>,
+    chunks: u64,
+    emit: F,
+}
+
+impl<F: FnMut(SegmentOutput)> IncrementalTreeEncoder<F> {
+    pub fn new(emit: F) -> Self {
+        IncrementalTreeEncoder {
+            stack: Vec::new(),
+            chunks: 0,
+            emit,
+        }
+    }
+
+    /// Number of chunks pushed so far.
+    pub fn chunk_count(&self) -> u64 {
+        self.chunks
+    }
+
+    /// Add the output of the next chunk, emitting it and every subtree it
+    /// completes. The outputs must come in chunk order.
+    pub fn push_chunk(&mut self, output: Output) {
+        let mut node = SegmentOutput {
+            first_chunk: self.chunks,
+            chunk_count: 1,
+            output,
+        };
+        (self.emit)(node);
+        self.chunks += 1;
+        // Same as add_chunk_chaining_value: one merge per trailing zero bit of
+        // the new chunk count
+        let mut total_chunks = self.chunks;
+        while total_chunks & 1 == 0 {
+            let left = self.stack.pop().expect("a completed subtree has a left sibling");
+            node = self.merge(left, node);
+            total_chunks >>= 1;
+        }
+        self.stack.push(node);
+    }
+
+    /// Emit the parents on the right edge and return the root output, with
+    /// the ROOT flag, as `BinaryMerkleTree::root` would. With no chunks pushed
+    /// the tree is one empty chunk, which is emitted first.
+    pub fn finish(mut self) -> Output {
+        if self.chunks == 0 {
+            self.push_chunk(chunk_output(0, &[]));
+        }
+        let mut node = self.stack.pop().expect("at least one chunk was pushed");
+        while let Some(left) = self.stack.pop() {
+            node = self.merge(left, node);
+        }
+        let mut root = node.output;
+        root.flags |= ROOT;
+        root
+    }
+
+    fn merge(&mut self, left: SegmentOutput, right: SegmentOutput) -> SegmentOutput {
+        let parent = SegmentOutput {
+            first_chunk: left.first_chunk,
+            chunk_count: left.chunk_count + right.chunk_count,
+            output: parent_output(left.output.chaining_value(), right.output.chaining_value(), IV, 0),
+        };
+        (self.emit)(parent);
+        parent
+    }
+}
+
+/// Write a post-order node export (see `export`) of the chunk outputs in
+/// `chunks` in one pass, holding only the pending subtrees in memory. The
+/// leaf count in the header is patched in once the chunks run out, which is
+/// why `writer` must be seekable. Returns the root hash.
+pub fn write_post_order_export<W, I>(mut writer: W, chunks: I) -> io::Result<Hash>
+where
+    W: Write + Seek,
+    I: IntoIterator<Item = Output>,
+{
+    let start = writer.stream_position()?;
+    writer.write_all(EXPORT_MAGIC)?;
+    writer.write_all(&[EXPORT_VERSION, NodeOrder::PostOrder as u8])?;
+    writer.write_all(&0u64.to_le_bytes())?;
+
+    let mut result = Ok(());
+    let mut record = Vec::new();
+    let mut encoder = IncrementalTreeEncoder::new(|node: SegmentOutput| {
+        if result.is_ok() {
+            record.clear();
+            encode_output(&node.output, &mut record);
+            result = writer.write_all(&record);
+        }
+    });
+    for chunk in chunks {
+        encoder.push_chunk(chunk);
+    }
+    let leaf_count = encoder.chunk_count().max(1);
+    let root = encoder.finish();
+    result?;
+
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(start + 6))?;
+    writer.write_all(&leaf_count.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+    writer.flush()?;
+    Ok(Hash::from_root(&root))
+}
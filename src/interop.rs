@@ -0,0 +1,57 @@
+//! Conversions between this crate's types and those of the official `blake3`
+//! crate, for code that already hashes with it.
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN};
+use crate::error::Error;
+use crate::hash::Hash;
+
+impl From<blake3::Hash> for Hash {
+    fn from(hash: blake3::Hash) -> Self {
+        Hash::from_bytes(*hash.as_bytes())
+    }
+}
+
+impl From<Hash> for blake3::Hash {
+    fn from(hash: Hash) -> Self {
+        blake3::Hash::from_bytes(*hash.as_bytes())
+    }
+}
+
+/// A chunk chaining value from `blake3::guts::ChunkState::finalize(false)` as
+/// the words this crate uses, e.g. for `VerifiedWriter::with_outboard`.
+pub fn import_chunk_cv(cv: &blake3::Hash) -> [u32; 8] {
+    Hash::from(*cv).to_chaining_value()
+}
+
+pub fn import_chunk_cvs(cvs: &[blake3::Hash]) -> Vec<[u32; 8]> {
+    cvs.iter().map(import_chunk_cv).collect()
+}
+
+/// The chaining value of every chunk of `input`, computed by the official
+/// crate.
+pub fn blake3_chunk_cvs(input: &[u8]) -> Vec<blake3::Hash> {
+    let chunk_count = input.len().div_ceil(CHUNK_LEN).max(1);
+    (0..chunk_count)
+        .map(|i| {
+            let chunk = &input[i * CHUNK_LEN..input.len().min((i + 1) * CHUNK_LEN)];
+            blake3::guts::ChunkState::new(i as u64).update(chunk).finalize(false)
+        })
+        .collect()
+}
+
+/// Check that `tree` agrees with the official crate on `input`: every leaf
+/// chaining value, and the root. On disagreement, `Error::HashMismatch` gives
+/// the byte offset of the first chunk that differs.
+pub fn check_against_blake3(tree: &BinaryMerkleTree, input: &[u8]) -> Result<(), Error> {
+    let expected = blake3_chunk_cvs(input);
+    let first_mismatch = (0..expected.len().max(tree.num_leaves()))
+        .find(|&i| tree.leaf_cv(i) != expected.get(i).map(import_chunk_cv));
+    if let Some(chunk) = first_mismatch {
+        return Err(Error::HashMismatch { offset: (chunk * CHUNK_LEN) as u64 });
+    }
+    // Matching leaves imply a matching root unless the tree itself is corrupt
+    if tree.root_hash() != Hash::from(blake3::hash(input)) {
+        return Err(Error::HashMismatch { offset: 0 });
+    }
+    Ok(())
+}
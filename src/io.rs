@@ -0,0 +1,657 @@
+use std::cmp::min;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::binary_merkle_tree::{
+    chunk_output, chunk_output_with_mode, left_subtree_len, parent_cv, parent_output, BinaryMerkleTree, ChunkSplitter,
+    HashMode, Output, CHUNK_LEN, IV,
+};
+use crate::file::read_full;
+use crate::hash::{cv_ct_eq, Hash};
+use crate::slice::{HEADER_LEN, PARENT_LEN};
+
+/// A `Write` adapter that forwards every byte to an inner writer while chunking
+/// it into a Merkle tree. Call `finish` once all data has been written to get the
+/// inner writer back along with the tree over everything that was written.
+#[derive(Debug)]
+pub struct HashingWriter<W> {
+    inner: W,
+    splitter: ChunkSplitter,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            splitter: ChunkSplitter::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Number of bytes successfully written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.splitter.total_len()
+    }
+
+    /// Flush the inner writer and build the tree. The root of the written data
+    /// is `tree.root()`.
+    pub fn finish(mut self) -> io::Result<(W, BinaryMerkleTree)> {
+        self.inner.flush()?;
+        let tree = BinaryMerkleTree::new_from_leaves(self.splitter.finalize());
+        Ok((self.inner, tree))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only hash what the inner writer actually accepted, so short writes
+        // retried by the caller are not counted twice.
+        let written = self.inner.write(buf)?;
+        self.splitter.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Write` adapter for data from an untrusted source, such as a download,
+/// whose root and length are known. It is fed the combined encoding of the
+/// data (see `encoding::encode_combined`): the length header, then every
+/// parent node and chunk in pre-order. Each parent is checked against the
+/// chaining value its own parent vouched for as soon as it arrives, and each
+/// chunk is buffered and only passed on to the inner writer once it matches,
+/// so the inner writer never sees unverified bytes and nothing beyond the
+/// root and length is needed up front.
+///
+/// `with_outboard` instead takes plain data, given the chaining value of every
+/// chunk (e.g. `ManifestEntry::chunk_cvs`) in advance.
+///
+/// The first mismatch fails the write with `ErrorKind::InvalidData`, and every
+/// later write fails too.
+#[derive(Debug)]
+pub struct VerifiedWriter<W> {
+    inner: W,
+    root: Hash,
+    len: u64,
+    header_seen: bool,
+    // Subtrees still to arrive, the next one last
+    subtrees: Vec<PendingSubtree>,
+    // Bytes of the header, parent or chunk being received
+    pending: Vec<u8>,
+    // Bytes already verified and passed on
+    verified: u64,
+    failed: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingSubtree {
+    first_leaf: u64,
+    leaf_count: u64,
+    // The chaining value it must have, or None for the whole tree, which is
+    // checked against the root
+    cv: Option<[u32; 8]>,
+}
+
+impl<W: Write> VerifiedWriter<W> {
+    /// Verify the combined encoding of a `len` byte input against `root`.
+    pub fn new(inner: W, root: Hash, len: u64) -> Self {
+        Self {
+            inner,
+            root,
+            len,
+            header_seen: false,
+            subtrees: Vec::new(),
+            pending: Vec::with_capacity(CHUNK_LEN),
+            verified: 0,
+            failed: false,
+        }
+    }
+
+    /// Verify plain data against `root` using the whole chunk outboard,
+    /// `chunk_cvs`, which must be supplied up front and is checked against the
+    /// root here. Fails with `ErrorKind::InvalidData` if `chunk_cvs` is not one
+    /// chaining value per chunk of a `len` byte input, or does not hash up to
+    /// `root`.
+    pub fn with_outboard(inner: W, root: Hash, len: u64, chunk_cvs: Vec<[u32; 8]>) -> io::Result<Self> {
+        let chunk_count = len.div_ceil(CHUNK_LEN as u64).max(1);
+        let consistent = chunk_cvs.len() as u64 == chunk_count
+            // A single chunk is checked directly against the root once it arrives
+            && (chunk_count == 1 || root_from_chunk_cvs(&chunk_cvs).ct_eq(&root));
+        if !consistent {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk chaining values do not match the root"));
+        }
+        let subtrees = (0..chunk_count)
+            .rev()
+            .map(|i| PendingSubtree {
+                first_leaf: i,
+                leaf_count: 1,
+                cv: (chunk_count > 1).then(|| chunk_cvs[i as usize]),
+            })
+            .collect();
+        Ok(Self { header_seen: true, subtrees, ..Self::new(inner, root, len) })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Number of bytes verified and passed on to the inner writer.
+    pub fn bytes_verified(&self) -> u64 {
+        self.verified
+    }
+
+    /// Check that all `len` bytes arrived and were verified, flush the inner
+    /// writer, and return it. Fails with `ErrorKind::UnexpectedEof` if the data
+    /// is incomplete.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.failed {
+            return Err(mismatch());
+        }
+        self.verify_complete()?;
+        if !self.is_done() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "verified data is incomplete"));
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn is_done(&self) -> bool {
+        self.header_seen && self.subtrees.is_empty()
+    }
+
+    // Length of the header, parent or chunk being received
+    fn piece_len(&self) -> usize {
+        match self.subtrees.last() {
+            _ if !self.header_seen => HEADER_LEN,
+            Some(subtree) if subtree.leaf_count > 1 => PARENT_LEN,
+            Some(subtree) => min(CHUNK_LEN as u64, self.len - subtree.first_leaf * CHUNK_LEN as u64) as usize,
+            None => 0,
+        }
+    }
+
+    // Verify pieces while they are complete. The empty chunk of an empty input
+    // is complete before any of it arrives.
+    fn verify_complete(&mut self) -> io::Result<()> {
+        while !self.is_done() && self.pending.len() == self.piece_len() {
+            self.verify_pending()?;
+        }
+        Ok(())
+    }
+
+    fn verify_pending(&mut self) -> io::Result<()> {
+        if !self.header_seen {
+            if u64::from_le_bytes(self.pending[..].try_into().unwrap()) != self.len {
+                self.failed = true;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "encoded length does not match"));
+            }
+            let leaf_count = self.len.div_ceil(CHUNK_LEN as u64).max(1);
+            self.subtrees.push(PendingSubtree { first_leaf: 0, leaf_count, cv: None });
+            self.header_seen = true;
+            self.pending.clear();
+            return Ok(());
+        }
+
+        let subtree = *self.subtrees.last().expect("a piece is pending");
+        let children = (subtree.leaf_count > 1).then(|| {
+            let (left, right) = self.pending.split_at(PARENT_LEN / 2);
+            let cv = |bytes: &[u8]| Hash::from_bytes(bytes.try_into().unwrap()).to_chaining_value();
+            (cv(left), cv(right))
+        });
+        let output = match children {
+            Some((left, right)) => parent_output(left, right, IV, 0),
+            None => chunk_output(subtree.first_leaf, &self.pending),
+        };
+        let matches = match &subtree.cv {
+            Some(cv) => cv_ct_eq(&output.chaining_value(), cv),
+            None => Hash::from_root(&output).ct_eq(&self.root),
+        };
+        if !matches {
+            self.failed = true;
+            return Err(mismatch());
+        }
+
+        match children {
+            Some((left_cv, right_cv)) => {
+                self.subtrees.pop();
+                let left_count = left_subtree_len(subtree.leaf_count as usize) as u64;
+                self.subtrees.push(PendingSubtree {
+                    first_leaf: subtree.first_leaf + left_count,
+                    leaf_count: subtree.leaf_count - left_count,
+                    cv: Some(right_cv),
+                });
+                self.subtrees.push(PendingSubtree {
+                    first_leaf: subtree.first_leaf,
+                    leaf_count: left_count,
+                    cv: Some(left_cv),
+                });
+            }
+            None => {
+                // Only drop the chunk once the inner writer has taken it
+                self.inner.write_all(&self.pending)?;
+                self.verified += self.pending.len() as u64;
+                self.subtrees.pop();
+            }
+        }
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for VerifiedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(mismatch());
+        }
+        self.verify_complete()?;
+        if self.is_done() && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "more data than the expected length"));
+        }
+        // Take at most the rest of the current piece
+        let take = min(buf.len(), self.piece_len() - self.pending.len());
+        self.pending.extend_from_slice(&buf[..take]);
+        self.verify_complete()?;
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Chunks verified by a `VerifiedReader` by default before the least
+/// recently used one is forgotten.
+pub const DEFAULT_VERIFIED_CHUNKS: usize = 1024;
+
+/// A `Read + Seek` adapter over a data source, such as a database file, whose
+/// tree is checked against a trusted root up front. Each chunk is read whole
+/// and verified against its leaf the first time any part of it is read, and
+/// reads fail with `ErrorKind::InvalidData` on a mismatch, so callers only
+/// ever see verified bytes. The indices of recently verified chunks are kept
+/// in an LRU, and reads inside them go straight to the source; a chunk that
+/// falls out of the LRU is verified again on its next read.
+#[derive(Debug)]
+pub struct VerifiedReader<R> {
+    inner: R,
+    tree: BinaryMerkleTree,
+    root: Hash,
+    len: u64,
+    position: u64,
+    verified: ChunkLru,
+}
+
+impl<R: Read + Seek> VerifiedReader<R> {
+    /// Like `with_capacity`, remembering `DEFAULT_VERIFIED_CHUNKS` chunks.
+    pub fn new(inner: R, tree: BinaryMerkleTree, root: Hash) -> io::Result<Self> {
+        Self::with_capacity(inner, tree, root, DEFAULT_VERIFIED_CHUNKS)
+    }
+
+    /// Fails with `ErrorKind::InvalidData` if `tree` does not hash to `root`
+    /// or has a different number of chunks than `inner`.
+    pub fn with_capacity(mut inner: R, tree: BinaryMerkleTree, root: Hash, verified_chunks: usize) -> io::Result<Self> {
+        if !tree.root_hash().ct_eq(&root) {
+            return Err(mismatch());
+        }
+        let len = inner.seek(SeekFrom::End(0))?;
+        if len.div_ceil(CHUNK_LEN as u64).max(1) != tree.num_leaves() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "data length does not match the tree"));
+        }
+        Ok(Self { inner, tree, root, len, position: 0, verified: ChunkLru::new(verified_chunks) })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Writes through this reference to chunks still in the LRU are not
+    /// noticed until they are verified again.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Length of the data in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn read_verified_chunk(&mut self, index: usize) -> io::Result<Vec<u8>> {
+        let start = (index * CHUNK_LEN) as u64;
+        let mut chunk = vec![0u8; min(CHUNK_LEN as u64, self.len - start) as usize];
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(&mut chunk)?;
+        let output = chunk_output(index as u64, &chunk);
+        let matches = if self.tree.num_leaves() == 1 {
+            Hash::from_root(&output).ct_eq(&self.root)
+        } else {
+            cv_ct_eq(&output.chaining_value(), &self.tree.leaf(index).chaining_value())
+        };
+        if !matches {
+            return Err(mismatch());
+        }
+        self.verified.insert(index);
+        Ok(chunk)
+    }
+}
+
+impl<R: Read + Seek> Read for VerifiedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.len {
+            return Ok(0);
+        }
+        // Serve at most the rest of the current chunk
+        let index = (self.position / CHUNK_LEN as u64) as usize;
+        let offset = (self.position % CHUNK_LEN as u64) as usize;
+        let chunk_end = min(self.len, ((index + 1) * CHUNK_LEN) as u64);
+        let take = min(buf.len() as u64, chunk_end - self.position) as usize;
+        if self.verified.touch(index) {
+            self.inner.seek(SeekFrom::Start(self.position))?;
+            self.inner.read_exact(&mut buf[..take])?;
+        } else {
+            let chunk = self.read_verified_chunk(index)?;
+            buf[..take].copy_from_slice(&chunk[offset..offset + take]);
+        }
+        self.position += take as u64;
+        Ok(take)
+    }
+}
+
+impl<R: Read + Seek> Seek for VerifiedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+        Ok(self.position)
+    }
+}
+
+// Least recently used set of chunk indices
+#[derive(Debug)]
+struct ChunkLru {
+    capacity: usize,
+    tick: u64,
+    last_used: HashMap<usize, u64>,
+    by_age: BTreeMap<u64, usize>,
+}
+
+impl ChunkLru {
+    fn new(capacity: usize) -> Self {
+        ChunkLru { capacity, tick: 0, last_used: HashMap::new(), by_age: BTreeMap::new() }
+    }
+
+    // Whether `index` is present, marking it as most recently used if so
+    fn touch(&mut self, index: usize) -> bool {
+        let Some(used) = self.last_used.get_mut(&index) else {
+            return false;
+        };
+        self.by_age.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.by_age.insert(self.tick, index);
+        true
+    }
+
+    fn insert(&mut self, index: usize) {
+        if self.capacity == 0 || self.touch(index) {
+            return;
+        }
+        if self.last_used.len() == self.capacity {
+            let (_, oldest) = self.by_age.pop_first().expect("a full cache is not empty");
+            self.last_used.remove(&oldest);
+        }
+        self.tick += 1;
+        self.last_used.insert(index, self.tick);
+        self.by_age.insert(self.tick, index);
+    }
+}
+
+/// When a `PositionalWriter` brings its tree up to date with its writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TreeUpdate {
+    /// After every `write_at`, rehashing the chunks it touched.
+    #[default]
+    EachWrite,
+    /// On `flush` or `root`, rehashing every chunk touched since, once.
+    OnFlush,
+}
+
+/// Positional writes over a backing file, such as a VM image or database,
+/// that keep a tree over its whole contents. `write_at` may overwrite any
+/// range or extend the file; the touched chunks are read back and rehashed
+/// along with their ancestors, either after each write or batched until
+/// `flush`, per `TreeUpdate`. `root` is always the BLAKE3 hash of the
+/// current contents, or its keyed or derived key counterpart for a writer
+/// made with `with_mode`.
+#[derive(Debug)]
+pub struct PositionalWriter<F> {
+    inner: F,
+    tree: BinaryMerkleTree,
+    len: u64,
+    update: TreeUpdate,
+    // Chunks written since the tree was last updated
+    dirty: BTreeSet<usize>,
+}
+
+impl<F: Read + Write + Seek> PositionalWriter<F> {
+    /// Hash the current contents of `inner`.
+    pub fn new(inner: F, update: TreeUpdate) -> io::Result<Self> {
+        Self::with_mode(inner, update, HashMode::HASH)
+    }
+
+    /// Hash the current contents of `inner` with `mode`.
+    pub fn with_mode(mut inner: F, update: TreeUpdate, mode: HashMode) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(0))?;
+        let (tree, len) = BinaryMerkleTree::from_reader_with_mode(&mut inner, mode)?;
+        Ok(Self { inner, tree, len, update, dirty: BTreeSet::new() })
+    }
+
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    /// The tree as of the last update, which with `TreeUpdate::OnFlush` may
+    /// not include recent writes yet.
+    pub fn tree(&self) -> &BinaryMerkleTree {
+        &self.tree
+    }
+
+    /// Length of the file in bytes, including unflushed writes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Write all of `buf` at byte `offset`. Writing past the end extends the
+    /// file, and any gap reads back as zeros.
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.write_all(buf)?;
+        let end = offset + buf.len() as u64;
+        // Growing also changes the old last chunk and fills any gap
+        let first = if end > self.len { min(offset, self.len) } else { offset };
+        let first_chunk = (first / CHUNK_LEN as u64) as usize;
+        let end_chunk = end.div_ceil(CHUNK_LEN as u64) as usize;
+        self.dirty.extend(first_chunk..end_chunk);
+        self.len = self.len.max(end);
+        if self.update == TreeUpdate::EachWrite {
+            self.update_tree()?;
+        }
+        Ok(())
+    }
+
+    /// Root of the current contents, first rehashing any pending writes.
+    pub fn root(&mut self) -> io::Result<Hash> {
+        self.update_tree()?;
+        Ok(self.tree.root_hash())
+    }
+
+    /// Bring the tree up to date, flush the file, and return both.
+    pub fn finish(mut self) -> io::Result<(F, BinaryMerkleTree)> {
+        self.flush()?;
+        Ok((self.inner, self.tree))
+    }
+
+    // Reread the dirty chunks and update their leaves and ancestors. Chunks
+    // stay dirty if reading fails.
+    fn update_tree(&mut self) -> io::Result<()> {
+        if self.dirty.is_empty() {
+            return Ok(());
+        }
+        let mut buffer = vec![0; CHUNK_LEN];
+        let mut outputs = Vec::with_capacity(self.dirty.len());
+        for &chunk in &self.dirty {
+            let offset = (chunk * CHUNK_LEN) as u64;
+            let len = min(CHUNK_LEN as u64, self.len - offset) as usize;
+            self.inner.seek(SeekFrom::Start(offset))?;
+            if read_full(&mut self.inner, &mut buffer[..len])? < len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file is shorter than what was written"));
+            }
+            outputs.push((chunk, chunk_output_with_mode(chunk as u64, &buffer[..len], &self.tree.mode)));
+        }
+
+        let chunk_count = (self.len.div_ceil(CHUNK_LEN as u64) as usize).max(1);
+        if chunk_count == self.tree.num_leaves() {
+            let changed: Vec<(usize, Output)> =
+                outputs.into_iter().filter(|(chunk, output)| self.tree.leaf(*chunk) != output).collect();
+            self.tree
+                .bulk_insert_leaves(changed.iter().map(|(i, _)| *i), changed.iter().map(|(_, output)| *output))
+                .expect("dirty chunks are visited in sorted order");
+        } else {
+            let mut leaves: Vec<Output> = (0..self.tree.num_leaves()).map(|i| *self.tree.leaf(i)).collect();
+            // Every chunk past the old end is dirty, and they come in order
+            for (chunk, output) in outputs {
+                if chunk < leaves.len() {
+                    leaves[chunk] = output;
+                } else {
+                    leaves.push(output);
+                }
+            }
+            self.tree.refresh_from_leaves(leaves);
+        }
+        self.dirty.clear();
+        Ok(())
+    }
+}
+
+impl<F: Read + Write + Seek> Write for PositionalWriter<F> {
+    /// Appends `buf` at the end of the file.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(self.len, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.update_tree()?;
+        self.inner.flush()
+    }
+}
+
+fn mismatch() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "data does not match the expected root")
+}
+
+// Root of a tree of at least two chunks, given only their chaining values
+fn root_from_chunk_cvs(cvs: &[[u32; 8]]) -> Hash {
+    fn subtree_cv(cvs: &[[u32; 8]]) -> [u32; 8] {
+        if cvs.len() == 1 {
+            return cvs[0];
+        }
+        let (left, right) = cvs.split_at(left_subtree_len(cvs.len()));
+        parent_cv(subtree_cv(left), subtree_cv(right), IV, 0)
+    }
+    let (left, right) = cvs.split_at(left_subtree_len(cvs.len()));
+    Hash::from_root(&parent_output(subtree_cv(left), subtree_cv(right), IV, 0))
+}
+
+#[cfg(feature = "tokio")]
+pub use self::async_writer::AsyncHashingWriter;
+
+#[cfg(feature = "tokio")]
+mod async_writer {
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+    use crate::binary_merkle_tree::{BinaryMerkleTree, ChunkSplitter};
+
+    /// The `AsyncWrite` counterpart of `HashingWriter`.
+    #[derive(Debug)]
+    pub struct AsyncHashingWriter<W> {
+        inner: W,
+        splitter: ChunkSplitter,
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncHashingWriter<W> {
+        pub fn new(inner: W) -> Self {
+            Self {
+                inner,
+                splitter: ChunkSplitter::new(),
+            }
+        }
+
+        pub fn get_ref(&self) -> &W {
+            &self.inner
+        }
+
+        /// Number of bytes successfully written so far.
+        pub fn bytes_written(&self) -> u64 {
+            self.splitter.total_len()
+        }
+
+        /// Shut down the inner writer and build the tree over everything that
+        /// was written.
+        pub async fn finish(mut self) -> io::Result<(W, BinaryMerkleTree)> {
+            self.inner.shutdown().await?;
+            let tree = BinaryMerkleTree::new_from_leaves(self.splitter.finalize());
+            Ok((self.inner, tree))
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncHashingWriter<W> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = &mut *self;
+            match Pin::new(&mut this.inner).poll_write(cx, buf) {
+                Poll::Ready(Ok(written)) => {
+                    this.splitter.update(&buf[..written]);
+                    Poll::Ready(Ok(written))
+                }
+                other => other,
+            }
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::hasher::TreeHasher;
+use crate::node_index::NodeIndex;
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    /// The real leaves, as `(leaf index, leaf)`.
+    pub fn iter_leaves(&self) -> impl Iterator<Item = (usize, &H::Node)> + '_ {
+        let offset = self.leaf_offset();
+        self.tree[offset..offset + self.num_leaves()].iter().enumerate()
+    }
+
+    /// The nodes `depth` levels below the root that cover at least one real
+    /// leaf, as `(position in the level, node)`. Depth 0 is the root; past the
+    /// leaf level the iterator is empty.
+    ///
+    /// A node whose right half covers no leaves holds the same value as its
+    /// left child, because BLAKE3 promotes it unchanged.
+    pub fn iter_level(&self, depth: u32) -> impl Iterator<Item = (usize, &H::Node)> + '_ {
+        let leaf_depth = self.leaf_offset().trailing_zeros();
+        let nodes = if depth > leaf_depth {
+            &self.tree[0..0]
+        } else {
+            let first = 1 << depth;
+            let count = self.num_leaves().div_ceil(1 << (leaf_depth - depth));
+            &self.tree[first..first + count]
+        };
+        nodes.iter().enumerate()
+    }
+
+    /// Every node covering at least one real leaf, level by level from the
+    /// root, as `(node index, node)`. Node indices use the heap layout: the
+    /// root is 1 and the children of node i are 2i and 2i + 1.
+    pub fn iter_nodes(&self) -> impl Iterator<Item = (usize, &H::Node)> + '_ {
+        let leaf_depth = self.leaf_offset().trailing_zeros();
+        (0..=leaf_depth).flat_map(move |depth| {
+            self.iter_level(depth).map(move |(position, node)| ((1 << depth) + position, node))
+        })
+    }
+
+    /// Every node covering at least one real leaf in breadth-first order, as
+    /// `(node index, node)`. Unlike `iter_nodes`, subtrees can be skipped
+    /// with `Traversal::prune`.
+    pub fn traverse_bfs(&self) -> Traversal<'_, H> {
+        Traversal::new(self, Order::BreadthFirst)
+    }
+
+    /// Every node covering at least one real leaf in depth-first pre-order
+    /// (a node, then its left subtree, then its right subtree), as
+    /// `(node index, node)`. Subtrees can be skipped with `Traversal::prune`.
+    pub fn traverse_dfs_preorder(&self) -> Traversal<'_, H> {
+        Traversal::new(self, Order::DepthFirstPreorder)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    BreadthFirst,
+    DepthFirstPreorder,
+}
+
+/// Iterator returned by `traverse_bfs` and `traverse_dfs_preorder`. Nodes
+/// whose right subtree covers no leaves have only their left child visited,
+/// and the walk stops at the leaves.
+pub struct Traversal<'a, H: TreeHasher, P = fn(NodeIndex, &<H as TreeHasher>::Node) -> bool> {
+    tree: &'a BinaryMerkleTree<H>,
+    order: Order,
+    // Queue for breadth-first, stack for pre-order
+    pending: VecDeque<NodeIndex>,
+    prune: P,
+}
+
+impl<'a, H: TreeHasher> Traversal<'a, H> {
+    fn new(tree: &'a BinaryMerkleTree<H>, order: Order) -> Self {
+        let mut pending = VecDeque::new();
+        if NodeIndex::ROOT.first_leaf(tree) < tree.num_leaves() {
+            pending.push_back(NodeIndex::ROOT);
+        }
+        Traversal {
+            tree,
+            order,
+            pending,
+            prune: |_, _| false,
+        }
+    }
+}
+
+impl<'a, H: TreeHasher, P> Traversal<'a, H, P> {
+    /// Skip the subtrees below every node for which `prune` returns true. The
+    /// node itself is still yielded; its descendants are not. A top-down diff
+    /// prunes where the two trees agree.
+    pub fn prune<Q: FnMut(NodeIndex, &H::Node) -> bool>(self, prune: Q) -> Traversal<'a, H, Q> {
+        Traversal {
+            tree: self.tree,
+            order: self.order,
+            pending: self.pending,
+            prune,
+        }
+    }
+}
+
+impl<'a, H: TreeHasher, P: FnMut(NodeIndex, &H::Node) -> bool> Iterator for Traversal<'a, H, P> {
+    type Item = (NodeIndex, &'a H::Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = match self.order {
+            Order::BreadthFirst => self.pending.pop_front()?,
+            Order::DepthFirstPreorder => self.pending.pop_back()?,
+        };
+        let node = &self.tree.tree[index.get()];
+        if !index.is_leaf(self.tree) && !(self.prune)(index, node) {
+            let right = index.right_child();
+            let has_right = right.first_leaf(self.tree) < self.tree.num_leaves();
+            match self.order {
+                Order::BreadthFirst => {
+                    self.pending.push_back(index.left_child());
+                    if has_right {
+                        self.pending.push_back(right);
+                    }
+                }
+                // The left child goes on top so it is visited first
+                Order::DepthFirstPreorder => {
+                    if has_right {
+                        self.pending.push_back(right);
+                    }
+                    self.pending.push_back(index.left_child());
+                }
+            }
+        }
+        Some((index, node))
+    }
+}
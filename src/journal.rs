@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Output};
+use crate::hasher::TreeHasher;
+
+/// The nodes overwritten since `BinaryMerkleTree::begin_journal`, oldest first.
+/// A node written several times appears once per write; restoring in reverse
+/// leaves it holding the value it had when the journal was opened. A rebuild
+/// that changes the leaf count is recorded as the whole node array it
+/// replaced, which is shared rather than copied.
+#[derive(Debug, Clone)]
+pub struct Journal<N = Output> {
+    entries: Vec<Entry<N>>,
+    // Number of entries when each nested journal was opened, innermost last
+    nested: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Entry<N> {
+    Node(usize, N),
+    Layout { nodes: Arc<Vec<N>>, leaf_count: usize },
+}
+
+impl<N> Journal<N> {
+    fn new() -> Self {
+        Journal {
+            entries: Vec::new(),
+            nested: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, node_index: usize, previous: N) {
+        self.entries.push(Entry::Node(node_index, previous));
+    }
+
+    pub(crate) fn record_layout(&mut self, nodes: Arc<Vec<N>>, leaf_count: usize) {
+        self.entries.push(Entry::Layout { nodes, leaf_count });
+    }
+
+    /// Number of node writes and rebuilds recorded so far.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of journals opened inside the outermost one and still open.
+    pub fn depth(&self) -> usize {
+        self.nested.len()
+    }
+
+    /// Whether a rebuild changed the leaf count, and with it the node layout,
+    /// since the journal was opened.
+    pub fn changes_layout(&self) -> bool {
+        self.entries.iter().any(|entry| matches!(entry, Entry::Layout { .. }))
+    }
+
+    /// Heap indices of every node written so far, each once, in increasing
+    /// order; what `BinaryMerkleTree::patch_proof` needs to refresh proofs
+    /// issued when the journal was opened. Meaningless once `changes_layout`.
+    pub fn node_indices(&self) -> Vec<usize> {
+        let mut node_indices: Vec<usize> = self
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Node(node_index, _) => Some(*node_index),
+                Entry::Layout { .. } => None,
+            })
+            .collect();
+        node_indices.sort_unstable();
+        node_indices.dedup();
+        node_indices
+    }
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    /// Start recording every node that `insert_leaf`, `bulk_insert_leaves`
+    /// and rebuilds overwrite, so the batch can later be undone with
+    /// `rollback_journal`. Opening a journal while one is already open nests
+    /// the new one inside it: committing the inner journal hands its writes to
+    /// the outer one, and rolling it back undoes only its own.
+    pub fn begin_journal(&mut self) {
+        match &mut self.journal {
+            Some(journal) => journal.nested.push(journal.entries.len()),
+            None => self.journal = Some(Journal::new()),
+        }
+    }
+
+    pub fn journal(&self) -> Option<&Journal<H::Node>> {
+        self.journal.as_ref()
+    }
+
+    /// Keep every update made since the innermost `begin_journal` and close
+    /// that journal. Returns false if no journal was open.
+    pub fn commit_journal(&mut self) -> bool {
+        let Some(journal) = &mut self.journal else {
+            return false;
+        };
+        if journal.nested.pop().is_none() {
+            self.journal = None;
+        }
+        true
+    }
+
+    /// Restore every node overwritten since the innermost `begin_journal` and
+    /// close that journal. Returns false if no journal was open.
+    pub fn rollback_journal(&mut self) -> bool {
+        let Some(mut journal) = self.journal.take() else {
+            return false;
+        };
+        let start = journal.nested.pop();
+        // The journal is out of the tree while restoring, so the restoring
+        // writes are not recorded
+        for entry in journal.entries.drain(start.unwrap_or(0)..).rev() {
+            match entry {
+                Entry::Node(node_index, previous) => self.set_node(node_index, previous),
+                Entry::Layout { nodes, leaf_count } => {
+                    self.tree = nodes;
+                    self.leaf_count = leaf_count;
+                    self.block_cvs = None;
+                }
+            }
+        }
+        if start.is_some() {
+            self.journal = Some(journal);
+        }
+        true
+    }
+
+    /// Run `update` inside a journal: keep its writes if it returns `Ok`, roll
+    /// them all back if it returns `Err`. Useful for applying a patch from an
+    /// untrusted source and only keeping it once it has been validated.
+    pub fn try_update<T, E>(
+        &mut self,
+        update: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.begin_journal();
+        let result = update(self);
+        if result.is_ok() {
+            self.commit_journal();
+        } else {
+            self.rollback_journal();
+        }
+        result
+    }
+}
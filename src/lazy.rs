@@ -0,0 +1,83 @@
+use std::collections::BTreeSet;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Output};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::hasher::{Blake3TreeHasher, TreeHasher};
+
+/// A `BinaryMerkleTree` that defers ancestor updates. `insert_leaf` only
+/// writes the leaf and marks it dirty; the next call that needs interior nodes
+/// (`root`, `tree`, ...) recomputes the ancestors of every dirty leaf in one
+/// pass, visiting shared ancestors once. Write-heavy workloads that rarely ask
+/// for the root skip a full ancestor walk per write.
+#[derive(Debug, Clone)]
+pub struct LazyMerkleTree<H: TreeHasher = Blake3TreeHasher> {
+    tree: BinaryMerkleTree<H>,
+    // Node indices of leaves written since the last flush
+    dirty: BTreeSet<usize>,
+}
+
+impl<H: TreeHasher> LazyMerkleTree<H> {
+    pub fn new(tree: BinaryMerkleTree<H>) -> Self {
+        LazyMerkleTree {
+            tree,
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.tree.num_leaves()
+    }
+
+    /// Number of leaves written since the last flush.
+    pub fn dirty_leaves(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// Replace the leaf at `leaf_index` without updating its ancestors yet.
+    /// Fails if the index is past the last leaf.
+    pub fn insert_leaf(&mut self, leaf_index: usize, leaf_output: H::Node) -> Result<(), Error> {
+        if leaf_index >= self.tree.num_leaves() {
+            return Err(Error::IndexOutOfRange {
+                index: leaf_index,
+                leaf_count: self.tree.num_leaves(),
+            });
+        }
+        let node_index = self.tree.set_leaf_only(leaf_index, leaf_output);
+        self.dirty.insert(node_index);
+        Ok(())
+    }
+
+    /// Recompute the ancestors of every dirty leaf.
+    pub fn flush(&mut self) {
+        if !self.dirty.is_empty() {
+            let dirty = std::mem::take(&mut self.dirty);
+            self.tree.update_ancestors(dirty.into_iter().collect());
+        }
+    }
+
+    /// The underlying tree, with every pending update applied.
+    pub fn tree(&mut self) -> &BinaryMerkleTree<H> {
+        self.flush();
+        &self.tree
+    }
+
+    pub fn into_tree(mut self) -> BinaryMerkleTree<H> {
+        self.flush();
+        self.tree
+    }
+
+    pub fn root_node(&mut self) -> H::Node {
+        self.tree().root_node()
+    }
+}
+
+impl LazyMerkleTree {
+    pub fn root(&mut self) -> Output {
+        self.tree().root()
+    }
+
+    pub fn root_hash(&mut self) -> Hash {
+        self.tree().root_hash()
+    }
+}
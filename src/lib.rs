@@ -1 +1,67 @@
-pub mod binary_merkle_tree; 
\ No newline at end of file
+pub mod archive;
+pub mod binary_merkle_tree;
+pub mod blocks;
+pub mod builder;
+#[cfg(feature = "serde")]
+pub mod canonical;
+pub mod chunk_store;
+mod codec;
+pub mod compose;
+pub mod compression;
+pub mod delta;
+pub mod diff;
+pub mod directory;
+pub mod disk_image;
+pub mod distributed;
+pub mod download;
+pub mod encoding;
+pub mod error;
+pub mod export;
+pub mod file;
+#[cfg(feature = "wgpu")]
+pub mod gpu;
+pub mod grouped;
+pub mod hash;
+pub mod hasher;
+pub mod http;
+pub mod incremental;
+#[cfg(feature = "blake3-interop")]
+pub mod interop;
+pub mod io;
+pub mod iter;
+pub mod journal;
+pub mod lazy;
+pub mod manifest;
+pub mod map;
+pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mmr;
+pub mod node_index;
+pub mod node_store;
+pub mod object_store;
+pub mod oplog;
+pub mod partial;
+pub mod patch;
+pub mod persistent;
+pub mod proof;
+pub mod protocol;
+pub mod provider;
+pub mod record;
+pub mod serialize;
+#[cfg(feature = "arc-swap")]
+pub mod shared;
+pub mod slice;
+pub mod snapshot;
+pub mod sparse;
+pub mod stats;
+pub mod sync;
+pub mod transition;
+pub mod validate;
+pub mod verify;
+#[cfg(feature = "sha2")]
+pub mod verity;
+#[cfg(feature = "notify")]
+pub mod watch;
+pub mod witness;
+pub mod zeros;
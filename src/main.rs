@@ -1,14 +1,351 @@
-mod binary_merkle_tree;
-
 use rand::Rng;
 use std::time::Instant;
 use std::collections::HashMap;
-use crate::binary_merkle_tree::{BinaryMerkleTree, process_input_to_chunks, ChunkState, Blake3Hasher, CHUNK_LEN, IV};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::process::ExitCode;
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, process_input_to_chunks, ChunkState, Blake3Hasher, CHUNK_LEN, IV};
+use merkle_tree::hash::Hash;
+use merkle_tree::proof::RangeProof;
+use merkle_tree::slice::decode_slice;
 
 const INPUT_SIZE: usize = 1048576; // 1MB = 2 ** 20 bytes
 const MUTATION_COUNTS: [usize; 8] = [5, 10, 50, 100, 500, 1000, 5000, 10000]; // Different numbers of mutations to test
 
-fn main() {
+const USAGE: &str = "usage:
+  merkle_tree hash [<file>...]
+  merkle_tree hash --check <sums>
+  merkle_tree tree <file> -o <out.tree>
+  merkle_tree prove <file> --range <start>..<end> -o <out.proof>
+  merkle_tree verify-proof <file> <proof> --root <hex>
+  merkle_tree slice <file> --range <start>..<end> -o <out.slice>
+  merkle_tree verify-slice <slice> --range <start>..<end> --root <hex> [-o <out>]
+  merkle_tree verify <file> --root <hex>
+  merkle_tree watch <file> [--count <n>]
+  merkle_tree diff <file_a> <file_b>
+  merkle_tree bench";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Run one subcommand. Returns Ok(false) when a verification fails.
+fn run(args: &[String]) -> Result<bool, String> {
+    let (command, rest) = args.split_first().ok_or(USAGE)?;
+    let options = Options::parse(rest)?;
+    match command.as_str() {
+        "hash" => {
+            if let Ok(sums) = options.required("--check") {
+                return check_sums(&sums);
+            }
+            // Like b3sum, no files means standard input
+            let files = if options.positional.is_empty() { vec!["-".to_string()] } else { options.positional.clone() };
+            for file in &files {
+                let root = hash_path(file).map_err(|e| format!("{}: {}", file, e))?;
+                println!("{}", sum_line(&root, file));
+            }
+            Ok(true)
+        }
+        "tree" => {
+            let file = options.positional(0)?;
+            let out = options.required("-o")?;
+            let (tree, _) = BinaryMerkleTree::from_file(file).map_err(|e| format!("{}: {}", file, e))?;
+            let writer = BufWriter::new(File::create(&out).map_err(|e| format!("{}: {}", out, e))?);
+            tree.write_to(writer).map_err(|e| format!("{}: {}", out, e))?;
+            println!("{}", tree.root_hash());
+            Ok(true)
+        }
+        "prove" => {
+            let file = options.positional(0)?;
+            let byte_range = parse_range(&options.required("--range")?)?;
+            let out = options.required("-o")?;
+            let (tree, len) = BinaryMerkleTree::from_file(file).map_err(|e| format!("{}: {}", file, e))?;
+            if byte_range.end > len {
+                return Err(format!("range {:?} is past the end of {} ({} bytes)", byte_range, file, len));
+            }
+            // Widen the byte range to the chunks that contain it
+            let chunks = (byte_range.start / CHUNK_LEN as u64) as usize
+                ..byte_range.end.div_ceil(CHUNK_LEN as u64) as usize;
+            let proof = tree.prove_range(chunks).ok_or("cannot prove an empty range")?;
+            std::fs::write(&out, proof.encode()).map_err(|e| format!("{}: {}", out, e))?;
+            Ok(true)
+        }
+        "verify-proof" => {
+            let file = options.positional(0)?;
+            let proof_path = options.positional(1)?;
+            let root = parse_root(&options.required("--root")?)?;
+            let proof_bytes = std::fs::read(proof_path).map_err(|e| format!("{}: {}", proof_path, e))?;
+            let proof = RangeProof::decode(&proof_bytes).ok_or_else(|| format!("{}: invalid proof", proof_path))?;
+            let data = read_range(file, proof.byte_range()).map_err(|e| format!("{}: {}", file, e))?;
+            report(proof.verify(&root, &data))
+        }
+        "slice" => {
+            let file = options.positional(0)?;
+            let byte_range = parse_range(&options.required("--range")?)?;
+            let out = options.required("-o")?;
+            let input = std::fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+            if byte_range.end > input.len() as u64 {
+                return Err(format!("range {:?} is past the end of {} ({} bytes)", byte_range, file, input.len()));
+            }
+            let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+            let slice = tree.extract_slice(&input, byte_range).ok_or("tree does not match the file")?;
+            std::fs::write(&out, slice).map_err(|e| format!("{}: {}", out, e))?;
+            Ok(true)
+        }
+        "verify-slice" => {
+            // Needs nothing but the slice and the root, not the original file
+            let slice_path = options.positional(0)?;
+            let byte_range = parse_range(&options.required("--range")?)?;
+            let root = parse_root(&options.required("--root")?)?;
+            let slice = std::fs::read(slice_path).map_err(|e| format!("{}: {}", slice_path, e))?;
+            let data = match decode_slice(&root, &slice, byte_range) {
+                Ok(data) => data,
+                Err(_) => return report(false),
+            };
+            if let Ok(out) = options.required("-o") {
+                std::fs::write(&out, data).map_err(|e| format!("{}: {}", out, e))?;
+            }
+            report(true)
+        }
+        "verify" => {
+            let file = options.positional(0)?;
+            let root = parse_root(&options.required("--root")?)?;
+            let (tree, _) = BinaryMerkleTree::from_file(file).map_err(|e| format!("{}: {}", file, e))?;
+            report(tree.root_hash().ct_eq(&root))
+        }
+        "watch" => {
+            let file = options.positional(0)?;
+            let count = match options.required("--count") {
+                Ok(count) => Some(count.parse::<usize>().map_err(|_| format!("bad count {:?}", count))?),
+                Err(_) => None,
+            };
+            watch(file, count)
+        }
+        "diff" => {
+            let (file_a, file_b) = (options.positional(0)?, options.positional(1)?);
+            let (tree_a, len_a) = BinaryMerkleTree::from_file(file_a).map_err(|e| format!("{}: {}", file_a, e))?;
+            let (tree_b, len_b) = BinaryMerkleTree::from_file(file_b).map_err(|e| format!("{}: {}", file_b, e))?;
+            let differing = tree_a.diff(&tree_b);
+            for chunks in chunk_runs(&differing) {
+                println!("{}", describe_chunks(&chunks, len_a.max(len_b)));
+            }
+            // Like cmp and diff, differing files are a failed comparison
+            if differing.is_empty() {
+                println!("identical");
+            } else {
+                println!("{} chunks differ", differing.len());
+            }
+            Ok(differing.is_empty())
+        }
+        "bench" => {
+            bench();
+            Ok(true)
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Root of the file at `path`, or of standard input for `-`.
+fn hash_path(path: &str) -> std::io::Result<Hash> {
+    let (tree, _) = if path == "-" {
+        BinaryMerkleTree::from_reader(std::io::stdin().lock())?
+    } else {
+        BinaryMerkleTree::from_file(path)?
+    };
+    Ok(tree.root_hash())
+}
+
+/// A b3sum line, `<hex>  <path>`. As in b3sum, a path containing a backslash
+/// or newline is escaped and the line marked with a leading backslash.
+fn sum_line(root: &Hash, path: &str) -> String {
+    let (marker, path) = escape_path(path);
+    format!("{}{}  {}", marker, root, path)
+}
+
+fn escape_path(path: &str) -> (&'static str, String) {
+    if path.contains('\\') || path.contains('\n') {
+        ("\\", path.replace('\\', "\\\\").replace('\n', "\\n"))
+    } else {
+        ("", path.to_string())
+    }
+}
+
+fn parse_sum_line(line: &str) -> Option<(Hash, String)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (hex, path) = line.split_once("  ")?;
+    let root = hex.parse().ok()?;
+    if !escaped {
+        return Some((root, path.to_string()));
+    }
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            'n' => unescaped.push('\n'),
+            _ => return None,
+        }
+    }
+    Some((root, unescaped))
+}
+
+/// Verify every line of a b3sum-format list, printing `<path>: OK` or
+/// `<path>: FAILED` for each. Returns Ok(false) if any file failed.
+fn check_sums(sums_path: &str) -> Result<bool, String> {
+    let sums = if sums_path == "-" {
+        let mut sums = String::new();
+        std::io::stdin().read_to_string(&mut sums).map(|_| sums)
+    } else {
+        std::fs::read_to_string(sums_path)
+    }
+    .map_err(|e| format!("{}: {}", sums_path, e))?;
+
+    let mut all_passed = true;
+    for (index, line) in sums.lines().enumerate().filter(|(_, line)| !line.is_empty()) {
+        let (expected, path) =
+            parse_sum_line(line).ok_or_else(|| format!("{}:{}: invalid checksum line", sums_path, index + 1))?;
+        let (marker, shown) = escape_path(&path);
+        match hash_path(&path) {
+            Ok(root) if root.ct_eq(&expected) => println!("{}{}: OK", marker, shown),
+            Ok(_) => {
+                println!("{}{}: FAILED", marker, shown);
+                all_passed = false;
+            }
+            Err(e) => {
+                println!("{}{}: FAILED ({})", marker, shown, e);
+                all_passed = false;
+            }
+        }
+    }
+    Ok(all_passed)
+}
+
+/// Print the root of `file`, then a new root and the changed chunk and byte
+/// ranges every time it changes, stopping after `count` changes if given.
+#[cfg(feature = "notify")]
+fn watch(file: &str, count: Option<usize>) -> Result<bool, String> {
+    use merkle_tree::hash::Hash;
+    use merkle_tree::watch::WatchedMerkleFile;
+    use std::io::Write;
+
+    let watched = WatchedMerkleFile::watch(file).map_err(|e| format!("{}: {}", file, e))?;
+    println!("watching {}: {}", file, Hash::from_root(&watched.root()));
+    let _ = std::io::stdout().flush();
+    for update in watched.updates().iter().take(count.unwrap_or(usize::MAX)) {
+        let len = std::fs::metadata(file).map(|m| m.len()).unwrap_or(u64::MAX);
+        let ranges: Vec<String> = chunk_runs(&update.changed_chunks)
+            .iter()
+            .map(|chunks| describe_chunks(chunks, len))
+            .collect();
+        println!("{} changed {}", Hash::from_root(&update.root), ranges.join(", "));
+        let _ = std::io::stdout().flush();
+    }
+    Ok(true)
+}
+
+#[cfg(not(feature = "notify"))]
+fn watch(_file: &str, _count: Option<usize>) -> Result<bool, String> {
+    Err("watch needs the notify feature".to_string())
+}
+
+/// A run of chunks and the bytes it covers in an input of `len` bytes.
+fn describe_chunks(chunks: &Range<usize>, len: u64) -> String {
+    let bytes = (chunks.start * CHUNK_LEN) as u64..((chunks.end * CHUNK_LEN) as u64).min(len);
+    format!("chunks {}..{} (bytes {}..{})", chunks.start, chunks.end, bytes.start, bytes.end)
+}
+
+/// Group sorted chunk indices into runs of consecutive indices.
+fn chunk_runs(indices: &[usize]) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for &index in indices {
+        match runs.last_mut() {
+            Some(run) if run.end == index => run.end += 1,
+            _ => runs.push(index..index + 1),
+        }
+    }
+    runs
+}
+
+fn report(passed: bool) -> Result<bool, String> {
+    println!("{}", if passed { "OK" } else { "FAILED" });
+    Ok(passed)
+}
+
+/// Positional arguments plus `-o value` / `--flag value` pairs.
+struct Options {
+    positional: Vec<String>,
+    named: HashMap<String, String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut named = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                // Everything after `--` is positional, even if it looks like a flag
+                positional.extend(args.by_ref().cloned());
+            } else if arg.starts_with('-') && arg != "-" {
+                let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+                named.insert(arg.clone(), value.clone());
+            } else {
+                // A bare `-` is a path meaning stdin
+                positional.push(arg.clone());
+            }
+        }
+        Ok(Self { positional, named })
+    }
+
+    fn positional(&self, index: usize) -> Result<&str, String> {
+        self.positional.get(index).map(String::as_str).ok_or_else(|| USAGE.to_string())
+    }
+
+    fn required(&self, name: &str) -> Result<String, String> {
+        self.named.get(name).cloned().ok_or_else(|| format!("missing {}\n{}", name, USAGE))
+    }
+}
+
+fn parse_range(range: &str) -> Result<Range<u64>, String> {
+    let (start, end) = range.split_once("..").ok_or("range must look like <start>..<end>")?;
+    let start: u64 = start.parse().map_err(|_| format!("bad range start {:?}", start))?;
+    let end: u64 = end.parse().map_err(|_| format!("bad range end {:?}", end))?;
+    if start >= end {
+        return Err("range must not be empty".to_string());
+    }
+    Ok(start..end)
+}
+
+fn parse_root(hex: &str) -> Result<Hash, String> {
+    hex.parse().map_err(|e| format!("bad root {:?}: {}", hex, e))
+}
+
+/// Read the bytes of `range` from `path`, stopping early at EOF.
+fn read_range(path: &str, range: Range<u64>) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    let mut data = Vec::new();
+    file.take(range.end - range.start).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn bench() {
     println!("Benchmarking Merkle Tree vs BLAKE3 with increasing mutations ({} bytes input):", INPUT_SIZE);
     println!("----------------------------------------------------------------");
     println!("| Mutations | Merkle Time | BLAKE3 Time | Speed Ratio |");
@@ -43,7 +380,7 @@ fn main() {
             
             let chunk_index = pos / CHUNK_LEN;
             chunk_updates.entry(chunk_index)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(pos);
         }
         
@@ -68,7 +405,7 @@ fn main() {
         
         // Time the Merkle tree bulk update
         let merkle_start = Instant::now();
-        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter());
+        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter()).expect("chunk indices are sorted and in range");
         let mutated_root = tree.root().chaining_value();
         let merkle_duration = merkle_start.elapsed();
         
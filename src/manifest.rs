@@ -0,0 +1,230 @@
+//! A text manifest listing relative paths, sizes, per-file roots, and per-chunk
+//! chaining values, so a directory can be re-verified later and mismatches
+//! narrowed down to the chunks that differ.
+//!
+//! ```text
+//! blake3-merkle-manifest v1
+//! file <root hex> <len> <path>
+//! chunks <chunk 0 cv hex><chunk 1 cv hex>...
+//! ```
+//!
+//! Each `file` line is followed by exactly one `chunks` line. The path runs to
+//! the end of the line, so it may contain spaces but not newlines.
+//!
+//! With the `compression` feature a manifest can also be written compressed:
+//! the bytes `B3MZ`, a compression flag byte, then the text above compressed
+//! with zstd. `Manifest::read` accepts both forms.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::compression::decoder;
+use crate::directory::DirectoryHasher;
+use crate::hash::Hash;
+
+const HEADER: &str = "blake3-merkle-manifest v1";
+const COMPRESSED_MAGIC: &[u8; 4] = b"B3MZ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path relative to the manifest's base directory, using `/` separators.
+    pub path: String,
+    pub len: u64,
+    pub root: Hash,
+    /// Chaining value of every chunk of the file, in order.
+    pub chunk_cvs: Vec<Hash>,
+}
+
+impl ManifestEntry {
+    pub fn from_tree(path: String, len: u64, tree: &BinaryMerkleTree) -> Self {
+        let chunk_cvs = (0..tree.num_leaves())
+            .map(|i| Hash::from_chaining_value(tree.leaf(i).chaining_value()))
+            .collect();
+        Self {
+            path,
+            len,
+            root: tree.root_hash(),
+            chunk_cvs,
+        }
+    }
+}
+
+/// Result of re-verifying one manifest entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Missing,
+    /// The file's root differs. `changed_chunks` lists chunk indices whose
+    /// chaining values differ, including chunks added or removed.
+    Modified { len: u64, changed_chunks: Vec<usize> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCheck {
+    pub path: String,
+    pub status: CheckStatus,
+}
+
+impl FileCheck {
+    pub fn passed(&self) -> bool {
+        self.status == CheckStatus::Ok
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Hash every file below `dir` into a manifest sorted by path.
+    pub fn from_directory<P: AsRef<Path>>(dir: P) -> io::Result<Manifest> {
+        let mut entries = Vec::new();
+        DirectoryHasher::new().visit_files(dir.as_ref(), &mut |entry, tree| {
+            entries.push(ManifestEntry::from_tree(entry.path, entry.len, &tree));
+        })?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest { entries })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for entry in &self.entries {
+            if entry.path.contains('\n') || entry.path.contains('\r') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("manifest paths cannot contain newlines: {:?}", entry.path),
+                ));
+            }
+            writeln!(writer, "file {} {} {}", entry.root, entry.len, entry.path)?;
+            write!(writer, "chunks ")?;
+            for cv in &entry.chunk_cvs {
+                write!(writer, "{}", cv)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Like `write`, compressing the text with zstd at `level`.
+    #[cfg(feature = "compression")]
+    pub fn write_compressed<W: Write>(&self, mut writer: W, level: i32) -> io::Result<()> {
+        let mut text = Vec::new();
+        self.write(&mut text)?;
+        writer.write_all(COMPRESSED_MAGIC)?;
+        writer.write_all(&[crate::compression::COMPRESSION_ZSTD])?;
+        writer.write_all(&crate::compression::compress(&text, level)?)?;
+        writer.flush()
+    }
+
+    /// Read a manifest written by `write` or `write_compressed`.
+    pub fn read<R: Read>(reader: R) -> io::Result<Manifest> {
+        let mut reader = BufReader::new(reader);
+        if !reader.fill_buf()?.starts_with(COMPRESSED_MAGIC) {
+            return Self::read_text(reader);
+        }
+        reader.consume(COMPRESSED_MAGIC.len());
+        let mut flag = [0u8; 1];
+        reader.read_exact(&mut flag)?;
+        Self::read_text(decoder(reader, flag[0])?)
+    }
+
+    fn read_text<R: Read>(reader: R) -> io::Result<Manifest> {
+        fn invalid(line_number: usize, message: &str) -> io::Error {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("manifest line {}: {}", line_number, message),
+            )
+        }
+
+        let mut lines = BufReader::new(reader).lines().enumerate();
+        let header = lines.next().map(|(_, line)| line).transpose()?;
+        if header.as_deref() != Some(HEADER) {
+            return Err(invalid(1, "missing manifest header"));
+        }
+
+        let mut entries = Vec::new();
+        while let Some((index, line)) = lines.next() {
+            let line = line?;
+            let line_number = index + 1;
+            let mut fields = line
+                .strip_prefix("file ")
+                .ok_or_else(|| invalid(line_number, "expected a file line"))?
+                .splitn(3, ' ');
+            let root = fields
+                .next()
+                .and_then(|hex| hex.parse().ok())
+                .ok_or_else(|| invalid(line_number, "bad root hash"))?;
+            let len = fields
+                .next()
+                .and_then(|len| len.parse().ok())
+                .ok_or_else(|| invalid(line_number, "bad length"))?;
+            let path = fields
+                .next()
+                .filter(|path| !path.is_empty())
+                .ok_or_else(|| invalid(line_number, "missing path"))?
+                .to_string();
+
+            let (index, chunks_line) = lines
+                .next()
+                .ok_or_else(|| invalid(line_number + 1, "missing chunks line"))?;
+            let chunks_line = chunks_line?;
+            let hex = chunks_line
+                .strip_prefix("chunks ")
+                .ok_or_else(|| invalid(index + 1, "expected a chunks line"))?;
+            if !hex.len().is_multiple_of(64) || !hex.is_ascii() {
+                return Err(invalid(index + 1, "bad chunk chaining values"));
+            }
+            let chunk_cvs = (0..hex.len() / 64)
+                .map(|i| hex[i * 64..(i + 1) * 64].parse())
+                .collect::<Result<Vec<Hash>, _>>()
+                .map_err(|_| invalid(index + 1, "bad chunk chaining values"))?;
+
+            entries.push(ManifestEntry {
+                path,
+                len,
+                root,
+                chunk_cvs,
+            });
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Re-hash every listed file relative to `base_dir` and report which ones
+    /// still match. Files that were added to the directory are not reported.
+    pub fn check<P: AsRef<Path>>(&self, base_dir: P) -> io::Result<Vec<FileCheck>> {
+        let base_dir = base_dir.as_ref();
+        self.entries
+            .iter()
+            .map(|entry| {
+                let status = match File::open(base_dir.join(&entry.path)) {
+                    Ok(file) => check_file(entry, file)?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => CheckStatus::Missing,
+                    Err(e) => return Err(e),
+                };
+                Ok(FileCheck {
+                    path: entry.path.clone(),
+                    status,
+                })
+            })
+            .collect()
+    }
+}
+
+fn check_file(entry: &ManifestEntry, file: File) -> io::Result<CheckStatus> {
+    let (tree, len) = BinaryMerkleTree::from_reader(file)?;
+    if len == entry.len && tree.root_hash() == entry.root {
+        return Ok(CheckStatus::Ok);
+    }
+    let current = ManifestEntry::from_tree(entry.path.clone(), len, &tree);
+    let longest = current.chunk_cvs.len().max(entry.chunk_cvs.len());
+    let changed_chunks = (0..longest)
+        .filter(|&i| current.chunk_cvs.get(i) != entry.chunk_cvs.get(i))
+        .collect();
+    Ok(CheckStatus::Modified {
+        len,
+        changed_chunks,
+    })
+}
@@ -0,0 +1,136 @@
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::hash::Hash;
+use crate::proof::RangeProof;
+
+/// An authenticated dictionary: a sorted map whose entries are the records of
+/// a record tree (see `record_leaf`), one leaf per entry in key order. The
+/// root commits to the whole map, and `prove` shows that a key maps to a value.
+///
+/// Updating the value of an existing key rehashes one path. Inserting a new key
+/// or removing one shifts the positions of every later entry, so the tree is
+/// rebuilt.
+#[derive(Debug, Clone)]
+pub struct MerkleMap<K, V> {
+    entries: BTreeMap<K, V>,
+    tree: BinaryMerkleTree,
+}
+
+/// Proof that a key maps to a value in a `MerkleMap`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapProof {
+    /// Proof for the entry's record, whose position is its rank in key order.
+    pub proof: RangeProof,
+}
+
+impl<K: Ord + AsRef<[u8]>, V: AsRef<[u8]>> Default for MerkleMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + AsRef<[u8]>, V: AsRef<[u8]>> MerkleMap<K, V> {
+    pub fn new() -> Self {
+        MerkleMap {
+            entries: BTreeMap::new(),
+            tree: BinaryMerkleTree::from_records(std::iter::empty::<&[u8]>()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.tree.root_hash()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.get(key)
+    }
+
+    /// Entries in key order, the order of the leaves.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter()
+    }
+
+    /// Set the value of `key`, returning the previous one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(index) = self.position(&key) {
+            let record = encode_entry(key.as_ref(), value.as_ref());
+            self.tree
+                .update_record(index, &record)
+                .expect("every entry has a leaf");
+            return self.entries.insert(key, value);
+        }
+        self.entries.insert(key, value);
+        self.rebuild();
+        None
+    }
+
+    /// Remove `key`, returning its value if it was present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let previous = self.entries.remove(key)?;
+        self.rebuild();
+        Some(previous)
+    }
+
+    /// Prove the current value of `key`, or None if it is absent.
+    pub fn prove<Q>(&self, key: &Q) -> Option<MapProof>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.position(key)?;
+        let proof = self.tree.prove_range(index..index + 1)?;
+        Some(MapProof { proof })
+    }
+
+    // Rank of `key` among the entries, if present
+    fn position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let before = (Bound::Unbounded, Bound::Excluded(key));
+        self.entries.contains_key(key).then(|| self.entries.range::<Q, _>(before).count())
+    }
+
+    fn rebuild(&mut self) {
+        self.tree = BinaryMerkleTree::from_records(
+            self.entries.iter().map(|(key, value)| encode_entry(key.as_ref(), value.as_ref())),
+        );
+    }
+}
+
+impl MapProof {
+    /// Check that `key` maps to `value` in the map with root `root`.
+    pub fn verify(&self, root: &Hash, key: &[u8], value: &[u8]) -> bool {
+        self.proof.chunks.len() == 1 && self.proof.verify_records(root, &[encode_entry(key, value)])
+    }
+}
+
+/// The record stored for an entry: the key length as u64 LE, the key, then the
+/// value. The length prefix keeps the split between key and value unambiguous.
+pub fn encode_entry(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(8 + key.len() + value.len());
+    record.extend_from_slice(&(key.len() as u64).to_le_bytes());
+    record.extend_from_slice(key);
+    record.extend_from_slice(value);
+    record
+}
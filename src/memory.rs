@@ -0,0 +1,88 @@
+use std::cmp::min;
+
+use crate::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, Output, CHUNK_LEN};
+use crate::hash::Hash;
+
+/// Keeps a tree over a fixed-size memory region, such as guest RAM, up to date
+/// from page-granularity dirty tracking. `refresh` takes the dirty bitmap for
+/// the pages written since the last call and rehashes only the chunks that
+/// overlap those pages, so the cost follows the number of touched pages rather
+/// than the size of the region.
+#[derive(Debug, Clone)]
+pub struct MemoryRegionHasher {
+    tree: BinaryMerkleTree,
+    page_size: usize,
+    region_len: usize,
+}
+
+impl MemoryRegionHasher {
+    /// Hash all of `region`. `page_size` is the granularity of the dirty
+    /// bitmaps passed to `refresh`, and need not be a multiple of CHUNK_LEN.
+    pub fn new(region: &[u8], page_size: usize) -> Self {
+        assert!(page_size > 0, "page size must be non-zero");
+        MemoryRegionHasher {
+            tree: BinaryMerkleTree::new_from_leaves(process_input_to_chunks(region)),
+            page_size,
+            region_len: region.len(),
+        }
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.region_len.div_ceil(self.page_size)
+    }
+
+    pub fn tree(&self) -> &BinaryMerkleTree {
+        &self.tree
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.tree.root_hash()
+    }
+
+    /// Rehash the chunks of `region` overlapping the pages set in
+    /// `dirty_bitmap`, where bit `i % 64` of word `i / 64` marks page `i`, and
+    /// return the indices of the chunks whose outputs changed. Bits past the
+    /// last page are ignored.
+    ///
+    /// Panics if `region` is not the same length as the one the hasher was
+    /// created with.
+    pub fn refresh(&mut self, region: &[u8], dirty_bitmap: &[u64]) -> Vec<usize> {
+        assert_eq!(region.len(), self.region_len, "memory region changed size");
+        let chunk_count = self.tree.num_leaves();
+        let mut dirty_chunks: Vec<usize> = Vec::new();
+        for page in dirty_pages(dirty_bitmap).take_while(|&page| page < self.page_count()) {
+            let first = page * self.page_size / CHUNK_LEN;
+            let end = min(((page + 1) * self.page_size).div_ceil(CHUNK_LEN), chunk_count);
+            // Pages are visited in order, so a page sharing a chunk with the
+            // previous one can only overlap the last chunk already collected
+            let first = dirty_chunks.last().map_or(first, |&last| first.max(last + 1));
+            dirty_chunks.extend(first..end);
+        }
+
+        let updates: Vec<(usize, Output)> = dirty_chunks
+            .into_iter()
+            .map(|i| {
+                let end = min((i + 1) * CHUNK_LEN, region.len());
+                (i, chunk_output(i as u64, &region[i * CHUNK_LEN..end]))
+            })
+            .filter(|(i, output)| self.tree.leaf(*i) != output)
+            .collect();
+        self.tree
+            .bulk_insert_leaves(updates.iter().map(|(i, _)| *i), updates.iter().map(|(_, output)| *output))
+            .expect("dirty chunks are produced in sorted order");
+        updates.into_iter().map(|(i, _)| i).collect()
+    }
+}
+
+// Indices of the set bits of `bitmap`, lowest first
+fn dirty_pages(bitmap: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    bitmap.iter().enumerate().flat_map(|(word_index, &word)| {
+        (0..u64::BITS as usize)
+            .filter(move |bit| word >> bit & 1 == 1)
+            .map(move |bit| word_index * u64::BITS as usize + bit)
+    })
+}
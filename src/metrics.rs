@@ -0,0 +1,91 @@
+//! Work counters kept by every `BinaryMerkleTree` when the `metrics` feature
+//! is enabled, for checking that updates really cost O(k log n) and for
+//! feeding an application's own metrics system.
+//!
+//! Compressions are counted per thread as they happen; a tree attributes to
+//! itself the ones made while it hashes parents. Hashes of the root with
+//! `root` or `root_hash` take `&self` and are not attributed to any tree.
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::hasher::TreeHasher;
+
+thread_local! {
+    static THREAD_COMPRESSIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+pub(crate) fn count_compressions(count: u64) {
+    THREAD_COMPRESSIONS.with(|compressions| compressions.set(compressions.get() + count));
+}
+
+/// Calls of the BLAKE3 compression function made on the current thread so
+/// far, by trees or by any of the chunk hashing helpers.
+pub fn thread_compressions() -> u64 {
+    THREAD_COMPRESSIONS.with(Cell::get)
+}
+
+/// A snapshot of a tree's counters, from `BinaryMerkleTree::metrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Compressions made while hashing parent nodes, which for BLAKE3 trees
+    /// is one per child chaining value.
+    pub compressions: u64,
+    /// Leaf writes, counting a leaf once per write.
+    pub leaves_updated: u64,
+    /// Ancestors rewritten after leaf writes, including ones promoted
+    /// unchanged from a lone left child. Building the tree is not counted.
+    pub ancestors_recomputed: u64,
+    /// Input bytes hashed into chunks by the tree's own byte-level helpers,
+    /// such as `update_bytes` and `refresh_from_bytes`.
+    pub bytes_hashed: u64,
+}
+
+/// Called with a tree's counters after each update.
+pub type MetricsCallback = Arc<dyn Fn(&Metrics) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub(crate) struct MetricsState {
+    pub(crate) totals: Metrics,
+    callback: Option<MetricsCallback>,
+}
+
+impl fmt::Debug for MetricsState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsState")
+            .field("totals", &self.totals)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+impl MetricsState {
+    pub(crate) fn report(&self) {
+        if let Some(callback) = &self.callback {
+            callback(&self.totals);
+        }
+    }
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.totals
+    }
+
+    /// Zero every counter. A callback stays installed.
+    pub fn reset_metrics(&mut self) {
+        self.metrics.totals = Metrics::default();
+    }
+
+    /// Call `callback` with the counters after every leaf update, bulk update,
+    /// flush of a lazy tree, and rebuild. Replaces any earlier callback.
+    pub fn set_metrics_callback(&mut self, callback: impl Fn(&Metrics) + Send + Sync + 'static) {
+        self.metrics.callback = Some(Arc::new(callback));
+    }
+
+    pub fn clear_metrics_callback(&mut self) {
+        self.metrics.callback = None;
+    }
+}
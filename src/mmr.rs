@@ -0,0 +1,194 @@
+use crate::binary_merkle_tree::{chunk_output, parent_cv, Blake3Hasher, IV, OUT_LEN};
+use crate::hash::Hash;
+
+// Domain separation for log entries, so an entry can never be confused with a
+// file chunk or a directory entry
+const ENTRY_CONTEXT: &str = "blake3_merkle_tree 2024 mmr entry v1";
+
+/// A Merkle Mountain Range for append-only logs. Entries are only ever
+/// appended, so the tree is a list of perfect binary trees ("peaks") whose
+/// sizes are the set bits of the entry count, largest first. Appending merges
+/// equal-sized peaks in O(1) amortized time, and every node ever created is
+/// kept, so proofs can be made against the current root or any earlier one.
+#[derive(Debug, Clone, Default)]
+pub struct MmrTree {
+    // levels[h][k] covers entries k * 2^h .. (k + 1) * 2^h
+    levels: Vec<Vec<[u32; 8]>>,
+}
+
+/// Proof that an entry is part of the log at a given size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmrProof {
+    pub leaf_index: u64,
+    /// Number of entries in the log the proof was made against.
+    pub leaf_count: u64,
+    /// Siblings on the path from the leaf up to its peak, bottom first.
+    pub siblings: Vec<[u32; 8]>,
+    /// Every other peak, largest first.
+    pub peaks: Vec<[u32; 8]>,
+}
+
+impl MmrTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn num_leaves(&self) -> u64 {
+        self.levels.first().map_or(0, |leaves| leaves.len() as u64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_leaves() == 0
+    }
+
+    /// The leaf committed to for `entry` at position `index`: the entry is
+    /// hashed with a dedicated derive-key context, then placed in a chunk whose
+    /// counter is its position.
+    pub fn entry_leaf(index: u64, entry: &[u8]) -> [u32; 8] {
+        let mut hasher = Blake3Hasher::new_derive_key(ENTRY_CONTEXT);
+        hasher.update(entry);
+        let mut digest = [0; OUT_LEN];
+        hasher.finalize(&mut digest);
+        chunk_output(index, &digest).chaining_value()
+    }
+
+    /// Append an entry and return its index.
+    pub fn append(&mut self, entry: &[u8]) -> u64 {
+        let index = self.num_leaves();
+        self.append_leaf(Self::entry_leaf(index, entry));
+        index
+    }
+
+    /// Append an already hashed leaf and return its index.
+    pub fn append_leaf(&mut self, leaf: [u32; 8]) -> u64 {
+        let index = self.num_leaves();
+        let mut height = 0;
+        let mut node = leaf;
+        loop {
+            if self.levels.len() == height {
+                self.levels.push(Vec::new());
+            }
+            let level = &mut self.levels[height];
+            level.push(node);
+            // An even count means the new node completed a pair, which merges
+            // into a peak one level up
+            if level.len() % 2 == 1 {
+                break;
+            }
+            node = parent_cv(level[level.len() - 2], level[level.len() - 1], IV, 0);
+            height += 1;
+        }
+        index
+    }
+
+    /// Peaks of the log as it was with `leaf_count` entries, largest first.
+    fn peaks_at(&self, leaf_count: u64) -> Vec<(u32, [u32; 8])> {
+        (0..u64::BITS)
+            .rev()
+            .filter(|height| leaf_count >> height & 1 == 1)
+            .map(|height| {
+                let position = (leaf_count >> height) as usize - 1;
+                (height, self.levels[height as usize][position])
+            })
+            .collect()
+    }
+
+    pub fn peaks(&self) -> Vec<[u32; 8]> {
+        self.peaks_at(self.num_leaves()).into_iter().map(|(_, peak)| peak).collect()
+    }
+
+    /// Root of the whole log, or None if it is empty.
+    pub fn root_hash(&self) -> Option<Hash> {
+        self.root_hash_at(self.num_leaves())
+    }
+
+    /// Root of the log as it was with `leaf_count` entries. Returns None for an
+    /// empty log or a count larger than the current one.
+    pub fn root_hash_at(&self, leaf_count: u64) -> Option<Hash> {
+        if leaf_count > self.num_leaves() {
+            return None;
+        }
+        let peaks: Vec<_> = self.peaks_at(leaf_count).into_iter().map(|(_, peak)| peak).collect();
+        bag_peaks(&peaks)
+    }
+
+    /// Prove that entry `leaf_index` is in the current log.
+    pub fn prove(&self, leaf_index: u64) -> Option<MmrProof> {
+        self.prove_at(leaf_index, self.num_leaves())
+    }
+
+    /// Prove that entry `leaf_index` is in the log as it was with `leaf_count`
+    /// entries, so the proof checks against `root_hash_at(leaf_count)`.
+    pub fn prove_at(&self, leaf_index: u64, leaf_count: u64) -> Option<MmrProof> {
+        if leaf_index >= leaf_count || leaf_count > self.num_leaves() {
+            return None;
+        }
+        let (peak_height, peak_position, _) = locate_peak(leaf_index, leaf_count);
+        let siblings = (0..peak_height)
+            .map(|height| self.levels[height as usize][(leaf_index >> height ^ 1) as usize])
+            .collect();
+        let mut peaks: Vec<_> = self.peaks_at(leaf_count).into_iter().map(|(_, peak)| peak).collect();
+        peaks.remove(peak_position);
+        Some(MmrProof {
+            leaf_index,
+            leaf_count,
+            siblings,
+            peaks,
+        })
+    }
+}
+
+impl MmrProof {
+    /// Check that `entry` is at `leaf_index` in a log of `leaf_count` entries
+    /// whose root is `root`.
+    pub fn verify(&self, root: &Hash, entry: &[u8]) -> bool {
+        self.verify_leaf(root, MmrTree::entry_leaf(self.leaf_index, entry))
+    }
+
+    pub fn verify_leaf(&self, root: &Hash, leaf: [u32; 8]) -> bool {
+        if self.leaf_index >= self.leaf_count {
+            return false;
+        }
+        let (peak_height, peak_position, peak_count) = locate_peak(self.leaf_index, self.leaf_count);
+        if self.siblings.len() != peak_height as usize || self.peaks.len() + 1 != peak_count {
+            return false;
+        }
+        let mut node = leaf;
+        for (height, sibling) in self.siblings.iter().enumerate() {
+            node = if self.leaf_index >> height & 1 == 0 {
+                parent_cv(node, *sibling, IV, 0)
+            } else {
+                parent_cv(*sibling, node, IV, 0)
+            };
+        }
+        let mut peaks = self.peaks.clone();
+        peaks.insert(peak_position, node);
+        bag_peaks(&peaks).is_some_and(|bagged| bagged.ct_eq(root))
+    }
+}
+
+// The peak containing `leaf_index`: its height, its position among the peaks,
+// and the total number of peaks
+fn locate_peak(leaf_index: u64, leaf_count: u64) -> (u32, usize, usize) {
+    let mut first_leaf = 0;
+    let mut found = None;
+    let mut peak_count = 0;
+    for height in (0..u64::BITS).rev().filter(|height| leaf_count >> height & 1 == 1) {
+        let size = 1 << height;
+        if found.is_none() && leaf_index < first_leaf + size {
+            found = Some((height, peak_count));
+        }
+        first_leaf += size;
+        peak_count += 1;
+    }
+    let (height, position) = found.expect("leaf_index < leaf_count");
+    (height, position, peak_count)
+}
+
+// Fold the peaks from right to left into a single chaining value, so the root
+// commits to every peak and to their order
+fn bag_peaks(peaks: &[[u32; 8]]) -> Option<Hash> {
+    let (last, rest) = peaks.split_last()?;
+    let bagged = rest.iter().rev().fold(*last, |right, left| parent_cv(*left, right, IV, 0));
+    Some(Hash::from_chaining_value(bagged))
+}
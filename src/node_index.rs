@@ -0,0 +1,77 @@
+use crate::binary_merkle_tree::{first_leaf_below, BinaryMerkleTree};
+use crate::hasher::TreeHasher;
+
+/// Position of a node in the heap layout of `BinaryMerkleTree`: the root is
+/// 1 and the children of node i are 2i and 2i + 1, so a node's level is the
+/// position of its highest set bit and its parent drops the lowest bit.
+/// Leaves follow all interior nodes, at `tree.num_leaves().next_power_of_two()`
+/// onwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeIndex(pub(crate) usize);
+
+impl NodeIndex {
+    pub const ROOT: NodeIndex = NodeIndex(1);
+
+    /// The node at heap index `index`, or None for 0, which is not a node.
+    pub fn new(index: usize) -> Option<Self> {
+        (index >= 1).then_some(NodeIndex(index))
+    }
+
+    /// The node holding leaf `leaf_index` of `tree`, or None past the last
+    /// leaf.
+    pub fn leaf<H: TreeHasher>(tree: &BinaryMerkleTree<H>, leaf_index: usize) -> Option<Self> {
+        (leaf_index < tree.num_leaves()).then(|| NodeIndex(tree.leaf_offset() + leaf_index))
+    }
+
+    /// The heap index, as taken by `get_node` and `node_cv`.
+    pub fn get(self) -> usize {
+        self.0
+    }
+
+    /// None for the root.
+    pub fn parent(self) -> Option<Self> {
+        (self.0 > 1).then_some(NodeIndex(self.0 >> 1))
+    }
+
+    /// The other child of this node's parent, or None for the root.
+    pub fn sibling(self) -> Option<Self> {
+        (self.0 > 1).then_some(NodeIndex(self.0 ^ 1))
+    }
+
+    pub fn left_child(self) -> Self {
+        NodeIndex(self.0 << 1)
+    }
+
+    pub fn right_child(self) -> Self {
+        NodeIndex(self.0 << 1 | 1)
+    }
+
+    /// Whether this node is the left child of its parent. The root is not.
+    pub fn is_left_child(self) -> bool {
+        self.0 > 1 && self.0 & 1 == 0
+    }
+
+    /// Levels below the root: 0 for the root, and the tree's height for its
+    /// leaves.
+    pub fn level(self) -> u32 {
+        self.0.ilog2()
+    }
+
+    /// Whether this node is on the leaf level of `tree`. Slots past the last
+    /// leaf are on that level too, but hold no leaf.
+    pub fn is_leaf<H: TreeHasher>(self, tree: &BinaryMerkleTree<H>) -> bool {
+        self.0 >= tree.leaf_offset()
+    }
+
+    /// Index of the leftmost leaf below this node in `tree`. The node covers
+    /// real leaves only if this is below `tree.num_leaves()`.
+    pub fn first_leaf<H: TreeHasher>(self, tree: &BinaryMerkleTree<H>) -> usize {
+        first_leaf_below(self.0, tree.leaf_offset())
+    }
+}
+
+impl From<NodeIndex> for usize {
+    fn from(index: NodeIndex) -> usize {
+        index.0
+    }
+}
@@ -0,0 +1,532 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::binary_merkle_tree::{chunk_output_with_mode, parent_output, HashMode, Output, PARENT, ROOT};
+use crate::hash::Hash;
+use crate::provider::{chunk_count, provider_chunk_output, ChunkProvider};
+
+/// Nodes written per batch while building a tree.
+const BUILD_BATCH_LEN: usize = 4096;
+
+/// Storage for the nodes of a `StoredMerkleTree`, keyed by their 1-indexed
+/// heap index in the same layout as `BinaryMerkleTree`: the leaves of a tree
+/// over `n` leaves start at `n.next_power_of_two()`.
+pub trait NodeStore {
+    fn get(&self, index: usize) -> io::Result<Option<Output>>;
+
+    /// Write every node in `nodes`. Backends that support it apply the batch
+    /// atomically, so an interrupted update never leaves a half-written path.
+    fn write_batch(&mut self, nodes: &[(usize, Output)]) -> io::Result<()>;
+
+    fn leaf_count(&self) -> io::Result<Option<usize>>;
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> io::Result<()>;
+
+    /// Make every write so far durable. Stores without their own persistence
+    /// have nothing to do.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// When a `StoredMerkleTree` makes its writes durable, trading throughput
+/// for how much can be lost in a crash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// Only on explicit `flush` calls, leaving the rest to the store.
+    #[default]
+    None,
+    /// After every update.
+    OnCommit,
+    /// After an update once at least this long has passed since the last flush.
+    Periodic(Duration),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryNodeStore {
+    nodes: HashMap<usize, Output>,
+    leaf_count: Option<usize>,
+}
+
+impl MemoryNodeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl NodeStore for MemoryNodeStore {
+    fn get(&self, index: usize) -> io::Result<Option<Output>> {
+        Ok(self.nodes.get(&index).copied())
+    }
+
+    fn write_batch(&mut self, nodes: &[(usize, Output)]) -> io::Result<()> {
+        self.nodes.extend(nodes.iter().copied());
+        Ok(())
+    }
+
+    fn leaf_count(&self) -> io::Result<Option<usize>> {
+        Ok(self.leaf_count)
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> io::Result<()> {
+        self.leaf_count = Some(leaf_count);
+        Ok(())
+    }
+}
+
+/// A `NodeStore` in a sled tree. Node keys are the heap index as a u64 BE (so
+/// nodes are stored in heap order) and values are serialized Outputs.
+#[cfg(feature = "sled")]
+#[derive(Debug, Clone)]
+pub struct SledNodeStore {
+    path: std::path::PathBuf,
+    db: sled::Db,
+    tree: sled::Tree,
+}
+
+#[cfg(feature = "sled")]
+const SLED_LEAF_COUNT_KEY: &[u8] = b"leaf_count";
+
+#[cfg(feature = "sled")]
+impl SledNodeStore {
+    /// Open (or create) the database at `path` and use its `nodes` tree.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let db = sled::open(&path).map_err(io::Error::from)?;
+        let tree = db.open_tree("nodes").map_err(io::Error::from)?;
+        Ok(SledNodeStore {
+            path: path.as_ref().to_path_buf(),
+            db,
+            tree,
+        })
+    }
+
+    pub fn tree(&self) -> &sled::Tree {
+        &self.tree
+    }
+
+    /// Flush every write to disk and close the database, so that the same
+    /// path can be reopened as soon as this returns. Dropping the store
+    /// instead leaves sled's background threads to release the database some
+    /// time later. Blocks until every clone of the store is dropped too.
+    pub fn close(self) -> io::Result<()> {
+        let SledNodeStore { path, db, tree } = self;
+        db.flush().map_err(io::Error::from)?;
+        drop(tree);
+        drop(db);
+        // sled holds an exclusive lock on its `db` file until the last of its
+        // threads lets go, so taking the lock waits for exactly that
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.join("db"))?
+            .lock()
+    }
+}
+
+#[cfg(feature = "sled")]
+impl NodeStore for SledNodeStore {
+    fn get(&self, index: usize) -> io::Result<Option<Output>> {
+        use crate::serialize::{decode_output, OUTPUT_ENCODED_LEN};
+
+        let Some(value) = self.tree.get((index as u64).to_be_bytes()).map_err(io::Error::from)? else {
+            return Ok(None);
+        };
+        let bytes: &[u8; OUTPUT_ENCODED_LEN] = value
+            .as_ref()
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("node {} has a bad length", index)))?;
+        Ok(Some(decode_output(bytes)))
+    }
+
+    fn write_batch(&mut self, nodes: &[(usize, Output)]) -> io::Result<()> {
+        let mut batch = sled::Batch::default();
+        for (index, output) in nodes {
+            let mut value = Vec::with_capacity(crate::serialize::OUTPUT_ENCODED_LEN);
+            crate::serialize::encode_output(output, &mut value);
+            batch.insert(&(*index as u64).to_be_bytes(), value);
+        }
+        self.tree.apply_batch(batch).map_err(io::Error::from)
+    }
+
+    fn leaf_count(&self) -> io::Result<Option<usize>> {
+        let Some(value) = self.tree.get(SLED_LEAF_COUNT_KEY).map_err(io::Error::from)? else {
+            return Ok(None);
+        };
+        let bytes: [u8; 8] = value
+            .as_ref()
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "leaf count has a bad length"))?;
+        Ok(Some(u64::from_be_bytes(bytes) as usize))
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> io::Result<()> {
+        self.tree
+            .insert(SLED_LEAF_COUNT_KEY, &(leaf_count as u64).to_be_bytes())
+            .map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tree.flush().map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+/// A tree whose nodes live in a `NodeStore` rather than in memory, for trees
+/// too large for RAM or that must survive restarts. Only the leaf count is
+/// held in memory; every read goes to the store, and each update writes the
+/// changed ancestors in a single batch.
+///
+/// The layout and hashing are those of `BinaryMerkleTree`, so for chunk
+/// leaves the root is the BLAKE3 hash of the input. The leaf count and the
+/// hash mode are fixed when the tree is created.
+///
+/// How often writes are made durable is set with `set_sync_mode`; the
+/// default leaves it to explicit `flush` calls.
+#[derive(Debug)]
+pub struct StoredMerkleTree<S> {
+    store: S,
+    leaf_count: usize,
+    mode: HashMode,
+    sync_mode: SyncMode,
+    last_flush: Instant,
+}
+
+impl<S: NodeStore> StoredMerkleTree<S> {
+    /// Build a tree over `leaves` in `store`, streaming nodes to it in
+    /// batches. No leaves hashes like empty input, one empty chunk.
+    pub fn create<I>(store: S, leaves: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = Output>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        Self::create_with_mode(store, leaves, HashMode::HASH)
+    }
+
+    /// `create` for a keyed or key derivation tree, hashing parents under
+    /// `mode`. The leaves must have been hashed under the same mode.
+    pub fn create_with_mode<I>(mut store: S, leaves: I, mode: HashMode) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = Output>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let mut leaves = leaves.into_iter();
+        let leaf_count = leaves.len().max(1);
+        let mut builder = Builder {
+            store: &mut store,
+            leaves: &mut leaves,
+            capacity: leaf_count.next_power_of_two(),
+            leaf_count,
+            mode,
+            pending: Vec::with_capacity(BUILD_BATCH_LEN),
+        };
+        builder.subtree(1, 0, builder.capacity)?;
+        let pending = std::mem::take(&mut builder.pending);
+        store.write_batch(&pending)?;
+        store.set_leaf_count(leaf_count)?;
+        Ok(Self::with_store(store, leaf_count, mode))
+    }
+
+    /// Reopen a tree previously created in `store`. The hash mode is read
+    /// back from the root, which for more than one leaf is a parent hashed
+    /// with the tree's key and flags.
+    pub fn open(store: S) -> io::Result<Self> {
+        let leaf_count = store
+            .leaf_count()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "store holds no tree"))?;
+        let mode = match store.get(1)? {
+            Some(root) if leaf_count > 1 => HashMode {
+                key_words: root.input_chaining_value,
+                flags: root.flags & !PARENT,
+            },
+            _ => HashMode::HASH,
+        };
+        Ok(Self::with_store(store, leaf_count, mode))
+    }
+
+    fn with_store(store: S, leaf_count: usize, mode: HashMode) -> Self {
+        StoredMerkleTree {
+            store,
+            leaf_count,
+            mode,
+            sync_mode: SyncMode::None,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// The key and flags parents are hashed with.
+    pub fn mode(&self) -> HashMode {
+        self.mode
+    }
+
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
+    pub fn set_sync_mode(&mut self, sync_mode: SyncMode) {
+        self.sync_mode = sync_mode;
+    }
+
+    /// Make every update so far durable and return the root that is now on
+    /// disk.
+    pub fn flush(&mut self) -> io::Result<Hash> {
+        self.store.flush()?;
+        self.last_flush = Instant::now();
+        self.root_hash()
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn root(&self) -> io::Result<Output> {
+        let mut root = self.node(1)?;
+        root.flags |= ROOT;
+        Ok(root)
+    }
+
+    pub fn root_hash(&self) -> io::Result<Hash> {
+        Ok(Hash::from_root(&self.root()?))
+    }
+
+    pub fn leaf(&self, leaf_index: usize) -> io::Result<Output> {
+        assert!(leaf_index < self.leaf_count, "leaf index out of range");
+        self.node(self.capacity() + leaf_index)
+    }
+
+    /// Set the leaves in `updates` and rehash their ancestors, reading each
+    /// sibling once and writing every changed node in one batch, then flush
+    /// if the sync mode calls for it.
+    ///
+    /// Panics if an index is out of range.
+    pub fn update_leaves(&mut self, updates: &[(usize, Output)]) -> io::Result<()> {
+        let mut changed: HashMap<usize, Output> = HashMap::new();
+        let mut level: Vec<usize> = Vec::new();
+        for &(leaf_index, output) in updates {
+            assert!(leaf_index < self.leaf_count, "leaf index out of range");
+            let index = self.capacity() + leaf_index;
+            changed.insert(index, output);
+            level.push(index / 2);
+        }
+        while !level.is_empty() {
+            level.sort_unstable();
+            level.dedup();
+            for &index in &level {
+                let output = if self.first_leaf_below(2 * index + 1) >= self.leaf_count {
+                    self.node_or_changed(2 * index, &changed)?
+                } else {
+                    let left = self.node_or_changed(2 * index, &changed)?;
+                    let right = self.node_or_changed(2 * index + 1, &changed)?;
+                    self.parent(&left, &right)
+                };
+                changed.insert(index, output);
+            }
+            level = level.iter().filter(|&&index| index > 1).map(|index| index / 2).collect();
+        }
+        let mut batch: Vec<(usize, Output)> = changed.into_iter().collect();
+        batch.sort_unstable_by_key(|(index, _)| *index);
+        self.store.write_batch(&batch)?;
+        let due = match self.sync_mode {
+            SyncMode::None => false,
+            SyncMode::OnCommit => true,
+            SyncMode::Periodic(interval) => self.last_flush.elapsed() >= interval,
+        };
+        if due {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Recompute every parent from its stored children and return the heap
+    /// indices, in increasing order, of nodes that differ from what their
+    /// children determine or are missing from the store. Each node is read
+    /// once, a level at a time from the leaves up, so a store that returns
+    /// wrong or stale values shows up as the nodes just above them.
+    pub fn validate(&self) -> io::Result<Vec<usize>> {
+        let mut inconsistent = Vec::new();
+        let mut below = self.read_level(self.capacity(), self.leaf_count, &mut inconsistent)?;
+        let mut level_start = self.capacity();
+        while level_start > 1 {
+            level_start /= 2;
+            let stored = self.read_level(level_start, below.len().div_ceil(2), &mut inconsistent)?;
+            for (position, node) in stored.iter().enumerate() {
+                let expected = match (below[2 * position], below.get(2 * position + 1)) {
+                    (Some(left), Some(Some(right))) => Some(self.parent(&left, right)),
+                    (Some(left), None) => Some(left),
+                    _ => None,
+                };
+                if let (Some(node), Some(expected)) = (node, expected) {
+                    if *node != expected {
+                        inconsistent.push(level_start + position);
+                    }
+                }
+            }
+            below = stored;
+        }
+        inconsistent.sort_unstable();
+        Ok(inconsistent)
+    }
+
+    /// Rebuild the nodes `validate` flags, and everything above them, from
+    /// the stored leaves, writing the fixed nodes in one batch. Returns their
+    /// heap indices in increasing order. Fails if a leaf itself is missing;
+    /// `repair_from_provider` can restore those.
+    pub fn repair(&mut self) -> io::Result<Vec<usize>> {
+        let missing = |index| Err(io::Error::new(io::ErrorKind::InvalidData, format!("leaf {} missing from store", index)));
+        self.repair_leaves_with(missing, false)
+    }
+
+    /// Like `repair`, but without trusting the leaves below a flagged parent
+    /// of leaves, or missing ones: those chunks are read back from `provider`
+    /// and rehashed before the nodes above them are rebuilt. The provider
+    /// must hold exactly as many chunks as the tree has leaves.
+    pub fn repair_from_provider<P: ChunkProvider + ?Sized>(&mut self, provider: &P) -> io::Result<Vec<usize>> {
+        let len = provider.len()?;
+        if chunk_count(len) != self.leaf_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("provider holds {} chunks but the tree has {} leaves", chunk_count(len), self.leaf_count),
+            ));
+        }
+        self.repair_leaves_with(|index| provider_chunk_output(provider, len, index), true)
+    }
+
+    // Repair the flagged nodes. `reread` supplies a leaf by leaf index; it is
+    // asked for missing leaves, and for the leaves below flagged parents of
+    // leaves when `suspect_leaves` is set.
+    fn repair_leaves_with(
+        &mut self,
+        reread: impl Fn(usize) -> io::Result<Output>,
+        suspect_leaves: bool,
+    ) -> io::Result<Vec<usize>> {
+        let capacity = self.capacity();
+        let flagged = self.validate()?;
+        let mut changed: HashMap<usize, Output> = HashMap::new();
+        let mut pending: BTreeSet<usize> = BTreeSet::new();
+        for &index in &flagged {
+            if index >= capacity {
+                changed.insert(index, reread(index - capacity)?);
+            } else if suspect_leaves && 2 * index >= capacity {
+                for leaf in [2 * index, 2 * index + 1].into_iter().filter(|&leaf| leaf - capacity < self.leaf_count) {
+                    let output = reread(leaf - capacity)?;
+                    if self.store.get(leaf)? != Some(output) {
+                        changed.insert(leaf, output);
+                    }
+                }
+            }
+            pending.insert(index);
+        }
+        // Children have larger heap indices than their parents, so taking the
+        // largest pending index first always sees both children final
+        while let Some(index) = pending.pop_last() {
+            if index < capacity {
+                let left = self.node_or_changed(2 * index, &changed)?;
+                let output = if self.first_leaf_below(2 * index + 1) >= self.leaf_count {
+                    left
+                } else {
+                    let right = self.node_or_changed(2 * index + 1, &changed)?;
+                    self.parent(&left, &right)
+                };
+                if self.store.get(index)? != Some(output) {
+                    changed.insert(index, output);
+                }
+            }
+            if index > 1 {
+                pending.insert(index / 2);
+            }
+        }
+        let mut batch: Vec<(usize, Output)> = changed.into_iter().collect();
+        batch.sort_unstable_by_key(|(index, _)| *index);
+        self.store.write_batch(&batch)?;
+        Ok(batch.into_iter().map(|(index, _)| index).collect())
+    }
+
+    // The first `count` nodes of the level starting at heap index
+    // `level_start`, noting missing ones in `missing`
+    fn read_level(&self, level_start: usize, count: usize, missing: &mut Vec<usize>) -> io::Result<Vec<Option<Output>>> {
+        (level_start..level_start + count)
+            .map(|index| {
+                let node = self.store.get(index)?;
+                if node.is_none() {
+                    missing.push(index);
+                }
+                Ok(node)
+            })
+            .collect()
+    }
+
+    fn capacity(&self) -> usize {
+        self.leaf_count.next_power_of_two()
+    }
+
+    fn parent(&self, left: &Output, right: &Output) -> Output {
+        parent_output(left.chaining_value(), right.chaining_value(), self.mode.key_words, self.mode.flags)
+    }
+
+    fn first_leaf_below(&self, index: usize) -> usize {
+        let height = self.capacity().trailing_zeros() - index.ilog2();
+        (index << height) - self.capacity()
+    }
+
+    fn node(&self, index: usize) -> io::Result<Output> {
+        self.store
+            .get(index)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("node {} missing from store", index)))
+    }
+
+    fn node_or_changed(&self, index: usize, changed: &HashMap<usize, Output>) -> io::Result<Output> {
+        match changed.get(&index) {
+            Some(output) => Ok(*output),
+            None => self.node(index),
+        }
+    }
+}
+
+struct Builder<'a, S, I> {
+    store: &'a mut S,
+    leaves: &'a mut I,
+    capacity: usize,
+    leaf_count: usize,
+    mode: HashMode,
+    pending: Vec<(usize, Output)>,
+}
+
+impl<S: NodeStore, I: Iterator<Item = Output>> Builder<'_, S, I> {
+    // Build the subtree at heap `index` spanning `span` leaf slots from
+    // `first_leaf`, in order, and return its output. A subtree whose right
+    // half is empty holds the same output as its left child.
+    fn subtree(&mut self, index: usize, first_leaf: usize, span: usize) -> io::Result<Output> {
+        let output = if span == 1 {
+            self.leaves.next().unwrap_or_else(|| chunk_output_with_mode(0, &[], &self.mode))
+        } else if first_leaf + span / 2 >= self.leaf_count {
+            self.subtree(2 * index, first_leaf, span / 2)?
+        } else {
+            let left = self.subtree(2 * index, first_leaf, span / 2)?;
+            let right = self.subtree(2 * index + 1, first_leaf + span / 2, span / 2)?;
+            parent_output(left.chaining_value(), right.chaining_value(), self.mode.key_words, self.mode.flags)
+        };
+        self.pending.push((index, output));
+        if self.pending.len() == BUILD_BATCH_LEN {
+            self.store.write_batch(&self.pending)?;
+            self.pending.clear();
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::binary_merkle_tree::CHUNK_LEN;
+use crate::chunk_store::ChunkStore;
+use crate::hash::Hash;
+use crate::provider::ChunkProvider;
+
+/// Minimal interface to an S3-compatible object store: whole-object get and
+/// put under string keys. Client libraries for S3, GCS and the like can be
+/// wrapped to implement it, so chunks can live remotely while only trees are
+/// kept locally.
+pub trait ObjectStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    fn put(&mut self, key: &str, object: &[u8]) -> io::Result<()>;
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+}
+
+/// An object store held in memory, mostly for tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryObjectStore {
+    objects: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.objects.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &str, object: &[u8]) -> io::Result<()> {
+        self.objects.insert(key.to_string(), object.to_vec());
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        Ok(self.objects.contains_key(key))
+    }
+}
+
+/// A `ChunkStore` keeping each chunk as the object `<prefix><hex CV>`, so
+/// identical chunks are stored once across every tree sharing the prefix.
+#[derive(Debug, Clone)]
+pub struct ObjectChunkStore<O> {
+    store: O,
+    prefix: String,
+}
+
+impl<O: ObjectStore> ObjectChunkStore<O> {
+    pub fn new(store: O, prefix: impl Into<String>) -> Self {
+        ObjectChunkStore {
+            store,
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn object_store(&self) -> &O {
+        &self.store
+    }
+
+    fn key(&self, cv: &Hash) -> String {
+        format!("{}{}", self.prefix, cv.to_hex())
+    }
+}
+
+impl<O: ObjectStore> ChunkStore for ObjectChunkStore<O> {
+    fn put(&mut self, cv: Hash, chunk: &[u8]) -> io::Result<()> {
+        let key = self.key(&cv);
+        if self.store.exists(&key)? {
+            return Ok(());
+        }
+        self.store.put(&key, chunk)
+    }
+
+    fn get(&self, cv: &Hash) -> io::Result<Option<Vec<u8>>> {
+        self.store.get(&self.key(cv))
+    }
+
+    fn has(&self, cv: &Hash) -> io::Result<bool> {
+        self.store.exists(&self.key(cv))
+    }
+}
+
+/// A `ChunkProvider` over the chunks of one input of `len` bytes stored by
+/// position, as the objects `<prefix><chunk index as 16 hex digits>`. Trees
+/// can then be updated or repaired from the remote copy with
+/// `update_from_provider` and `refresh_from_provider`.
+#[derive(Debug, Clone)]
+pub struct IndexedObjectChunks<O> {
+    store: O,
+    prefix: String,
+    len: u64,
+}
+
+impl<O: ObjectStore> IndexedObjectChunks<O> {
+    pub fn new(store: O, prefix: impl Into<String>, len: u64) -> Self {
+        IndexedObjectChunks {
+            store,
+            prefix: prefix.into(),
+            len,
+        }
+    }
+
+    /// Store every chunk of `input` under its index and return the chunks for it.
+    pub fn upload(mut store: O, prefix: impl Into<String>, input: &[u8]) -> io::Result<Self> {
+        let prefix = prefix.into();
+        for (index, chunk) in input.chunks(CHUNK_LEN).enumerate() {
+            store.put(&index_key(&prefix, index), chunk)?;
+        }
+        Ok(Self::new(store, prefix, input.len() as u64))
+    }
+
+    pub fn object_store(&self) -> &O {
+        &self.store
+    }
+
+    /// Replace chunk `index`, for example after a write to the remote copy.
+    pub fn put_chunk(&mut self, index: usize, chunk: &[u8]) -> io::Result<()> {
+        self.store.put(&index_key(&self.prefix, index), chunk)
+    }
+}
+
+impl<O: ObjectStore> ChunkProvider for IndexedObjectChunks<O> {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+
+    fn read_chunk(&self, index: usize) -> io::Result<Vec<u8>> {
+        if self.len == 0 {
+            return Ok(Vec::new());
+        }
+        let key = index_key(&self.prefix, index);
+        self.store
+            .get(&key)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("object {} missing from store", key)))
+    }
+}
+
+fn index_key(prefix: &str, index: usize) -> String {
+    format!("{}{:016x}", prefix, index)
+}
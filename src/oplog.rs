@@ -0,0 +1,201 @@
+//! Record every leaf operation applied to a tree, and replay the log onto an
+//! empty tree. Two services that should hold the same tree can each record,
+//! and replaying both logs side by side shows the first operation after which
+//! their roots differ.
+//!
+//! A log starts with a snapshot of the leaves the tree held when recording
+//! began, so replaying never needs the original tree. Serialized logs are the
+//! magic `B3OP` and a version byte (1), followed by operations until the end of
+//! the stream, each a tag byte then:
+//!
+//! ```text
+//! tag 0  Rebuild   leaf count u64 LE, then that many Output records
+//! tag 1  SetLeaf   leaf index u64 LE, then one Output record
+//! ```
+//!
+//! Output records use the layout in `serialize`.
+
+use std::io::{self, Read, Write};
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Output};
+use crate::error::Error;
+use crate::hasher::TreeHasher;
+use crate::serialize::{decode_output, encode_output, OUTPUT_ENCODED_LEN};
+
+const LOG_MAGIC: &[u8; 4] = b"B3OP";
+const LOG_VERSION: u8 = 1;
+const TAG_REBUILD: u8 = 0;
+const TAG_SET_LEAF: u8 = 1;
+
+/// One recorded change to a tree's leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation<N = Output> {
+    /// Every leaf was replaced, possibly changing the leaf count.
+    Rebuild(Vec<N>),
+    /// The leaf at `index` was replaced.
+    SetLeaf { index: usize, leaf: N },
+}
+
+/// The operations applied to a tree since `BinaryMerkleTree::begin_recording`,
+/// oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationLog<N = Output> {
+    operations: Vec<Operation<N>>,
+}
+
+impl<N> OperationLog<N> {
+    pub(crate) fn record(&mut self, operation: Operation<N>) {
+        self.operations.push(operation);
+    }
+
+    pub fn operations(&self) -> &[Operation<N>] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl<N: Copy> OperationLog<N> {
+    /// Apply every operation, in order, to an empty tree.
+    pub fn replay<H: TreeHasher<Node = N>>(&self) -> Result<BinaryMerkleTree<H>, Error> {
+        self.replay_with(|_, _| {})
+    }
+
+    /// Like `replay`, calling `inspect` with the position of each operation and
+    /// the tree right after it was applied, for example to compare roots
+    /// against another replay. Fails if a leaf index falls outside the tree.
+    pub fn replay_with<H: TreeHasher<Node = N>>(
+        &self,
+        mut inspect: impl FnMut(usize, &BinaryMerkleTree<H>),
+    ) -> Result<BinaryMerkleTree<H>, Error> {
+        let mut tree = BinaryMerkleTree::new_from_leaves(Vec::new());
+        for (position, operation) in self.operations.iter().enumerate() {
+            match operation {
+                Operation::Rebuild(leaves) => tree = BinaryMerkleTree::new_from_leaves(leaves.clone()),
+                Operation::SetLeaf { index, leaf } => tree.insert_leaf(*index, *leaf)?,
+            }
+            inspect(position, &tree);
+        }
+        Ok(tree)
+    }
+}
+
+impl OperationLog {
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut bytes = LOG_MAGIC.to_vec();
+        bytes.push(LOG_VERSION);
+        for operation in &self.operations {
+            match operation {
+                Operation::Rebuild(leaves) => {
+                    bytes.push(TAG_REBUILD);
+                    bytes.extend_from_slice(&(leaves.len() as u64).to_le_bytes());
+                    for leaf in leaves {
+                        encode_output(leaf, &mut bytes);
+                    }
+                }
+                Operation::SetLeaf { index, leaf } => {
+                    bytes.push(TAG_SET_LEAF);
+                    bytes.extend_from_slice(&(*index as u64).to_le_bytes());
+                    encode_output(leaf, &mut bytes);
+                }
+            }
+        }
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    /// Read a log written by `write_to`.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<OperationLog> {
+        fn invalid(message: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+        }
+        fn read_u64<R: Read>(reader: &mut R) -> io::Result<usize> {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            usize::try_from(u64::from_le_bytes(bytes)).map_err(|_| invalid("operation log value too large"))
+        }
+        fn read_output<R: Read>(reader: &mut R) -> io::Result<Output> {
+            let mut record = [0u8; OUTPUT_ENCODED_LEN];
+            reader.read_exact(&mut record)?;
+            Ok(decode_output(&record))
+        }
+
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != LOG_MAGIC {
+            return Err(invalid("not an operation log"));
+        }
+        if header[4] != LOG_VERSION {
+            return Err(invalid("unsupported operation log version"));
+        }
+
+        let mut operations = Vec::new();
+        let mut tag = [0u8; 1];
+        while reader.read(&mut tag)? == 1 {
+            let operation = match tag[0] {
+                TAG_REBUILD => {
+                    let count = read_u64(&mut reader)?;
+                    let leaves = (0..count).map(|_| read_output(&mut reader)).collect::<io::Result<_>>()?;
+                    Operation::Rebuild(leaves)
+                }
+                TAG_SET_LEAF => {
+                    let index = read_u64(&mut reader)?;
+                    Operation::SetLeaf { index, leaf: read_output(&mut reader)? }
+                }
+                _ => return Err(invalid("unknown operation tag")),
+            };
+            operations.push(operation);
+        }
+        Ok(OperationLog { operations })
+    }
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    /// Start logging every leaf write: `insert_leaf`, `bulk_insert_leaves`, and
+    /// the helpers built on them, plus rebuilds that change the leaf count. The
+    /// log begins with a copy of the current leaves. Starting again discards
+    /// the old log.
+    pub fn begin_recording(&mut self) {
+        let leaves = (0..self.num_leaves()).map(|i| *self.leaf(i)).collect();
+        self.recording = Some(OperationLog {
+            operations: vec![Operation::Rebuild(leaves)],
+        });
+    }
+
+    pub fn recording(&self) -> Option<&OperationLog<H::Node>> {
+        self.recording.as_ref()
+    }
+
+    /// Stop recording and return the log, or None if none was being recorded.
+    pub fn end_recording(&mut self) -> Option<OperationLog<H::Node>> {
+        self.recording.take()
+    }
+
+    /// Replace the whole tree with one over `leaves`, keeping an open
+    /// recording, the hash mode and tuning, and an open journal, which records
+    /// the replaced node array so a rollback can bring it back.
+    pub(crate) fn rebuild_from_leaves(&mut self, leaves: Vec<H::Node>) {
+        if let Some(log) = &mut self.recording {
+            log.record(Operation::Rebuild(leaves.clone()));
+        }
+        let rebuilt = BinaryMerkleTree::<H>::new_from_leaves_with_mode(leaves, self.mode);
+        let replaced = std::mem::replace(&mut self.tree, rebuilt.tree);
+        if let Some(journal) = &mut self.journal {
+            journal.record_layout(replaced, self.leaf_count);
+        }
+        self.leaf_count = rebuilt.leaf_count;
+        self.block_cvs = None;
+        self.parent_hashes += rebuilt.parent_hashes;
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.totals.compressions += rebuilt.metrics.totals.compressions;
+        }
+        self.report_metrics();
+    }
+}
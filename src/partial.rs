@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{left_subtree_len, parent_output, Output, IV};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::proof::RangeProof;
+
+/// A pruned view of a tree for light clients. It starts from nothing but a
+/// trusted root and the chunk count, and learns leaves from range proofs: each
+/// proof is checked against the root, then the proven leaves and every node on
+/// their paths are kept. Reads and new proofs can be served for any range whose
+/// leaves it has learned, without ever holding the full node array.
+#[derive(Debug, Clone)]
+pub struct PartialTree {
+    root: Hash,
+    total_chunks: usize,
+    // Chaining values of known subtrees, keyed by (first leaf, leaf count)
+    nodes: HashMap<(usize, usize), [u32; 8]>,
+    leaves: BTreeMap<usize, Output>,
+}
+
+impl PartialTree {
+    pub fn new(root: Hash, total_chunks: usize) -> Self {
+        PartialTree {
+            root,
+            total_chunks,
+            nodes: HashMap::new(),
+            leaves: BTreeMap::new(),
+        }
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        self.root
+    }
+
+    pub fn total_chunks(&self) -> usize {
+        self.total_chunks
+    }
+
+    /// Number of leaves learned so far.
+    pub fn known_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// The leaf at `leaf_index`, if it has been proven.
+    pub fn leaf(&self, leaf_index: usize) -> Option<&Output> {
+        self.leaves.get(&leaf_index)
+    }
+
+    /// Check `proof` for `leaves`, the outputs of the chunks it covers, against
+    /// the trusted root, and keep the leaves and their paths if it holds.
+    /// Nothing is kept from a proof that fails.
+    pub fn ingest(&mut self, proof: &RangeProof, leaves: &[Output]) -> Result<(), Error> {
+        let chunks = &proof.chunks;
+        if proof.total_chunks != self.total_chunks
+            || chunks.start >= chunks.end
+            || chunks.end > self.total_chunks
+            || leaves.len() != chunks.len()
+        {
+            return Err(Error::InvalidProof);
+        }
+        let mut learned = HashMap::new();
+        let mut nodes = proof.nodes.iter();
+        let root = if self.total_chunks == 1 {
+            leaves[0]
+        } else {
+            let left_count = left_subtree_len(self.total_chunks);
+            let right_count = self.total_chunks - left_count;
+            let left = learn(0, left_count, chunks, leaves, &mut nodes, &mut learned);
+            let right = learn(left_count, right_count, chunks, leaves, &mut nodes, &mut learned);
+            match (left, right) {
+                (Some(left), Some(right)) => parent_output(left, right, IV, 0),
+                _ => return Err(Error::InvalidProof),
+            }
+        };
+        if nodes.next().is_some() || !Hash::from_root(&root).ct_eq(&self.root) {
+            return Err(Error::InvalidProof);
+        }
+
+        self.nodes.extend(learned);
+        self.leaves.extend(chunks.clone().zip(leaves.iter().copied()));
+        Ok(())
+    }
+
+    /// A proof for `chunks` against the trusted root, or None if any leaf in
+    /// the range has not been learned.
+    pub fn prove_range(&self, chunks: Range<usize>) -> Option<RangeProof> {
+        if chunks.start >= chunks.end || chunks.end > self.total_chunks {
+            return None;
+        }
+        if !chunks.clone().all(|i| self.leaves.contains_key(&i)) {
+            return None;
+        }
+        let mut nodes = Vec::new();
+        self.collect_proof_nodes(0, self.total_chunks, &chunks, &mut nodes)?;
+        Some(RangeProof {
+            total_chunks: self.total_chunks,
+            chunks,
+            nodes,
+        })
+    }
+
+    /// Chaining value of the subtree of `leaf_count` leaves at `first_leaf`, if
+    /// a proof has revealed it.
+    pub(crate) fn subtree_cv(&self, first_leaf: usize, leaf_count: usize) -> Option<[u32; 8]> {
+        self.nodes.get(&(first_leaf, leaf_count)).copied()
+    }
+
+    fn collect_proof_nodes(
+        &self,
+        first_leaf: usize,
+        leaf_count: usize,
+        chunks: &Range<usize>,
+        nodes: &mut Vec<[u32; 8]>,
+    ) -> Option<()> {
+        if first_leaf + leaf_count <= chunks.start || chunks.end <= first_leaf {
+            nodes.push(*self.nodes.get(&(first_leaf, leaf_count))?);
+        } else if leaf_count > 1 {
+            let left_count = left_subtree_len(leaf_count);
+            self.collect_proof_nodes(first_leaf, left_count, chunks, nodes)?;
+            self.collect_proof_nodes(first_leaf + left_count, leaf_count - left_count, chunks, nodes)?;
+        }
+        Some(())
+    }
+}
+
+// Recompute the chaining value of a subtree the way RangeProof does, recording
+// every subtree on the way
+fn learn<'a>(
+    first_leaf: usize,
+    leaf_count: usize,
+    chunks: &Range<usize>,
+    leaves: &[Output],
+    nodes: &mut impl Iterator<Item = &'a [u32; 8]>,
+    learned: &mut HashMap<(usize, usize), [u32; 8]>,
+) -> Option<[u32; 8]> {
+    let cv = if first_leaf + leaf_count <= chunks.start || chunks.end <= first_leaf {
+        *nodes.next()?
+    } else if leaf_count == 1 {
+        leaves[first_leaf - chunks.start].chaining_value()
+    } else {
+        let left_count = left_subtree_len(leaf_count);
+        let left = learn(first_leaf, left_count, chunks, leaves, nodes, learned)?;
+        let right = learn(first_leaf + left_count, leaf_count - left_count, chunks, leaves, nodes, learned)?;
+        parent_output(left, right, IV, 0).chaining_value()
+    };
+    learned.insert((first_leaf, leaf_count), cv);
+    Some(cv)
+}
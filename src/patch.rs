@@ -0,0 +1,228 @@
+use std::cmp::min;
+
+use crate::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use crate::codec::Decoder;
+use crate::error::Error;
+use crate::hash::Hash;
+
+const PATCH_MAGIC: &[u8; 4] = b"B3PT";
+const PATCH_VERSION: u8 = 1;
+
+/// New bytes for some chunks of an input, with the roots of the input before
+/// and after. The chunk count stays the same; only the final chunk may change
+/// length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerklePatch {
+    pub old_root: Hash,
+    pub new_root: Hash,
+    /// (chunk index, new chunk bytes), in ascending index order.
+    pub chunks: Vec<(usize, Vec<u8>)>,
+}
+
+impl MerklePatch {
+    /// The patch turning `old` into `new`, which must have the same number of
+    /// chunks. Returns None if they do not.
+    pub fn between(old: &[u8], new: &[u8]) -> Option<MerklePatch> {
+        let old_tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(old));
+        let new_tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(new));
+        if old_tree.num_leaves() != new_tree.num_leaves() {
+            return None;
+        }
+        let chunks = old_tree
+            .diff(&new_tree)
+            .into_iter()
+            .map(|i| (i, new[i * CHUNK_LEN..new.len().min((i + 1) * CHUNK_LEN)].to_vec()))
+            .collect();
+        Some(MerklePatch {
+            old_root: old_tree.root_hash(),
+            new_root: new_tree.root_hash(),
+            chunks,
+        })
+    }
+
+    /// Serialize as: magic, version, the old and new roots, the chunk count as
+    /// u64 LE, then each chunk as its index (u64 LE), its length (u32 LE), and
+    /// its bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(PATCH_MAGIC);
+        bytes.push(PATCH_VERSION);
+        bytes.extend_from_slice(self.old_root.as_bytes());
+        bytes.extend_from_slice(self.new_root.as_bytes());
+        encode_chunks(&self.chunks, &mut bytes);
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<MerklePatch> {
+        let mut decoder = Decoder::with_header(bytes, PATCH_MAGIC, PATCH_VERSION)?;
+        let old_root = decoder.take_hash()?;
+        let new_root = decoder.take_hash()?;
+        let chunks = decode_chunks(&mut decoder)?;
+        decoder.is_empty().then_some(MerklePatch {
+            old_root,
+            new_root,
+            chunks,
+        })
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Apply `patch` if this tree is the one it was made for. The old root is
+    /// checked first and nothing changes if it differs. The chunks are then
+    /// applied inside `try_update`, and rolled back if the result is not the
+    /// patch's new root. A journal the caller has open stays open: the patch
+    /// nests inside it, so rolling that journal back later also undoes an
+    /// applied patch. Chunks other than the last must be exactly `CHUNK_LEN`
+    /// bytes.
+    pub fn apply_patch(&mut self, patch: &MerklePatch) -> Result<(), Error> {
+        if !self.root_hash().ct_eq(&patch.old_root) {
+            return Err(Error::InvalidProof);
+        }
+        let last = self.num_leaves() - 1;
+        let misshapen = patch.chunks.iter().any(|(index, chunk)| {
+            if *index < last {
+                chunk.len() != CHUNK_LEN
+            } else {
+                chunk.is_empty() && last > 0
+            }
+        });
+        if misshapen {
+            return Err(Error::InvalidProof);
+        }
+        self.try_update(|tree| {
+            tree.count_bytes_hashed(patch.chunks.iter().map(|(_, chunk)| chunk.len() as u64).sum());
+            tree.bulk_insert_leaves(
+                patch.chunks.iter().map(|(index, _)| *index),
+                patch.chunks.iter().map(|(index, chunk)| chunk_output(*index as u64, chunk)),
+            )?;
+            if tree.root_hash().ct_eq(&patch.new_root) {
+                Ok(())
+            } else {
+                Err(Error::InvalidProof)
+            }
+        })
+    }
+}
+
+const FILE_PATCH_MAGIC: &[u8; 4] = b"B3FP";
+const FILE_PATCH_VERSION: u8 = 1;
+
+/// The chunks of version B of a file that differ from version A, with A's
+/// and B's roots and B's length, for shipping B to holders of A. Unlike
+/// `MerklePatch`, B may have any length, and applying it upgrades A's data
+/// along with its tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    pub old_root: Hash,
+    pub new_root: Hash,
+    pub new_len: u64,
+    /// (chunk index, B's chunk bytes), in ascending index order.
+    pub chunks: Vec<(usize, Vec<u8>)>,
+}
+
+impl FilePatch {
+    /// The patch from the version with tree `old_tree` to `new_data`, whose
+    /// tree is `new_tree`. Only chunks whose outputs differ are included, so
+    /// A's data itself is not needed.
+    pub fn between(old_tree: &BinaryMerkleTree, new_tree: &BinaryMerkleTree, new_data: &[u8]) -> FilePatch {
+        let chunks = old_tree
+            .diff(new_tree)
+            .into_iter()
+            .take_while(|&i| i < new_tree.num_leaves())
+            .map(|i| (i, new_data[min(i * CHUNK_LEN, new_data.len())..min((i + 1) * CHUNK_LEN, new_data.len())].to_vec()))
+            .collect();
+        FilePatch { old_root: old_tree.root_hash(), new_root: new_tree.root_hash(), new_len: new_data.len() as u64, chunks }
+    }
+
+    /// Serialize as: magic, version, the old and new roots, the new length
+    /// and the chunk count as u64 LE, then each chunk as its index (u64 LE),
+    /// its length (u32 LE), and its bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(FILE_PATCH_MAGIC);
+        bytes.push(FILE_PATCH_VERSION);
+        bytes.extend_from_slice(self.old_root.as_bytes());
+        bytes.extend_from_slice(self.new_root.as_bytes());
+        bytes.extend_from_slice(&self.new_len.to_le_bytes());
+        encode_chunks(&self.chunks, &mut bytes);
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<FilePatch> {
+        let mut decoder = Decoder::with_header(bytes, FILE_PATCH_MAGIC, FILE_PATCH_VERSION)?;
+        let old_root = decoder.take_hash()?;
+        let new_root = decoder.take_hash()?;
+        let new_len = decoder.take_u64()?;
+        let chunks = decode_chunks(&mut decoder)?;
+        decoder.is_empty().then_some(FilePatch { old_root, new_root, new_len, chunks })
+    }
+
+    /// Upgrade `data` and `tree`, version A, to version B. `tree` must be
+    /// A's tree, checked against the old root, and `data` must match it. The
+    /// result is checked against the new root before anything is changed, so
+    /// on any error both are left as they were.
+    pub fn apply(&self, data: &mut Vec<u8>, tree: &mut BinaryMerkleTree) -> Result<(), Error> {
+        if !tree.root_hash().ct_eq(&self.old_root) {
+            return Err(Error::InvalidProof);
+        }
+        let new_len = usize::try_from(self.new_len).map_err(|_| Error::InvalidProof)?;
+        let new_count = new_len.div_ceil(CHUNK_LEN).max(1);
+        let expected_len = |index: usize| min(CHUNK_LEN, new_len.saturating_sub(index * CHUNK_LEN));
+        let sorted = self.chunks.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        let misshapen =
+            self.chunks.iter().any(|(index, chunk)| *index >= new_count || chunk.len() != expected_len(*index));
+        if !sorted || misshapen {
+            return Err(Error::InvalidProof);
+        }
+
+        // Unpatched chunks keep A's leaves, and chunks past A's end must all
+        // be in the patch
+        let mut patched = self.chunks.iter().peekable();
+        let mut leaves = Vec::with_capacity(new_count);
+        for i in 0..new_count {
+            match patched.next_if(|(index, _)| *index == i) {
+                Some((_, chunk)) => leaves.push(chunk_output(i as u64, chunk)),
+                None if i < tree.num_leaves() => leaves.push(*tree.leaf(i)),
+                None => return Err(Error::InvalidProof),
+            }
+        }
+        let mut new_tree = tree.clone();
+        new_tree.count_bytes_hashed(self.chunks.iter().map(|(_, chunk)| chunk.len() as u64).sum());
+        new_tree.refresh_from_leaves(leaves);
+        if !new_tree.root_hash().ct_eq(&self.new_root) {
+            return Err(Error::InvalidProof);
+        }
+
+        data.resize(new_len, 0);
+        for (index, chunk) in &self.chunks {
+            data[index * CHUNK_LEN..index * CHUNK_LEN + chunk.len()].copy_from_slice(chunk);
+        }
+        *tree = new_tree;
+        Ok(())
+    }
+}
+
+// The chunk list both patch formats end with: the count as u64 LE, then each
+// chunk as its index (u64 LE), its length (u32 LE), and its bytes
+fn encode_chunks(chunks: &[(usize, Vec<u8>)], bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&(chunks.len() as u64).to_le_bytes());
+    for (index, chunk) in chunks {
+        bytes.extend_from_slice(&(*index as u64).to_le_bytes());
+        bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(chunk);
+    }
+}
+
+fn decode_chunks(decoder: &mut Decoder) -> Option<Vec<(usize, Vec<u8>)>> {
+    let count = decoder.take_u64()?;
+    let mut chunks = Vec::new();
+    for _ in 0..count {
+        let index = decoder.take_usize()?;
+        let len = decoder.take_u32()? as usize;
+        if len > CHUNK_LEN {
+            return None;
+        }
+        chunks.push((index, decoder.take(len)?.to_vec()));
+    }
+    Some(chunks)
+}
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::binary_merkle_tree::{chunk_output, left_subtree_len, parent_output, Output, IV, ROOT};
+use crate::error::Error;
+use crate::hash::Hash;
+
+#[derive(Debug)]
+enum Node {
+    Leaf(Output),
+    Parent {
+        output: Output,
+        left: Arc<Node>,
+        right: Arc<Node>,
+    },
+}
+
+impl Node {
+    fn output(&self) -> Output {
+        match self {
+            Node::Leaf(output) | Node::Parent { output, .. } => *output,
+        }
+    }
+
+    fn parent(left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+        let output = parent_output(left.output().chaining_value(), right.output().chaining_value(), IV, 0);
+        Arc::new(Node::Parent { output, left, right })
+    }
+}
+
+/// An immutable Merkle tree. `insert_leaf` returns a new tree that shares every
+/// node off the updated path with the old one, so keeping many versions alive
+/// costs O(log n) nodes per update rather than a full copy.
+///
+/// The tree has the same shape as BLAKE3's, so for chunk leaves the root
+/// matches `Blake3Hasher::finalize` over the input.
+#[derive(Debug, Clone)]
+pub struct PersistentMerkleTree {
+    root: Option<Arc<Node>>,
+    leaf_count: usize,
+}
+
+impl PersistentMerkleTree {
+    pub fn new_from_leaves(leaves: Vec<Output>) -> Self {
+        fn build(leaves: &[Output]) -> Arc<Node> {
+            if leaves.len() == 1 {
+                return Arc::new(Node::Leaf(leaves[0]));
+            }
+            let (left, right) = leaves.split_at(left_subtree_len(leaves.len()));
+            Node::parent(build(left), build(right))
+        }
+
+        Self {
+            root: (!leaves.is_empty()).then(|| build(&leaves)),
+            leaf_count: leaves.len(),
+        }
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn root(&self) -> Output {
+        // An empty tree hashes like empty input: a single empty chunk
+        let mut root = match &self.root {
+            Some(node) => node.output(),
+            None => chunk_output(0, &[]),
+        };
+        // Apply ROOT flag to the final root output
+        root.flags |= ROOT;
+        root
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        Hash::from_root(&self.root())
+    }
+
+    pub fn get_leaf(&self, leaf_index: usize) -> Option<Output> {
+        if leaf_index >= self.leaf_count {
+            return None;
+        }
+        let mut node = self.root.as_ref()?;
+        let (mut first, mut count) = (0, self.leaf_count);
+        loop {
+            match node.as_ref() {
+                Node::Leaf(output) => return Some(*output),
+                Node::Parent { left, right, .. } => {
+                    let left_count = left_subtree_len(count);
+                    if leaf_index < first + left_count {
+                        node = left;
+                        count = left_count;
+                    } else {
+                        node = right;
+                        first += left_count;
+                        count -= left_count;
+                    }
+                }
+            }
+        }
+    }
+
+    /// A new version of the tree with leaf `leaf_index` replaced. `self` is left
+    /// untouched. Fails if `leaf_index` is past the last leaf.
+    pub fn insert_leaf(&self, leaf_index: usize, leaf_output: Output) -> Result<Self, Error> {
+        if leaf_index >= self.leaf_count {
+            return Err(Error::IndexOutOfRange {
+                index: leaf_index,
+                leaf_count: self.leaf_count,
+            });
+        }
+
+        fn with_leaf(node: &Arc<Node>, count: usize, index: usize, leaf_output: Output) -> Arc<Node> {
+            match node.as_ref() {
+                Node::Leaf(_) => Arc::new(Node::Leaf(leaf_output)),
+                Node::Parent { left, right, .. } => {
+                    let left_count = left_subtree_len(count);
+                    if index < left_count {
+                        Node::parent(with_leaf(left, left_count, index, leaf_output), Arc::clone(right))
+                    } else {
+                        let right = with_leaf(right, count - left_count, index - left_count, leaf_output);
+                        Node::parent(Arc::clone(left), right)
+                    }
+                }
+            }
+        }
+
+        let root = self.root.as_ref().expect("non-empty tree has a root");
+        Ok(Self {
+            root: Some(with_leaf(root, self.leaf_count, leaf_index, leaf_output)),
+            leaf_count: self.leaf_count,
+        })
+    }
+
+    /// Whether two versions share the same root node, i.e. one was derived from
+    /// the other without any leaf changing.
+    pub fn shares_root_with(&self, other: &PersistentMerkleTree) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The most recent versions of a persistent tree, for snapshots and audits.
+/// Versions are numbered from 0 in commit order; once more than `capacity`
+/// versions have been committed, the oldest are dropped.
+#[derive(Debug, Clone)]
+pub struct TreeHistory {
+    versions: VecDeque<PersistentMerkleTree>,
+    capacity: usize,
+    first_version: u64,
+}
+
+impl TreeHistory {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "history must keep at least one version");
+        Self {
+            versions: VecDeque::with_capacity(capacity),
+            capacity,
+            first_version: 0,
+        }
+    }
+
+    /// Record a new version and return its number.
+    pub fn commit(&mut self, tree: PersistentMerkleTree) -> u64 {
+        if self.versions.len() == self.capacity {
+            self.versions.pop_front();
+            self.first_version += 1;
+        }
+        self.versions.push_back(tree);
+        self.first_version + self.versions.len() as u64 - 1
+    }
+
+    pub fn latest(&self) -> Option<&PersistentMerkleTree> {
+        self.versions.back()
+    }
+
+    /// The tree committed as `version`, if it is still retained.
+    pub fn get(&self, version: u64) -> Option<&PersistentMerkleTree> {
+        let offset = version.checked_sub(self.first_version)?;
+        self.versions.get(usize::try_from(offset).ok()?)
+    }
+
+    /// Root hashes of the retained versions, oldest first.
+    pub fn roots(&self) -> Vec<(u64, Hash)> {
+        self.versions
+            .iter()
+            .enumerate()
+            .map(|(i, tree)| (self.first_version + i as u64, tree.root_hash()))
+            .collect()
+    }
+}
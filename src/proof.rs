@@ -0,0 +1,353 @@
+use std::cmp::min;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{
+    chunk_output_with_mode, left_subtree_len, parent_output, BinaryMerkleTree, HashMode, Output, CHUNK_LEN,
+};
+use crate::hash::Hash;
+
+const PROOF_MAGIC: &[u8; 4] = b"B3RP";
+const PROOF_VERSION: u8 = 1;
+
+/// Proof that a contiguous range of chunks belongs to a tree with a given root.
+/// It holds the chaining values of the subtrees outside the range that the
+/// verifier cannot recompute from the chunk data itself, in pre-order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeProof {
+    /// Number of chunks in the whole tree, which fixes its shape.
+    pub total_chunks: usize,
+    /// The proven chunks.
+    pub chunks: Range<usize>,
+    /// Chaining values of the subtrees left and right of the range.
+    pub nodes: Vec<[u32; 8]>,
+}
+
+impl BinaryMerkleTree {
+    /// Build a proof for the chunks in `chunks`. Returns None if the range is
+    /// empty or extends past the last leaf.
+    pub fn prove_range(&self, chunks: Range<usize>) -> Option<RangeProof> {
+        if chunks.start >= chunks.end || chunks.end > self.num_leaves() {
+            return None;
+        }
+        let nodes = self
+            .proof_node_indices(&chunks)
+            .into_iter()
+            .map(|node_index| self.tree[node_index].chaining_value())
+            .collect();
+        Some(RangeProof {
+            total_chunks: self.num_leaves(),
+            chunks,
+            nodes,
+        })
+    }
+
+    /// Like `generate_proofs`, computing the shared nodes on `pool` rather
+    /// than the global rayon pool.
+    #[cfg(feature = "rayon")]
+    pub fn generate_proofs_in(&self, leaf_indices: &[usize], pool: &rayon::ThreadPool) -> Option<Vec<RangeProof>> {
+        pool.install(|| self.generate_proofs(leaf_indices))
+    }
+
+    /// Single-chunk proofs for every leaf in `leaf_indices`, in the same order,
+    /// each identical to `prove_range(i..i + 1)`. Proofs for nearby leaves share
+    /// most of their nodes, so every needed chaining value is computed once for
+    /// the whole batch; with the `rayon` feature that work is spread across
+    /// threads. Returns None if any index is past the last leaf.
+    pub fn generate_proofs(&self, leaf_indices: &[usize]) -> Option<Vec<RangeProof>> {
+        if leaf_indices.iter().any(|&i| i >= self.num_leaves()) {
+            return None;
+        }
+        let paths: Vec<Vec<usize>> = leaf_indices
+            .iter()
+            .map(|&leaf| self.proof_node_indices(&(leaf..leaf + 1)))
+            .collect();
+
+        let mut needed: Vec<usize> = paths.iter().flatten().copied().collect();
+        needed.sort_unstable();
+        needed.dedup();
+        let node_cv = |&node_index: &usize| self.tree[node_index].chaining_value();
+        #[cfg(feature = "rayon")]
+        let cvs: Vec<[u32; 8]> = {
+            use rayon::prelude::*;
+            needed.par_iter().map(node_cv).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let cvs: Vec<[u32; 8]> = needed.iter().map(node_cv).collect();
+
+        let proofs = leaf_indices
+            .iter()
+            .zip(paths)
+            .map(|(&leaf, path)| RangeProof {
+                total_chunks: self.num_leaves(),
+                chunks: leaf..leaf + 1,
+                nodes: path.iter().map(|node| cvs[needed.binary_search(node).unwrap()]).collect(),
+            })
+            .collect();
+        Some(proofs)
+    }
+
+    /// Bring `proof`, issued before some of the tree's nodes were overwritten,
+    /// up to date with the current root. `changed_nodes` holds the heap indices
+    /// of every node written since the proof was made, for example
+    /// `journal.node_indices()` from a journal opened when it was issued; only
+    /// proof nodes among them are recomputed. Returns the number of proof nodes
+    /// replaced, or None if the proof does not have this tree's shape.
+    pub fn patch_proof(&self, proof: &mut RangeProof, changed_nodes: &[usize]) -> Option<usize> {
+        if proof.total_chunks != self.num_leaves() || proof.chunks.start >= proof.chunks.end {
+            return None;
+        }
+        let node_indices = self.proof_node_indices(&proof.chunks);
+        if node_indices.len() != proof.nodes.len() {
+            return None;
+        }
+        let changed_nodes: HashSet<usize> = changed_nodes.iter().copied().collect();
+        let mut patched = 0;
+        for (node, node_index) in proof.nodes.iter_mut().zip(node_indices) {
+            if changed_nodes.contains(&node_index) {
+                *node = self.tree[node_index].chaining_value();
+                patched += 1;
+            }
+        }
+        Some(patched)
+    }
+
+    // Heap indices of the subtrees outside `chunks` whose chaining values a
+    // proof for `chunks` carries, in pre-order
+    fn proof_node_indices(&self, chunks: &Range<usize>) -> Vec<usize> {
+        let mut node_indices = Vec::new();
+        self.collect_proof_node_indices(0, self.num_leaves(), chunks, &mut node_indices);
+        node_indices
+    }
+
+    fn collect_proof_node_indices(
+        &self,
+        first_leaf: usize,
+        leaf_count: usize,
+        chunks: &Range<usize>,
+        node_indices: &mut Vec<usize>,
+    ) {
+        let subtree_end = first_leaf + leaf_count;
+        if subtree_end <= chunks.start || chunks.end <= first_leaf {
+            // Entirely outside the range, so the verifier needs its chaining value
+            node_indices.push(self.subtree_node_index(first_leaf, leaf_count));
+        } else if leaf_count > 1 {
+            let left_count = left_subtree_len(leaf_count);
+            self.collect_proof_node_indices(first_leaf, left_count, chunks, node_indices);
+            self.collect_proof_node_indices(first_leaf + left_count, leaf_count - left_count, chunks, node_indices);
+        }
+    }
+}
+
+/// Proof that an arbitrary byte range of the input belongs to a tree. Chunks
+/// can only be hashed whole, so alongside the range proof for the chunks
+/// covering the range it carries the bytes of those chunks that fall outside
+/// the range: at most one partial chunk on each side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteRangeProof {
+    pub chunks: RangeProof,
+    /// Bytes of the first covering chunk before the start of the range.
+    pub prefix: Vec<u8>,
+    /// Bytes of the last covering chunk after the end of the range.
+    pub suffix: Vec<u8>,
+}
+
+impl BinaryMerkleTree {
+    /// Build a proof for `byte_range` of `input`, the data this tree was built
+    /// from. Returns None if the range is empty or runs past the end of the
+    /// input, or if `input` does not have as many chunks as the tree.
+    pub fn prove_bytes(&self, input: &[u8], byte_range: Range<u64>) -> Option<ByteRangeProof> {
+        let start = usize::try_from(byte_range.start).ok()?;
+        let end = usize::try_from(byte_range.end).ok()?;
+        if start >= end || end > input.len() || input.len().div_ceil(CHUNK_LEN) != self.num_leaves() {
+            return None;
+        }
+        let chunks = start / CHUNK_LEN..end.div_ceil(CHUNK_LEN);
+        let covered_end = min(chunks.end * CHUNK_LEN, input.len());
+        Some(ByteRangeProof {
+            prefix: input[chunks.start * CHUNK_LEN..start].to_vec(),
+            suffix: input[end..covered_end].to_vec(),
+            chunks: self.prove_range(chunks)?,
+        })
+    }
+}
+
+/// Check that `data` is exactly the bytes at `byte_range` of an input whose
+/// tree has root `root`. Only the chunks covering the range are hashed, plus
+/// the uncle chaining values carried by the proof.
+pub fn verify_range(root: &Hash, byte_range: Range<u64>, data: &[u8], proof: &ByteRangeProof) -> bool {
+    verify_range_with_mode(root, byte_range, data, proof, &HashMode::HASH)
+}
+
+/// `verify_range` for a keyed or key derivation tree.
+pub fn verify_range_with_mode(
+    root: &Hash,
+    byte_range: Range<u64>,
+    data: &[u8],
+    proof: &ByteRangeProof,
+    mode: &HashMode,
+) -> bool {
+    let (Ok(start), Ok(end)) = (usize::try_from(byte_range.start), usize::try_from(byte_range.end)) else {
+        return false;
+    };
+    // The range must be the one the proof was made for, with the edge bytes
+    // filling out exactly the covering chunks
+    let chunks = start / CHUNK_LEN..end.div_ceil(CHUNK_LEN);
+    if start >= end
+        || data.len() != end - start
+        || proof.chunks.chunks != chunks
+        || proof.prefix.len() != start - chunks.start * CHUNK_LEN
+        || proof.suffix.len() > chunks.end * CHUNK_LEN - end
+    {
+        return false;
+    }
+    let covered = [&proof.prefix[..], data, &proof.suffix[..]].concat();
+    proof.chunks.verify_with_mode(root, &covered, mode)
+}
+
+impl RangeProof {
+    /// Byte range of the original input covered by the proven chunks. The end is
+    /// only an upper bound when the range includes the final, possibly partial,
+    /// chunk.
+    pub fn byte_range(&self) -> Range<u64> {
+        (self.chunks.start * CHUNK_LEN) as u64..(self.chunks.end * CHUNK_LEN) as u64
+    }
+
+    /// Check that `data`, the bytes of the proven chunks, hashes up to `root`.
+    pub fn verify(&self, root: &Hash, data: &[u8]) -> bool {
+        self.verify_with_mode(root, data, &HashMode::HASH)
+    }
+
+    /// `verify` for a keyed or key derivation tree. The proof does not carry
+    /// the mode, since a verifier of a keyed tree has to hold the key anyway.
+    pub fn verify_with_mode(&self, root: &Hash, data: &[u8], mode: &HashMode) -> bool {
+        match self.compute_root_with_mode(data, mode) {
+            Some(computed) => computed.ct_eq(root),
+            None => false,
+        }
+    }
+
+    /// Recompute the root implied by `data` and the proof, or None if the data
+    /// does not have the shape the proof describes.
+    pub fn compute_root(&self, data: &[u8]) -> Option<Hash> {
+        self.compute_root_with_mode(data, &HashMode::HASH)
+    }
+
+    /// `compute_root` for a keyed or key derivation tree.
+    pub fn compute_root_with_mode(&self, data: &[u8], mode: &HashMode) -> Option<Hash> {
+        if self.chunks.start >= self.chunks.end || self.chunks.end > self.total_chunks {
+            return None;
+        }
+        // Every proven chunk but the final chunk of the input must be full
+        let full_chunks = self.chunks.len() - 1;
+        let last_chunk_len = data.len().checked_sub(full_chunks * CHUNK_LEN)?;
+        let last_chunk_is_final = self.chunks.end == self.total_chunks;
+        if last_chunk_len > CHUNK_LEN
+            || (last_chunk_len < CHUNK_LEN && !last_chunk_is_final)
+            || (last_chunk_len == 0 && self.total_chunks > 1)
+        {
+            return None;
+        }
+
+        let outputs: Vec<Output> = data
+            .chunks(CHUNK_LEN)
+            .enumerate()
+            .map(|(i, chunk)| chunk_output_with_mode((self.chunks.start + i) as u64, chunk, mode))
+            .chain(data.is_empty().then(|| chunk_output_with_mode(0, &[], mode)))
+            .collect();
+        self.compute_root_from_leaves_with_mode(&outputs, mode)
+    }
+
+    /// Recompute the root from the outputs of the proven leaves themselves,
+    /// for trees whose leaves are not plain chunks of the input. Returns None
+    /// if the number of outputs does not match the proven range.
+    pub fn compute_root_from_leaves(&self, outputs: &[Output]) -> Option<Hash> {
+        self.compute_root_from_leaves_with_mode(outputs, &HashMode::HASH)
+    }
+
+    /// `compute_root_from_leaves` for a keyed or key derivation tree, whose
+    /// parents are hashed with `mode`.
+    pub fn compute_root_from_leaves_with_mode(&self, outputs: &[Output], mode: &HashMode) -> Option<Hash> {
+        if self.chunks.start >= self.chunks.end
+            || self.chunks.end > self.total_chunks
+            || outputs.len() != self.chunks.len()
+        {
+            return None;
+        }
+        let mut nodes = self.nodes.iter();
+
+        let root = if self.total_chunks == 1 {
+            outputs[0]
+        } else {
+            let left_count = left_subtree_len(self.total_chunks);
+            let left = self.subtree_cv(0, left_count, outputs, &mut nodes, mode)?;
+            let right = self.subtree_cv(left_count, self.total_chunks - left_count, outputs, &mut nodes, mode)?;
+            parent_output(left, right, mode.key_words, mode.flags)
+        };
+        if nodes.next().is_some() {
+            return None;
+        }
+        Some(Hash::from_root(&root))
+    }
+
+    fn subtree_cv<'a>(
+        &self,
+        first_leaf: usize,
+        leaf_count: usize,
+        outputs: &[Output],
+        nodes: &mut impl Iterator<Item = &'a [u32; 8]>,
+        mode: &HashMode,
+    ) -> Option<[u32; 8]> {
+        let subtree_end = first_leaf + leaf_count;
+        if subtree_end <= self.chunks.start || self.chunks.end <= first_leaf {
+            return nodes.next().copied();
+        }
+        if leaf_count == 1 {
+            return Some(outputs[first_leaf - self.chunks.start].chaining_value());
+        }
+        let left_count = left_subtree_len(leaf_count);
+        let left = self.subtree_cv(first_leaf, left_count, outputs, nodes, mode)?;
+        let right = self.subtree_cv(first_leaf + left_count, leaf_count - left_count, outputs, nodes, mode)?;
+        Some(parent_output(left, right, mode.key_words, mode.flags).chaining_value())
+    }
+
+    /// Serialize as: magic, version, then total chunks, range start, range end,
+    /// and node count as u64 LE, followed by each node's words in LE.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + 1 + 4 * 8 + 32 * self.nodes.len());
+        bytes.extend_from_slice(PROOF_MAGIC);
+        bytes.push(PROOF_VERSION);
+        for value in [self.total_chunks, self.chunks.start, self.chunks.end, self.nodes.len()] {
+            bytes.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+        for node in &self.nodes {
+            bytes.extend_from_slice(Hash::from_chaining_value(*node).as_bytes());
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<RangeProof> {
+        let rest = bytes.strip_prefix(PROOF_MAGIC)?;
+        let (&version, rest) = rest.split_first()?;
+        if version != PROOF_VERSION || rest.len() < 32 {
+            return None;
+        }
+        let (header, rest) = rest.split_at(32);
+        let field = |i: usize| -> Option<usize> {
+            usize::try_from(u64::from_le_bytes(header[i * 8..(i + 1) * 8].try_into().unwrap())).ok()
+        };
+        let (total_chunks, start, end, node_count) = (field(0)?, field(1)?, field(2)?, field(3)?);
+        if rest.len() != node_count.checked_mul(32)? {
+            return None;
+        }
+        let nodes = rest
+            .chunks_exact(32)
+            .map(|node| Hash::from_bytes(node.try_into().unwrap()).to_chaining_value())
+            .collect();
+        Some(RangeProof {
+            total_chunks,
+            chunks: start..end,
+            nodes,
+        })
+    }
+}
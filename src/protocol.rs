@@ -0,0 +1,283 @@
+//! Framed request/response messages for exchanging tree nodes and chunks over
+//! any byte stream.
+//!
+//! Every frame is `[len: u32 LE][version: u8][kind: u8][payload]`, where `len`
+//! counts the version, kind, and payload bytes. Integers in payloads are u64 LE
+//! and chaining values are 32 bytes LE.
+
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN};
+use crate::hash::Hash;
+
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Frames larger than this are rejected before allocating a buffer for them.
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+const GET_ROOT: u8 = 1;
+const GET_NODES: u8 = 2;
+const GET_CHUNKS: u8 = 3;
+const ROOT: u8 = 0x81;
+const NODES: u8 = 0x82;
+const CHUNKS: u8 = 0x83;
+const ERROR: u8 = 0xFF;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    GetRoot,
+    /// Chaining values of the nodes with these 1-indexed heap indices.
+    GetNodes(Range<u64>),
+    /// Raw bytes of these chunks.
+    GetChunks(Vec<u64>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Root { leaf_count: u64, root: Hash },
+    /// Chaining values for consecutive heap indices starting at `first`.
+    Nodes { first: u64, cvs: Vec<[u32; 8]> },
+    Chunks(Vec<(u64, Vec<u8>)>),
+    Error(String),
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        let kind = match self {
+            Request::GetRoot => GET_ROOT,
+            Request::GetNodes(range) => {
+                put_u64(&mut payload, range.start);
+                put_u64(&mut payload, range.end);
+                GET_NODES
+            }
+            Request::GetChunks(indices) => {
+                put_u64(&mut payload, indices.len() as u64);
+                for &index in indices {
+                    put_u64(&mut payload, index);
+                }
+                GET_CHUNKS
+            }
+        };
+        frame(kind, payload)
+    }
+
+    pub fn decode(frame: &[u8]) -> io::Result<Request> {
+        let (kind, mut payload) = unframe(frame)?;
+        let request = match kind {
+            GET_ROOT => Request::GetRoot,
+            GET_NODES => Request::GetNodes(payload.u64()?..payload.u64()?),
+            GET_CHUNKS => {
+                let count = payload.count(8)?;
+                Request::GetChunks((0..count).map(|_| payload.u64()).collect::<io::Result<_>>()?)
+            }
+            _ => return Err(invalid("unknown request kind")),
+        };
+        payload.finish()?;
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        let kind = match self {
+            Response::Root { leaf_count, root } => {
+                put_u64(&mut payload, *leaf_count);
+                payload.extend_from_slice(root.as_bytes());
+                ROOT
+            }
+            Response::Nodes { first, cvs } => {
+                put_u64(&mut payload, *first);
+                put_u64(&mut payload, cvs.len() as u64);
+                for cv in cvs {
+                    payload.extend_from_slice(Hash::from_chaining_value(*cv).as_bytes());
+                }
+                NODES
+            }
+            Response::Chunks(chunks) => {
+                put_u64(&mut payload, chunks.len() as u64);
+                for (index, data) in chunks {
+                    put_u64(&mut payload, *index);
+                    put_u64(&mut payload, data.len() as u64);
+                    payload.extend_from_slice(data);
+                }
+                CHUNKS
+            }
+            Response::Error(message) => {
+                payload.extend_from_slice(message.as_bytes());
+                ERROR
+            }
+        };
+        frame(kind, payload)
+    }
+
+    pub fn decode(frame: &[u8]) -> io::Result<Response> {
+        let (kind, mut payload) = unframe(frame)?;
+        let response = match kind {
+            ROOT => Response::Root {
+                leaf_count: payload.u64()?,
+                root: Hash::from_bytes(payload.bytes(32)?.try_into().unwrap()),
+            },
+            NODES => {
+                let first = payload.u64()?;
+                let count = payload.count(32)?;
+                let cvs = (0..count)
+                    .map(|_| Ok(Hash::from_bytes(payload.bytes(32)?.try_into().unwrap()).to_chaining_value()))
+                    .collect::<io::Result<_>>()?;
+                Response::Nodes { first, cvs }
+            }
+            CHUNKS => {
+                let count = payload.count(16)?;
+                let mut chunks = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let index = payload.u64()?;
+                    let len = payload.count(1)?;
+                    chunks.push((index, payload.bytes(len)?.to_vec()));
+                }
+                Response::Chunks(chunks)
+            }
+            ERROR => {
+                let message = payload.bytes(payload.remaining())?;
+                Response::Error(String::from_utf8_lossy(message).into_owned())
+            }
+            _ => return Err(invalid("unknown response kind")),
+        };
+        payload.finish()?;
+        Ok(response)
+    }
+}
+
+/// Answer `request` from a tree and the data it was built over.
+pub fn respond(tree: &BinaryMerkleTree, data: &[u8], request: &Request) -> Response {
+    match request {
+        Request::GetRoot => Response::Root {
+            leaf_count: tree.num_leaves() as u64,
+            root: tree.root_hash(),
+        },
+        Request::GetNodes(range) => {
+            if range.start == 0 || range.start > range.end || range.end > tree.tree.len() as u64 {
+                return Response::Error(format!("node range {:?} out of bounds", range));
+            }
+            let cvs = tree.tree[range.start as usize..range.end as usize]
+                .iter()
+                .map(|node| node.chaining_value())
+                .collect();
+            Response::Nodes { first: range.start, cvs }
+        }
+        Request::GetChunks(indices) => {
+            let mut chunks = Vec::with_capacity(indices.len());
+            for &index in indices {
+                if index >= tree.num_leaves() as u64 {
+                    return Response::Error(format!("chunk {} out of bounds", index));
+                }
+                let start = index as usize * CHUNK_LEN;
+                let end = (start + CHUNK_LEN).min(data.len());
+                chunks.push((index, data[start..end].to_vec()));
+            }
+            Response::Chunks(chunks)
+        }
+    }
+}
+
+/// Read one complete frame, including its length prefix, from `reader`.
+pub fn read_frame<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes)?;
+    let len = u32::from_le_bytes(len_bytes) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid("frame too large"));
+    }
+    let mut frame = vec![0u8; 4 + len];
+    frame[..4].copy_from_slice(&len_bytes);
+    reader.read_exact(&mut frame[4..])?;
+    Ok(frame)
+}
+
+pub fn write_request<W: Write>(mut writer: W, request: &Request) -> io::Result<()> {
+    writer.write_all(&request.encode())?;
+    writer.flush()
+}
+
+pub fn read_request<R: Read>(reader: R) -> io::Result<Request> {
+    Request::decode(&read_frame(reader)?)
+}
+
+pub fn write_response<W: Write>(mut writer: W, response: &Response) -> io::Result<()> {
+    writer.write_all(&response.encode())?;
+    writer.flush()
+}
+
+pub fn read_response<R: Read>(reader: R) -> io::Result<Response> {
+    Response::decode(&read_frame(reader)?)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn frame(kind: u8, payload: Vec<u8>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(6 + payload.len());
+    frame.extend_from_slice(&((2 + payload.len()) as u32).to_le_bytes());
+    frame.push(PROTOCOL_VERSION);
+    frame.push(kind);
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+fn unframe(frame: &[u8]) -> io::Result<(u8, Payload<'_>)> {
+    if frame.len() < 6 || u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize != frame.len() - 4 {
+        return Err(invalid("bad frame length"));
+    }
+    if frame[4] != PROTOCOL_VERSION {
+        return Err(invalid("unsupported protocol version"));
+    }
+    Ok((frame[5], Payload { bytes: &frame[6..] }))
+}
+
+/// Cursor over a frame payload that turns truncation into InvalidData errors.
+struct Payload<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Payload<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            return Err(invalid("truncated payload"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    // A length field for items of at least `item_len` bytes each, checked
+    // against the remaining payload so it cannot trigger a huge allocation
+    fn count(&mut self, item_len: usize) -> io::Result<usize> {
+        let count = usize::try_from(self.u64()?).map_err(|_| invalid("count too large"))?;
+        if count.saturating_mul(item_len) > self.bytes.len() {
+            return Err(invalid("truncated payload"));
+        }
+        Ok(count)
+    }
+
+    fn finish(self) -> io::Result<()> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("trailing bytes in payload"))
+        }
+    }
+}
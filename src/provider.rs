@@ -0,0 +1,180 @@
+use std::cmp::min;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::binary_merkle_tree::{chunk_output, BinaryMerkleTree, Output, CHUNK_LEN};
+use crate::file::{bytes_in_chunks, read_full};
+use crate::verify::{unskipped, VerifiedMarks};
+
+/// Source of raw chunk bytes that a tree reads back from whenever a leaf has
+/// to be recomputed, so maintaining the tree does not require holding the
+/// whole input in memory.
+pub trait ChunkProvider {
+    /// Total length of the input in bytes.
+    fn len(&self) -> io::Result<u64>;
+
+    /// The bytes of chunk `index`: CHUNK_LEN bytes, or fewer for the last chunk.
+    fn read_chunk(&self, index: usize) -> io::Result<Vec<u8>>;
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl ChunkProvider for [u8] {
+    fn len(&self) -> io::Result<u64> {
+        Ok(<[u8]>::len(self) as u64)
+    }
+
+    fn read_chunk(&self, index: usize) -> io::Result<Vec<u8>> {
+        let start = min(index * CHUNK_LEN, <[u8]>::len(self));
+        let end = min(start + CHUNK_LEN, <[u8]>::len(self));
+        Ok(self[start..end].to_vec())
+    }
+}
+
+impl ChunkProvider for Vec<u8> {
+    fn len(&self) -> io::Result<u64> {
+        ChunkProvider::len(self.as_slice())
+    }
+
+    fn read_chunk(&self, index: usize) -> io::Result<Vec<u8>> {
+        self.as_slice().read_chunk(index)
+    }
+}
+
+/// Reads chunks from a file on demand, seeking to each one.
+#[derive(Debug)]
+pub struct FileChunkProvider {
+    file: File,
+}
+
+impl FileChunkProvider {
+    pub fn new(file: File) -> Self {
+        FileChunkProvider { file }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
+
+impl ChunkProvider for FileChunkProvider {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn read_chunk(&self, index: usize) -> io::Result<Vec<u8>> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start((index * CHUNK_LEN) as u64))?;
+        let mut chunk = vec![0; CHUNK_LEN];
+        let filled = read_full(&mut file, &mut chunk)?;
+        chunk.truncate(filled);
+        Ok(chunk)
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Like `update_bytes`, reading the rehashed chunks from `provider` instead
+    /// of a byte slice holding the whole input. Only the chunks that need
+    /// rehashing are read.
+    pub fn update_from_provider<P: ChunkProvider + ?Sized>(
+        &mut self,
+        provider: &P,
+        changed: Range<u64>,
+    ) -> io::Result<Vec<usize>> {
+        let new_leaf_count = chunk_count(provider.len()?);
+        let first = min(changed.start as usize / CHUNK_LEN, new_leaf_count);
+        let end = (changed.end as usize).div_ceil(CHUNK_LEN).clamp(first, new_leaf_count);
+        if new_leaf_count == self.num_leaves() {
+            return self.rehash_from_provider(provider, first..end);
+        }
+        let old_last = min(self.num_leaves(), new_leaf_count).saturating_sub(1);
+        self.rehash_from_provider(provider, min(first, old_last)..new_leaf_count)
+    }
+
+    /// Rehash every chunk from `provider`, one chunk at a time, and return the
+    /// indices of the leaves that changed. This repairs a tree whose leaves may
+    /// have drifted from the data without loading the data into memory.
+    pub fn refresh_from_provider<P: ChunkProvider + ?Sized>(&mut self, provider: &P) -> io::Result<Vec<usize>> {
+        let len = provider.len()?;
+        let leaves = (0..chunk_count(len))
+            .map(|i| provider_chunk_output(provider, len, i))
+            .collect::<io::Result<Vec<Output>>>()?;
+        self.count_bytes_hashed(len);
+        Ok(self.refresh_from_leaves(leaves))
+    }
+
+    /// Like `refresh_from_provider`, reusing the stored leaves under the
+    /// subtrees with a current mark in `marks` instead of reading them. If the
+    /// provider's chunk count changed, every chunk is read.
+    pub fn refresh_from_provider_skipping<P: ChunkProvider + ?Sized>(
+        &mut self,
+        provider: &P,
+        marks: &VerifiedMarks,
+    ) -> io::Result<Vec<usize>> {
+        let len = provider.len()?;
+        if chunk_count(len) != self.num_leaves() {
+            return self.refresh_from_provider(provider);
+        }
+        let skipped = marks.verified_chunks(self);
+        let mut leaves: Vec<Output> = (0..self.num_leaves()).map(|i| *self.leaf(i)).collect();
+        for range in unskipped(leaves.len(), &skipped) {
+            self.count_bytes_hashed(bytes_in_chunks(len, range.clone()));
+            for i in range {
+                leaves[i] = provider_chunk_output(provider, len, i)?;
+            }
+        }
+        Ok(self.refresh_from_leaves(leaves))
+    }
+
+    // Rehash the chunks in `dirty` from `provider`, reusing the stored leaves
+    // for every other chunk
+    fn rehash_from_provider<P: ChunkProvider + ?Sized>(
+        &mut self,
+        provider: &P,
+        dirty: Range<usize>,
+    ) -> io::Result<Vec<usize>> {
+        let len = provider.len()?;
+        if chunk_count(len) != self.num_leaves() {
+            let leaves = (0..chunk_count(len))
+                .map(|i| if dirty.contains(&i) { provider_chunk_output(provider, len, i) } else { Ok(*self.leaf(i)) })
+                .collect::<io::Result<Vec<Output>>>()?;
+            self.count_bytes_hashed(bytes_in_chunks(len, dirty));
+            return Ok(self.refresh_from_leaves(leaves));
+        }
+
+        self.count_bytes_hashed(bytes_in_chunks(len, dirty.clone()));
+        let mut updates: Vec<(usize, Output)> = Vec::new();
+        for i in dirty {
+            let output = provider_chunk_output(provider, len, i)?;
+            if *self.leaf(i) != output {
+                updates.push((i, output));
+            }
+        }
+        self.bulk_insert_leaves(updates.iter().map(|(i, _)| *i), updates.iter().map(|(_, output)| *output))
+            .expect("dirty indices are produced in sorted order");
+        Ok(updates.into_iter().map(|(i, _)| i).collect())
+    }
+}
+
+// Empty input is still one (empty) chunk
+pub(crate) fn chunk_count(len: u64) -> usize {
+    (len.div_ceil(CHUNK_LEN as u64) as usize).max(1)
+}
+
+// Output of chunk `index` of an input of `len` bytes, checking that the
+// provider returned the whole chunk
+pub(crate) fn provider_chunk_output<P: ChunkProvider + ?Sized>(provider: &P, len: u64, index: usize) -> io::Result<Output> {
+    let expected = min(CHUNK_LEN as u64, len.saturating_sub((index * CHUNK_LEN) as u64)) as usize;
+    let chunk = provider.read_chunk(index)?;
+    if chunk.len() != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("chunk provider returned {} bytes for chunk {}, expected {}", chunk.len(), index, expected),
+        ));
+    }
+    Ok(chunk_output(index as u64, &chunk))
+}
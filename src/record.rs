@@ -0,0 +1,210 @@
+use crate::binary_merkle_tree::{chunk_output, BinaryMerkleTree, Blake3Hasher, Output, OUT_LEN};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::proof::RangeProof;
+
+// Domain separation for records, so a record can never be confused with a file
+// chunk or a directory entry
+const RECORD_CONTEXT: &str = "blake3_merkle_tree 2024 record v1";
+
+/// The leaf for `record` at position `index`. The record is first hashed with a
+/// dedicated derive-key context, then placed in a chunk whose counter is its
+/// position, so records of any length can be leaves and reordering them
+/// changes the root.
+///
+/// A tree of record leaves is NOT the BLAKE3 hash of the concatenated records;
+/// its root only commits to the sequence of records.
+pub fn record_leaf(index: u64, record: &[u8]) -> Output {
+    chunk_output(index, record_hash(record).as_bytes())
+}
+
+// The derive-key hash of a record, before it is placed in its chunk
+fn record_hash(record: &[u8]) -> Hash {
+    let mut hasher = Blake3Hasher::new_derive_key(RECORD_CONTEXT);
+    hasher.update(record);
+    let mut digest = [0; OUT_LEN];
+    hasher.finalize(&mut digest);
+    Hash::from_bytes(digest)
+}
+
+/// A value with a canonical byte encoding, so it can be a record leaf without
+/// a hand-written encoder. With the `derive` feature, `#[derive(MerkleLeaf)]`
+/// implements it for structs and enums from their fields.
+///
+/// The encoding is fixed: integers are little-endian at their full width,
+/// `bool` is one byte, `usize`/`isize` widen to 64 bits, byte strings and
+/// sequences are prefixed with their length as a u64, `Option` with a 0 or 1
+/// byte, and fixed-size arrays and tuples are their elements in order. Derived
+/// structs are their fields in declaration order; derived enums are the variant
+/// index as a u32, then the variant's fields.
+pub trait MerkleLeaf {
+    /// Append the canonical encoding of `self` to `out`.
+    fn encode_canonical(&self, out: &mut Vec<u8>);
+
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_canonical(&mut out);
+        out
+    }
+
+    /// The record hash of the canonical encoding, independent of position.
+    fn leaf_hash(&self) -> Hash {
+        record_hash(&self.canonical_bytes())
+    }
+
+    /// The leaf for `self` at position `index`, the same as `record_leaf` of
+    /// its canonical bytes, so `BinaryMerkleTree::from_records` over
+    /// `canonical_bytes()` builds the same tree.
+    fn leaf(&self, index: u64) -> Output {
+        chunk_output(index, self.leaf_hash().as_bytes())
+    }
+}
+
+#[cfg(feature = "derive")]
+pub use merkle_tree_derive::MerkleLeaf;
+
+macro_rules! impl_merkle_leaf_for_int {
+    ($($int:ty),*) => {$(
+        impl MerkleLeaf for $int {
+            fn encode_canonical(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+    )*};
+}
+
+impl_merkle_leaf_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl MerkleLeaf for usize {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode_canonical(out);
+    }
+}
+
+impl MerkleLeaf for isize {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode_canonical(out);
+    }
+}
+
+impl MerkleLeaf for bool {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl MerkleLeaf for str {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode_canonical(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl MerkleLeaf for String {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_canonical(out);
+    }
+}
+
+impl MerkleLeaf for Hash {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl<T: MerkleLeaf> MerkleLeaf for [T] {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode_canonical(out);
+        for item in self {
+            item.encode_canonical(out);
+        }
+    }
+}
+
+impl<T: MerkleLeaf> MerkleLeaf for Vec<T> {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_canonical(out);
+    }
+}
+
+impl<T: MerkleLeaf, const N: usize> MerkleLeaf for [T; N] {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        for item in self {
+            item.encode_canonical(out);
+        }
+    }
+}
+
+impl<T: MerkleLeaf> MerkleLeaf for Option<T> {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode_canonical(out);
+            }
+        }
+    }
+}
+
+impl<T: MerkleLeaf + ?Sized> MerkleLeaf for &T {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        (**self).encode_canonical(out);
+    }
+}
+
+impl<T: MerkleLeaf + ?Sized> MerkleLeaf for Box<T> {
+    fn encode_canonical(&self, out: &mut Vec<u8>) {
+        (**self).encode_canonical(out);
+    }
+}
+
+macro_rules! impl_merkle_leaf_for_tuple {
+    ($(($($name:ident),+)),*) => {$(
+        impl<$($name: MerkleLeaf),+> MerkleLeaf for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_canonical(&self, out: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_canonical(out);)+
+            }
+        }
+    )*};
+}
+
+impl_merkle_leaf_for_tuple!((A), (A, B), (A, B, C), (A, B, C, D));
+
+impl BinaryMerkleTree {
+    /// Build a tree with one leaf per record, for example one per database row.
+    /// See `record_leaf` for how records are hashed.
+    pub fn from_records<I, R>(records: I) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[u8]>,
+    {
+        let leaves = records
+            .into_iter()
+            .enumerate()
+            .map(|(index, record)| record_leaf(index as u64, record.as_ref()))
+            .collect();
+        BinaryMerkleTree::new_from_leaves(leaves)
+    }
+
+    /// Replace the record at `index` and update its ancestors. Fails if the
+    /// index is past the last record.
+    pub fn update_record(&mut self, index: usize, record: &[u8]) -> Result<(), Error> {
+        self.insert_leaf(index, record_leaf(index as u64, record))
+    }
+}
+
+impl RangeProof {
+    /// Check a proof from a record tree: `records` are the proven records, in
+    /// order.
+    pub fn verify_records<R: AsRef<[u8]>>(&self, root: &Hash, records: &[R]) -> bool {
+        let outputs: Vec<Output> = records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| record_leaf((self.chunks.start + i) as u64, record.as_ref()))
+            .collect();
+        self.compute_root_from_leaves(&outputs).is_some_and(|computed| computed.ct_eq(root))
+    }
+}
@@ -0,0 +1,210 @@
+//! Byte layouts of persisted nodes and tree files. Every integer is written
+//! little-endian with explicit conversions, never by reinterpreting memory, so
+//! files are identical whatever the byte order of the machine that wrote them.
+//!
+//! An `Output` record is `OUTPUT_ENCODED_LEN` (112) bytes:
+//!
+//! ```text
+//! offset  size  field
+//!      0    32  input_chaining_value, 8 x u32 LE
+//!     32    64  block_words, 16 x u32 LE
+//!     96     8  counter, u64 LE
+//!    104     4  block_len, u32 LE
+//!    108     4  flags, u32 LE
+//! ```
+//!
+//! A tree file is the magic `B3MT` and a version byte. Version 1 continues
+//! with the leaf count as a u64 LE and that many `Output` records. Version 2
+//! has a compression flag byte (see `compression`) before that body.
+//!
+//! Version 3, the one written now, checks itself. Its header is:
+//!
+//! ```text
+//! offset  size  field
+//!      0     4  magic "B3MT"
+//!      4     1  version, 3
+//!      5     1  compression flag
+//!      6     8  leaf count, u64 LE
+//!     14    32  root hash of the tree
+//!     46     8  first 8 bytes of the BLAKE3 hash of bytes 0..46
+//! ```
+//!
+//! followed by the body, the `Output` record of every leaf, compressed as the
+//! flag says. A header whose checksum does not match, or leaves that do not
+//! hash to the recorded root, are rejected instead of loading as a different
+//! tree. Versions 1 and 2 still load, without those checks, and
+//! `migrate_tree_file` rewrites them as version 3.
+
+use std::io::{self, Read, Write};
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, Output, OUT_LEN};
+use crate::compression::{decoder, COMPRESSION_NONE};
+use crate::hash::Hash;
+
+const TREE_MAGIC: &[u8; 4] = b"B3MT";
+const TREE_VERSION: u8 = 1;
+// Version 2 adds a compression flag byte after the version
+const TREE_VERSION_FLAGGED: u8 = 2;
+/// The tree file version `write_to` and `write_to_compressed` produce.
+pub const TREE_FORMAT_VERSION: u8 = 3;
+// Magic, version, flag, leaf count, and root, covered by the checksum
+const CHECKED_HEADER_LEN: usize = 4 + 1 + 1 + 8 + OUT_LEN;
+const HEADER_CHECKSUM_LEN: usize = 8;
+
+/// Size of one serialized Output: input chaining value (8 words), block words
+/// (16 words), counter (u64), block length (u32), and flags (u32).
+pub const OUTPUT_ENCODED_LEN: usize = 8 * 4 + 16 * 4 + 8 + 4 + 4;
+
+pub fn encode_output(output: &Output, out: &mut Vec<u8>) {
+    for word in output.input_chaining_value.iter().chain(output.block_words.iter()) {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    out.extend_from_slice(&output.counter.to_le_bytes());
+    out.extend_from_slice(&output.block_len.to_le_bytes());
+    out.extend_from_slice(&output.flags.to_le_bytes());
+}
+
+pub fn decode_output(bytes: &[u8; OUTPUT_ENCODED_LEN]) -> Output {
+    let word = |i: usize| u32::from_le_bytes(bytes[i * 4..(i + 1) * 4].try_into().unwrap());
+    let mut input_chaining_value = [0; 8];
+    let mut block_words = [0; 16];
+    for (i, w) in input_chaining_value.iter_mut().enumerate() {
+        *w = word(i);
+    }
+    for (i, w) in block_words.iter_mut().enumerate() {
+        *w = word(8 + i);
+    }
+    Output {
+        input_chaining_value,
+        block_words,
+        counter: u64::from_le_bytes(bytes[96..104].try_into().unwrap()),
+        block_len: word(26),
+        flags: word(27),
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Write the tree as a version 3 file: the checked header, then every
+    /// leaf output. Interior nodes are recomputed when the tree is read back.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let body = self.encode_leaves();
+        self.write_checked(writer, COMPRESSION_NONE, &body)
+    }
+
+    /// Like `write_to`, with the leaves compressed with zstd at `level`. The
+    /// header stays uncompressed.
+    #[cfg(feature = "compression")]
+    pub fn write_to_compressed<W: Write>(&self, writer: W, level: i32) -> io::Result<()> {
+        let body = crate::compression::compress(&self.encode_leaves(), level)?;
+        self.write_checked(writer, crate::compression::COMPRESSION_ZSTD, &body)
+    }
+
+    /// Read a tree file of any version. Version 3 files are checked: a bad
+    /// header checksum or leaves that do not hash to the recorded root fail
+    /// with `InvalidData`.
+    pub fn read_from<R: Read>(reader: R) -> io::Result<BinaryMerkleTree> {
+        read_tree_file(reader).map(|(tree, _, _)| tree)
+    }
+
+    fn write_checked<W: Write>(&self, mut writer: W, flag: u8, body: &[u8]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(CHECKED_HEADER_LEN + HEADER_CHECKSUM_LEN + body.len());
+        bytes.extend_from_slice(TREE_MAGIC);
+        bytes.push(TREE_FORMAT_VERSION);
+        bytes.push(flag);
+        bytes.extend_from_slice(&(self.num_leaves() as u64).to_le_bytes());
+        bytes.extend_from_slice(self.root_hash().as_bytes());
+        let checksum = header_checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+        bytes.extend_from_slice(body);
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    // Every leaf output, in order
+    fn encode_leaves(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(OUTPUT_ENCODED_LEN * self.num_leaves());
+        for i in 0..self.num_leaves() {
+            encode_output(self.leaf(i), &mut bytes);
+        }
+        bytes
+    }
+}
+
+/// Rewrite a tree file of any older version as the current version, keeping
+/// its compression. Returns the version it was read as. Version 3 files are
+/// checked on the way through, so this also re-verifies current files.
+pub fn migrate_tree_file<R: Read, W: Write>(reader: R, writer: W) -> io::Result<u8> {
+    let (tree, version, flag) = read_tree_file(reader)?;
+    match flag {
+        #[cfg(feature = "compression")]
+        crate::compression::COMPRESSION_ZSTD => {
+            tree.write_to_compressed(writer, crate::compression::DEFAULT_LEVEL)?
+        }
+        _ => tree.write_to(writer)?,
+    }
+    Ok(version)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// The tree, the version it was stored as, and its compression flag
+fn read_tree_file<R: Read>(mut reader: R) -> io::Result<(BinaryMerkleTree, u8, u8)> {
+    let mut header = [0u8; CHECKED_HEADER_LEN + HEADER_CHECKSUM_LEN];
+    reader.read_exact(&mut header[..5])?;
+    if &header[..4] != TREE_MAGIC {
+        return Err(invalid("not a merkle tree file"));
+    }
+    let version = header[4];
+    let (flag, recorded) = match version {
+        TREE_VERSION => (COMPRESSION_NONE, None),
+        TREE_VERSION_FLAGGED => {
+            reader.read_exact(&mut header[5..6])?;
+            (header[5], None)
+        }
+        TREE_FORMAT_VERSION => {
+            reader.read_exact(&mut header[5..])?;
+            let (checked, checksum) = header.split_at(CHECKED_HEADER_LEN);
+            if header_checksum(checked) != checksum {
+                return Err(invalid("tree file header is corrupt: checksum mismatch"));
+            }
+            let leaf_count = u64::from_le_bytes(checked[6..14].try_into().unwrap());
+            let root = Hash::from_bytes(checked[14..].try_into().unwrap());
+            (checked[5], Some((leaf_count, root)))
+        }
+        _ => return Err(invalid("unsupported merkle tree file version")),
+    };
+    let mut body = decoder(reader, flag)?;
+
+    let leaf_count = match recorded {
+        Some((leaf_count, _)) => leaf_count,
+        None => {
+            let mut count = [0u8; 8];
+            body.read_exact(&mut count)?;
+            u64::from_le_bytes(count)
+        }
+    };
+    let leaf_count = usize::try_from(leaf_count).map_err(|_| invalid("leaf count too large"))?;
+    let mut leaves = Vec::new();
+    let mut record = [0u8; OUTPUT_ENCODED_LEN];
+    for _ in 0..leaf_count {
+        body.read_exact(&mut record)?;
+        leaves.push(decode_output(&record));
+    }
+    let tree = BinaryMerkleTree::new_from_leaves(leaves);
+    if let Some((_, root)) = recorded {
+        if !tree.root_hash().ct_eq(&root) {
+            return Err(invalid("tree file is corrupt: leaves do not hash to the recorded root"));
+        }
+    }
+    Ok((tree, version, flag))
+}
+
+fn header_checksum(header: &[u8]) -> [u8; HEADER_CHECKSUM_LEN] {
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(header);
+    let mut checksum = [0; HEADER_CHECKSUM_LEN];
+    hasher.finalize(&mut checksum);
+    checksum
+}
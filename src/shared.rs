@@ -0,0 +1,43 @@
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+
+/// A tree for read-mostly workloads shared between threads. Readers `load` the
+/// current version without ever blocking, and keep a consistent root and node
+/// set for as long as they hold it. Writers apply a batch of updates to a
+/// private copy and publish it atomically when the batch is done.
+///
+/// Each published version copies the node array once, so group many leaf
+/// updates into one `update` call rather than publishing each on its own.
+#[derive(Debug)]
+pub struct SharedMerkleTree {
+    current: ArcSwap<BinaryMerkleTree>,
+    // Serializes writers so no update is lost between copy and publish
+    writer: Mutex<()>,
+}
+
+impl SharedMerkleTree {
+    pub fn new(tree: BinaryMerkleTree) -> Self {
+        SharedMerkleTree {
+            current: ArcSwap::from_pointee(tree),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The current version. Later updates do not affect it.
+    pub fn load(&self) -> Arc<BinaryMerkleTree> {
+        self.current.load_full()
+    }
+
+    /// Apply `update` to a copy of the current version, then publish the copy.
+    /// Readers see either the old version or the new one, never a mix.
+    pub fn update<R>(&self, update: impl FnOnce(&mut BinaryMerkleTree) -> R) -> R {
+        let _writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut next = BinaryMerkleTree::clone(&self.current.load());
+        let result = update(&mut next);
+        self.current.store(Arc::new(next));
+        result
+    }
+}
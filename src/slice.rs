@@ -0,0 +1,165 @@
+use std::cmp::{max, min};
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{
+    chunk_output, left_subtree_len, parent_output, BinaryMerkleTree, Output, CHUNK_LEN, IV,
+};
+use crate::error::Error;
+use crate::hash::{cv_ct_eq, Hash};
+
+/// Length of an encoded parent node: the left and right chaining values.
+pub const PARENT_LEN: usize = 64;
+/// Length of the header holding the input length as a u64 LE.
+pub const HEADER_LEN: usize = 8;
+
+/// The chunks a slice for `byte_range` of an input of `len` bytes contains. A
+/// range that is empty or starts at or past the end still gets one chunk, the
+/// chunk containing its start or the final chunk, so the decoder always checks
+/// something against the root.
+fn slice_chunks(len: u64, byte_range: &Range<u64>) -> Range<usize> {
+    let leaf_count = max(1, len.div_ceil(CHUNK_LEN as u64));
+    let first = min(byte_range.start / CHUNK_LEN as u64, leaf_count - 1);
+    let end = byte_range.end.div_ceil(CHUNK_LEN as u64).clamp(first + 1, leaf_count);
+    first as usize..end as usize
+}
+
+impl BinaryMerkleTree {
+    /// Extract a Bao-style slice of `input`, the data this tree was built
+    /// from, for `byte_range`. The slice is the input length as a u64 LE,
+    /// followed by a pre-order walk of the tree that descends only into
+    /// subtrees overlapping the range: each parent on the way is written as
+    /// its two child chaining values, and each chunk in the range in full. A
+    /// client that knows only the root can check it with `decode_slice`.
+    ///
+    /// Returns None if `input` does not have as many chunks as the tree.
+    pub fn extract_slice(&self, input: &[u8], byte_range: Range<u64>) -> Option<Vec<u8>> {
+        if max(1, input.len().div_ceil(CHUNK_LEN)) != self.num_leaves() {
+            return None;
+        }
+        let chunks = slice_chunks(input.len() as u64, &byte_range);
+        let mut slice = (input.len() as u64).to_le_bytes().to_vec();
+        self.write_slice_subtree(input, 0, self.num_leaves(), &chunks, &mut slice);
+        Some(slice)
+    }
+
+    fn write_slice_subtree(
+        &self,
+        input: &[u8],
+        first_leaf: usize,
+        leaf_count: usize,
+        chunks: &Range<usize>,
+        slice: &mut Vec<u8>,
+    ) {
+        if leaf_count == 1 {
+            let end = min((first_leaf + 1) * CHUNK_LEN, input.len());
+            slice.extend_from_slice(&input[first_leaf * CHUNK_LEN..end]);
+            return;
+        }
+        let left_count = left_subtree_len(leaf_count);
+        let right_first = first_leaf + left_count;
+        for (first, count) in [(first_leaf, left_count), (right_first, leaf_count - left_count)] {
+            let cv = self.subtree_output(first, count).chaining_value();
+            slice.extend_from_slice(Hash::from_chaining_value(cv).as_bytes());
+        }
+        if chunks.start < right_first {
+            self.write_slice_subtree(input, first_leaf, left_count, chunks, slice);
+        }
+        if right_first < chunks.end {
+            self.write_slice_subtree(input, right_first, leaf_count - left_count, chunks, slice);
+        }
+    }
+}
+
+/// Check a slice from `extract_slice` against `root` and return the bytes of
+/// `byte_range` it carries, clipped to the end of the input. Every parent and
+/// chunk is verified before its contents are used. Bytes after the end of the
+/// slice are ignored.
+///
+/// The returned bytes are always authentic. The input length in the header is
+/// only authenticated as far as it shapes the verified path, and fully once
+/// the final chunk is verified, which happens whenever the range reaches the
+/// end of the input.
+pub fn decode_slice(root: &Hash, slice: &[u8], byte_range: Range<u64>) -> Result<Vec<u8>, Error> {
+    let header = slice.get(..HEADER_LEN).ok_or(Error::UnexpectedEnd)?;
+    let len = u64::from_le_bytes(header.try_into().unwrap());
+    let leaf_count = usize::try_from(max(1, len.div_ceil(CHUNK_LEN as u64))).map_err(|_| Error::UnexpectedEnd)?;
+    let mut decoder = SliceDecoder {
+        slice,
+        position: HEADER_LEN,
+        len,
+        chunks: slice_chunks(len, &byte_range),
+        wanted: byte_range.start..min(byte_range.end, len),
+        output: Vec::new(),
+    };
+    decoder.subtree(0, leaf_count, Expected::Root(root))?;
+    Ok(decoder.output)
+}
+
+// What a subtree must hash to: the root hash for the top of the tree, the
+// chaining value held by its parent everywhere else
+enum Expected<'a> {
+    Root(&'a Hash),
+    Cv([u32; 8]),
+}
+
+impl Expected<'_> {
+    fn matches(&self, output: &Output) -> bool {
+        match self {
+            Expected::Root(root) => Hash::from_root(output).ct_eq(root),
+            Expected::Cv(cv) => cv_ct_eq(&output.chaining_value(), cv),
+        }
+    }
+}
+
+struct SliceDecoder<'a> {
+    slice: &'a [u8],
+    position: usize,
+    len: u64,
+    chunks: Range<usize>,
+    wanted: Range<u64>,
+    output: Vec<u8>,
+}
+
+impl<'a> SliceDecoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        let slice = self.slice;
+        let bytes = slice
+            .get(self.position..self.position + count)
+            .ok_or(Error::UnexpectedEnd)?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn subtree(&mut self, first_leaf: usize, leaf_count: usize, expected: Expected) -> Result<(), Error> {
+        let offset = self.position as u64;
+        if leaf_count == 1 {
+            let start = first_leaf as u64 * CHUNK_LEN as u64;
+            let end = min(start + CHUNK_LEN as u64, self.len);
+            let chunk = self.take((end - start) as usize)?;
+            if !expected.matches(&chunk_output(first_leaf as u64, chunk)) {
+                return Err(Error::HashMismatch { offset });
+            }
+            let from = self.wanted.start.clamp(start, end);
+            let to = self.wanted.end.clamp(from, end);
+            let wanted = &chunk[(from - start) as usize..(to - start) as usize];
+            self.output.extend_from_slice(wanted);
+            return Ok(());
+        }
+
+        let parent = self.take(PARENT_LEN)?;
+        let left = Hash::from_bytes(parent[..32].try_into().unwrap()).to_chaining_value();
+        let right = Hash::from_bytes(parent[32..].try_into().unwrap()).to_chaining_value();
+        if !expected.matches(&parent_output(left, right, IV, 0)) {
+            return Err(Error::HashMismatch { offset });
+        }
+        let left_count = left_subtree_len(leaf_count);
+        let right_first = first_leaf + left_count;
+        if self.chunks.start < right_first {
+            self.subtree(first_leaf, left_count, Expected::Cv(left))?;
+        }
+        if right_first < self.chunks.end {
+            self.subtree(right_first, leaf_count - left_count, Expected::Cv(right))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::hasher::{Blake3TreeHasher, TreeHasher};
+
+/// A read-only view of a `BinaryMerkleTree` as it was when `snapshot` was
+/// called. Taking a snapshot is O(1): it shares the node array with the tree,
+/// and the tree copies the array on its next write. Readers can keep computing
+/// roots, proofs, and diffs against the snapshot while the writer moves on.
+#[derive(Debug, Clone)]
+pub struct TreeSnapshot<H: TreeHasher = Blake3TreeHasher> {
+    tree: BinaryMerkleTree<H>,
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    pub fn snapshot(&self) -> TreeSnapshot<H> {
+        // A snapshot is read-only, so an open journal has nothing to undo in it,
+        // there is nothing to record, and cached block chaining values would
+        // never be used. All three are left out rather than copied.
+        let tree = BinaryMerkleTree {
+            tree: Arc::clone(&self.tree),
+            leaf_count: self.leaf_count,
+            journal: None,
+            recording: None,
+            parent_hashes: self.parent_hashes,
+            level_rebuild_threshold: self.level_rebuild_threshold,
+            mode: self.mode,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+            block_cvs: None,
+        };
+        TreeSnapshot { tree }
+    }
+}
+
+impl<H: TreeHasher> TreeSnapshot<H> {
+    /// Turn the snapshot into an independent, writable tree. It does not
+    /// cache block chaining values until `cache_block_cvs` is called on it.
+    pub fn into_tree(self) -> BinaryMerkleTree<H> {
+        self.tree
+    }
+
+    /// Whether the snapshot still shares its node array with `tree`, i.e. the
+    /// tree has not been written since the snapshot was taken.
+    pub fn shares_nodes_with(&self, tree: &BinaryMerkleTree<H>) -> bool {
+        Arc::ptr_eq(&self.tree.tree, &tree.tree)
+    }
+}
+
+// Only the tree's `&self` methods are reachable through the snapshot
+impl<H: TreeHasher> Deref for TreeSnapshot<H> {
+    type Target = BinaryMerkleTree<H>;
+
+    fn deref(&self) -> &BinaryMerkleTree<H> {
+        &self.tree
+    }
+}
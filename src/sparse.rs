@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::binary_merkle_tree::{parent_cv, Blake3Hasher, IV, OUT_LEN};
+use crate::hash::Hash;
+
+// Domain separation for the two kinds of hashing the sparse tree does, so a
+// key can never be confused with a leaf, or either with a file chunk
+const KEY_CONTEXT: &str = "blake3_merkle_tree 2024 sparse key v1";
+const LEAF_CONTEXT: &str = "blake3_merkle_tree 2024 sparse leaf v1";
+
+/// Depth of the tree: one level per bit of a derived key.
+pub const SPARSE_DEPTH: usize = OUT_LEN * 8;
+
+// An absent leaf. Real leaves are BLAKE3 outputs, so none of them is all zeros
+// in practice.
+const EMPTY_LEAF: [u32; 8] = [0; 8];
+
+/// A sparse Merkle tree over 2^256 leaves, addressed by the BLAKE3 hash of a key
+/// rather than by position. Only the nodes above present keys are stored; every
+/// other subtree is empty and has a fixed, precomputed chaining value. Proofs
+/// show either that a key maps to a value or that the key is absent.
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree {
+    values: BTreeMap<Hash, Vec<u8>>,
+    // Non-empty nodes keyed by (height, path prefix with the low `height` bits
+    // cleared). Height 0 is a leaf, SPARSE_DEPTH the root.
+    nodes: HashMap<(u16, [u8; OUT_LEN]), [u32; 8]>,
+    empty: Vec<[u32; 8]>,
+}
+
+/// Proof of the value (or absence) of a key. Only the siblings on the path
+/// that are not empty subtrees are included; `non_empty` marks which heights
+/// they belong to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseProof {
+    /// Bit h is set if the sibling at height h is included in `siblings`.
+    pub non_empty: [u8; SPARSE_DEPTH / 8],
+    /// The included siblings, lowest first.
+    pub siblings: Vec<[u32; 8]>,
+}
+
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseMerkleTree {
+    pub fn new() -> Self {
+        SparseMerkleTree {
+            values: BTreeMap::new(),
+            nodes: HashMap::new(),
+            empty: empty_subtrees(),
+        }
+    }
+
+    /// The 256-bit address a key is stored at.
+    pub fn key_hash(key: &[u8]) -> Hash {
+        let mut hasher = Blake3Hasher::new_derive_key(KEY_CONTEXT);
+        hasher.update(key);
+        let mut digest = [0; OUT_LEN];
+        hasher.finalize(&mut digest);
+        Hash::from_bytes(digest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn root_hash(&self) -> Hash {
+        Hash::from_chaining_value(self.node(SPARSE_DEPTH, &[0; OUT_LEN]))
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.values.get(&Self::key_hash(key)).map(Vec::as_slice)
+    }
+
+    /// Set the value of `key`, returning the previous one.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Option<Vec<u8>> {
+        let address = Self::key_hash(key);
+        self.update_path(&address, leaf_cv(&address, &value));
+        self.values.insert(address, value)
+    }
+
+    /// Remove `key`, returning its value if it was present.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let address = Self::key_hash(key);
+        let previous = self.values.remove(&address)?;
+        self.update_path(&address, EMPTY_LEAF);
+        Some(previous)
+    }
+
+    /// Prove the current value of `key`, or that it is absent.
+    pub fn prove(&self, key: &[u8]) -> SparseProof {
+        let address = Self::key_hash(key);
+        let mut non_empty = [0; SPARSE_DEPTH / 8];
+        let mut siblings = Vec::new();
+        for height in 0..SPARSE_DEPTH {
+            let sibling = self.node(height, &sibling_prefix(&address, height));
+            if sibling != self.empty[height] {
+                non_empty[height / 8] |= 1 << (height % 8);
+                siblings.push(sibling);
+            }
+        }
+        SparseProof { non_empty, siblings }
+    }
+
+    fn node(&self, height: usize, prefix: &[u8; OUT_LEN]) -> [u32; 8] {
+        self.nodes
+            .get(&(height as u16, *prefix))
+            .copied()
+            .unwrap_or(self.empty[height])
+    }
+
+    fn set_node(&mut self, height: usize, prefix: [u8; OUT_LEN], cv: [u32; 8]) {
+        // Empty subtrees are implied, so only non-empty nodes take up space
+        if cv == self.empty[height] {
+            self.nodes.remove(&(height as u16, prefix));
+        } else {
+            self.nodes.insert((height as u16, prefix), cv);
+        }
+    }
+
+    fn update_path(&mut self, address: &Hash, leaf: [u32; 8]) {
+        let mut node = leaf;
+        self.set_node(0, *address.as_bytes(), node);
+        for height in 0..SPARSE_DEPTH {
+            let sibling = self.node(height, &sibling_prefix(address, height));
+            node = combine(address, height, node, sibling);
+            self.set_node(height + 1, prefix(address, height + 1), node);
+        }
+    }
+}
+
+impl SparseProof {
+    /// Check that `key` maps to `value` in the tree with root `root`, or, if
+    /// `value` is None, that `key` is absent from it.
+    pub fn verify(&self, root: &Hash, key: &[u8], value: Option<&[u8]>) -> bool {
+        let expected_siblings = self.non_empty.iter().map(|byte| byte.count_ones() as usize).sum();
+        if self.siblings.len() != expected_siblings {
+            return false;
+        }
+        let address = SparseMerkleTree::key_hash(key);
+        let empty = empty_subtrees();
+        let mut siblings = self.siblings.iter();
+        let mut node = value.map_or(EMPTY_LEAF, |value| leaf_cv(&address, value));
+        for (height, empty_sibling) in empty.iter().take(SPARSE_DEPTH).enumerate() {
+            let sibling = if self.non_empty[height / 8] >> (height % 8) & 1 == 1 {
+                *siblings.next().expect("sibling count checked above")
+            } else {
+                *empty_sibling
+            };
+            node = combine(&address, height, node, sibling);
+        }
+        Hash::from_chaining_value(node).ct_eq(root)
+    }
+}
+
+fn leaf_cv(address: &Hash, value: &[u8]) -> [u32; 8] {
+    let mut hasher = Blake3Hasher::new_derive_key(LEAF_CONTEXT);
+    hasher.update(address.as_bytes());
+    hasher.update(value);
+    let mut digest = [0; OUT_LEN];
+    hasher.finalize(&mut digest);
+    Hash::from_bytes(digest).to_chaining_value()
+}
+
+// empty[h] is the chaining value of an empty subtree of height h
+fn empty_subtrees() -> Vec<[u32; 8]> {
+    let mut empty = Vec::with_capacity(SPARSE_DEPTH + 1);
+    empty.push(EMPTY_LEAF);
+    for height in 0..SPARSE_DEPTH {
+        empty.push(parent_cv(empty[height], empty[height], IV, 0));
+    }
+    empty
+}
+
+// Bits are taken most significant first, so the root splits on the top bit of
+// the first byte and a leaf's parent on the lowest bit of the last byte
+fn path_bit(address: &Hash, height: usize) -> u8 {
+    let bit = SPARSE_DEPTH - 1 - height;
+    address.as_bytes()[bit / 8] >> (7 - bit % 8) & 1
+}
+
+// Combine the node at `height` on the path of `address` with its sibling
+fn combine(address: &Hash, height: usize, node: [u32; 8], sibling: [u32; 8]) -> [u32; 8] {
+    if path_bit(address, height) == 0 {
+        parent_cv(node, sibling, IV, 0)
+    } else {
+        parent_cv(sibling, node, IV, 0)
+    }
+}
+
+// The address with its low `height` bits cleared, identifying the node at
+// `height` on its path
+fn prefix(address: &Hash, height: usize) -> [u8; OUT_LEN] {
+    let mut prefix = *address.as_bytes();
+    for bit in SPARSE_DEPTH - height..SPARSE_DEPTH {
+        prefix[bit / 8] &= !(1 << (7 - bit % 8));
+    }
+    prefix
+}
+
+fn sibling_prefix(address: &Hash, height: usize) -> [u8; OUT_LEN] {
+    let mut sibling = prefix(address, height);
+    let bit = SPARSE_DEPTH - 1 - height;
+    sibling[bit / 8] ^= 1 << (7 - bit % 8);
+    sibling
+}
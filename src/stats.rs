@@ -0,0 +1,101 @@
+use std::mem::size_of;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::blocks::BlockCvs;
+use crate::grouped::GroupedMerkleTree;
+use crate::hasher::TreeHasher;
+use crate::oplog::Operation;
+
+/// Size and work counters for a `BinaryMerkleTree`, from `stats()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Number of levels above the leaves; 0 for a single leaf.
+    pub depth: u32,
+    pub leaf_count: usize,
+    /// Interior node slots in the node array.
+    pub interior_nodes: usize,
+    /// Leaf slots holding a real leaf.
+    pub populated_leaves: usize,
+    /// Leaf slots past the last leaf, which pad the array to a power of two.
+    pub empty_leaves: usize,
+    /// Approximate heap memory held by the tree, in bytes. A node array shared
+    /// with snapshots is counted in full.
+    pub heap_bytes: usize,
+    /// Parent nodes hashed since the tree was constructed, including building it.
+    pub parent_hashes: u64,
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    pub fn stats(&self) -> TreeStats {
+        let leaf_slots = self.leaf_offset();
+        let journal_bytes = self
+            .journal()
+            .map_or(0, |journal| journal.len() * size_of::<(usize, H::Node)>());
+        TreeStats {
+            depth: leaf_slots.trailing_zeros(),
+            leaf_count: self.num_leaves(),
+            interior_nodes: leaf_slots - 1,
+            populated_leaves: self.num_leaves(),
+            empty_leaves: leaf_slots - self.num_leaves(),
+            heap_bytes: self.tree.capacity() * size_of::<H::Node>() + journal_bytes,
+            parent_hashes: self.parent_hash_count(),
+        }
+    }
+}
+
+/// Bytes held by a tree, from `memory_usage()`, for estimating how many trees
+/// fit in a process. Heap sizes use allocated capacity where it is known and
+/// length otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The tree value itself, wherever it is stored.
+    pub inline_bytes: usize,
+    /// The heap node array. An array shared with snapshots is counted in full
+    /// by every tree holding it.
+    pub node_bytes: usize,
+    /// Bookkeeping beside the nodes: an open journal and operation recording.
+    pub index_bytes: usize,
+    /// Chaining values cached below the leaves, by `cache_block_cvs`.
+    pub cached_cv_bytes: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.inline_bytes + self.node_bytes + self.index_bytes + self.cached_cv_bytes
+    }
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let journal_bytes = self
+            .journal()
+            .map_or(0, |journal| journal.len() * size_of::<(usize, H::Node)>());
+        let recording_bytes = self.recording().map_or(0, |log| {
+            log.operations()
+                .iter()
+                .map(|operation| match operation {
+                    Operation::Rebuild(leaves) => size_of::<Operation<H::Node>>() + leaves.capacity() * size_of::<H::Node>(),
+                    Operation::SetLeaf { .. } => size_of::<Operation<H::Node>>(),
+                })
+                .sum()
+        });
+        MemoryUsage {
+            inline_bytes: size_of::<Self>(),
+            node_bytes: self.tree.capacity() * size_of::<H::Node>(),
+            index_bytes: journal_bytes + recording_bytes,
+            cached_cv_bytes: self.block_cvs.as_ref().map_or(0, BlockCvs::heap_bytes),
+        }
+    }
+}
+
+impl GroupedMerkleTree {
+    /// As for `BinaryMerkleTree::memory_usage`, over the group leaves. Groups
+    /// keep only their subtree output, so no chunk chaining values are cached
+    /// and `cached_cv_bytes` is 0.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            inline_bytes: size_of::<Self>(),
+            ..self.tree().memory_usage()
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::ops::Range;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN};
+use crate::diff::{diff_leaves, SubtreeCvs};
+use crate::hash::Hash;
+
+const SUMMARY_MAGIC: &[u8; 4] = b"B3NS";
+const SUMMARY_VERSION: u8 = 1;
+
+/// The chaining values of every node of a tree, in the same 1-indexed heap
+/// layout the tree uses. This is what one side of a sync sends the other so
+/// differences can be located without exchanging chunk data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeSummary {
+    leaf_count: usize,
+    nodes: Vec<[u32; 8]>,
+}
+
+impl TreeSummary {
+    pub fn from_tree(tree: &BinaryMerkleTree) -> Self {
+        Self {
+            leaf_count: tree.num_leaves(),
+            nodes: tree.tree.iter().map(|node| node.chaining_value()).collect(),
+        }
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Serialize as: magic, version, leaf count and node count as u64 LE, then
+    /// every node chaining value in LE.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + 1 + 16 + 32 * self.nodes.len());
+        bytes.extend_from_slice(SUMMARY_MAGIC);
+        bytes.push(SUMMARY_VERSION);
+        bytes.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.nodes.len() as u64).to_le_bytes());
+        for node in &self.nodes {
+            bytes.extend_from_slice(Hash::from_chaining_value(*node).as_bytes());
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<TreeSummary> {
+        let rest = bytes.strip_prefix(SUMMARY_MAGIC)?;
+        let (&version, rest) = rest.split_first()?;
+        if version != SUMMARY_VERSION || rest.len() < 16 {
+            return None;
+        }
+        let leaf_count = usize::try_from(u64::from_le_bytes(rest[..8].try_into().unwrap())).ok()?;
+        let node_count = usize::try_from(u64::from_le_bytes(rest[8..16].try_into().unwrap())).ok()?;
+        let rest = &rest[16..];
+        // A valid summary has a power-of-two node array large enough for its leaves
+        if !node_count.is_power_of_two()
+            || node_count < 2
+            || leaf_count > node_count / 2
+            || rest.len() != node_count.checked_mul(32)?
+        {
+            return None;
+        }
+        let nodes = rest
+            .chunks_exact(32)
+            .map(|node| Hash::from_bytes(node.try_into().unwrap()).to_chaining_value())
+            .collect();
+        Some(TreeSummary { leaf_count, nodes })
+    }
+}
+
+impl SubtreeCvs for TreeSummary {
+    fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    fn subtree_cv(&self, first_leaf: usize, leaf_count: usize) -> [u32; 8] {
+        let height = leaf_count.next_power_of_two().trailing_zeros();
+        self.nodes[(self.nodes.len() / 2 + first_leaf) >> height]
+    }
+}
+
+/// What a local copy must fetch to match a remote tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncPlan {
+    /// Chunk ranges to fetch from the remote, ascending and non-overlapping.
+    pub fetch: Vec<Range<usize>>,
+    /// Number of chunks the remote has. Local chunks at or past this index
+    /// must be dropped.
+    pub remote_leaf_count: usize,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.fetch.is_empty()
+    }
+
+    pub fn chunks_to_fetch(&self) -> usize {
+        self.fetch.iter().map(|range| range.len()).sum()
+    }
+
+    /// The fetch ranges in bytes, given the remote's total length in bytes.
+    pub fn byte_ranges(&self, remote_len: u64) -> Vec<Range<u64>> {
+        self.fetch
+            .iter()
+            .map(|range| {
+                let start = (range.start * CHUNK_LEN) as u64;
+                let end = ((range.end * CHUNK_LEN) as u64).min(remote_len);
+                start..end
+            })
+            .collect()
+    }
+}
+
+/// Plan the chunk transfers that make `local` match the tree `remote` summarizes.
+pub fn plan_sync(local: &BinaryMerkleTree, remote: &TreeSummary) -> SyncPlan {
+    let mut fetch: Vec<Range<usize>> = Vec::new();
+    for index in diff_leaves(local, remote) {
+        if index >= remote.leaf_count() {
+            // Only exists locally; dropped rather than fetched
+            break;
+        }
+        match fetch.last_mut() {
+            Some(last) if last.end == index => last.end += 1,
+            _ => fetch.push(index..index + 1),
+        }
+    }
+    SyncPlan {
+        fetch,
+        remote_leaf_count: remote.leaf_count(),
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use crate::binary_merkle_tree::{chunk_output, left_subtree_len, parent_output, Output, CHUNK_LEN, IV};
+use crate::error::Error;
+use crate::hash::Hash;
+use crate::partial::PartialTree;
+use crate::proof::RangeProof;
+
+/// One leaf of an announced state transition: a proof of the leaf as it was
+/// under the old root, and the chunk bytes that replace it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafTransition {
+    /// Proof for exactly one chunk against the old root.
+    pub proof: RangeProof,
+    /// The leaf the proof covers, as `BinaryMerkleTree::get_leaf` returns it.
+    pub old_leaf: Output,
+    pub new_chunk: Vec<u8>,
+}
+
+/// Check every transition against `old_root` and return the root after all
+/// of them are applied, using only the proofs: no tree is built. Each proof
+/// supplies the siblings along its leaf's path, and siblings that contain
+/// another changed leaf are recomputed rather than taken from the proof, so
+/// the proofs may all be made against the old tree independently.
+///
+/// Fails with `InvalidProof` if a proof does not hold under `old_root`, the
+/// proofs disagree about the tree's size, a leaf appears twice, or a new chunk
+/// has a length no chunk at that position could have.
+pub fn verify_transition(old_root: &Hash, transitions: &[LeafTransition]) -> Result<Hash, Error> {
+    let Some(first) = transitions.first() else {
+        return Ok(*old_root);
+    };
+    let total_chunks = first.proof.total_chunks;
+    let mut partial = PartialTree::new(*old_root, total_chunks);
+    let mut new_leaves = BTreeMap::new();
+    for transition in transitions {
+        let index = transition.proof.chunks.start;
+        let len = transition.new_chunk.len();
+        let fits = if index + 1 < total_chunks { len == CHUNK_LEN } else { len <= CHUNK_LEN };
+        if transition.proof.chunks.len() != 1 || !fits {
+            return Err(Error::InvalidProof);
+        }
+        partial.ingest(&transition.proof, &[transition.old_leaf])?;
+        let new_leaf = chunk_output(index as u64, &transition.new_chunk);
+        if new_leaves.insert(index, new_leaf).is_some() {
+            return Err(Error::InvalidProof);
+        }
+    }
+    let root = new_subtree(&partial, &new_leaves, 0, total_chunks).ok_or(Error::InvalidProof)?;
+    Ok(Hash::from_root(&root))
+}
+
+// The output of a subtree holding at least one changed leaf
+fn new_subtree(
+    partial: &PartialTree,
+    new_leaves: &BTreeMap<usize, Output>,
+    first_leaf: usize,
+    leaf_count: usize,
+) -> Option<Output> {
+    if leaf_count == 1 {
+        return new_leaves.get(&first_leaf).copied();
+    }
+    let left_count = left_subtree_len(leaf_count);
+    let child_cv = |first: usize, count: usize| {
+        if new_leaves.range(first..first + count).next().is_some() {
+            new_subtree(partial, new_leaves, first, count).map(|output| output.chaining_value())
+        } else {
+            partial.subtree_cv(first, count)
+        }
+    };
+    let left = child_cv(first_leaf, left_count)?;
+    let right = child_cv(first_leaf + left_count, leaf_count - left_count)?;
+    Some(parent_output(left, right, IV, 0))
+}
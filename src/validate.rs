@@ -0,0 +1,109 @@
+use std::collections::BTreeSet;
+use std::io;
+
+use crate::binary_merkle_tree::{first_leaf_below, BinaryMerkleTree};
+use crate::file::bytes_in_chunks;
+use crate::hasher::TreeHasher;
+use crate::node_index::NodeIndex;
+use crate::provider::{chunk_count, provider_chunk_output, ChunkProvider};
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    /// Recompute every parent from its stored children and return the heap
+    /// indices, in increasing order, of the nodes whose stored value differs.
+    /// Leaves cannot be checked without the input, so a bad leaf shows up as
+    /// its parent disagreeing with it. An empty list means every interior
+    /// node is consistent with the leaves.
+    pub fn validate(&self) -> Vec<usize> {
+        (1..self.leaf_offset())
+            .filter(|&index| self.covers_leaves(index) && self.expected_node(index) != self.tree[index])
+            .collect()
+    }
+
+    /// Rebuild the nodes `validate` flags, and everything above them, from
+    /// the leaves, which are trusted as they are. Returns the heap indices of
+    /// the nodes that changed, in increasing order; afterwards `validate`
+    /// finds nothing.
+    pub fn repair(&mut self) -> Vec<usize> {
+        let flagged = self.validate();
+        self.rebuild_above(flagged)
+    }
+
+    // Recompute the nodes in `dirty` and all their ancestors from the leaves
+    // up, returning those whose value changed
+    pub(crate) fn rebuild_above(&mut self, dirty: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let mut pending: BTreeSet<usize> = dirty.into_iter().collect();
+        let mut changed = Vec::new();
+        // Children have larger heap indices than their parents, so taking the
+        // largest pending index first always sees both children final
+        while let Some(index) = pending.pop_last() {
+            let node_index = NodeIndex(index);
+            if !node_index.is_leaf(self) {
+                let node = self.parent_of(node_index.left_child().get(), node_index.right_child().get());
+                if node != self.tree[index] {
+                    self.set_node(index, node);
+                    changed.push(index);
+                }
+            }
+            if let Some(parent) = node_index.parent() {
+                pending.insert(parent.get());
+            }
+        }
+        self.report_metrics();
+        changed.sort_unstable();
+        changed
+    }
+
+    // Whether the node at `index` has at least one real leaf below it
+    pub(crate) fn covers_leaves(&self, index: usize) -> bool {
+        first_leaf_below(index, self.leaf_offset()) < self.leaf_count
+    }
+
+    // The interior node at `index` as its stored children determine it,
+    // without counting the hash as work done on the tree
+    pub(crate) fn expected_node(&self, index: usize) -> H::Node {
+        let (left, right) = (2 * index, 2 * index + 1);
+        if self.covers_leaves(right) {
+            H::parent_with_mode(&self.tree[left], &self.tree[right], &self.mode)
+        } else {
+            self.tree[left]
+        }
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Like `repair`, but without trusting the leaves below a flagged parent
+    /// of leaves: those chunks are read back from `provider` and rehashed
+    /// first, so a corrupted leaf is restored rather than built upon. The
+    /// provider must hold exactly as many chunks as the tree has leaves.
+    /// Returns the heap indices of every node that changed, leaves included.
+    pub fn repair_from_provider<P: ChunkProvider + ?Sized>(&mut self, provider: &P) -> io::Result<Vec<usize>> {
+        let len = provider.len()?;
+        if chunk_count(len) != self.num_leaves() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("provider holds {} chunks but the tree has {} leaves", chunk_count(len), self.num_leaves()),
+            ));
+        }
+        let flagged = self.validate();
+        let leaf_offset = self.leaf_offset();
+        let suspect: BTreeSet<usize> = flagged
+            .iter()
+            .filter(|&&index| 2 * index >= leaf_offset)
+            .flat_map(|&index| [2 * index, 2 * index + 1])
+            .filter(|&index| index - leaf_offset < self.num_leaves())
+            .collect();
+        let mut changed = Vec::new();
+        for index in suspect {
+            let leaf_index = index - leaf_offset;
+            let leaf = provider_chunk_output(provider, len, leaf_index)?;
+            self.count_bytes_hashed(bytes_in_chunks(len, leaf_index..leaf_index + 1));
+            if leaf != *self.leaf(leaf_index) {
+                self.set_leaf_only(leaf_index, leaf);
+                changed.push(index);
+            }
+        }
+        changed.extend(self.rebuild_above(flagged));
+        changed.sort_unstable();
+        Ok(changed)
+    }
+}
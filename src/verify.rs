@@ -0,0 +1,542 @@
+//! Parallel integrity scans of files. Every thread opens the file itself and
+//! reads its own contiguous run of chunks, so verification runs at the speed
+//! of hashing across all cores rather than of one reader.
+
+use std::cmp::min;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::binary_merkle_tree::{chunk_output, first_leaf_below, BinaryMerkleTree, Output, CHUNK_LEN};
+use crate::file::{read_full, READ_BUFFER_LEN};
+use crate::hash::Hash;
+use crate::node_index::NodeIndex;
+
+/// How much of a file `verify_file` checks once a chunk does not match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatches {
+    /// Stop at the first mismatching chunk, the cheapest answer to whether
+    /// the file is intact.
+    First,
+    /// Check every chunk and report every mismatching range.
+    All,
+}
+
+/// Check the file at `path` against a trusted `root` alone, hashing its
+/// chunks on every available thread. Without leaves to compare against, a
+/// mismatch cannot be narrowed down; use `BinaryMerkleTree::verify_file` for
+/// that.
+pub fn verify_file_root<P: AsRef<Path>>(path: P, root: &Hash) -> io::Result<bool> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    verify_file_root_with_threads(path, root, threads)
+}
+
+/// Like `verify_file_root`, hashing on at most `max_threads` threads.
+pub fn verify_file_root_with_threads<P: AsRef<Path>>(path: P, root: &Hash, max_threads: usize) -> io::Result<bool> {
+    let path = path.as_ref();
+    let chunk_count = file_chunk_count(path)?;
+    let outputs = std::thread::scope(|scope| {
+        let handles: Vec<_> = split_chunks(0..chunk_count, max_threads)
+            .map(|range| {
+                scope.spawn(move || {
+                    let mut outputs = Vec::with_capacity(range.len());
+                    hash_chunks(path, range, |_, output| {
+                        outputs.push(output);
+                        true
+                    })?;
+                    Ok(outputs)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("verification thread panicked"))
+            .collect::<io::Result<Vec<Vec<Output>>>>()
+    })?;
+    let tree = BinaryMerkleTree::new_from_leaves(outputs.into_iter().flatten().collect());
+    Ok(tree.root_hash().ct_eq(root))
+}
+
+impl BinaryMerkleTree {
+    /// Re-hash the file at `path` on every available thread and compare each
+    /// chunk with this tree's leaf. Returns the byte ranges of the chunks that
+    /// differ, adjacent ones merged, so an empty list means the file matches
+    /// the tree; check `root_hash` against a trusted root to trust the tree
+    /// itself. Chunks present in only one of the file and the tree count as
+    /// differing. With `Mismatches::First` at most the first differing chunk
+    /// is reported.
+    pub fn verify_file<P: AsRef<Path>>(&self, path: P, mismatches: Mismatches) -> io::Result<Vec<Range<u64>>> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.verify_file_with_threads(path, mismatches, threads)
+    }
+
+    /// Like `verify_file`, hashing on at most `max_threads` threads.
+    pub fn verify_file_with_threads<P: AsRef<Path>>(
+        &self,
+        path: P,
+        mismatches: Mismatches,
+        max_threads: usize,
+    ) -> io::Result<Vec<Range<u64>>> {
+        self.verify_unskipped(path.as_ref(), mismatches, max_threads, &[])
+    }
+
+    /// Like `verify_file`, skipping the subtrees with a current mark in
+    /// `marks`: their chunks are trusted to still match.
+    pub fn verify_file_skipping<P: AsRef<Path>>(
+        &self,
+        path: P,
+        mismatches: Mismatches,
+        marks: &VerifiedMarks,
+    ) -> io::Result<Vec<Range<u64>>> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.verify_unskipped(path.as_ref(), mismatches, threads, &marks.verified_chunks(self))
+    }
+
+    fn verify_unskipped(
+        &self,
+        path: &Path,
+        mismatches: Mismatches,
+        max_threads: usize,
+        skipped: &[Range<usize>],
+    ) -> io::Result<Vec<Range<u64>>> {
+        let file_chunks = file_chunk_count(path)?;
+        let mut bad_chunks = Vec::new();
+        for range in unskipped(file_chunks.max(self.num_leaves()), skipped) {
+            bad_chunks.extend(self.mismatched_chunks(path, range, mismatches, max_threads)?);
+            if mismatches == Mismatches::First && !bad_chunks.is_empty() {
+                bad_chunks.truncate(1);
+                break;
+            }
+        }
+        let len = std::fs::metadata(path)?.len();
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        let mut previous = None;
+        for index in bad_chunks {
+            let start = (index * CHUNK_LEN) as u64;
+            // Chunks past the end of the file cover their nominal bytes
+            let end = if index < file_chunks { min(start + CHUNK_LEN as u64, len) } else { start + CHUNK_LEN as u64 };
+            match ranges.last_mut() {
+                Some(last) if previous == Some(index - 1) => last.end = end,
+                _ => ranges.push(start..end),
+            }
+            previous = Some(index);
+        }
+        Ok(ranges)
+    }
+
+    // Sorted indices of the chunks in `range` whose bytes in the file at
+    // `path` differ from this tree's leaves, chunks present in only one of the
+    // two included. With `Mismatches::First` the lowest is exact but others
+    // may be missing.
+    pub(crate) fn mismatched_chunks(
+        &self,
+        path: &Path,
+        range: Range<usize>,
+        mismatches: Mismatches,
+        max_threads: usize,
+    ) -> io::Result<Vec<usize>> {
+        let file_chunks = file_chunk_count(path)?;
+        let common_end = range.end.min(file_chunks).min(self.num_leaves()).max(range.start);
+        // Lowest mismatching chunk found so far; in First mode threads past it
+        // give up
+        let first_bad = AtomicUsize::new(usize::MAX);
+        let bad_chunks = std::thread::scope(|scope| {
+            let first_bad = &first_bad;
+            let handles: Vec<_> = split_chunks(range.start..common_end, max_threads)
+                .map(|range| {
+                    scope.spawn(move || {
+                        let mut bad = Vec::new();
+                        hash_chunks(path, range, |index, output| {
+                            if output != *self.leaf(index) {
+                                bad.push(index);
+                                first_bad.fetch_min(index, Ordering::Relaxed);
+                            }
+                            mismatches == Mismatches::All || index < first_bad.load(Ordering::Relaxed)
+                        })?;
+                        Ok(bad)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("verification thread panicked"))
+                .collect::<io::Result<Vec<Vec<usize>>>>()
+        })?;
+
+        let mut bad_chunks: Vec<usize> = bad_chunks.into_iter().flatten().collect();
+        bad_chunks.extend(common_end..range.end);
+        bad_chunks.sort_unstable();
+        Ok(bad_chunks)
+    }
+}
+
+const CURSOR_MAGIC: &[u8; 4] = b"B3VC";
+const CURSOR_VERSION: u8 = 1;
+
+/// Progress of a verification that runs in steps, so an interrupted
+/// verification of a huge file resumes where it left off. Chunks are checked
+/// left to right in aligned subtrees; each subtree that matches is recorded,
+/// merged with its sibling once both have passed, and chunks that do not
+/// match are kept as ranges. Save the cursor with `write_to` between steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyCursor {
+    leaf_count: usize,
+    root: Hash,
+    next_chunk: usize,
+    passed: BTreeSet<NodeIndex>,
+    failed: Vec<Range<usize>>,
+}
+
+impl VerifyCursor {
+    /// A cursor at the start of `tree`, which later steps must be given.
+    pub fn new(tree: &BinaryMerkleTree) -> Self {
+        VerifyCursor {
+            leaf_count: tree.num_leaves(),
+            root: tree.root_hash(),
+            next_chunk: 0,
+            passed: BTreeSet::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next_chunk == self.leaf_count
+    }
+
+    /// Chunks checked so far, whether they matched or not.
+    pub fn chunks_checked(&self) -> usize {
+        self.next_chunk
+    }
+
+    /// Roots of the largest subtrees whose chunks all matched, left to right.
+    pub fn passed_subtrees(&self) -> Vec<NodeIndex> {
+        self.passed.iter().copied().collect()
+    }
+
+    /// Chunk index ranges found not to match, adjacent ones merged.
+    pub fn failed_chunks(&self) -> &[Range<usize>] {
+        &self.failed
+    }
+
+    /// Check up to `max_chunks` more chunks of the file at `path` against
+    /// `tree`, hashing on at most `max_threads` threads, and return how many
+    /// were checked. Fails with `InvalidInput` if `tree` is not the tree the
+    /// cursor was created for.
+    pub fn step<P: AsRef<Path>>(
+        &mut self,
+        tree: &BinaryMerkleTree,
+        path: P,
+        max_chunks: usize,
+        max_threads: usize,
+    ) -> io::Result<usize> {
+        if tree.num_leaves() != self.leaf_count || !tree.root_hash().ct_eq(&self.root) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "verification cursor belongs to another tree"));
+        }
+        let path = path.as_ref();
+        let start = self.next_chunk;
+        while self.next_chunk < self.leaf_count && self.next_chunk - start < max_chunks {
+            // The largest aligned subtree starting here that fits the budget
+            let budget = max_chunks - (self.next_chunk - start);
+            let mut height = 0;
+            while (self.next_chunk >> height) & 1 == 0
+                && 2usize << height <= budget
+                && 2usize << height <= tree.leaf_offset()
+            {
+                height += 1;
+            }
+            let end = min(self.next_chunk + (1 << height), self.leaf_count);
+            let bad = tree.mismatched_chunks(path, self.next_chunk..end, Mismatches::All, max_threads)?;
+            if bad.is_empty() {
+                self.pass(NodeIndex((tree.leaf_offset() + self.next_chunk) >> height));
+            }
+            for index in bad {
+                match self.failed.last_mut() {
+                    Some(last) if last.end == index => last.end += 1,
+                    _ => self.failed.push(index..index + 1),
+                }
+            }
+            self.next_chunk = end;
+        }
+        Ok(self.next_chunk - start)
+    }
+
+    // Record a passed subtree, folding it into its parent while its sibling
+    // has passed too or covers no leaves
+    fn pass(&mut self, mut node: NodeIndex) {
+        let leaf_offset = self.leaf_count.next_power_of_two();
+        while let Some(sibling) = node.sibling() {
+            let empty = first_leaf_below(sibling.get(), leaf_offset) >= self.leaf_count;
+            if !empty && !self.passed.remove(&sibling) {
+                break;
+            }
+            node = node.parent().expect("a node with a sibling has a parent");
+        }
+        self.passed.insert(node);
+    }
+
+    /// Serialize as: magic, version, leaf count, root hash, next chunk, the
+    /// passed subtree roots, then the failed ranges, with every number and
+    /// count a u64 LE.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut bytes = CURSOR_MAGIC.to_vec();
+        bytes.push(CURSOR_VERSION);
+        bytes.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        bytes.extend_from_slice(self.root.as_bytes());
+        bytes.extend_from_slice(&(self.next_chunk as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.passed.len() as u64).to_le_bytes());
+        for node in &self.passed {
+            bytes.extend_from_slice(&(node.get() as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.failed.len() as u64).to_le_bytes());
+        for range in &self.failed {
+            bytes.extend_from_slice(&(range.start as u64).to_le_bytes());
+            bytes.extend_from_slice(&(range.end as u64).to_le_bytes());
+        }
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    /// Read a cursor written by `write_to`.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<VerifyCursor> {
+        fn invalid(message: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+        }
+        fn read_u64<R: Read>(reader: &mut R) -> io::Result<usize> {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            usize::try_from(u64::from_le_bytes(bytes)).map_err(|_| invalid("verification cursor value too large"))
+        }
+
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != CURSOR_MAGIC {
+            return Err(invalid("not a verification cursor"));
+        }
+        if header[4] != CURSOR_VERSION {
+            return Err(invalid("unsupported verification cursor version"));
+        }
+        let leaf_count = read_u64(&mut reader)?;
+        let mut root = [0u8; 32];
+        reader.read_exact(&mut root)?;
+        let next_chunk = read_u64(&mut reader)?;
+        if next_chunk > leaf_count {
+            return Err(invalid("verification cursor is past the end of its tree"));
+        }
+        let passed_len = read_u64(&mut reader)?;
+        let passed = (0..passed_len)
+            .map(|_| read_u64(&mut reader).and_then(|index| NodeIndex::new(index).ok_or_else(|| invalid("invalid node index"))))
+            .collect::<io::Result<_>>()?;
+        let failed_len = read_u64(&mut reader)?;
+        let failed = (0..failed_len)
+            .map(|_| Ok(read_u64(&mut reader)?..read_u64(&mut reader)?))
+            .collect::<io::Result<_>>()?;
+        Ok(VerifyCursor { leaf_count, root: Hash::from_bytes(root), next_chunk, passed, failed })
+    }
+}
+
+const MARKS_MAGIC: &[u8; 4] = b"B3VM";
+const MARKS_VERSION: u8 = 1;
+
+/// A verified subtree: the generation it was verified in, and its chaining
+/// value at the time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifiedMark {
+    pub generation: u64,
+    cv: [u32; 8],
+}
+
+/// Subtrees whose data has been checked, so repeated scans of cold data can
+/// skip them. A mark only counts while the tree still holds the chaining
+/// value it was made with; once the subtree changes it is checked again.
+/// Generations are the caller's: a timestamp, or a scan counter that
+/// `expire_before` uses to force a fresh check every so often.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifiedMarks {
+    marks: BTreeMap<NodeIndex, VerifiedMark>,
+}
+
+impl VerifiedMarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.marks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty()
+    }
+
+    /// Mark the subtree at `node` of `tree` as verified in `generation`.
+    ///
+    /// Panics if `node` covers no leaves of `tree`.
+    pub fn mark(&mut self, tree: &BinaryMerkleTree, node: NodeIndex, generation: u64) {
+        assert!(covers_leaves(tree, node), "node {} covers no leaves", node.get());
+        let cv = tree.node_cv(node.get()).expect("node is in the tree");
+        self.marks.insert(node, VerifiedMark { generation, cv });
+    }
+
+    /// Mark every subtree `cursor` has seen pass.
+    pub fn mark_passed(&mut self, tree: &BinaryMerkleTree, cursor: &VerifyCursor, generation: u64) {
+        for node in cursor.passed_subtrees() {
+            self.mark(tree, node, generation);
+        }
+    }
+
+    /// The mark on `node`, if there is one and the subtree has not changed
+    /// since.
+    pub fn get(&self, tree: &BinaryMerkleTree, node: NodeIndex) -> Option<VerifiedMark> {
+        self.marks.get(&node).copied().filter(|mark| is_current(tree, node, mark))
+    }
+
+    /// Drop the marks made before `generation`, so those subtrees are checked
+    /// again.
+    pub fn expire_before(&mut self, generation: u64) {
+        self.marks.retain(|_, mark| mark.generation >= generation);
+    }
+
+    // Chunk ranges under marks that are still current in `tree`, sorted and
+    // merged
+    pub(crate) fn verified_chunks(&self, tree: &BinaryMerkleTree) -> Vec<Range<usize>> {
+        let leaf_level = tree.leaf_offset().trailing_zeros();
+        let mut ranges: Vec<Range<usize>> = self
+            .marks
+            .iter()
+            .filter(|(node, mark)| is_current(tree, **node, mark))
+            .map(|(node, _)| {
+                let first = node.first_leaf(tree);
+                first..min(first + (1 << (leaf_level - node.level())), tree.num_leaves())
+            })
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// Serialize as: magic, version, mark count as u64 LE, then per mark the
+    /// node index and generation as u64 LE and the chaining value as eight
+    /// u32 LE words.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut bytes = MARKS_MAGIC.to_vec();
+        bytes.push(MARKS_VERSION);
+        bytes.extend_from_slice(&(self.marks.len() as u64).to_le_bytes());
+        for (node, mark) in &self.marks {
+            bytes.extend_from_slice(&(node.get() as u64).to_le_bytes());
+            bytes.extend_from_slice(&mark.generation.to_le_bytes());
+            for word in mark.cv {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    /// Read marks written by `write_to`.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<VerifiedMarks> {
+        fn invalid(message: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+        }
+        fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        }
+
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MARKS_MAGIC {
+            return Err(invalid("not a verified marks file"));
+        }
+        if header[4] != MARKS_VERSION {
+            return Err(invalid("unsupported verified marks version"));
+        }
+        let mut marks = BTreeMap::new();
+        for _ in 0..read_u64(&mut reader)? {
+            let node = usize::try_from(read_u64(&mut reader)?)
+                .ok()
+                .and_then(NodeIndex::new)
+                .ok_or_else(|| invalid("invalid node index"))?;
+            let generation = read_u64(&mut reader)?;
+            let mut cv = [0u32; 8];
+            for word in &mut cv {
+                let mut bytes = [0u8; 4];
+                reader.read_exact(&mut bytes)?;
+                *word = u32::from_le_bytes(bytes);
+            }
+            marks.insert(node, VerifiedMark { generation, cv });
+        }
+        Ok(VerifiedMarks { marks })
+    }
+}
+
+fn covers_leaves(tree: &BinaryMerkleTree, node: NodeIndex) -> bool {
+    node.get() < 2 * tree.leaf_offset() && node.first_leaf(tree) < tree.num_leaves()
+}
+
+fn is_current(tree: &BinaryMerkleTree, node: NodeIndex, mark: &VerifiedMark) -> bool {
+    covers_leaves(tree, node) && tree.node_cv(node.get()) == Some(mark.cv)
+}
+
+// The parts of 0..end outside the sorted, disjoint `skipped` ranges
+pub(crate) fn unskipped(end: usize, skipped: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for range in skipped {
+        if range.start > start {
+            ranges.push(start..min(range.start, end));
+        }
+        start = start.max(range.end);
+    }
+    if start < end {
+        ranges.push(start..end);
+    }
+    ranges
+}
+
+// Empty files are still one (empty) chunk
+fn file_chunk_count(path: &Path) -> io::Result<usize> {
+    let len = std::fs::metadata(path)?.len();
+    Ok((len.div_ceil(CHUNK_LEN as u64) as usize).max(1))
+}
+
+// Contiguous runs of the chunks in `range`, one per thread
+fn split_chunks(range: Range<usize>, threads: usize) -> impl Iterator<Item = Range<usize>> {
+    let per_thread = range.len().div_ceil(threads.max(1)).max(1);
+    let end = range.end;
+    range.step_by(per_thread).map(move |start| start..min(start + per_thread, end))
+}
+
+// Hash the chunks in `range` of the file at `path` in order, passing each to
+// `visit` until it returns false
+fn hash_chunks(path: &Path, range: Range<usize>, mut visit: impl FnMut(usize, Output) -> bool) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start((range.start * CHUNK_LEN) as u64))?;
+    let mut reader = file.take(((range.end - range.start) * CHUNK_LEN) as u64);
+    let mut buffer = vec![0u8; READ_BUFFER_LEN];
+    let mut index = range.start;
+    while index < range.end {
+        let filled = read_full(&mut reader, &mut buffer)?;
+        // An empty file is one empty chunk; otherwise a short read means the
+        // file shrank while being read
+        if filled == 0 && index > 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank during verification"));
+        }
+        for chunk in buffer[..filled].chunks(CHUNK_LEN).chain((filled == 0).then_some(&[][..])) {
+            if !visit(index, chunk_output(index as u64, chunk)) {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+//! Hash trees in the formats of Linux dm-verity (hash type 1) and fs-verity,
+//! so the same files can be handed to `veritysetup`, `fsverity`, and the
+//! kernel. Unlike the binary trees elsewhere in this crate, each hash block
+//! holds as many SHA-256 digests as fit, the levels are stored from the top
+//! down, and every digest is salted: SHA-256(salt || block).
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::file::read_full;
+
+const DIGEST_LEN: usize = 32;
+const SUPERBLOCK_LEN: usize = 512;
+const SIGNATURE: &[u8; 8] = b"verity\0\0";
+const MAX_SALT_LEN: usize = 256;
+
+/// Parameters of a dm-verity hash tree, as passed to `veritysetup format`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerityParams {
+    pub data_block_size: u32,
+    pub hash_block_size: u32,
+    /// At most 256 bytes.
+    pub salt: Vec<u8>,
+    /// Recorded in the superblock only.
+    pub uuid: [u8; 16],
+    /// Whether to start the hash device with a superblock, padded to a hash
+    /// block, or to write the bare tree (`--no-superblock`).
+    pub superblock: bool,
+}
+
+impl Default for VerityParams {
+    /// The `veritysetup` defaults apart from the salt, which is empty rather
+    /// than random so that output is reproducible.
+    fn default() -> Self {
+        VerityParams { data_block_size: 4096, hash_block_size: 4096, salt: Vec::new(), uuid: [0; 16], superblock: true }
+    }
+}
+
+/// What `write_verity_tree` wrote, and what `veritysetup open` needs besides
+/// the parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerityTree {
+    pub root_hash: [u8; 32],
+    pub data_blocks: u64,
+    /// Blocks of the tree itself, not counting the superblock.
+    pub hash_blocks: u64,
+}
+
+impl VerityTree {
+    pub fn root_hash_hex(&self) -> String {
+        self.root_hash.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+}
+
+/// Hash the data read from `data` in data blocks, zero-padding a final
+/// partial block, and write the hash device contents to `hash_device`.
+/// Fails with `ErrorKind::InvalidInput` for block sizes that are not powers
+/// of two from 512 bytes up, or salts longer than 256 bytes.
+pub fn write_verity_tree<R: Read, W: Write>(
+    mut data: R,
+    params: &VerityParams,
+    mut hash_device: W,
+) -> io::Result<VerityTree> {
+    let valid_size = |size: u32| size.is_power_of_two() && size >= 512;
+    if !valid_size(params.data_block_size) || !valid_size(params.hash_block_size) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "block sizes must be powers of two of at least 512"));
+    }
+    if params.salt.len() > MAX_SALT_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "salt is longer than 256 bytes"));
+    }
+    let hash_block_size = params.hash_block_size as usize;
+
+    // Level 0 digests the data blocks; each level above digests the hash
+    // blocks of the one below, until a level fits in one block
+    let mut data_digests = Vec::new();
+    let mut block = vec![0u8; params.data_block_size as usize];
+    let mut data_blocks = 0u64;
+    loop {
+        let filled = read_full(&mut data, &mut block)?;
+        if filled == 0 {
+            break;
+        }
+        block[filled..].fill(0);
+        data_digests.extend_from_slice(&salted_digest(&params.salt, &block));
+        data_blocks += 1;
+    }
+    let mut levels = vec![pack_hash_blocks(&data_digests, hash_block_size)];
+    while levels.last().expect("there is at least one level").len() > hash_block_size {
+        let below = levels.last().expect("there is at least one level");
+        let digests: Vec<u8> =
+            below.chunks(hash_block_size).flat_map(|block| salted_digest(&params.salt, block)).collect();
+        levels.push(pack_hash_blocks(&digests, hash_block_size));
+    }
+    let root_hash = salted_digest(&params.salt, levels.last().expect("there is at least one level"));
+
+    if params.superblock {
+        let mut superblock = encode_superblock(params, data_blocks);
+        superblock.resize(hash_block_size, 0);
+        hash_device.write_all(&superblock)?;
+    }
+    for level in levels.iter().rev() {
+        hash_device.write_all(level)?;
+    }
+    hash_device.flush()?;
+    let hash_blocks = levels.iter().map(|level| (level.len() / hash_block_size) as u64).sum();
+    Ok(VerityTree { root_hash, data_blocks, hash_blocks })
+}
+
+/// `write_verity_tree` over the file at `path`.
+pub fn verity_tree_from_file<P: AsRef<Path>, W: Write>(
+    path: P,
+    params: &VerityParams,
+    hash_device: W,
+) -> io::Result<VerityTree> {
+    write_verity_tree(File::open(path)?, params, hash_device)
+}
+
+const FSVERITY_MAX_SALT_LEN: usize = 32;
+const FSVERITY_DESCRIPTOR_LEN: usize = 256;
+// FS_VERITY_HASH_ALG_SHA256
+const FSVERITY_SHA256: u8 = 1;
+// SHA-256's input block size, which fs-verity pads the salt to
+const SHA256_BLOCK_LEN: usize = 64;
+
+/// Parameters of an fs-verity Merkle tree, as passed to `fsverity enable`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsVerityParams {
+    /// 4096 unless the filesystem supports others.
+    pub block_size: u32,
+    /// At most 32 bytes.
+    pub salt: Vec<u8>,
+}
+
+impl Default for FsVerityParams {
+    fn default() -> Self {
+        FsVerityParams { block_size: 4096, salt: Vec::new() }
+    }
+}
+
+/// An fs-verity Merkle tree and the file measurement the kernel reports for
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsVerityTree {
+    pub params: FsVerityParams,
+    pub data_size: u64,
+    /// Zero for an empty file, the digest of the one data block for files of
+    /// a single block, and otherwise the digest of the top hash block.
+    pub root_hash: [u8; 32],
+    /// The hash blocks, level by level from the top down; empty for files of
+    /// at most one block.
+    pub tree: Vec<u8>,
+}
+
+impl FsVerityTree {
+    /// The `fsverity_descriptor` whose SHA-256 is the file digest.
+    pub fn descriptor(&self) -> [u8; FSVERITY_DESCRIPTOR_LEN] {
+        let mut descriptor = [0u8; FSVERITY_DESCRIPTOR_LEN];
+        descriptor[0] = 1;
+        descriptor[1] = FSVERITY_SHA256;
+        descriptor[2] = self.params.block_size.trailing_zeros() as u8;
+        descriptor[3] = self.params.salt.len() as u8;
+        descriptor[8..16].copy_from_slice(&self.data_size.to_le_bytes());
+        descriptor[16..48].copy_from_slice(&self.root_hash);
+        descriptor[80..80 + self.params.salt.len()].copy_from_slice(&self.params.salt);
+        descriptor
+    }
+
+    /// The file measurement, as returned by `FS_IOC_MEASURE_VERITY`.
+    pub fn digest(&self) -> [u8; 32] {
+        Sha256::digest(self.descriptor()).into()
+    }
+
+    /// The measurement as printed by `fsverity digest`, e.g. `sha256:3d24...`.
+    pub fn digest_string(&self) -> String {
+        let hex: String = self.digest().iter().map(|byte| format!("{byte:02x}")).collect();
+        format!("sha256:{hex}")
+    }
+}
+
+/// Build the fs-verity tree of the data read from `data`. Fails with
+/// `ErrorKind::InvalidInput` for block sizes that are not powers of two from
+/// 1024 to 65536 bytes, or salts longer than 32 bytes.
+pub fn build_fsverity_tree<R: Read>(mut data: R, params: &FsVerityParams) -> io::Result<FsVerityTree> {
+    if !params.block_size.is_power_of_two() || !(1024..=65536).contains(&params.block_size) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "block size must be a power of two from 1024 to 65536"));
+    }
+    if params.salt.len() > FSVERITY_MAX_SALT_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "salt is longer than 32 bytes"));
+    }
+    let block_size = params.block_size as usize;
+    let mut salt = params.salt.clone();
+    if !salt.is_empty() {
+        salt.resize(salt.len().next_multiple_of(SHA256_BLOCK_LEN), 0);
+    }
+
+    let mut digests = Vec::new();
+    let mut block = vec![0u8; block_size];
+    let mut data_size = 0u64;
+    loop {
+        let filled = read_full(&mut data, &mut block)?;
+        if filled == 0 {
+            break;
+        }
+        block[filled..].fill(0);
+        digests.extend_from_slice(&salted_digest(&salt, &block));
+        data_size += filled as u64;
+    }
+
+    // Levels are added until one holds a single digest, which is the root
+    let mut levels = Vec::new();
+    while digests.len() > DIGEST_LEN {
+        let level = pack_hash_blocks(&digests, block_size);
+        digests = level.chunks(block_size).flat_map(|block| salted_digest(&salt, block)).collect();
+        levels.push(level);
+    }
+    let root_hash = digests.try_into().unwrap_or([0; DIGEST_LEN]);
+    let tree = levels.into_iter().rev().flatten().collect();
+    Ok(FsVerityTree { params: params.clone(), data_size, root_hash, tree })
+}
+
+/// `build_fsverity_tree` over the file at `path`.
+pub fn fsverity_tree_from_file<P: AsRef<Path>>(path: P, params: &FsVerityParams) -> io::Result<FsVerityTree> {
+    build_fsverity_tree(File::open(path)?, params)
+}
+
+fn salted_digest(salt: &[u8], block: &[u8]) -> [u8; DIGEST_LEN] {
+    Sha256::new().chain_update(salt).chain_update(block).finalize().into()
+}
+
+// Digests packed into whole hash blocks, the last zero-padded. Even no
+// digests make one block, as for empty data.
+fn pack_hash_blocks(digests: &[u8], hash_block_size: usize) -> Vec<u8> {
+    let mut blocks = digests.to_vec();
+    blocks.resize(digests.len().div_ceil(hash_block_size).max(1) * hash_block_size, 0);
+    blocks
+}
+
+// struct verity_sb from veritysetup, little-endian throughout
+fn encode_superblock(params: &VerityParams, data_blocks: u64) -> Vec<u8> {
+    let mut superblock = Vec::with_capacity(SUPERBLOCK_LEN);
+    superblock.extend_from_slice(SIGNATURE);
+    // Format version, then hash type 1
+    superblock.extend_from_slice(&1u32.to_le_bytes());
+    superblock.extend_from_slice(&1u32.to_le_bytes());
+    superblock.extend_from_slice(&params.uuid);
+    let mut algorithm = [0u8; 32];
+    algorithm[..6].copy_from_slice(b"sha256");
+    superblock.extend_from_slice(&algorithm);
+    superblock.extend_from_slice(&params.data_block_size.to_le_bytes());
+    superblock.extend_from_slice(&params.hash_block_size.to_le_bytes());
+    superblock.extend_from_slice(&data_blocks.to_le_bytes());
+    superblock.extend_from_slice(&(params.salt.len() as u16).to_le_bytes());
+    superblock.extend_from_slice(&[0; 6]);
+    let mut salt = [0u8; MAX_SALT_LEN];
+    salt[..params.salt.len()].copy_from_slice(&params.salt);
+    superblock.extend_from_slice(&salt);
+    superblock.resize(SUPERBLOCK_LEN, 0);
+    superblock
+}
@@ -0,0 +1,93 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Output};
+
+/// A new root emitted after the watched file changed.
+#[derive(Debug, Clone)]
+pub struct RootUpdate {
+    pub root: Output,
+    /// Indices of the chunks whose contents changed since the previous root.
+    pub changed_chunks: Vec<usize>,
+}
+
+/// Keeps a Merkle tree in sync with a file on disk. Every time the file is
+/// modified, its chunks are re-hashed, only the ancestors of changed chunks are
+/// recomputed, and the new root is sent on the channel returned by `updates`.
+pub struct WatchedMerkleFile {
+    path: PathBuf,
+    tree: Arc<Mutex<BinaryMerkleTree>>,
+    updates: Receiver<RootUpdate>,
+    // Dropping the watcher stops the event subscription
+    _watcher: RecommendedWatcher,
+}
+
+impl WatchedMerkleFile {
+    /// Hash the file at `path` and start watching it for changes.
+    pub fn watch<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (tree, _) = BinaryMerkleTree::from_file(&path)?;
+        let tree = Arc::new(Mutex::new(tree));
+        let (sender, updates) = mpsc::channel();
+
+        let handler_tree = Arc::clone(&tree);
+        let handler_path = path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+                    rehash(&handler_path, &handler_tree, &sender);
+                }
+            }
+        })
+        .map_err(io::Error::other)?;
+        watcher
+            .watch(&path, RecursiveMode::NonRecursive)
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            path,
+            tree,
+            updates,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Receiver for the roots emitted after each change.
+    pub fn updates(&self) -> &Receiver<RootUpdate> {
+        &self.updates
+    }
+
+    /// The current root of the watched file.
+    pub fn root(&self) -> Output {
+        self.tree.lock().unwrap().root()
+    }
+
+    /// A copy of the current tree.
+    pub fn tree(&self) -> BinaryMerkleTree {
+        self.tree.lock().unwrap().clone()
+    }
+}
+
+fn rehash(path: &Path, tree: &Mutex<BinaryMerkleTree>, sender: &Sender<RootUpdate>) {
+    let mut tree = tree.lock().unwrap();
+    // The file may be mid-rewrite or briefly missing; the next event retries
+    let Ok(changed_chunks) = tree.refresh_from_file(path) else {
+        return;
+    };
+    if changed_chunks.is_empty() {
+        return;
+    }
+    // The receiver going away only means nobody is listening any more
+    let _ = sender.send(RootUpdate {
+        root: tree.root(),
+        changed_chunks,
+    });
+}
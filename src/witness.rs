@@ -0,0 +1,149 @@
+//! Every compression on the path from one leaf to the root, with its exact
+//! inputs and outputs, for auditors or circuit builders who want to re-check
+//! the arithmetic independently. With the `serde` feature the witness types
+//! implement `Serialize`, so a witness can be exported as JSON or any other
+//! serde format.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::binary_merkle_tree::{compress, BinaryMerkleTree, Output, ROOT};
+use crate::hash::Hash;
+use crate::node_index::NodeIndex;
+
+/// One call of the BLAKE3 compression function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Compression {
+    pub input_chaining_value: [u32; 8],
+    pub block_words: [u32; 16],
+    pub counter: u64,
+    pub block_len: u32,
+    pub flags: u32,
+    /// All 16 output words. The first 8 are the chaining value, or the root
+    /// hash as little-endian words when `flags` has ROOT set.
+    pub output: [u32; 16],
+}
+
+impl Compression {
+    fn of(node: &Output, extra_flags: u32) -> Self {
+        let flags = node.flags | extra_flags;
+        Compression {
+            input_chaining_value: node.input_chaining_value,
+            block_words: node.block_words,
+            counter: node.counter,
+            block_len: node.block_len,
+            flags,
+            output: compress(&node.input_chaining_value, &node.block_words, node.counter, node.block_len, flags),
+        }
+    }
+
+    pub fn chaining_value(&self) -> [u32; 8] {
+        self.output[..8].try_into().unwrap()
+    }
+
+    /// Whether `output` is what compressing the recorded inputs gives.
+    pub fn is_consistent(&self) -> bool {
+        compress(&self.input_chaining_value, &self.block_words, self.counter, self.block_len, self.flags) == self.output
+    }
+}
+
+/// Which child of a parent the path came up through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Side {
+    /// The path's chaining value is block words 0..8.
+    Left,
+    /// The path's chaining value is block words 8..16.
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct WitnessStep {
+    /// Heap index of the node compressed (1 is the root).
+    pub node_index: usize,
+    /// For parent nodes, where the previous step's chaining value sits in the
+    /// block. None for the leaf.
+    pub path_child: Option<Side>,
+    pub compression: Compression,
+}
+
+/// The compressions from a leaf to the root. Nodes promoted unchanged from a
+/// lone left child involve no compression and have no step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PathWitness {
+    pub leaf_index: usize,
+    pub total_chunks: usize,
+    /// Leaf first. The last step has the ROOT flag and yields the root hash;
+    /// in a one-chunk tree it is the only step.
+    pub steps: Vec<WitnessStep>,
+}
+
+impl PathWitness {
+    /// The root hash the last step yields.
+    pub fn root_hash(&self) -> Hash {
+        Hash::from_chaining_value(self.steps.last().expect("a witness has at least one step").compression.chaining_value())
+    }
+
+    /// Recompute every step, check that each one's chaining value feeds the
+    /// next at the recorded side, and that the last yields `root`.
+    pub fn verify(&self, root: &Hash) -> bool {
+        let Some((last, _)) = self.steps.split_last() else {
+            return false;
+        };
+        let linked = self.steps.windows(2).all(|pair| {
+            let words = &pair[1].compression.block_words;
+            let child = match pair[1].path_child {
+                Some(Side::Left) => &words[..8],
+                Some(Side::Right) => &words[8..],
+                None => return false,
+            };
+            child == pair[0].compression.chaining_value()
+        });
+        linked
+            && last.compression.flags & ROOT != 0
+            && self.steps.iter().all(|step| step.compression.is_consistent())
+            && self.root_hash().ct_eq(root)
+    }
+}
+
+impl BinaryMerkleTree {
+    /// The witness for the path from leaf `leaf_index` to the root, or None
+    /// past the last leaf.
+    pub fn path_witness(&self, leaf_index: usize) -> Option<PathWitness> {
+        self.get_leaf(leaf_index)?;
+        let mut steps = Vec::new();
+        let mut node_index = self.leaf_offset() + leaf_index;
+        let mut path_child = None;
+        loop {
+            let is_root = node_index == 1;
+            steps.push(WitnessStep {
+                node_index,
+                path_child,
+                compression: Compression::of(&self.tree[node_index], if is_root { ROOT } else { 0 }),
+            });
+            if is_root {
+                break;
+            }
+            // Skip the ancestors promoted unchanged because their right
+            // subtree holds no leaves; the first real parent compresses this
+            // node's chaining value
+            let mut child = NodeIndex(node_index);
+            while let Some(parent) = child
+                .parent()
+                .filter(|&parent| parent != NodeIndex::ROOT && parent.right_child().first_leaf(self) >= self.num_leaves())
+            {
+                child = parent;
+            }
+            path_child = Some(if child.is_left_child() { Side::Left } else { Side::Right });
+            node_index = child.parent().expect("the root ends the walk").get();
+        }
+        Some(PathWitness {
+            leaf_index,
+            total_chunks: self.num_leaves(),
+            steps,
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use crate::binary_merkle_tree::BinaryMerkleTree;
+use crate::hasher::{Blake3TreeHasher, TreeHasher};
+
+/// The nodes of subtrees whose leaves all hold one filler value, by height:
+/// `node(0)` is the filler leaf and `node(h)` the root of 2^h copies of it.
+/// Computing them costs one parent per level, after which zero-filled trees
+/// and their untouched regions need no hashing, and sparse representations
+/// can leave out any node equal to the table entry for its height.
+///
+/// BLAKE3 chunk leaves carry their chunk counter, so the all-zero chunk has a
+/// different output at every position and a file of zeros has no repeating
+/// subtrees. The table is for leaves that do not depend on their position,
+/// such as those of the SHA-256 and Keccak-256 hashers.
+#[derive(Debug, Clone)]
+pub struct ZeroSubtrees<H: TreeHasher = Blake3TreeHasher> {
+    nodes: Vec<H::Node>,
+}
+
+impl<H: TreeHasher> ZeroSubtrees<H> {
+    /// The nodes of subtrees of `leaf` from height 0 up to `height`.
+    pub fn new(leaf: H::Node, height: u32) -> Self {
+        let mut nodes = Vec::with_capacity(height as usize + 1);
+        nodes.push(leaf);
+        for below in 0..height as usize {
+            nodes.push(H::parent(&nodes[below], &nodes[below]));
+        }
+        ZeroSubtrees { nodes }
+    }
+
+    /// Height of the tallest subtree in the table.
+    pub fn height(&self) -> u32 {
+        self.nodes.len() as u32 - 1
+    }
+
+    /// The root of a complete subtree of `height` filler leaves, or None above
+    /// the table.
+    pub fn node(&self, height: u32) -> Option<&H::Node> {
+        self.nodes.get(height as usize)
+    }
+
+    /// Whether `node` is the root of a subtree of `height` filler leaves, so a
+    /// sparse representation can omit it.
+    pub fn is_zero(&self, height: u32, node: &H::Node) -> bool {
+        self.node(height) == Some(node)
+    }
+}
+
+impl<H: TreeHasher> BinaryMerkleTree<H> {
+    /// A tree of `leaf_count` copies of the table's filler leaf. Complete
+    /// subtrees are copied from `zeros`, so only the partial node at the end
+    /// of each level is hashed: at most one parent per level instead of one
+    /// per leaf. Panics if `zeros` is shorter than the tree.
+    pub fn new_zeroed(leaf_count: usize, zeros: &ZeroSubtrees<H>) -> Self {
+        let capacity = leaf_count.max(1).next_power_of_two();
+        let tree_height = capacity.trailing_zeros();
+        assert!(
+            tree_height <= zeros.height(),
+            "a tree of {} leaves needs a table of height {}",
+            leaf_count,
+            tree_height
+        );
+        let mut tree = Self::new_empty(capacity as u64);
+        tree.leaf_count = leaf_count;
+        let leaf_offset = tree.leaf_offset();
+        let nodes = Arc::make_mut(&mut tree.tree);
+        for height in 0..=tree_height {
+            let level_start = leaf_offset >> height;
+            nodes[level_start..level_start + (leaf_count >> height)].fill(zeros.nodes[height as usize]);
+        }
+        // The partial subtree at the end of a level is built on the one below,
+        // so these go bottom up
+        for height in 1..=tree_height {
+            if !leaf_count.is_multiple_of(1 << height) {
+                let index = (leaf_offset >> height) + (leaf_count >> height);
+                let parent = tree.parent_of(2 * index, 2 * index + 1);
+                tree.set_node(index, parent);
+            }
+        }
+        tree
+    }
+}
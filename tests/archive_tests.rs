@@ -0,0 +1,76 @@
+use merkle_tree::archive::ArchiveProof;
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::directory::{DirectoryEntry, DirectoryManifest};
+use merkle_tree::hash::Hash;
+
+fn file(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(seed).wrapping_add(seed)).collect()
+}
+
+fn archive(files: &[(&str, Vec<u8>)]) -> (DirectoryManifest, Vec<BinaryMerkleTree>) {
+    let trees: Vec<BinaryMerkleTree> =
+        files.iter().map(|(_, data)| BinaryMerkleTree::new_from_leaves(process_input_to_chunks(data))).collect();
+    let entries = files
+        .iter()
+        .zip(&trees)
+        .map(|((path, data), tree)| DirectoryEntry {
+            path: path.to_string(),
+            mode: 0o644,
+            len: data.len() as u64,
+            root: tree.root_hash(),
+        })
+        .collect();
+    (DirectoryManifest::from_entries(entries), trees)
+}
+
+#[test]
+fn test_proves_byte_range_of_one_file() {
+    let files = [
+        ("bin/tool", file(3, 5 * CHUNK_LEN + 17)),
+        ("README", file(5, 300)),
+        ("lib/libfoo.so", file(7, 9 * CHUNK_LEN)),
+    ];
+    let (manifest, trees) = archive(&files);
+    let root = manifest.root_hash();
+
+    let (path, data) = &files[2];
+    let range = 2 * CHUNK_LEN as u64 + 10..4 * CHUNK_LEN as u64 + 1;
+    let proof = manifest.prove_file_bytes(path, &trees[2], data, range.clone()).unwrap();
+    assert_eq!(proof.entry.path, "lib/libfoo.so");
+    let slice = &data[range.start as usize..range.end as usize];
+    assert!(proof.verify(&root, range.clone(), slice));
+
+    let decoded = ArchiveProof::decode(&proof.encode()).unwrap();
+    assert_eq!(decoded, proof);
+    assert!(decoded.verify(&root, range.clone(), slice));
+
+    // Wrong bytes, wrong archive root
+    let mut tampered = slice.to_vec();
+    tampered[0] ^= 1;
+    assert!(!proof.verify(&root, range.clone(), &tampered));
+    assert!(!proof.verify(&Hash::from_bytes([0; 32]), range, slice));
+}
+
+#[test]
+fn test_entry_cannot_be_swapped() {
+    let files = [("a", file(1, 2 * CHUNK_LEN)), ("b", file(2, 2 * CHUNK_LEN))];
+    let (manifest, trees) = archive(&files);
+    let root = manifest.root_hash();
+    let proof = manifest.prove_file_bytes("a", &trees[0], &files[0].1, 0..10).unwrap();
+
+    let mut renamed = proof.clone();
+    renamed.entry.path = "b".to_string();
+    assert!(!renamed.verify(&root, 0..10, &files[0].1[..10]));
+    let mut moved = proof.clone();
+    moved.entry_index = 1;
+    assert!(!moved.verify(&root, 0..10, &files[0].1[..10]));
+}
+
+#[test]
+fn test_prove_rejects_unknown_file_or_mismatched_tree() {
+    let files = [("a", file(1, 100)), ("b", file(2, 100))];
+    let (manifest, trees) = archive(&files);
+    assert!(manifest.prove_file_bytes("c", &trees[0], &files[0].1, 0..10).is_none());
+    assert!(manifest.prove_file_bytes("a", &trees[1], &files[1].1, 0..10).is_none());
+    assert!(ArchiveProof::decode(b"B3AP").is_none());
+}
@@ -0,0 +1,85 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, HashMode, CHUNK_LEN};
+use merkle_tree::error::Error;
+
+fn tree_over(content: &[u8]) -> BinaryMerkleTree {
+    BinaryMerkleTree::new_from_leaves(process_input_to_chunks(content))
+}
+
+#[test]
+fn test_cached_updates_match_fresh_trees() {
+    let mut content: Vec<u8> = (0..5 * CHUNK_LEN + 300).map(|i| (i % 251) as u8).collect();
+    let mut tree = tree_over(&content);
+    tree.cache_block_cvs(&content).unwrap();
+    assert!(tree.memory_usage().cached_cv_bytes >= 6 * 512);
+
+    for offset in [0, 63, 64, CHUNK_LEN - 1, 2 * CHUNK_LEN + 700, 5 * CHUNK_LEN + 299] {
+        content[offset] ^= 0xff;
+        tree.update_bytes(&content, offset as u64..offset as u64 + 1);
+        assert!(tree.has_block_cvs());
+        assert_eq!(tree.root_hash(), tree_over(&content).root_hash(), "write at {}", offset);
+    }
+
+    // Growing the final chunk resumes from its old final block, then shrinking
+    // drops cached chunks past the new end
+    for len in [6 * CHUNK_LEN - 10, 7 * CHUNK_LEN + 1, 2 * CHUNK_LEN + 64, 0, 130] {
+        content.resize(len, 7);
+        tree.set_len(&content);
+        assert!(tree.has_block_cvs());
+        assert_eq!(tree.root_hash(), tree_over(&content).root_hash(), "length {}", len);
+    }
+    content.extend_from_slice(&[9u8; 2000]);
+    tree.update_bytes(&content, 130..content.len() as u64);
+    assert_eq!(tree.root_hash(), tree_over(&content).root_hash());
+}
+
+#[test]
+fn test_cache_requires_matching_content_and_dropped_by_other_writes() {
+    let content = vec![1u8; 3 * CHUNK_LEN];
+    let mut tree = tree_over(&content);
+    let mut other = content.clone();
+    other[CHUNK_LEN + 5] = 2;
+    assert_eq!(
+        tree.cache_block_cvs(&other).unwrap_err(),
+        Error::HashMismatch { offset: CHUNK_LEN as u64 }
+    );
+    assert_eq!(tree.cache_block_cvs(&content[..CHUNK_LEN]).unwrap_err(), Error::HashMismatch { offset: CHUNK_LEN as u64 });
+    assert!(!tree.has_block_cvs());
+
+    tree.cache_block_cvs(&content).unwrap();
+    tree.insert_leaf(0, chunk_output(0, b"other")).unwrap();
+    assert!(!tree.has_block_cvs());
+    assert_eq!(tree.memory_usage().cached_cv_bytes, 0);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_write_in_last_block_hashes_only_that_block() {
+    let mut content = vec![4u8; 4 * CHUNK_LEN];
+    let mut tree = tree_over(&content);
+    tree.cache_block_cvs(&content).unwrap();
+    tree.reset_metrics();
+
+    let offset = 2 * CHUNK_LEN + CHUNK_LEN - 10;
+    content[offset] = 5;
+    tree.update_bytes(&content, offset as u64..offset as u64 + 1);
+    assert_eq!(tree.metrics().bytes_hashed, 64);
+    assert_eq!(tree.root_hash(), tree_over(&content).root_hash());
+}
+
+#[test]
+fn test_cached_updates_keep_keyed_mode() {
+    let key = [3u8; 32];
+    let mut content: Vec<u8> = (0..4 * CHUNK_LEN + 10).map(|i| (i % 241) as u8).collect();
+    let (mut tree, _) = BinaryMerkleTree::from_reader_with_mode(&content[..], HashMode::keyed(&key)).unwrap();
+    tree.cache_block_cvs(&content).unwrap();
+
+    for offset in [5, CHUNK_LEN + 900, 4 * CHUNK_LEN + 9] {
+        content[offset] ^= 0xff;
+        tree.update_bytes(&content, offset as u64..offset as u64 + 1);
+        assert!(tree.has_block_cvs());
+        assert_eq!(tree.root_hash().as_bytes(), blake3::keyed_hash(&key, &content).as_bytes());
+    }
+    content.resize(6 * CHUNK_LEN + 1, 1);
+    tree.set_len(&content);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::keyed_hash(&key, &content).as_bytes());
+}
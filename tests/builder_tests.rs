@@ -0,0 +1,110 @@
+use merkle_tree::binary_merkle_tree::{
+    chunk_output_with_mode, process_input_to_chunks_from, BinaryMerkleTree, HashMode, CHUNK_LEN, KEYED_HASH,
+};
+use merkle_tree::builder::MerkleTreeBuilder;
+use merkle_tree::grouped::GroupedMerkleTree;
+use merkle_tree::node_store::{MemoryNodeStore, StoredMerkleTree};
+
+fn input() -> Vec<u8> {
+    (0..45 * CHUNK_LEN + 321).map(|i| (i % 227) as u8).collect()
+}
+
+#[test]
+fn test_builder_configurations_agree() {
+    let input = input();
+    let expected = blake3::hash(&input);
+    for parallel in [false, true] {
+        let builder = MerkleTreeBuilder::new().parallel(parallel);
+        assert_eq!(builder.build(&input).root_hash().as_bytes(), expected.as_bytes());
+        assert_eq!(builder.clone().group_log(3).build(&input).num_leaves(), 6);
+        assert_eq!(builder.clone().group_log(3).build(&input).root_hash().as_bytes(), expected.as_bytes());
+        let stored = builder.build_stored(MemoryNodeStore::new(), &input).unwrap();
+        assert_eq!(stored.root_hash().unwrap().as_bytes(), expected.as_bytes());
+    }
+
+    let grouped = MerkleTreeBuilder::new().group_log(2).build_grouped(&input);
+    assert_eq!(grouped.root_hash(), GroupedMerkleTree::from_bytes(&input, 2).root_hash());
+    assert_eq!(grouped.group_len(), 4 * CHUNK_LEN);
+}
+
+#[test]
+fn test_builder_segments() {
+    let input = input();
+    let segment = &input[32 * CHUNK_LEN..];
+    let tree = MerkleTreeBuilder::new().start_chunk_counter(32).parallel(true).build(segment);
+    let expected: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks_from(segment, 32));
+    assert_eq!(tree.root_node(), expected.root_node());
+}
+
+#[test]
+fn test_builder_keyed_and_derive_key_match_blake3() {
+    let key = [42u8; 32];
+    for len in [0, 1, CHUNK_LEN, 5 * CHUNK_LEN + 7, 45 * CHUNK_LEN + 321] {
+        let input = &input()[..len];
+        let keyed = MerkleTreeBuilder::new().keyed(&key);
+        let expected = blake3::keyed_hash(&key, input);
+        assert_eq!(keyed.build(input).root_hash().as_bytes(), expected.as_bytes());
+        assert_eq!(keyed.clone().group_log(2).build_grouped(input).root_hash().as_bytes(), expected.as_bytes());
+        let stored = keyed.build_stored(MemoryNodeStore::new(), input).unwrap();
+        assert_eq!(stored.root_hash().unwrap().as_bytes(), expected.as_bytes());
+
+        let derived = MerkleTreeBuilder::new().derive_key("merkle_tree builder test").parallel(true);
+        let expected = blake3::derive_key("merkle_tree builder test", input);
+        assert_eq!(derived.build(input).root_hash().as_bytes(), &expected);
+        assert_eq!(derived.clone().group_log(3).build(input).root_hash().as_bytes(), &expected);
+    }
+
+    // Overriding the flags of a keyed builder with the same flags changes nothing
+    let input = input();
+    let flagged = MerkleTreeBuilder::new().keyed(&key).flags(KEYED_HASH).build(&input);
+    assert_eq!(flagged.root_hash().as_bytes(), blake3::keyed_hash(&key, &input).as_bytes());
+    let unkeyed = MerkleTreeBuilder::new().keyed(&key).flags(0).build(&input);
+    assert_ne!(unkeyed.root_hash(), flagged.root_hash());
+}
+
+#[test]
+fn test_keyed_trees_stay_keyed_after_updates() {
+    let key = [7u8; 32];
+    let mode = HashMode::keyed(&key);
+    let mut input = input();
+    let mut tree = MerkleTreeBuilder::new().keyed(&key).build(&input);
+    let mut grouped = MerkleTreeBuilder::new().keyed(&key).group_log(2).build_grouped(&input);
+    let mut stored = MerkleTreeBuilder::new().keyed(&key).build_stored(MemoryNodeStore::new(), &input).unwrap();
+    assert_eq!(tree.mode(), mode);
+
+    input[20 * CHUNK_LEN] ^= 1;
+    let chunk = chunk_output_with_mode(20, &input[20 * CHUNK_LEN..21 * CHUNK_LEN], &mode);
+    tree.insert_leaf(20, chunk).unwrap();
+    grouped.update_bytes(&input, 20 * CHUNK_LEN as u64..20 * CHUNK_LEN as u64 + 1);
+    stored.update_leaves(&[(20, chunk)]).unwrap();
+
+    let expected = blake3::keyed_hash(&key, &input);
+    assert_eq!(tree.root_hash().as_bytes(), expected.as_bytes());
+    assert_eq!(grouped.root_hash().as_bytes(), expected.as_bytes());
+    assert_eq!(stored.root_hash().unwrap().as_bytes(), expected.as_bytes());
+
+    // Reopening reads the mode back from the stored root
+    let reopened = StoredMerkleTree::open(stored.into_store()).unwrap();
+    assert_eq!(reopened.mode(), mode);
+    assert!(reopened.validate().unwrap().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "segment trees cannot have grouped leaves")]
+fn test_builder_rejects_grouped_segments() {
+    MerkleTreeBuilder::new().start_chunk_counter(8).group_log(2).build(&input());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_builder_uses_given_thread_pool() {
+    use std::sync::Arc;
+
+    let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+    let input = input();
+    let tree = MerkleTreeBuilder::new().thread_pool(Arc::clone(&pool)).build(&input);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&input).as_bytes());
+
+    let proofs = tree.generate_proofs_in(&[0, 7, 45], &pool).unwrap();
+    assert_eq!(proofs[1], tree.prove_range(7..8).unwrap());
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "serde")]
+
+use merkle_tree::binary_merkle_tree::BinaryMerkleTree;
+use merkle_tree::canonical::{hash_canonical, to_canonical_cbor};
+use merkle_tree::error::Error;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Serialize)]
+struct Order {
+    id: u64,
+    customer: String,
+    total: f64,
+    items: Vec<(String, u32)>,
+    note: Option<String>,
+}
+
+#[test]
+fn test_canonical_cbor_matches_deterministic_encoding() {
+    // Examples from RFC 8949 appendix A
+    assert_eq!(to_canonical_cbor(&0u8).unwrap(), [0x00]);
+    assert_eq!(to_canonical_cbor(&1_000_000u64).unwrap(), [0x1a, 0x00, 0x0f, 0x42, 0x40]);
+    assert_eq!(to_canonical_cbor(&-1000i32).unwrap(), [0x39, 0x03, 0xe7]);
+    assert_eq!(to_canonical_cbor(&1.5f64).unwrap(), [0xf9, 0x3e, 0x00]);
+    assert_eq!(to_canonical_cbor(&100000.0f64).unwrap(), [0xfa, 0x47, 0xc3, 0x50, 0x00]);
+    assert_eq!(to_canonical_cbor(&1.1f64).unwrap(), [0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]);
+    assert_eq!(to_canonical_cbor(&5.960464477539063e-8f64).unwrap(), [0xf9, 0x00, 0x01]);
+    assert_eq!(to_canonical_cbor(&f32::NAN).unwrap(), [0xf9, 0x7e, 0x00]);
+    assert_eq!(to_canonical_cbor("IETF").unwrap(), [0x64, b'I', b'E', b'T', b'F']);
+    assert_eq!(to_canonical_cbor(&vec![1u8, 2, 3]).unwrap(), [0x83, 0x01, 0x02, 0x03]);
+    assert_eq!(to_canonical_cbor(&None::<u8>).unwrap(), [0xf6]);
+
+    // Keys sort by their encoded bytes: shorter text first
+    #[derive(Serialize)]
+    struct Keys {
+        bb: bool,
+        a: bool,
+    }
+    let keys = Keys { bb: true, a: false };
+    assert_eq!(to_canonical_cbor(&keys).unwrap(), [0xa2, 0x61, b'a', 0xf4, 0x62, b'b', b'b', 0xf5]);
+    assert!(matches!(to_canonical_cbor(&u128::MAX), Err(Error::Unserializable(_))));
+}
+
+#[test]
+fn test_hash_canonical_ignores_map_and_document_order() {
+    let first: serde_json::Value = serde_json::from_str(r#"{"b": [1, 2.5, null], "a": {"y": true, "x": "s"}}"#).unwrap();
+    let second: serde_json::Value = serde_json::from_str(r#"{"a": {"x": "s", "y": true}, "b": [1, 2.5, null]}"#).unwrap();
+    assert_eq!(hash_canonical(&first).unwrap(), hash_canonical(&second).unwrap());
+
+    let mut map = HashMap::new();
+    for i in 0..50u32 {
+        map.insert(format!("key{}", i), i);
+    }
+    let sorted: BTreeMap<_, _> = map.iter().map(|(k, v)| (k.clone(), *v)).collect();
+    assert_eq!(hash_canonical(&map).unwrap(), hash_canonical(&sorted).unwrap());
+    assert_eq!(
+        hash_canonical(&map).unwrap().as_bytes(),
+        blake3::hash(&to_canonical_cbor(&map).unwrap()).as_bytes()
+    );
+}
+
+#[test]
+fn test_serialized_records_match_record_trees() {
+    let orders: Vec<Order> = (0..5)
+        .map(|i| Order {
+            id: i,
+            customer: format!("customer {}", i),
+            total: i as f64 * 9.99,
+            items: vec![("widget".to_string(), i as u32)],
+            note: (i % 2 == 0).then(|| "gift".to_string()),
+        })
+        .collect();
+    let tree = BinaryMerkleTree::from_serialized_records(&orders).unwrap();
+    let encoded: Vec<Vec<u8>> = orders.iter().map(|order| to_canonical_cbor(order).unwrap()).collect();
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::from_records(&encoded).root_hash());
+    assert_eq!(tree.num_leaves(), 5);
+}
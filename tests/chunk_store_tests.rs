@@ -0,0 +1,52 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, CHUNK_LEN};
+use merkle_tree::chunk_store::{rebuild, store_input, ChunkStore, DirectoryChunkStore, MemoryChunkStore};
+use merkle_tree::hash::Hash;
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_versions_share_unchanged_chunks() {
+    let mut store = MemoryChunkStore::new();
+    let v1 = random_input(10 * CHUNK_LEN + 3);
+    let mut v2 = v1.clone();
+    v2[4 * CHUNK_LEN] ^= 1;
+
+    let t1 = store_input(&mut store, &v1).unwrap();
+    assert_eq!(store.len(), 11);
+    let t2 = store_input(&mut store, &v2).unwrap();
+    // Only the changed chunk is new
+    assert_eq!(store.len(), 12);
+
+    let mut out = Vec::new();
+    assert_eq!(rebuild(&t1, &store, &mut out).unwrap(), v1.len() as u64);
+    assert_eq!(out, v1);
+    out.clear();
+    rebuild(&t2, &store, &mut out).unwrap();
+    assert_eq!(out, v2);
+}
+
+#[test]
+fn test_rebuild_rejects_bad_store_contents() {
+    let dir = std::env::temp_dir().join(format!("merkle_tree_chunk_store_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut store = DirectoryChunkStore::open(&dir).unwrap();
+    let input = random_input(3 * CHUNK_LEN);
+    let tree = store_input(&mut store, &input).unwrap();
+
+    let mut out = Vec::new();
+    rebuild(&tree, &store, &mut out).unwrap();
+    assert_eq!(out, input);
+
+    // Corrupt one stored chunk on disk
+    let key = Hash::from_chaining_value(chunk_output(1, &input[CHUNK_LEN..2 * CHUNK_LEN]).chaining_value());
+    assert!(store.has(&key).unwrap());
+    std::fs::write(dir.join(key.to_hex()), vec![0u8; CHUNK_LEN]).unwrap();
+    let err = rebuild(&tree, &store, Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
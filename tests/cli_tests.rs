@@ -0,0 +1,195 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree};
+use rand::Rng;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn cli(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_merkle_tree")).args(args).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // The last line carries the result
+    let last_line = stdout.lines().last().unwrap_or_default().to_string();
+    (output.status.success(), last_line)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("merkle_tree_cli_{}_{}", name, std::process::id()))
+}
+
+#[test]
+fn test_cli_hash_prove_and_verify() {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..5 * 1024 + 10).map(|_| rng.gen()).collect();
+    let file = temp_path("input");
+    let proof = temp_path("proof");
+    std::fs::write(&file, &input).unwrap();
+    let file_arg = file.to_str().unwrap();
+    let proof_arg = proof.to_str().unwrap();
+
+    let expected = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input)).root_hash().to_hex();
+    assert_eq!(cli(&["hash", file_arg]), (true, format!("{}  {}", expected, file_arg)));
+    assert_eq!(cli(&["verify", file_arg, "--root", &expected]), (true, "OK".to_string()));
+
+    assert!(cli(&["prove", file_arg, "--range", "1500..2100", "-o", proof_arg]).0);
+    assert_eq!(cli(&["verify-proof", file_arg, proof_arg, "--root", &expected]), (true, "OK".to_string()));
+
+    // A modified file no longer verifies
+    let mut modified = input.clone();
+    modified[1600] ^= 1;
+    std::fs::write(&file, &modified).unwrap();
+    assert_eq!(cli(&["verify-proof", file_arg, proof_arg, "--root", &expected]), (false, "FAILED".to_string()));
+    assert_eq!(cli(&["verify", file_arg, "--root", &expected]), (false, "FAILED".to_string()));
+
+    std::fs::remove_file(&file).unwrap();
+    std::fs::remove_file(&proof).unwrap();
+}
+
+#[test]
+fn test_cli_prints_only_results() {
+    let file = temp_path("quiet");
+    std::fs::write(&file, vec![9u8; 4 * 1024 + 3]).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_merkle_tree")).args(["hash", file.to_str().unwrap()]).output().unwrap();
+    let expected = format!("{}  {}\n", blake3::hash(&[9u8; 4 * 1024 + 3]).to_hex(), file.to_str().unwrap());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn test_cli_rejects_unknown_command() {
+    assert!(!cli(&["frobnicate"]).0);
+}
+
+#[test]
+fn test_cli_hash_matches_b3sum_and_checks_lists() {
+    let first = temp_path("sum_first");
+    let second = temp_path("sum_second");
+    let sums = temp_path("sums");
+    std::fs::write(&first, vec![1u8; 3000]).unwrap();
+    std::fs::write(&second, b"second").unwrap();
+    let (first_arg, second_arg) = (first.to_str().unwrap(), second.to_str().unwrap());
+
+    let first_line = format!("{}  {}", blake3::hash(&[1u8; 3000]).to_hex(), first_arg);
+    let second_line = format!("{}  {}", blake3::hash(b"second").to_hex(), second_arg);
+    assert_eq!(cli(&["hash", first_arg]), (true, first_line.clone()));
+    assert_eq!(cli(&["hash", first_arg, second_arg]), (true, second_line.clone()));
+
+    std::fs::write(&sums, format!("{}\n{}\n", first_line, second_line)).unwrap();
+    let sums_arg = sums.to_str().unwrap();
+    assert_eq!(cli(&["hash", "--check", sums_arg]), (true, format!("{}: OK", second_arg)));
+    std::fs::write(&second, b"changed").unwrap();
+    assert_eq!(cli(&["hash", "--check", sums_arg]), (false, format!("{}: FAILED", second_arg)));
+
+    for path in [first, second, sums] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_cli_hash_reads_stdin_and_dash_paths() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_merkle_tree"))
+        .args(["hash", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"abc").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}  -\n", blake3::hash(b"abc").to_hex()));
+
+    // After `--`, a file name starting with `-` is a path, not a flag
+    let name = format!("-merkle_tree_cli_dash_{}", std::process::id());
+    std::fs::write(std::env::temp_dir().join(&name), b"dash").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_merkle_tree"))
+        .args(["hash", "--", &name])
+        .current_dir(std::env::temp_dir())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}  {}\n", blake3::hash(b"dash").to_hex(), name));
+    std::fs::remove_file(std::env::temp_dir().join(&name)).unwrap();
+}
+
+#[test]
+fn test_cli_slice_verifies_without_the_file() {
+    let input: Vec<u8> = (0..9 * 1024 + 77).map(|i| (i % 199) as u8).collect();
+    let file = temp_path("slice_input");
+    let slice = temp_path("slice");
+    let out = temp_path("slice_out");
+    std::fs::write(&file, &input).unwrap();
+    let (file_arg, slice_arg, out_arg) = (file.to_str().unwrap(), slice.to_str().unwrap(), out.to_str().unwrap());
+    let root = blake3::hash(&input).to_hex().to_string();
+
+    assert!(cli(&["slice", file_arg, "--range", "3000..7000", "-o", slice_arg]).0);
+    std::fs::remove_file(&file).unwrap();
+    let verify = ["verify-slice", slice_arg, "--range", "3000..7000", "--root", &root, "-o", out_arg];
+    assert_eq!(cli(&verify), (true, "OK".to_string()));
+    assert_eq!(std::fs::read(&out).unwrap(), &input[3000..7000]);
+
+    let mut tampered = std::fs::read(&slice).unwrap();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    std::fs::write(&slice, tampered).unwrap();
+    assert_eq!(cli(&verify[..6]), (false, "FAILED".to_string()));
+
+    std::fs::remove_file(&slice).unwrap();
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn test_cli_diff_reports_differing_chunks() {
+    let (a, b) = (temp_path("diff_a"), temp_path("diff_b"));
+    let original: Vec<u8> = (0..20 * 1024).map(|i| (i % 211) as u8).collect();
+    let mut modified = original.clone();
+    modified[5 * 1024] ^= 1;
+    modified[6 * 1024 + 9] ^= 1;
+    modified.extend_from_slice(b"tail");
+    std::fs::write(&a, &original).unwrap();
+    std::fs::write(&b, &modified).unwrap();
+    let (a_arg, b_arg) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_merkle_tree")).args(["diff", a_arg, b_arg]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!output.status.success());
+    assert!(stdout.contains("chunks 5..7 (bytes 5120..7168)\n"));
+    assert!(stdout.contains("chunks 20..21 (bytes 20480..20484)\n"));
+    assert_eq!(stdout.lines().last(), Some("3 chunks differ"));
+    assert_eq!(cli(&["diff", a_arg, a_arg]), (true, "identical".to_string()));
+
+    std::fs::remove_file(&a).unwrap();
+    std::fs::remove_file(&b).unwrap();
+}
+
+#[cfg(feature = "notify")]
+#[test]
+fn test_cli_watch_reports_changed_ranges() {
+    use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+    use std::process::Stdio;
+
+    let file = temp_path("watched");
+    std::fs::write(&file, vec![0u8; 8 * 1024]).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_merkle_tree"))
+        .args(["watch", file.to_str().unwrap(), "--count", "1"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines().map(Result::unwrap);
+    assert!(lines.any(|line| line.starts_with("watching ")));
+
+    let mut modified = vec![0u8; 8 * 1024];
+    modified[3 * 1024 + 1] = 1;
+    modified[4 * 1024] = 1;
+    // Overwrite in place; fs::write would truncate first, and the watcher
+    // could report the empty file
+    let mut writer = std::fs::OpenOptions::new().write(true).open(&file).unwrap();
+    writer.seek(SeekFrom::Start(3 * 1024)).unwrap();
+    writer.write_all(&modified[3 * 1024..4 * 1024 + 1]).unwrap();
+    drop(writer);
+    let changed = lines.find(|line| line.contains(" changed ")).unwrap();
+    let expected = format!("{} changed chunks 3..5 (bytes 3072..5120)", blake3::hash(&modified).to_hex());
+    assert_eq!(changed, expected);
+    assert!(child.wait().unwrap().success());
+    std::fs::remove_file(&file).unwrap();
+}
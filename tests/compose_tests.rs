@@ -0,0 +1,87 @@
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::compose::{compose_subtrees, Composition};
+use merkle_tree::distributed::SegmentOutput;
+use merkle_tree::error::Error;
+
+fn input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+// Subtrees of `group` chunks each over `data`
+fn subtrees(data: &[u8], group: usize) -> Vec<SegmentOutput> {
+    data.chunks(group * CHUNK_LEN)
+        .enumerate()
+        .map(|(i, segment)| {
+            let first_chunk = (i * group) as u64;
+            SegmentOutput::from_tree(&BinaryMerkleTree::from_segment(segment, first_chunk), first_chunk)
+        })
+        .collect()
+}
+
+#[test]
+fn test_aligned_subtrees_compose_to_blake3() {
+    for (len, group) in [(13 * CHUNK_LEN + 5, 4), (16 * CHUNK_LEN, 8), (3 * CHUNK_LEN, 1), (100, 1), (5 * CHUNK_LEN, 8)] {
+        let data = input(len);
+        let tree = compose_subtrees(&subtrees(&data, group), Composition::Blake3).unwrap();
+        assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&data).as_bytes(), "len {len}, group {group}");
+    }
+}
+
+#[test]
+fn test_blake3_composition_rejects_misalignment() {
+    let data = input(12 * CHUNK_LEN);
+    let mut parts = subtrees(&data, 4);
+
+    // A group size that is not a power of two
+    let uneven = subtrees(&data, 3);
+    assert_eq!(
+        compose_subtrees(&uneven, Composition::Blake3).unwrap_err(),
+        Error::MisalignedSegment { first_chunk: 0, chunk_count: 3 }
+    );
+    // A short subtree before the last
+    let segment = |chunks: std::ops::Range<usize>| {
+        let bytes = &data[chunks.start * CHUNK_LEN..chunks.end * CHUNK_LEN];
+        SegmentOutput::from_tree(&BinaryMerkleTree::from_segment(bytes, chunks.start as u64), chunks.start as u64)
+    };
+    let short = [segment(0..4), segment(4..6), segment(6..10)];
+    assert_eq!(
+        compose_subtrees(&short, Composition::Blake3).unwrap_err(),
+        Error::MisalignedSegment { first_chunk: 4, chunk_count: 2 }
+    );
+    // A gap
+    parts.remove(1);
+    assert_eq!(compose_subtrees(&parts, Composition::Blake3).unwrap_err(), Error::MissingChunks { first_chunk: 4 });
+    assert_eq!(compose_subtrees(&[], Composition::Opaque).unwrap_err(), Error::MissingChunks { first_chunk: 0 });
+}
+
+#[test]
+fn test_blake3_composition_checks_counters_and_flags() {
+    let data = input(4 * CHUNK_LEN);
+    // Chunks hashed with counters starting at 0 each, as standalone inputs
+    let standalone: Vec<SegmentOutput> = data
+        .chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(i, chunk)| SegmentOutput::from_tree(&BinaryMerkleTree::from_segment(chunk, 0), i as u64))
+        .collect();
+    assert_eq!(
+        compose_subtrees(&standalone, Composition::Blake3).unwrap_err(),
+        Error::MisalignedSegment { first_chunk: 1, chunk_count: 1 }
+    );
+    // Opaque composition accepts them, but the root is not BLAKE3's
+    let opaque = compose_subtrees(&standalone, Composition::Opaque).unwrap();
+    assert_ne!(opaque.root_hash().as_bytes(), blake3::hash(&data).as_bytes());
+
+    let mut parts = subtrees(&data, 2);
+    parts[1].output.flags |= 1 << 3;
+    assert!(matches!(compose_subtrees(&parts, Composition::Blake3), Err(Error::MisalignedSegment { .. })));
+}
+
+#[test]
+fn test_composed_tree_proves_subtrees() {
+    let data = input(10 * CHUNK_LEN);
+    let parts = subtrees(&data, 4);
+    let tree = compose_subtrees(&parts, Composition::Blake3).unwrap();
+    assert_eq!(tree.num_leaves(), 3);
+    let proof = tree.prove_range(1..2).unwrap();
+    assert_eq!(proof.compute_root_from_leaves(&[parts[1].output]), Some(tree.root_hash()));
+}
@@ -0,0 +1,46 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+
+#[test]
+fn test_uncompressed_tree_file_round_trip() {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[4; 5 * CHUNK_LEN]));
+    let mut bytes = Vec::new();
+    tree.write_to(&mut bytes).unwrap();
+    assert_eq!(&bytes[4..6], &[3, 0]);
+    assert_eq!(BinaryMerkleTree::read_from(&bytes[..]).unwrap().root_hash(), tree.root_hash());
+
+    // A flagged (version 2) file with the "none" flag holds the leaf count and
+    // leaves as its body
+    let mut flagged = bytes[..4].to_vec();
+    flagged.extend_from_slice(&[2, 0]);
+    flagged.extend_from_slice(&bytes[6..14]);
+    flagged.extend_from_slice(&bytes[54..]);
+    assert_eq!(BinaryMerkleTree::read_from(&flagged[..]).unwrap().root_hash(), tree.root_hash());
+    flagged[5] = 9;
+    assert!(BinaryMerkleTree::read_from(&flagged[..]).is_err());
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compressed_tree_and_manifest() {
+    use merkle_tree::compression::DEFAULT_LEVEL;
+    use merkle_tree::manifest::{Manifest, ManifestEntry};
+
+    let input: Vec<u8> = (0..64 * CHUNK_LEN).map(|i| (i / CHUNK_LEN) as u8).collect();
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let (mut plain, mut compressed) = (Vec::new(), Vec::new());
+    tree.write_to(&mut plain).unwrap();
+    tree.write_to_compressed(&mut compressed, DEFAULT_LEVEL).unwrap();
+    assert_eq!(&compressed[4..6], &[3, 1]);
+    assert!(compressed.len() < plain.len());
+    assert_eq!(BinaryMerkleTree::read_from(&compressed[..]).unwrap().root_hash(), tree.root_hash());
+
+    let manifest = Manifest {
+        entries: vec![ManifestEntry::from_tree("data.bin".to_string(), input.len() as u64, &tree)],
+    };
+    let (mut text, mut compressed) = (Vec::new(), Vec::new());
+    manifest.write(&mut text).unwrap();
+    manifest.write_compressed(&mut compressed, DEFAULT_LEVEL).unwrap();
+    assert!(compressed.len() < text.len());
+    assert_eq!(Manifest::read(&compressed[..]).unwrap(), manifest);
+    assert_eq!(Manifest::read(&text[..]).unwrap(), manifest);
+}
@@ -0,0 +1,23 @@
+use merkle_tree::binary_merkle_tree::{const_derive_key, const_hash, const_keyed_hash};
+
+const CONTEXT: &str = "blake3_merkle_tree 2026-10-14 const hashing test";
+const CONTEXT_KEY: [u8; 32] = const_derive_key(CONTEXT, b"");
+const LABEL: [u8; 32] = const_hash(b"protocol label");
+
+#[test]
+fn test_const_items_match_blake3() {
+    assert_eq!(LABEL, *blake3::hash(b"protocol label").as_bytes());
+    assert_eq!(CONTEXT_KEY, blake3::derive_key(CONTEXT, b""));
+}
+
+#[test]
+fn test_const_hash_matches_blake3_across_chunk_boundaries() {
+    let input: Vec<u8> = (0..9 * 1024 + 1).map(|i| (i % 251) as u8).collect();
+    let key = [7u8; 32];
+    for len in [0, 1, 63, 64, 65, 1023, 1024, 1025, 2048, 3 * 1024 + 5, 8 * 1024, 9 * 1024 + 1] {
+        let input = &input[..len];
+        assert_eq!(const_hash(input), *blake3::hash(input).as_bytes(), "len {}", len);
+        assert_eq!(const_keyed_hash(&key, input), *blake3::keyed_hash(&key, input).as_bytes(), "len {}", len);
+        assert_eq!(const_derive_key(CONTEXT, input), blake3::derive_key(CONTEXT, input), "len {}", len);
+    }
+}
@@ -0,0 +1,51 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::delta::TreeDelta;
+use merkle_tree::error::Error;
+
+fn primary_and_replica() -> (BinaryMerkleTree, BinaryMerkleTree) {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![4u8; 13 * CHUNK_LEN + 7]));
+    (tree.clone(), tree)
+}
+
+#[test]
+fn test_delta_brings_replica_to_new_root() {
+    let (mut primary, mut replica) = primary_and_replica();
+    primary.begin_journal();
+    primary.insert_leaf(2, chunk_output(2, &[1; 100])).unwrap();
+    primary.insert_leaf(13, chunk_output(13, &[2; 7])).unwrap();
+    let delta = primary.journal_delta().unwrap();
+    primary.commit_journal();
+    assert_eq!(delta.changed_leaves(), 2);
+    assert_eq!(delta.root_hash(), primary.root_hash());
+
+    let decoded = TreeDelta::decode(&delta.encode()).unwrap();
+    assert_eq!(decoded, delta);
+    // Far smaller than the whole tree
+    assert!(delta.encode().len() < 2 * 112 + 10 * 40 + 100);
+    replica.apply_delta(&decoded).unwrap();
+    assert_eq!(replica.root_hash(), primary.root_hash());
+    assert_eq!(replica.diff(&primary), Vec::<usize>::new());
+}
+
+#[test]
+fn test_replica_rejects_wrong_or_tampered_deltas() {
+    let (mut primary, mut replica) = primary_and_replica();
+    let original_root = replica.root_hash();
+    primary.begin_journal();
+    primary.insert_leaf(5, chunk_output(5, &[9])).unwrap();
+    let mut bytes = primary.journal_delta().unwrap().encode();
+
+    // A parent chaining value that does not follow from the leaves
+    let last_parent_byte = bytes.len() - 33;
+    bytes[last_parent_byte] ^= 1;
+    let tampered = TreeDelta::decode(&bytes).unwrap();
+    assert_eq!(replica.apply_delta(&tampered), Err(Error::InvalidProof));
+    assert_eq!(replica.root_hash(), original_root);
+
+    // A delta for a tree of another shape
+    let mut other = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[1u8; 3 * CHUNK_LEN]));
+    other.begin_journal();
+    other.insert_leaf(0, chunk_output(0, &[1])).unwrap();
+    assert_eq!(replica.apply_delta(&other.journal_delta().unwrap()), Err(Error::InvalidProof));
+    assert!(TreeDelta::decode(&bytes[..bytes.len() - 1]).is_none());
+}
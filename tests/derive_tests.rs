@@ -0,0 +1,69 @@
+#![cfg(feature = "derive")]
+
+use merkle_tree::binary_merkle_tree::BinaryMerkleTree;
+use merkle_tree::record::{record_leaf, MerkleLeaf};
+
+#[derive(MerkleLeaf)]
+struct Account {
+    id: u64,
+    name: String,
+    balance: i128,
+    tags: Vec<String>,
+    frozen: bool,
+}
+
+#[derive(MerkleLeaf)]
+struct Pair<T>(T, Option<T>);
+
+#[derive(MerkleLeaf)]
+enum Event {
+    Opened,
+    Deposit { account: u32, amount: u64 },
+    Note(String),
+}
+
+#[test]
+fn test_derived_encoding_is_fields_in_order() {
+    let account = Account {
+        id: 7,
+        name: "alice".to_string(),
+        balance: -5,
+        tags: vec!["a".to_string()],
+        frozen: true,
+    };
+    let mut expected = 7u64.to_le_bytes().to_vec();
+    expected.extend_from_slice(&5u64.to_le_bytes());
+    expected.extend_from_slice(b"alice");
+    expected.extend_from_slice(&(-5i128).to_le_bytes());
+    expected.extend_from_slice(&1u64.to_le_bytes());
+    expected.extend_from_slice(&1u64.to_le_bytes());
+    expected.extend_from_slice(b"a");
+    expected.push(1);
+    assert_eq!(account.canonical_bytes(), expected);
+
+    let mut pair = 3u16.to_le_bytes().to_vec();
+    pair.push(0);
+    assert_eq!(Pair(3u16, None).canonical_bytes(), pair);
+
+    assert_eq!(Event::Opened.canonical_bytes(), 0u32.to_le_bytes());
+    let mut deposit = 1u32.to_le_bytes().to_vec();
+    deposit.extend_from_slice(&9u32.to_le_bytes());
+    deposit.extend_from_slice(&100u64.to_le_bytes());
+    assert_eq!(Event::Deposit { account: 9, amount: 100 }.canonical_bytes(), deposit);
+    assert_ne!(Event::Note(String::new()).leaf_hash(), Event::Opened.leaf_hash());
+}
+
+#[test]
+fn test_derived_leaves_match_record_trees() {
+    let events = [
+        Event::Opened,
+        Event::Deposit { account: 1, amount: 50 },
+        Event::Note("hello".to_string()),
+    ];
+    let leaves: Vec<_> = events.iter().enumerate().map(|(i, event)| event.leaf(i as u64)).collect();
+    for (i, event) in events.iter().enumerate() {
+        assert_eq!(leaves[i], record_leaf(i as u64, &event.canonical_bytes()));
+    }
+    let tree = BinaryMerkleTree::from_records(events.iter().map(MerkleLeaf::canonical_bytes));
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::new_from_leaves(leaves).root_hash());
+}
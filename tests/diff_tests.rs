@@ -0,0 +1,36 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn tree_of(input: &[u8]) -> BinaryMerkleTree {
+    BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input))
+}
+
+#[test]
+fn test_diff_finds_mutated_chunks() {
+    let original = random_input(37 * CHUNK_LEN + 11);
+    let mut modified = original.clone();
+    for &chunk in &[0, 13, 14, 36, 37] {
+        modified[chunk * CHUNK_LEN + 5] ^= 0xFF;
+    }
+    let (a, b) = (tree_of(&original), tree_of(&modified));
+    assert_eq!(a.diff(&b), vec![0, 13, 14, 36, 37]);
+    assert_eq!(b.diff(&a), vec![0, 13, 14, 36, 37]);
+    assert!(a.diff(&a.clone()).is_empty());
+}
+
+#[test]
+fn test_diff_with_different_lengths() {
+    let original = random_input(10 * CHUNK_LEN);
+    let mut longer = original.clone();
+    longer.extend(random_input(3 * CHUNK_LEN + 1));
+    longer[2 * CHUNK_LEN] ^= 1;
+
+    let (a, b) = (tree_of(&original), tree_of(&longer));
+    assert_eq!(a.diff(&b), vec![2, 10, 11, 12, 13]);
+    assert_eq!(a.diff(&tree_of(&[])), (0..10).collect::<Vec<_>>());
+}
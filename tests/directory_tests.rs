@@ -0,0 +1,54 @@
+use merkle_tree::binary_merkle_tree::BinaryMerkleTree;
+use merkle_tree::directory::DirectoryHasher;
+use merkle_tree::hash::Hash;
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("merkle_tree_dir_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("nested/deeper")).unwrap();
+    fs::write(dir.join("a.txt"), b"hello").unwrap();
+    fs::write(dir.join("nested/b.bin"), vec![7u8; 5000]).unwrap();
+    fs::write(dir.join("nested/deeper/c"), b"").unwrap();
+    dir
+}
+
+#[test]
+fn test_directory_manifest_is_deterministic() {
+    let dir = temp_dir("deterministic");
+    let first = DirectoryHasher::new().hash(&dir).unwrap();
+    let second = DirectoryHasher::new().hash(&dir).unwrap();
+
+    let paths: Vec<_> = first.entries().iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec!["a.txt", "nested/b.bin", "nested/deeper/c"]);
+    assert_eq!(first.root_hash(), second.root_hash());
+
+    // Per-file roots are the roots of the files' own trees
+    let (b_tree, b_len) = BinaryMerkleTree::from_file(dir.join("nested/b.bin")).unwrap();
+    let b = &first.entries()[first.position("nested/b.bin").unwrap()];
+    assert_eq!(b.len, b_len);
+    assert_eq!(b.root, b_tree.root_hash());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_directory_manifest_changes_with_contents() {
+    let dir = temp_dir("changes");
+    let before = DirectoryHasher::new().hash(&dir).unwrap().root_hash();
+    fs::write(dir.join("nested/deeper/c"), b"x").unwrap();
+    let after = DirectoryHasher::new().hash(&dir).unwrap().root_hash();
+    assert_ne!(before, after);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_hash_hex_round_trip() {
+    let hash = Hash::from_chaining_value([1, 2, 3, 4, 5, 6, 7, 0xdeadbeef]);
+    let hex = hash.to_hex();
+    assert_eq!(hex.len(), 64);
+    assert!(hex.ends_with("efbeadde"));
+    assert_eq!(Hash::from_hex(&hex).unwrap(), hash);
+    assert!(Hash::from_hex("zz").is_err());
+}
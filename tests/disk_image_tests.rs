@@ -0,0 +1,42 @@
+use std::io::{Cursor, Seek, SeekFrom, Write};
+
+use merkle_tree::binary_merkle_tree::CHUNK_LEN;
+use merkle_tree::disk_image::DiskImageTree;
+use rand::Rng;
+
+fn random_image(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_sector_writes_flush_to_blake3_root() {
+    let mut image = Cursor::new(random_image(64 * 4096 + 512));
+    let mut tree = DiskImageTree::from_reader(&mut image, 512).unwrap();
+
+    // A 512-byte sector dirties one chunk, a 4 KiB write at sector 8 four
+    let writes = [(3u64, vec![1u8; 512]), (8, vec![2u8; 4096]), (512, vec![3u8; 512])];
+    for (sector, data) in &writes {
+        image.seek(SeekFrom::Start(sector * 512)).unwrap();
+        image.write_all(data).unwrap();
+        tree.mark_sectors(*sector, data.len() as u64 / 512);
+    }
+    assert_eq!(tree.dirty_chunks(), 1 + 4 + 1);
+
+    let changed = tree.flush(&mut image).unwrap();
+    assert_eq!(changed, vec![1, 4, 5, 6, 7, 256]);
+    assert_eq!(tree.dirty_chunks(), 0);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(image.get_ref()).as_bytes());
+}
+
+#[test]
+fn test_unchanged_rewrite_is_not_reported() {
+    let data = random_image(10 * CHUNK_LEN);
+    let mut image = Cursor::new(data.clone());
+    let mut tree = DiskImageTree::from_reader(&mut image, 4096).unwrap();
+    let root = tree.root_hash();
+
+    tree.mark_bytes(100, 3000);
+    assert_eq!(tree.flush(&mut image).unwrap(), Vec::<usize>::new());
+    assert_eq!(tree.root_hash(), root);
+}
@@ -0,0 +1,44 @@
+use merkle_tree::binary_merkle_tree::CHUNK_LEN;
+use merkle_tree::distributed::{merge_segments, plan_segments, SegmentOutput};
+use merkle_tree::error::Error;
+
+fn input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 247) as u8).collect()
+}
+
+#[test]
+fn test_workers_merge_to_blake3_root() {
+    for (len, workers) in [(0, 3), (CHUNK_LEN, 4), (13 * CHUNK_LEN + 5, 4), (100 * CHUNK_LEN, 7), (9 * CHUNK_LEN, 1)] {
+        let input = input(len);
+        let plan = plan_segments(len as u64, workers);
+        assert!(plan.len() <= workers.max(1));
+
+        // Workers report in reverse, through the wire format
+        let reports: Vec<Vec<u8>> = plan
+            .iter()
+            .rev()
+            .map(|range| {
+                let segment = &input[range.start as usize..range.end as usize];
+                SegmentOutput::hash(segment, range.start / CHUNK_LEN as u64).encode()
+            })
+            .collect();
+        let segments: Vec<SegmentOutput> = reports.iter().map(|bytes| SegmentOutput::decode(bytes).unwrap()).collect();
+        assert_eq!(merge_segments(&segments).unwrap().as_bytes(), blake3::hash(&input).as_bytes(), "len {len}");
+    }
+}
+
+#[test]
+fn test_merge_rejects_bad_segments() {
+    let input = input(12 * CHUNK_LEN);
+    let segment = |first: usize, count: usize| {
+        SegmentOutput::hash(&input[first * CHUNK_LEN..(first + count) * CHUNK_LEN], first as u64)
+    };
+    // 12 chunks split as 8 + 4, so a segment from chunk 6 to 12 straddles the
+    // root's children
+    assert_eq!(
+        merge_segments(&[segment(0, 6), segment(6, 6)]),
+        Err(Error::MisalignedSegment { first_chunk: 6, chunk_count: 6 })
+    );
+    assert_eq!(merge_segments(&[segment(0, 8), segment(9, 3)]), Err(Error::MissingChunks { first_chunk: 8 }));
+    assert!(merge_segments(&[segment(0, 8), segment(8, 4)]).is_ok());
+}
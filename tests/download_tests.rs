@@ -0,0 +1,48 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::download::Download;
+use merkle_tree::error::Error;
+
+#[test]
+fn test_download_loop_completes_with_verified_data() {
+    let input: Vec<u8> = (0..21 * CHUNK_LEN + 100).map(|i| (i % 241) as u8).collect();
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut download = Download::new(tree.root_hash(), input.len() as u64);
+    assert_eq!(download.total_chunks(), 22);
+
+    // Two requests in flight at once; the first fails and is retried
+    let first = download.next_request(8).unwrap();
+    let second = download.next_request(8).unwrap();
+    assert_eq!((first.clone(), second.clone()), (0..8, 8..16));
+    download.cancel(first);
+
+    let mut output = vec![0u8; input.len()];
+    while let Some(chunks) = download.next_request(8) {
+        let bytes = chunks.start * CHUNK_LEN..(chunks.end * CHUNK_LEN).min(input.len());
+        let offset = download.receive(&tree.prove_range(chunks).unwrap(), &input[bytes.clone()]).unwrap();
+        output[offset as usize..offset as usize + bytes.len()].copy_from_slice(&input[bytes]);
+    }
+    assert_eq!(download.missing_ranges(), vec![8..16]);
+    let bytes = 8 * CHUNK_LEN..16 * CHUNK_LEN;
+    download.receive(&tree.prove_range(second).unwrap(), &input[bytes.clone()]).unwrap();
+    output[bytes.clone()].copy_from_slice(&input[bytes]);
+
+    assert!(download.is_complete());
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_download_rejects_corrupt_chunks() {
+    let input = vec![9u8; 5 * CHUNK_LEN];
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut download = Download::new(tree.root_hash(), input.len() as u64);
+
+    let chunks = download.next_request(2).unwrap();
+    let mut data = input[..2 * CHUNK_LEN].to_vec();
+    data[10] ^= 1;
+    let proof = tree.prove_range(chunks).unwrap();
+    assert_eq!(download.receive(&proof, &data), Err(Error::InvalidProof));
+    assert_eq!(download.receive(&proof, &input[..CHUNK_LEN]), Err(Error::InvalidProof));
+    assert_eq!(download.received_chunks(), 0);
+    // The rejected chunks are still in flight until cancelled
+    assert_eq!(download.next_request(2), Some(2..4));
+}
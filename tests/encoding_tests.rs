@@ -0,0 +1,46 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::encoding::{decode_combined, encode_combined, slice_from_combined};
+use merkle_tree::error::Error;
+use merkle_tree::slice::decode_slice;
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_combined_round_trip() {
+    for len in [0, 1, CHUNK_LEN, 2 * CHUNK_LEN, 11 * CHUNK_LEN + 3] {
+        let input = random_input(len);
+        let (encoded, root) = encode_combined(&input);
+        let chunks = len.div_ceil(CHUNK_LEN).max(1);
+        assert_eq!(encoded.len(), 8 + len + (chunks - 1) * 64);
+        assert_eq!(root, BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input)).root_hash());
+        assert_eq!(decode_combined(&root, &encoded).unwrap(), input);
+    }
+}
+
+#[test]
+fn test_combined_rejects_corruption() {
+    let input = random_input(4 * CHUNK_LEN + 9);
+    let (mut encoded, root) = encode_combined(&input);
+    let last = encoded.len() - 1;
+    encoded[last] ^= 1;
+    assert!(matches!(decode_combined(&root, &encoded), Err(Error::HashMismatch { .. })));
+}
+
+#[test]
+fn test_slices_cut_from_combined_match_extracted_slices() {
+    let input = random_input(13 * CHUNK_LEN + 100);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let (encoded, root) = encode_combined(&input);
+    for range in [0..1, 5000..9000, 13 * CHUNK_LEN as u64..14 * CHUNK_LEN as u64, 0..input.len() as u64] {
+        let slice = slice_from_combined(&encoded, range.clone()).unwrap();
+        assert_eq!(slice, tree.extract_slice(&input, range.clone()).unwrap());
+        let start = range.start as usize;
+        let end = (range.end as usize).min(input.len());
+        assert_eq!(decode_slice(&root, &slice, range).unwrap(), &input[start..end]);
+    }
+    assert_eq!(slice_from_combined(&encoded[..encoded.len() - 1], 0..1), Err(Error::UnexpectedEnd));
+}
@@ -0,0 +1,51 @@
+use std::io::ErrorKind;
+
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::export::{ordered_nodes, NodeOrder};
+use merkle_tree::serialize::OUTPUT_ENCODED_LEN;
+
+fn indices(leaf_count: usize, order: NodeOrder) -> Vec<usize> {
+    ordered_nodes(leaf_count, order).into_iter().map(|index| index.get()).collect()
+}
+
+#[test]
+fn test_node_orders() {
+    // Five leaves at 8..13; node 3 is leaf 4 promoted, so it is not a node of
+    // the BLAKE3 tree
+    assert_eq!(indices(5, NodeOrder::PreOrder), vec![1, 2, 4, 8, 9, 5, 10, 11, 12]);
+    assert_eq!(indices(5, NodeOrder::PostOrder), vec![8, 9, 4, 10, 11, 5, 2, 12, 1]);
+    assert_eq!(indices(5, NodeOrder::LevelOrder), vec![1, 2, 4, 5, 8, 9, 10, 11, 12]);
+    assert_eq!(indices(1, NodeOrder::PreOrder), vec![1]);
+    assert_eq!(indices(1, NodeOrder::LevelOrder), vec![1]);
+}
+
+#[test]
+fn test_export_round_trip_in_every_order() {
+    let input: Vec<u8> = (0..13 * CHUNK_LEN + 9).map(|i| (i % 239) as u8).collect();
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    for order in [NodeOrder::PreOrder, NodeOrder::PostOrder, NodeOrder::LevelOrder] {
+        let mut bytes = Vec::new();
+        tree.export_nodes(&mut bytes, order).unwrap();
+        assert_eq!(bytes[5], order as u8);
+        assert_eq!(bytes.len(), 14 + (2 * 14 - 1) * OUTPUT_ENCODED_LEN);
+        let (imported, read_order) = BinaryMerkleTree::import_nodes(&bytes[..]).unwrap();
+        assert_eq!(read_order, order);
+        assert_eq!(imported.root_hash(), tree.root_hash());
+    }
+}
+
+#[test]
+fn test_corrupted_export_is_rejected() {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[5; 4 * CHUNK_LEN]));
+    let mut bytes = Vec::new();
+    tree.export_nodes(&mut bytes, NodeOrder::PreOrder).unwrap();
+
+    // The first record is the root
+    let mut corrupted = bytes.clone();
+    corrupted[14 + 3] ^= 1;
+    assert_eq!(BinaryMerkleTree::import_nodes(&corrupted[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+    let mut unknown = bytes.clone();
+    unknown[5] = 7;
+    assert_eq!(BinaryMerkleTree::import_nodes(&unknown[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(BinaryMerkleTree::import_nodes(&bytes[..bytes.len() - 1]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,185 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, Blake3Hasher, HashMode};
+use rand::Rng;
+use std::path::PathBuf;
+
+fn blake3_chaining_value(input: &[u8]) -> [u32; 8] {
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(input);
+    let mut hash = [0; 32];
+    hasher.finalize(&mut hash);
+
+    let mut chaining_value = [0u32; 8];
+    for i in 0..8 {
+        chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
+    }
+    chaining_value
+}
+
+fn temp_file_with(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("merkle_tree_{}_{}", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_from_file_matches_blake3() {
+    let mut rng = rand::thread_rng();
+    // Spans several read buffers and ends in a partial chunk
+    let input: Vec<u8> = (0..200 * 1024 + 513).map(|_| rng.gen()).collect();
+    let path = temp_file_with("from_file", &input);
+
+    let (tree, len) = BinaryMerkleTree::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(len, input.len() as u64);
+    assert_eq!(tree.num_leaves(), 201);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[test]
+fn test_from_file_missing_path() {
+    let path = std::env::temp_dir().join("merkle_tree_file_that_does_not_exist");
+    assert!(BinaryMerkleTree::from_file(path).is_err());
+}
+
+#[cfg(feature = "memmap2")]
+#[test]
+fn test_from_file_mmap_matches_from_file() {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..37 * 1024 + 9).map(|_| rng.gen()).collect();
+    let path = temp_file_with("from_file_mmap", &input);
+
+    let (mmap_tree, mmap_len) = BinaryMerkleTree::from_file_mmap(&path).unwrap();
+    let (read_tree, read_len) = BinaryMerkleTree::from_file(&path).unwrap();
+    let (single_thread_tree, _) = BinaryMerkleTree::from_file_mmap_with_threads(&path, 1).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(single_thread_tree.root(), mmap_tree.root());
+
+    assert_eq!(mmap_len, read_len);
+    assert_eq!(mmap_tree.num_leaves(), read_tree.num_leaves());
+    assert_eq!(mmap_tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[cfg(feature = "memmap2")]
+#[test]
+fn test_from_file_mmap_empty_file() {
+    let path = temp_file_with("from_file_mmap_empty", &[]);
+    let (tree, len) = BinaryMerkleTree::from_file_mmap(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(len, 0);
+    assert_eq!(tree.num_leaves(), 1);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&[]));
+}
+
+#[test]
+fn test_refresh_reports_only_changed_chunks() {
+    let mut rng = rand::thread_rng();
+    let mut input: Vec<u8> = (0..64 * 1024 + 100).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+
+    // Nothing changed
+    assert!(tree.refresh_from_bytes(&input).is_empty());
+
+    // Mutate bytes in chunks 3, 40 and the trailing partial chunk 64
+    input[3 * 1024 + 17] ^= 0xFF;
+    input[40 * 1024] ^= 0xFF;
+    input[64 * 1024 + 99] ^= 0xFF;
+    let path = temp_file_with("refresh", &input);
+    let changed = tree.refresh_from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(changed, vec![3, 40, 64]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[test]
+fn test_refresh_handles_length_change() {
+    let mut rng = rand::thread_rng();
+    let mut input: Vec<u8> = (0..4 * 1024).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+
+    input.extend((0..2 * 1024 + 5).map(|_| rng.gen::<u8>()));
+    let changed = tree.refresh_from_bytes(&input);
+    assert_eq!(changed, vec![4, 5, 6]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+
+    input.truncate(1024 + 1);
+    let changed = tree.refresh_from_bytes(&input);
+    assert_eq!(changed, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[test]
+fn test_update_bytes_rehashes_partial_last_chunk() {
+    let mut rng = rand::thread_rng();
+    let mut input: Vec<u8> = (0..3 * 1024 + 100).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+
+    // Overwrite inside one chunk
+    input[1500] ^= 0xff;
+    assert_eq!(tree.update_bytes(&input, 1500..1501), vec![1]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+
+    // Append within the partial final chunk, then past it
+    input.extend([7u8; 200]);
+    assert_eq!(tree.update_bytes(&input, 3 * 1024 + 100..3 * 1024 + 300), vec![3]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+    input.extend([9u8; 2000]);
+    assert_eq!(tree.update_bytes(&input, 3 * 1024 + 300..3 * 1024 + 2300), vec![3, 4, 5]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[test]
+fn test_set_len_truncate_and_extend() {
+    let mut rng = rand::thread_rng();
+    let mut input: Vec<u8> = (0..5 * 1024).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+
+    input.truncate(2 * 1024 + 10);
+    assert_eq!(tree.set_len(&input), vec![2, 3, 4]);
+    assert_eq!(tree.num_leaves(), 3);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+
+    input.truncate(2 * 1024 + 3);
+    assert_eq!(tree.set_len(&input), vec![2]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+
+    input.resize(4 * 1024, 0);
+    assert_eq!(tree.set_len(&input), vec![2, 3]);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[test]
+fn test_byte_level_updates_keep_keyed_and_derive_key_modes() {
+    let key = [5u8; 32];
+    let context = "merkle_tree file test";
+    check_byte_level_updates(HashMode::keyed(&key), |input| *blake3::keyed_hash(&key, input).as_bytes());
+    check_byte_level_updates(HashMode::derive_key(context), |input| blake3::derive_key(context, input));
+}
+
+fn check_byte_level_updates(mode: HashMode, expected: impl Fn(&[u8]) -> [u8; 32]) {
+    let mut rng = rand::thread_rng();
+    let mut input: Vec<u8> = (0..5 * 1024 + 77).map(|_| rng.gen()).collect();
+    let (mut tree, _) = BinaryMerkleTree::from_reader_with_mode(&input[..], mode).unwrap();
+    assert_eq!(tree.root_hash().as_bytes(), &expected(&input));
+
+    input[2000] ^= 1;
+    assert_eq!(tree.refresh_from_bytes(&input), vec![1]);
+    assert_eq!(tree.root_hash().as_bytes(), &expected(&input));
+
+    input[4000] ^= 1;
+    tree.refresh_from_reader(&input[..]).unwrap();
+    assert_eq!(tree.root_hash().as_bytes(), &expected(&input));
+
+    input[3000] ^= 1;
+    assert_eq!(tree.update_bytes(&input, 3000..3001), vec![2]);
+    assert_eq!(tree.root_hash().as_bytes(), &expected(&input));
+
+    input.truncate(1500);
+    tree.set_len(&input);
+    assert_eq!(tree.root_hash().as_bytes(), &expected(&input));
+
+    input.resize(9 * 1024, 3);
+    tree.refresh_from_bytes(&input);
+    assert_eq!(tree.root_hash().as_bytes(), &expected(&input));
+}
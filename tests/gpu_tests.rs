@@ -0,0 +1,27 @@
+#![cfg(feature = "wgpu")]
+
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::gpu::GpuChunkHasher;
+
+fn input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_gpu_tree_matches_cpu_tree() {
+    let input = input(37 * CHUNK_LEN + 100);
+    let tree = BinaryMerkleTree::from_bytes_gpu(&input);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&input).as_bytes());
+}
+
+#[test]
+fn test_gpu_chunk_outputs_match_cpu() {
+    // Machines without a GPU adapter only exercise the fallback above
+    let Some(hasher) = GpuChunkHasher::new() else {
+        return;
+    };
+    for len in [0, 1, CHUNK_LEN, CHUNK_LEN + 1, 70 * CHUNK_LEN] {
+        let input = input(len);
+        assert_eq!(hasher.chunk_outputs(&input), process_input_to_chunks(&input), "len {len}");
+    }
+}
@@ -0,0 +1,40 @@
+use merkle_tree::binary_merkle_tree::CHUNK_LEN;
+use merkle_tree::grouped::GroupedMerkleTree;
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_grouped_root_is_blake3_hash() {
+    for group_log in [0, 1, 3] {
+        for len in [0, 1, CHUNK_LEN, 8 * CHUNK_LEN, 8 * CHUNK_LEN + 1, 37 * CHUNK_LEN + 5] {
+            let input = random_input(len);
+            let tree = GroupedMerkleTree::from_bytes(&input, group_log);
+            assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&input).as_bytes(), "group_log {group_log}, len {len}");
+            let expected_groups = len.div_ceil(CHUNK_LEN << group_log).max(1);
+            assert_eq!(tree.num_groups(), expected_groups);
+        }
+    }
+}
+
+#[test]
+fn test_grouped_update_bytes() {
+    let mut input = random_input(37 * CHUNK_LEN + 5);
+    let mut tree = GroupedMerkleTree::from_bytes(&input, 2);
+
+    // One byte in the middle of group 3
+    input[3 * 4 * CHUNK_LEN + 100] ^= 1;
+    let changed = tree.update_bytes(&input, (3 * 4 * CHUNK_LEN + 100) as u64..(3 * 4 * CHUNK_LEN + 101) as u64);
+    assert_eq!(changed, vec![3]);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&input).as_bytes());
+
+    // Growing past the last group adds groups and rehashes the old last one
+    let old_len = input.len();
+    input.extend(random_input(9 * CHUNK_LEN));
+    let changed = tree.update_bytes(&input, old_len as u64..input.len() as u64);
+    assert_eq!(changed, vec![9, 10, 11]);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&input).as_bytes());
+}
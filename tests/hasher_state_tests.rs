@@ -0,0 +1,84 @@
+use merkle_tree::binary_merkle_tree::{Blake3Hasher, CHUNK_LEN};
+use rand::Rng;
+
+fn finalize(hasher: &Blake3Hasher) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hasher.finalize(&mut hash);
+    hash
+}
+
+#[test]
+fn test_resume_continues_hashing() {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..37 * CHUNK_LEN + 501).map(|_| rng.gen()).collect();
+    let mut whole = Blake3Hasher::new();
+    whole.update(&input);
+    let expected = finalize(&whole);
+
+    // Checkpoint at chunk boundaries, block boundaries, and in between
+    for split in [0, 1, 64, CHUNK_LEN, 3 * CHUNK_LEN + 17, 32 * CHUNK_LEN, input.len()] {
+        let mut first = Blake3Hasher::new();
+        first.update(&input[..split]);
+        let state = first.serialize_state();
+        assert!(state.len() < 2048);
+
+        let mut resumed = Blake3Hasher::resume(&state).unwrap();
+        assert_eq!(resumed.total_len(), split as u64);
+        resumed.update(&input[split..]);
+        assert_eq!(finalize(&resumed), expected, "split at {}", split);
+    }
+}
+
+#[test]
+fn test_resume_keeps_mode() {
+    let mut keyed = Blake3Hasher::new_keyed(&[7; 32]);
+    keyed.update(&[1; 3000]);
+    let mut resumed = Blake3Hasher::resume(&keyed.serialize_state()).unwrap();
+    keyed.update(b"more");
+    resumed.update(b"more");
+    assert_eq!(finalize(&resumed), finalize(&keyed));
+}
+
+#[test]
+fn test_resume_rejects_malformed_state() {
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&[0; 5 * CHUNK_LEN]);
+    let state = hasher.serialize_state();
+
+    assert!(Blake3Hasher::resume(&state[..state.len() - 1]).is_none());
+    assert!(Blake3Hasher::resume(&[state.as_slice(), &[0]].concat()).is_none());
+    let mut bad_magic = state.clone();
+    bad_magic[0] ^= 1;
+    assert!(Blake3Hasher::resume(&bad_magic).is_none());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_update_rayon_matches_update() {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..100 * CHUNK_LEN + 3).map(|_| rng.gen()).collect();
+    let key = [7; 32];
+    for len in [0, 1, CHUNK_LEN, CHUNK_LEN + 1, 64 * CHUNK_LEN, input.len()] {
+        // Starting mid-chunk and on odd chunk counters exercises the alignment
+        for prefix in [0, 5, CHUNK_LEN, 3 * CHUNK_LEN + 1] {
+            let prefix = prefix.min(len);
+            for new_hasher in [Blake3Hasher::new as fn() -> Blake3Hasher, || Blake3Hasher::new_keyed(&[7; 32])] {
+                let mut sequential = new_hasher();
+                sequential.update(&input[..len]);
+                let mut parallel = new_hasher();
+                parallel.update(&input[..prefix]);
+                parallel.update_rayon(&input[prefix..len]);
+                assert_eq!(finalize(&parallel), finalize(&sequential), "len {len}, prefix {prefix}");
+            }
+        }
+    }
+
+    let mut keyed = Blake3Hasher::new_keyed(&key);
+    keyed.update_rayon(&input);
+    assert_eq!(finalize(&keyed), *blake3::keyed_hash(&key, &input).as_bytes());
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let mut pooled = Blake3Hasher::new();
+    pooled.update_rayon_in(&input, &pool);
+    assert_eq!(finalize(&pooled), *blake3::hash(&input).as_bytes());
+}
@@ -0,0 +1,58 @@
+use merkle_tree::binary_merkle_tree::BinaryMerkleTree;
+use merkle_tree::hasher::TreeHasher;
+
+// A toy hasher that records the tree shape, to check the generic machinery
+// combines nodes exactly as the BLAKE3 tree does
+#[derive(Debug, Clone, Copy)]
+struct ShapeHasher;
+
+impl TreeHasher for ShapeHasher {
+    type Node = u64;
+
+    fn parent(left: &u64, right: &u64) -> u64 {
+        left.wrapping_mul(31).wrapping_add(*right).wrapping_add(7)
+    }
+
+    fn empty_node() -> u64 {
+        0
+    }
+}
+
+#[test]
+fn test_custom_hasher_shape_and_updates() {
+    let mut tree: BinaryMerkleTree<ShapeHasher> = BinaryMerkleTree::new_from_leaves(vec![1, 2, 3]);
+    // Three leaves: parent(parent(1, 2), 3), the lone third leaf is promoted
+    let expected = ShapeHasher::parent(&ShapeHasher::parent(&1, &2), &3);
+    assert_eq!(tree.root_node(), expected);
+
+    tree.insert_leaf(2, 9).unwrap();
+    assert_eq!(tree.root_node(), ShapeHasher::parent(&ShapeHasher::parent(&1, &2), &9));
+    assert_eq!(tree.num_leaves(), 3);
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn test_sha256_tree_root() {
+    use merkle_tree::hasher::Sha256TreeHasher;
+
+    let leaves: Vec<_> = [b"a", b"b", b"c", b"d"].iter().map(|data| Sha256TreeHasher::leaf(*data)).collect();
+    let tree: BinaryMerkleTree<Sha256TreeHasher> = BinaryMerkleTree::new_from_leaves(leaves.clone());
+    let left = Sha256TreeHasher::parent(&leaves[0], &leaves[1]);
+    let right = Sha256TreeHasher::parent(&leaves[2], &leaves[3]);
+    assert_eq!(tree.root_node(), Sha256TreeHasher::parent(&left, &right));
+}
+
+#[cfg(feature = "sha3")]
+#[test]
+fn test_keccak256_tree_root() {
+    use merkle_tree::hasher::Keccak256TreeHasher;
+
+    // keccak256("") is a well known constant
+    let empty = Keccak256TreeHasher::leaf(b"");
+    assert_eq!(
+        merkle_tree::hash::Hash::from_bytes(empty).to_hex(),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+    let tree: BinaryMerkleTree<Keccak256TreeHasher> = BinaryMerkleTree::new_from_leaves(vec![empty, empty]);
+    assert_eq!(tree.root_node(), Keccak256TreeHasher::parent(&empty, &empty));
+}
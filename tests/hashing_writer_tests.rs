@@ -0,0 +1,64 @@
+use merkle_tree::binary_merkle_tree::Blake3Hasher;
+use merkle_tree::io::HashingWriter;
+use rand::Rng;
+use std::io::Write;
+
+fn blake3_chaining_value(input: &[u8]) -> [u32; 8] {
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(input);
+    let mut hash = [0; 32];
+    hasher.finalize(&mut hash);
+
+    let mut chaining_value = [0u32; 8];
+    for i in 0..8 {
+        chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
+    }
+    chaining_value
+}
+
+#[test]
+fn test_hashing_writer_passes_bytes_through() {
+    let mut rng = rand::thread_rng();
+    // Deliberately not a multiple of the chunk size, and not a power of two chunks
+    let input: Vec<u8> = (0..5 * 1024 + 77).map(|_| rng.gen()).collect();
+
+    let mut writer = HashingWriter::new(Vec::new());
+    for piece in input.chunks(333) {
+        writer.write_all(piece).unwrap();
+    }
+    assert_eq!(writer.bytes_written(), input.len() as u64);
+
+    let (output, tree) = writer.finish().unwrap();
+    assert_eq!(output, input);
+    assert_eq!(tree.num_leaves(), 6);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[test]
+fn test_hashing_writer_copy() {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..3 * 1024).map(|_| rng.gen()).collect();
+
+    let mut writer = HashingWriter::new(std::io::sink());
+    std::io::copy(&mut input.as_slice(), &mut writer).unwrap();
+    let (_, tree) = writer.finish().unwrap();
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_hashing_writer() {
+    use merkle_tree::io::AsyncHashingWriter;
+    use tokio::io::AsyncWriteExt;
+
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..9 * 1024 + 5).map(|_| rng.gen()).collect();
+
+    let mut writer = AsyncHashingWriter::new(Vec::new());
+    for piece in input.chunks(1000) {
+        writer.write_all(piece).await.unwrap();
+    }
+    let (output, tree) = writer.finish().await.unwrap();
+    assert_eq!(output, input);
+    assert_eq!(tree.root().chaining_value(), blake3_chaining_value(&input));
+}
@@ -0,0 +1,51 @@
+use std::cell::Cell;
+use std::io;
+use std::ops::Range;
+
+use merkle_tree::binary_merkle_tree::CHUNK_LEN;
+use merkle_tree::encoding::{combined_slice_ranges, encode_combined, slice_from_combined};
+use merkle_tree::http::VerifiedHttpReader;
+
+// Serves `blob` the way a static file server answers range requests
+fn serve(blob: Vec<u8>, requests: &Cell<usize>) -> impl Fn(&str, Range<u64>) -> io::Result<Vec<u8>> + '_ {
+    move |url, range| {
+        assert_eq!(url, "https://cdn.example/file.b3");
+        requests.set(requests.get() + 1);
+        Ok(blob[range.start as usize..(range.end as usize).min(blob.len())].to_vec())
+    }
+}
+
+#[test]
+fn test_verified_range_reads() {
+    let input: Vec<u8> = (0..40 * CHUNK_LEN + 17).map(|i| (i % 247) as u8).collect();
+    let (encoded, root) = encode_combined(&input);
+    for range in [0..10, 5000..9000, 40 * CHUNK_LEN as u64..input.len() as u64] {
+        let mut slice = encoded[..8].to_vec();
+        for piece in combined_slice_ranges(input.len() as u64, range.clone()) {
+            slice.extend_from_slice(&encoded[piece.start as usize..piece.end as usize]);
+        }
+        assert_eq!(slice, slice_from_combined(&encoded, range).unwrap());
+    }
+
+    let requests = Cell::new(0);
+    let mut reader = VerifiedHttpReader::new(serve(encoded, &requests), "https://cdn.example/file.b3", root);
+    assert_eq!(reader.len().unwrap(), input.len() as u64);
+    assert_eq!(reader.read_range(5000..9000).unwrap(), &input[5000..9000]);
+    assert_eq!(reader.read_range(40_000..50_000).unwrap(), &input[40_000..]);
+    // The header is fetched once; each read only needs a few ranges
+    assert!(requests.get() < 16);
+}
+
+#[test]
+fn test_tampered_server_is_rejected() {
+    let input = vec![3u8; 10 * CHUNK_LEN];
+    let (mut encoded, root) = encode_combined(&input);
+    let last = encoded.len() - 1;
+    encoded[last] ^= 1;
+
+    let requests = Cell::new(0);
+    let mut reader = VerifiedHttpReader::new(serve(encoded, &requests), "https://cdn.example/file.b3", root);
+    assert_eq!(reader.read_range(0..CHUNK_LEN as u64).unwrap(), &input[..CHUNK_LEN]);
+    let err = reader.read_range(9 * CHUNK_LEN as u64..10 * CHUNK_LEN as u64).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,37 @@
+use std::io::Cursor;
+
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::distributed::SegmentOutput;
+use merkle_tree::export::NodeOrder;
+use merkle_tree::incremental::{write_post_order_export, IncrementalTreeEncoder};
+
+#[test]
+fn test_nodes_are_emitted_as_soon_as_known() {
+    let leaves = process_input_to_chunks(&[6; 5 * CHUNK_LEN]);
+    let mut emitted: Vec<(u64, u64)> = Vec::new();
+    let mut encoder = IncrementalTreeEncoder::new(|node: SegmentOutput| emitted.push((node.first_chunk, node.chunk_count)));
+    for leaf in &leaves[..4] {
+        encoder.push_chunk(*leaf);
+    }
+    encoder.push_chunk(leaves[4]);
+    let root = encoder.finish();
+    assert_eq!(root, BinaryMerkleTree::new_from_leaves(leaves).root());
+    // The four-chunk subtree is complete after the fourth chunk; the root
+    // only once the encoder finishes
+    assert_eq!(emitted, vec![(0, 1), (1, 1), (0, 2), (2, 1), (3, 1), (2, 2), (0, 4), (4, 1), (0, 5)]);
+}
+
+#[test]
+fn test_streamed_export_matches_post_order_export() {
+    for len in [0, CHUNK_LEN, 13 * CHUNK_LEN + 7, 64 * CHUNK_LEN] {
+        let input: Vec<u8> = (0..len).map(|i| (i % 233) as u8).collect();
+        let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+        let mut expected = Vec::new();
+        tree.export_nodes(&mut expected, NodeOrder::PostOrder).unwrap();
+
+        let mut streamed = Cursor::new(Vec::new());
+        let root = write_post_order_export(&mut streamed, process_input_to_chunks(&input)).unwrap();
+        assert_eq!(root, tree.root_hash(), "length {}", len);
+        assert_eq!(streamed.into_inner(), expected, "length {}", len);
+    }
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "blake3-interop")]
+
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::error::Error;
+use merkle_tree::hash::Hash;
+use merkle_tree::interop::{blake3_chunk_cvs, check_against_blake3, import_chunk_cvs};
+use merkle_tree::io::VerifiedWriter;
+
+#[test]
+fn test_hash_and_chunk_cv_conversions() {
+    let input: Vec<u8> = (0..5 * CHUNK_LEN + 17).map(|i| i as u8).collect();
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+
+    let official = blake3::hash(&input);
+    assert_eq!(Hash::from(official), tree.root_hash());
+    assert_eq!(blake3::Hash::from(tree.root_hash()), official);
+
+    let cvs = import_chunk_cvs(&blake3_chunk_cvs(&input));
+    assert_eq!(cvs, (0..6).map(|i| tree.leaf_cv(i).unwrap()).collect::<Vec<_>>());
+    assert!(VerifiedWriter::with_outboard(Vec::new(), tree.root_hash(), input.len() as u64, cvs).is_ok());
+}
+
+#[test]
+fn test_check_against_blake3_finds_first_bad_chunk() {
+    let input = vec![9; 4 * CHUNK_LEN];
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    assert_eq!(check_against_blake3(&tree, &input), Ok(()));
+
+    tree.insert_leaf(2, chunk_output(2, b"stale")).unwrap();
+    assert_eq!(check_against_blake3(&tree, &input), Err(Error::HashMismatch { offset: 2 * CHUNK_LEN as u64 }));
+}
@@ -0,0 +1,57 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::node_index::NodeIndex;
+
+#[test]
+fn test_iter_leaves_and_levels() {
+    let leaves = process_input_to_chunks(&vec![3u8; 5 * CHUNK_LEN]);
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(leaves.clone());
+
+    let iterated: Vec<_> = tree.iter_leaves().map(|(i, leaf)| (i, *leaf)).collect();
+    assert_eq!(iterated, leaves.iter().copied().enumerate().collect::<Vec<_>>());
+
+    // Five leaves: levels of 1, 2, 3, 5 populated nodes
+    let level_sizes: Vec<usize> = (0..4).map(|depth| tree.iter_level(depth).count()).collect();
+    assert_eq!(level_sizes, vec![1, 2, 3, 5]);
+    assert_eq!(tree.iter_level(4).count(), 0);
+    assert_eq!(tree.iter_level(0).next().unwrap().1, tree.get_node(1).unwrap());
+}
+
+#[test]
+fn test_iter_nodes_yields_heap_indices() {
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![1u8; 3 * CHUNK_LEN]));
+    let indices: Vec<usize> = tree.iter_nodes().map(|(index, _)| index).collect();
+    assert_eq!(indices, vec![1, 2, 3, 4, 5, 6]);
+    for (index, node) in tree.iter_nodes() {
+        assert_eq!(Some(node), tree.get_node(index));
+    }
+}
+
+#[test]
+fn test_traversal_orders() {
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![2u8; 5 * CHUNK_LEN]));
+    let bfs: Vec<usize> = tree.traverse_bfs().map(|(index, _)| index.get()).collect();
+    assert_eq!(bfs, tree.iter_nodes().map(|(index, _)| index).collect::<Vec<_>>());
+    assert_eq!(bfs, vec![1, 2, 3, 4, 5, 6, 8, 9, 10, 11, 12]);
+
+    let dfs: Vec<usize> = tree.traverse_dfs_preorder().map(|(index, _)| index.get()).collect();
+    assert_eq!(dfs, vec![1, 2, 4, 8, 9, 5, 10, 11, 3, 6, 12]);
+    for (index, node) in tree.traverse_dfs_preorder() {
+        assert_eq!(Some(node), tree.get_node(index.get()));
+    }
+}
+
+#[test]
+fn test_traversal_pruning() {
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![2u8; 8 * CHUNK_LEN]));
+    let left = NodeIndex::ROOT.left_child();
+    let dfs: Vec<usize> = tree
+        .traverse_dfs_preorder()
+        .prune(|index, _| index == left)
+        .map(|(index, _)| index.get())
+        .collect();
+    assert_eq!(dfs, vec![1, 2, 3, 6, 12, 13, 7, 14, 15]);
+
+    // Stop at a depth by pruning every node on it
+    let top: Vec<usize> = tree.traverse_bfs().prune(|index, _| index.level() == 1).map(|(index, _)| index.get()).collect();
+    assert_eq!(top, vec![1, 2, 3]);
+}
@@ -0,0 +1,93 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use rand::Rng;
+
+fn random_tree(chunks: usize) -> BinaryMerkleTree {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..chunks * CHUNK_LEN).map(|_| rng.gen()).collect();
+    BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input))
+}
+
+#[test]
+fn test_rollback_restores_every_node() {
+    let mut tree = random_tree(13);
+    let original: Vec<_> = tree.iter_nodes().map(|(_, node)| *node).collect();
+
+    tree.begin_journal();
+    tree.insert_leaf(4, chunk_output(4, &[1; CHUNK_LEN])).unwrap();
+    tree.bulk_insert_leaves([4, 5, 12].into_iter(), (0..3).map(|i| chunk_output(i, &[2; 10]))).unwrap();
+    assert!(!tree.journal().unwrap().is_empty());
+    assert!(tree.rollback_journal());
+
+    assert_eq!(tree.iter_nodes().map(|(_, node)| *node).collect::<Vec<_>>(), original);
+    assert!(tree.journal().is_none());
+    assert!(!tree.rollback_journal());
+}
+
+#[test]
+fn test_try_update_keeps_ok_and_undoes_err() {
+    let mut tree = random_tree(6);
+    let original_root = tree.root_hash();
+
+    let rejected: Result<(), &str> = tree.try_update(|tree| {
+        tree.insert_leaf(1, chunk_output(1, b"untrusted")).unwrap();
+        Err("patch failed validation")
+    });
+    assert!(rejected.is_err());
+    assert_eq!(tree.root_hash(), original_root);
+
+    let accepted: Result<(), &str> = tree.try_update(|tree| {
+        tree.insert_leaf(1, chunk_output(1, b"trusted")).unwrap();
+        Ok(())
+    });
+    assert!(accepted.is_ok());
+    assert_ne!(tree.root_hash(), original_root);
+    assert!(tree.journal().is_none());
+}
+
+#[test]
+fn test_try_update_rolls_back_a_growing_update() {
+    let mut tree = random_tree(5);
+    tree.set_level_rebuild_threshold(0.5);
+    let original: Vec<_> = tree.iter_nodes().map(|(_, node)| *node).collect();
+    let original_root = tree.root_hash();
+
+    let grown = vec![3u8; 9 * CHUNK_LEN + 10];
+    let rejected: Result<(), &str> = tree.try_update(|tree| {
+        tree.update_bytes(&grown, 0..grown.len() as u64);
+        assert_eq!(tree.num_leaves(), 10);
+        Err("grown input failed validation")
+    });
+    assert!(rejected.is_err());
+    assert_eq!(tree.num_leaves(), 5);
+    assert_eq!(tree.root_hash(), original_root);
+    assert_eq!(tree.iter_nodes().map(|(_, node)| *node).collect::<Vec<_>>(), original);
+    // The rebuild kept the tree's tuning
+    assert_eq!(tree.level_rebuild_threshold(), 0.5);
+
+    tree.update_bytes(&grown, 0..grown.len() as u64);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&grown).as_bytes());
+}
+
+#[test]
+fn test_nested_journals_roll_back_independently() {
+    let mut tree = random_tree(8);
+    let original_root = tree.root_hash();
+
+    tree.begin_journal();
+    tree.insert_leaf(1, chunk_output(1, &[1; CHUNK_LEN])).unwrap();
+    let outer_root = tree.root_hash();
+    tree.begin_journal();
+    assert_eq!(tree.journal().unwrap().depth(), 1);
+    tree.insert_leaf(6, chunk_output(6, &[2; CHUNK_LEN])).unwrap();
+    assert!(tree.rollback_journal());
+    assert_eq!(tree.root_hash(), outer_root);
+
+    // A committed inner journal leaves its writes to the outer one
+    tree.begin_journal();
+    tree.insert_leaf(6, chunk_output(6, &[2; CHUNK_LEN])).unwrap();
+    assert!(tree.commit_journal());
+    assert!(tree.journal().is_some());
+    assert!(tree.rollback_journal());
+    assert_eq!(tree.root_hash(), original_root);
+    assert!(tree.journal().is_none());
+}
@@ -0,0 +1,29 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::lazy::LazyMerkleTree;
+use rand::Rng;
+
+#[test]
+fn test_lazy_tree_matches_eager_updates() {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..37 * CHUNK_LEN + 11).map(|_| rng.gen()).collect();
+    let mut eager = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut lazy = LazyMerkleTree::new(eager.clone());
+
+    for _ in 0..100 {
+        let index = rng.gen_range(0..eager.num_leaves());
+        let leaf = chunk_output(index as u64, &[rng.gen(); 16]);
+        eager.insert_leaf(index, leaf).unwrap();
+        lazy.insert_leaf(index, leaf).unwrap();
+    }
+    assert!(lazy.dirty_leaves() > 0);
+    assert_eq!(lazy.root_hash(), eager.root_hash());
+    assert_eq!(lazy.dirty_leaves(), 0);
+    assert!(lazy.tree().iter_nodes().eq(eager.iter_nodes()));
+}
+
+#[test]
+fn test_lazy_tree_rejects_out_of_range() {
+    let mut lazy: LazyMerkleTree = LazyMerkleTree::new(BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[0; 3 * CHUNK_LEN])));
+    assert!(lazy.insert_leaf(3, chunk_output(3, b"x")).is_err());
+    assert_eq!(lazy.dirty_leaves(), 0);
+}
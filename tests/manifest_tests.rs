@@ -0,0 +1,47 @@
+use merkle_tree::manifest::{CheckStatus, Manifest};
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("merkle_tree_manifest_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("with space.txt"), b"hello world").unwrap();
+    fs::write(dir.join("sub/big.bin"), vec![3u8; 10 * 1024 + 1]).unwrap();
+    dir
+}
+
+#[test]
+fn test_manifest_round_trip() {
+    let dir = temp_dir("round_trip");
+    let manifest = Manifest::from_directory(&dir).unwrap();
+    assert_eq!(manifest.entries.len(), 2);
+    assert_eq!(manifest.entries[0].path, "sub/big.bin");
+    assert_eq!(manifest.entries[0].chunk_cvs.len(), 11);
+
+    let mut encoded = Vec::new();
+    manifest.write(&mut encoded).unwrap();
+    let decoded = Manifest::read(encoded.as_slice()).unwrap();
+    assert_eq!(decoded, manifest);
+
+    assert!(Manifest::read(&b"not a manifest\n"[..]).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_manifest_check_reports_changed_chunks() {
+    let dir = temp_dir("check");
+    let manifest = Manifest::from_directory(&dir).unwrap();
+    assert!(manifest.check(&dir).unwrap().iter().all(|check| check.passed()));
+
+    let mut big = fs::read(dir.join("sub/big.bin")).unwrap();
+    big[4 * 1024 + 10] ^= 1;
+    big.truncate(9 * 1024);
+    fs::write(dir.join("sub/big.bin"), &big).unwrap();
+    fs::remove_file(dir.join("with space.txt")).unwrap();
+
+    let checks = manifest.check(&dir).unwrap();
+    assert_eq!(checks[0].status, CheckStatus::Modified { len: 9 * 1024, changed_chunks: vec![4, 9, 10] });
+    assert_eq!(checks[1].status, CheckStatus::Missing);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,47 @@
+use merkle_tree::map::MerkleMap;
+
+#[test]
+fn test_merkle_map_membership_proofs() {
+    let mut map: MerkleMap<String, Vec<u8>> = MerkleMap::new();
+    let empty_root = map.root_hash();
+    for (key, value) in [("carol", b"3"), ("alice", b"1"), ("bob", b"2")] {
+        assert_eq!(map.insert(key.to_string(), value.to_vec()), None);
+    }
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>(), ["alice", "bob", "carol"]);
+
+    let root = map.root_hash();
+    let proof = map.prove("bob").unwrap();
+    assert!(proof.verify(&root, b"bob", b"2"));
+    assert!(!proof.verify(&root, b"bob", b"3"));
+    assert!(!proof.verify(&root, b"alice", b"2"));
+    assert!(map.prove("dave").is_none());
+
+    // Updating in place and removing both move the root
+    assert_eq!(map.insert("bob".to_string(), b"22".to_vec()), Some(b"2".to_vec()));
+    assert!(!proof.verify(&map.root_hash(), b"bob", b"2"));
+    assert!(map.prove("bob").unwrap().verify(&map.root_hash(), b"bob", b"22"));
+    for key in ["alice", "bob", "carol"] {
+        assert!(map.remove(key).is_some());
+    }
+    assert_eq!(map.root_hash(), empty_root);
+}
+
+#[test]
+fn test_merkle_map_root_is_canonical() {
+    // The same entries give the same root whatever order they arrive in
+    let mut forward: MerkleMap<Vec<u8>, Vec<u8>> = MerkleMap::new();
+    let mut backward: MerkleMap<Vec<u8>, Vec<u8>> = MerkleMap::new();
+    for i in 0..20u8 {
+        forward.insert(vec![i], vec![i; 3]);
+        backward.insert(vec![19 - i], vec![19 - i; 3]);
+    }
+    assert_eq!(forward.root_hash(), backward.root_hash());
+
+    // The key/value split is part of the commitment
+    let mut a: MerkleMap<Vec<u8>, Vec<u8>> = MerkleMap::new();
+    let mut b: MerkleMap<Vec<u8>, Vec<u8>> = MerkleMap::new();
+    a.insert(b"ab".to_vec(), b"c".to_vec());
+    b.insert(b"a".to_vec(), b"bc".to_vec());
+    assert_ne!(a.root_hash(), b.root_hash());
+}
@@ -0,0 +1,36 @@
+use merkle_tree::binary_merkle_tree::CHUNK_LEN;
+use merkle_tree::memory::MemoryRegionHasher;
+use rand::Rng;
+
+const PAGE_SIZE: usize = 4096;
+
+#[test]
+fn test_refresh_rehashes_dirty_pages_only() {
+    let mut rng = rand::thread_rng();
+    let mut region: Vec<u8> = (0..130 * PAGE_SIZE).map(|_| rng.gen()).collect();
+    let mut hasher = MemoryRegionHasher::new(&region, PAGE_SIZE);
+    assert_eq!(hasher.page_count(), 130);
+
+    // Touch pages 3 and 64 (second bitmap word); page 100 is marked dirty but
+    // unchanged, so it is rehashed without being reported
+    region[3 * PAGE_SIZE + 5] ^= 1;
+    region[64 * PAGE_SIZE + 2 * CHUNK_LEN] ^= 1;
+    let bitmap = [1 << 3, 1 | 1 << (100 - 64), 0];
+    let changed = hasher.refresh(&region, &bitmap);
+    assert_eq!(changed, vec![12, 258]);
+    assert_eq!(hasher.root_hash().as_bytes(), blake3::hash(&region).as_bytes());
+}
+
+#[test]
+fn test_pages_smaller_than_chunks() {
+    let mut region = vec![0u8; 10 * CHUNK_LEN + 300];
+    let mut hasher = MemoryRegionHasher::new(&region, 512);
+
+    // Pages 1 and 2 fall in different chunks; page 20 is the partial last page
+    region[600] = 1;
+    region[1100] = 2;
+    region[10 * CHUNK_LEN + 299] = 3;
+    let changed = hasher.refresh(&region, &[1 << 1 | 1 << 2 | 1 << 20]);
+    assert_eq!(changed, vec![0, 1, 10]);
+    assert_eq!(hasher.root_hash().as_bytes(), blake3::hash(&region).as_bytes());
+}
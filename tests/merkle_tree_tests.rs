@@ -1,5 +1,7 @@
-use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, UnbalancedMerkleTree, Output, process_input_to_chunks, Blake3Hasher, CHUNK_LEN, IV, ChunkState};
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, process_input_to_chunks, process_input_to_chunks_from, parent_output, Blake3Hasher, CHUNK_LEN, IV, ChunkState};
+use merkle_tree::hash::Hash;
 use rand::Rng;
+use std::cmp::min;
 use std::time::Instant;
 use std::collections::HashMap;
 
@@ -37,6 +39,26 @@ fn test_initial_hash_value_match() {
         initial_root, initial_blake3_chaining_value);
 }
 
+#[test]
+fn test_non_power_of_two_chunk_counts_match_blake3() {
+    // The right edge of the tree promotes lone left children, as BLAKE3 does
+    for chunk_count in [3, 5, 6, 7, 9, 13] {
+        let input: Vec<u8> = (0..chunk_count * CHUNK_LEN - 100).map(|i| (i % 251) as u8).collect();
+        let chunks = process_input_to_chunks(&input);
+        let mut tree = BinaryMerkleTree::new_from_leaves(chunks.clone());
+        assert_eq!(tree.num_leaves(), chunk_count);
+        let mut root = [0; 32];
+        tree.root().root_output_bytes(&mut root);
+        assert_eq!(&root, blake3::hash(&input).as_bytes());
+
+        // Rewriting the last leaves goes through the same promotion
+        let last = chunk_count - 2..chunk_count;
+        tree.bulk_insert_leaves(last.clone(), chunks[last].iter().copied()).unwrap();
+        tree.root().root_output_bytes(&mut root);
+        assert_eq!(&root, blake3::hash(&input).as_bytes());
+    }
+}
+
 #[test]
 fn test_single_mutation_hash_value_match() {
     // Generate random input
@@ -64,7 +86,7 @@ fn test_single_mutation_hash_value_match() {
 
     // Time the tree update operation
     let update_start = Instant::now();
-    tree.insert_leaf(chunk_index, mutated_chunk_output);
+    tree.insert_leaf(chunk_index, mutated_chunk_output).unwrap();
     let mutated_root = tree.root().chaining_value();
     let update_duration = update_start.elapsed();
     println!("Tree root computation in updated merkle tree took: {:?}", update_duration);
@@ -118,7 +140,7 @@ fn test_fuzz_single_mutation() {
         let mutated_chunk_output = chunk_state.output();
 
         // Update merkle tree and get new root
-        tree.insert_leaf(chunk_index, mutated_chunk_output);
+        tree.insert_leaf(chunk_index, mutated_chunk_output).unwrap();
         let mutated_root = tree.root().chaining_value();
 
         // Compute full BLAKE3 hash for comparison
@@ -176,7 +198,7 @@ fn test_bulk_mutations() {
             // Group mutations by chunk
             let chunk_index = pos / CHUNK_LEN;
             chunk_updates.entry(chunk_index)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(pos);
         }
         
@@ -202,7 +224,7 @@ fn test_bulk_mutations() {
         
         // Time the Merkle tree bulk update
         let merkle_start = Instant::now();
-        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter());
+        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter()).unwrap();
         let mutated_root = tree.root().chaining_value();
         let merkle_duration = merkle_start.elapsed();
         println!("Merkle tree bulk update + root computation took: {:?}", merkle_duration);
@@ -264,7 +286,7 @@ fn test_fuzz_bulk_mutations() {
             
             let chunk_index = pos / CHUNK_LEN;
             chunk_updates.entry(chunk_index)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(pos);
         }
         
@@ -288,7 +310,7 @@ fn test_fuzz_bulk_mutations() {
         }
         
         // Update merkle tree with bulk mutations
-        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter());
+        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter()).unwrap();
         let mutated_root = tree.root().chaining_value();
         
         // Compute full BLAKE3 hash for comparison
@@ -313,4 +335,243 @@ fn test_fuzz_bulk_mutations() {
         }
     }
     println!("Successfully completed {} fuzz test iterations with random bulk mutations", FUZZ_ITERATIONS);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_empty_and_single_chunk_inputs_match_blake3() {
+    for len in [0, 1, 63, 64, 65, 1000, CHUNK_LEN] {
+        let input: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&input);
+        let mut expected = [0; 32];
+        hasher.finalize(&mut expected);
+
+        let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+        assert_eq!(tree.num_leaves(), 1);
+        assert_eq!(tree.root_hash().as_bytes(), &expected, "input length {}", len);
+    }
+
+    // A tree built without any leaves hashes like empty input too
+    let empty = BinaryMerkleTree::new_from_leaves(Vec::new());
+    assert_eq!(empty.root_hash(), BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[])).root_hash());
+}
+
+#[test]
+fn test_bulk_insert_errors_leave_tree_unchanged() {
+    use merkle_tree::binary_merkle_tree::chunk_output;
+    use merkle_tree::error::Error;
+
+    let input = vec![3u8; 6 * CHUNK_LEN];
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let root = tree.root_hash();
+    let leaf = chunk_output(0, b"new");
+
+    assert_eq!(
+        tree.bulk_insert_leaves([1, 4, 2].into_iter(), [leaf; 3].into_iter()),
+        Err(Error::UnsortedIndices { position: 2, previous: 4, index: 2 })
+    );
+    assert_eq!(
+        tree.bulk_insert_leaves([1, 6].into_iter(), [leaf; 2].into_iter()),
+        Err(Error::IndexOutOfRange { index: 6, leaf_count: 6 })
+    );
+    assert_eq!(
+        tree.bulk_insert_leaves([1, 2].into_iter(), [leaf; 1].into_iter()),
+        Err(Error::LengthMismatch { indices: 2, leaves: 1 })
+    );
+    assert_eq!(tree.root_hash(), root);
+}
+
+#[test]
+fn test_insert_leaf_out_of_range() {
+    use merkle_tree::binary_merkle_tree::chunk_output;
+    use merkle_tree::error::Error;
+
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[5u8; 3 * CHUNK_LEN]));
+    let root = tree.root_hash();
+    // Index 3 still fits in the power-of-two node array, but is not a leaf
+    assert_eq!(
+        tree.insert_leaf(3, chunk_output(3, b"x")),
+        Err(Error::IndexOutOfRange { index: 3, leaf_count: 3 })
+    );
+    assert_eq!(tree.root_hash(), root);
+
+    tree.insert_leaf_unchecked(2, chunk_output(2, b"x"));
+    assert_ne!(tree.root_hash(), root);
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn test_insert_leaf_unchecked_panics_out_of_range() {
+    use merkle_tree::binary_merkle_tree::chunk_output;
+
+    let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[5u8; 3 * CHUNK_LEN]));
+    tree.insert_leaf_unchecked(7, chunk_output(7, b"x"));
+}
+
+#[test]
+fn test_bulk_insert_unordered_last_write_wins() {
+    use merkle_tree::binary_merkle_tree::chunk_output;
+
+    let input = vec![9u8; 8 * CHUNK_LEN];
+    let mut unordered = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut sorted = unordered.clone();
+
+    let a = chunk_output(5, b"first");
+    let b = chunk_output(5, b"second");
+    let c = chunk_output(1, b"other");
+    unordered.bulk_insert_leaves_unordered([(5, a), (1, c), (5, b)]).unwrap();
+    sorted.bulk_insert_leaves([1, 5].into_iter(), [c, b].into_iter()).unwrap();
+    assert_eq!(unordered.root_hash(), sorted.root_hash());
+}
+
+#[test]
+fn test_bulk_insert_hashes_each_dirty_parent_once() {
+    use merkle_tree::binary_merkle_tree::chunk_output;
+
+    let leaf_count = 1024;
+    let depth = 10;
+    let input = vec![1u8; leaf_count * CHUNK_LEN];
+    let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    // Building the tree hashes every interior node once
+    assert_eq!(tree.parent_hash_count(), leaf_count as u64 - 1);
+
+    // Leaves 0, 2, 4, ... are not siblings, but pairs of them share every
+    // ancestor from the grandparent up
+    let k = 64;
+    let indices: Vec<usize> = (0..k).map(|i| 2 * i).collect();
+    let before = tree.parent_hash_count();
+    tree.bulk_insert_leaves(indices.iter().copied(), indices.iter().map(|&i| chunk_output(i as u64, b"dirty"))).unwrap();
+    let hashed = tree.parent_hash_count() - before;
+
+    // The dirty set is leaves 0..128: k parents, then 32, 16, ... 1 up to
+    // the top of that subtree, then one per level above it
+    let expected: u64 = 64 + 32 + 16 + 8 + 4 + 2 + 1 + 3;
+    assert_eq!(hashed, expected);
+    assert!(hashed <= (k * depth) as u64);
+}
+
+#[test]
+fn test_leaf_and_node_accessors() {
+    let leaves = process_input_to_chunks(&vec![8u8; 3 * CHUNK_LEN]);
+    let tree = BinaryMerkleTree::new_from_leaves(leaves.clone());
+
+    assert_eq!(tree.get_leaf(1), Some(&leaves[1]));
+    assert_eq!(tree.get_leaf(3), None);
+    assert_eq!(tree.leaf_cv(2), Some(leaves[2].chaining_value()));
+    assert_eq!(tree.node_cv(1), Some(tree.get_node(1).unwrap().chaining_value()));
+    assert_eq!(tree.node_cv(0), None);
+    assert_eq!(tree.node_cv(8), None);
+}
+
+#[test]
+fn test_subtree_cv_matches_standalone_subtrees() {
+    let mut rng = rand::thread_rng();
+    let input: Vec<u8> = (0..7 * CHUNK_LEN).map(|_| rng.gen()).collect();
+    let leaves = process_input_to_chunks(&input);
+    let tree = BinaryMerkleTree::new_from_leaves(leaves.clone());
+
+    // A subtree's chaining value is the non-root output of a tree over just its leaves
+    for (first, count) in [(0, 4), (4, 2), (4, 3), (6, 1), (0, 7), (2, 1)] {
+        let standalone: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(leaves[first..first + count].to_vec());
+        assert_eq!(tree.subtree_cv(first, count), Some(standalone.root_node().chaining_value()), "{}..+{}", first, count);
+    }
+    // Misaligned, partial in the middle, empty, or past the end
+    for (first, count) in [(1, 2), (0, 3), (4, 0), (4, 4), (8, 1)] {
+        assert_eq!(tree.subtree_cv(first, count), None, "{}..+{}", first, count);
+    }
+}
+
+#[test]
+fn test_segments_hash_with_their_chunk_counters() {
+    let input: Vec<u8> = (0..13 * CHUNK_LEN + 77).map(|i| (i % 249) as u8).collect();
+    let whole = process_input_to_chunks(&input);
+    assert_eq!(process_input_to_chunks_from(&input[5 * CHUNK_LEN..], 5), whole[5..]);
+
+    // The left and right subtrees of a 14-chunk input are 8 chunks and the rest,
+    // hashed independently and in either order
+    let right = BinaryMerkleTree::from_segment(&input[8 * CHUNK_LEN..], 8);
+    let left = BinaryMerkleTree::from_segment(&input[..8 * CHUNK_LEN], 0);
+    let root = parent_output(left.root_node().chaining_value(), right.root_node().chaining_value(), IV, 0);
+    assert_eq!(Hash::from_root(&root).as_bytes(), blake3::hash(&input).as_bytes());
+}
+
+#[test]
+fn test_merge_segment_trees() {
+    let input: Vec<u8> = (0..29 * CHUNK_LEN + 100).map(|i| (i % 241) as u8).collect();
+    for (left_chunks, right_chunks) in [(16, 14), (1, 1), (4, 1), (8, 8)] {
+        let input = &input[..min(input.len(), (left_chunks + right_chunks) * CHUNK_LEN)];
+        let split = left_chunks * CHUNK_LEN;
+        let left = BinaryMerkleTree::from_segment(&input[..split], 0);
+        let right = BinaryMerkleTree::from_segment(&input[split..], left_chunks as u64);
+        let merged = BinaryMerkleTree::merge(left, right).unwrap();
+
+        let whole: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input));
+        assert_eq!(merged.num_leaves(), whole.num_leaves());
+        assert_eq!(merged.root_hash().as_bytes(), blake3::hash(input).as_bytes());
+        for node_index in 0..2 * whole.num_leaves().next_power_of_two() {
+            assert_eq!(merged.get_node(node_index), whole.get_node(node_index), "node {node_index}");
+        }
+    }
+
+    // Wrong counters, a left side that is not a power of two, or a right side
+    // larger than the left are all rejected
+    let tree = |first: usize, count: usize| {
+        BinaryMerkleTree::from_segment(&input[first * CHUNK_LEN..(first + count) * CHUNK_LEN], first as u64)
+    };
+    assert!(BinaryMerkleTree::merge(tree(0, 4), tree(5, 3)).is_err());
+    assert!(BinaryMerkleTree::merge(tree(0, 6), tree(6, 2)).is_err());
+    assert!(BinaryMerkleTree::merge(tree(0, 4), tree(4, 5)).is_err());
+}
+
+#[test]
+fn test_split_at_aligned_boundary() {
+    let input: Vec<u8> = (0..13 * CHUNK_LEN + 9).map(|i| (i % 239) as u8).collect();
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+
+    let (left, right) = tree.split_at(8).unwrap();
+    assert_eq!(left.root_hash().as_bytes(), blake3::hash(&input[..8 * CHUNK_LEN]).as_bytes());
+    assert_eq!(right.root_node(), BinaryMerkleTree::from_segment(&input[8 * CHUNK_LEN..], 8).root_node());
+    assert_eq!(BinaryMerkleTree::merge(left, right).unwrap().root_hash(), tree.root_hash());
+
+    // Chunks 12.. are the last subtree; chunks 4.. are not a subtree at all
+    let (prefix, tail) = tree.split_at(12).unwrap();
+    assert_eq!((prefix.num_leaves(), tail.num_leaves()), (12, 2));
+    assert!(tree.split_at(4).is_none());
+    assert!(tree.split_at(0).is_none());
+    assert!(tree.split_at(14).is_none());
+}
+
+#[test]
+fn test_level_rebuild_threshold() {
+    use merkle_tree::binary_merkle_tree::{chunk_output, DEFAULT_LEVEL_REBUILD_THRESHOLD};
+
+    let leaf_count = 1000;
+    let input = vec![3u8; leaf_count * CHUNK_LEN];
+    let base: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    assert_eq!(base.level_rebuild_threshold(), DEFAULT_LEVEL_REBUILD_THRESHOLD);
+
+    for indices in [vec![5, 500, 999], (0..leaf_count).step_by(3).collect(), (0..leaf_count).collect::<Vec<_>>()] {
+        let leaves = || indices.iter().map(|&i| chunk_output(i as u64, b"new"));
+        let mut per_path = base.clone();
+        per_path.set_level_rebuild_threshold(f64::INFINITY);
+        let per_path_before = per_path.parent_hash_count();
+        per_path.bulk_insert_leaves(indices.iter().copied(), leaves()).unwrap();
+        let mut default = base.clone();
+        let default_before = default.parent_hash_count();
+        default.bulk_insert_leaves(indices.iter().copied(), leaves()).unwrap();
+        let mut rebuilt = base.clone();
+        rebuilt.set_level_rebuild_threshold(0.0);
+        let before = rebuilt.parent_hash_count();
+        rebuilt.bulk_insert_leaves(indices.iter().copied(), leaves()).unwrap();
+
+        assert_eq!(per_path.root_hash(), rebuilt.root_hash());
+        assert_eq!(default.root_hash(), rebuilt.root_hash());
+        // Below the threshold only dirty ancestors are hashed
+        assert_eq!(
+            default.parent_hash_count() - default_before,
+            per_path.parent_hash_count() - per_path_before
+        );
+        // Rebuilding from the bottom level rehashes every parent
+        assert_eq!(rebuilt.parent_hash_count() - before, leaf_count as u64 - 1);
+    }
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "metrics")]
+
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::metrics::Metrics;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_single_insert_costs_one_path() {
+    let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![1u8; 16 * CHUNK_LEN]));
+    // Building 16 leaves hashes 15 parents of two chaining values each
+    assert_eq!(tree.metrics().compressions, 30);
+    tree.reset_metrics();
+
+    tree.insert_leaf(5, chunk_output(5, b"new")).unwrap();
+    assert_eq!(
+        tree.metrics(),
+        Metrics {
+            compressions: 8,
+            leaves_updated: 1,
+            ancestors_recomputed: 4,
+            bytes_hashed: 0,
+        }
+    );
+}
+
+#[test]
+fn test_bulk_insert_shares_ancestors_and_reports() {
+    let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![2u8; 1024 * CHUNK_LEN]));
+    tree.reset_metrics();
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    tree.set_metrics_callback(move |metrics| sink.lock().unwrap().push(*metrics));
+
+    // Siblings share every ancestor, so the 10 levels above them are hashed once
+    tree.bulk_insert_leaves([6, 7].into_iter(), [chunk_output(6, b"a"), chunk_output(7, b"b")].into_iter())
+        .unwrap();
+    let metrics = tree.metrics();
+    assert_eq!(metrics.leaves_updated, 2);
+    assert_eq!(metrics.ancestors_recomputed, 10);
+    assert_eq!(metrics.compressions, 20);
+    assert_eq!(*reports.lock().unwrap(), vec![metrics]);
+
+    tree.clear_metrics_callback();
+    tree.insert_leaf(0, chunk_output(0, b"c")).unwrap();
+    assert_eq!(reports.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_byte_helpers_count_rehashed_bytes() {
+    let mut content = vec![3u8; 4 * CHUNK_LEN + 100];
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&content));
+    content[CHUNK_LEN + 7] = 9;
+    tree.update_bytes(&content, (CHUNK_LEN + 7) as u64..(CHUNK_LEN + 8) as u64);
+    assert_eq!(tree.metrics().bytes_hashed, CHUNK_LEN as u64);
+
+    // The final chunk is shorter than the rest
+    content[4 * CHUNK_LEN] = 9;
+    tree.update_bytes(&content, (4 * CHUNK_LEN) as u64..(4 * CHUNK_LEN + 1) as u64);
+    assert_eq!(tree.metrics().bytes_hashed, CHUNK_LEN as u64 + 100);
+    assert_eq!(tree.metrics().leaves_updated, 2);
+}
@@ -0,0 +1,54 @@
+use merkle_tree::mmr::MmrTree;
+
+fn entry(i: u64) -> Vec<u8> {
+    format!("log entry {}", i).into_bytes()
+}
+
+#[test]
+fn test_mmr_append_and_peaks() {
+    let mut mmr = MmrTree::new();
+    assert!(mmr.root_hash().is_none());
+    for i in 0..11 {
+        assert_eq!(mmr.append(&entry(i)), i);
+    }
+    // 11 = 8 + 2 + 1
+    assert_eq!(mmr.num_leaves(), 11);
+    assert_eq!(mmr.peaks().len(), 3);
+
+    let mut single = MmrTree::new();
+    single.append(&entry(0));
+    assert_eq!(single.peaks().len(), 1);
+}
+
+#[test]
+fn test_mmr_proofs_for_every_entry_and_size() {
+    let mut mmr = MmrTree::new();
+    let mut roots = Vec::new();
+    for i in 0..20 {
+        mmr.append(&entry(i));
+        roots.push(mmr.root_hash().unwrap());
+    }
+    for leaf_count in 1..=20u64 {
+        let root = mmr.root_hash_at(leaf_count).unwrap();
+        assert_eq!(root, roots[leaf_count as usize - 1]);
+        for leaf_index in 0..leaf_count {
+            let proof = mmr.prove_at(leaf_index, leaf_count).unwrap();
+            assert!(proof.verify(&root, &entry(leaf_index)));
+            assert!(!proof.verify(&root, b"forged entry"));
+        }
+    }
+    assert!(mmr.prove(20).is_none());
+    assert!(mmr.root_hash_at(21).is_none());
+}
+
+#[test]
+fn test_mmr_proof_rejects_other_position() {
+    let mut mmr = MmrTree::new();
+    for i in 0..7 {
+        mmr.append(&entry(i));
+    }
+    let root = mmr.root_hash().unwrap();
+    let mut proof = mmr.prove(3).unwrap();
+    proof.leaf_index = 2;
+    assert!(!proof.verify(&root, &entry(3)));
+}
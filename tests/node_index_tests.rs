@@ -0,0 +1,39 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::node_index::NodeIndex;
+
+#[test]
+fn test_navigation() {
+    let root = NodeIndex::ROOT;
+    assert_eq!(root.level(), 0);
+    assert_eq!(root.parent(), None);
+    assert_eq!(root.sibling(), None);
+    assert!(!root.is_left_child());
+
+    let left = root.left_child();
+    let right = root.right_child();
+    assert_eq!((left.get(), right.get()), (2, 3));
+    assert_eq!(left.sibling(), Some(right));
+    assert_eq!(right.sibling(), Some(left));
+    assert!(left.is_left_child() && !right.is_left_child());
+    assert_eq!(right.right_child().left_child().parent().unwrap().parent(), Some(right));
+    assert_eq!(NodeIndex::new(13).unwrap().level(), 3);
+    assert_eq!(NodeIndex::new(0), None);
+}
+
+#[test]
+fn test_leaves_of_a_tree() {
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[1; 5 * CHUNK_LEN]));
+    let leaf = NodeIndex::leaf(&tree, 4).unwrap();
+    assert_eq!(leaf.get(), 12);
+    assert!(leaf.is_leaf(&tree));
+    assert_eq!(leaf.level(), 3);
+    assert_eq!(leaf.first_leaf(&tree), 4);
+    assert_eq!(tree.get_node(leaf.get()), tree.get_leaf(4));
+    assert!(NodeIndex::leaf(&tree, 5).is_none());
+
+    // Node 3 covers leaves 4..8, of which only leaf 4 exists
+    let right = NodeIndex::ROOT.right_child();
+    assert!(!right.is_leaf(&tree));
+    assert_eq!(right.first_leaf(&tree), 4);
+    assert_eq!(right.right_child().first_leaf(&tree), 6);
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, Output, CHUNK_LEN};
+use merkle_tree::node_store::{MemoryNodeStore, NodeStore, StoredMerkleTree, SyncMode};
+
+#[test]
+fn test_stored_tree_matches_in_memory_tree() {
+    let mut input: Vec<u8> = (0..37 * CHUNK_LEN + 5).map(|i| (i % 229) as u8).collect();
+    let leaves = process_input_to_chunks(&input);
+    let mut tree = StoredMerkleTree::create(MemoryNodeStore::new(), leaves.clone()).unwrap();
+    assert_eq!(tree.root_hash().unwrap().as_bytes(), blake3::hash(&input).as_bytes());
+    assert_eq!(tree.leaf(36).unwrap(), leaves[36]);
+
+    input[2] ^= 1;
+    input[30 * CHUNK_LEN] ^= 1;
+    let updates = [(0, chunk_output(0, &input[..CHUNK_LEN])), (30, chunk_output(30, &input[30 * CHUNK_LEN..31 * CHUNK_LEN]))];
+    tree.update_leaves(&updates).unwrap();
+    assert_eq!(tree.root_hash().unwrap().as_bytes(), blake3::hash(&input).as_bytes());
+
+    // Reopening from the store gives the same tree
+    let reopened = StoredMerkleTree::open(tree.into_store()).unwrap();
+    assert_eq!(reopened.num_leaves(), 38);
+    let expected = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    assert_eq!(reopened.root().unwrap(), expected.root());
+}
+
+#[test]
+fn test_empty_and_single_leaf_trees() {
+    let empty = StoredMerkleTree::create(MemoryNodeStore::new(), Vec::new()).unwrap();
+    assert_eq!(empty.root_hash().unwrap().as_bytes(), blake3::hash(b"").as_bytes());
+    let single = StoredMerkleTree::create(MemoryNodeStore::new(), process_input_to_chunks(b"abc")).unwrap();
+    assert_eq!(single.root_hash().unwrap().as_bytes(), blake3::hash(b"abc").as_bytes());
+    assert!(StoredMerkleTree::open(MemoryNodeStore::new()).is_err());
+}
+
+#[cfg(feature = "sled")]
+#[test]
+fn test_sled_store_survives_reopen() {
+    use merkle_tree::node_store::SledNodeStore;
+
+    let dir = std::env::temp_dir().join(format!("merkle_tree_sled_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let input = vec![7u8; 9 * CHUNK_LEN + 1];
+    let store = SledNodeStore::open(&dir).unwrap();
+    let mut tree = StoredMerkleTree::create(store, process_input_to_chunks(&input)).unwrap();
+    tree.update_leaves(&[(9, chunk_output(9, &[7]))]).unwrap();
+    tree.flush().unwrap();
+    tree.into_store().close().unwrap();
+    let tree = StoredMerkleTree::open(SledNodeStore::open(&dir).unwrap()).unwrap();
+    assert_eq!(tree.root_hash().unwrap().as_bytes(), blake3::hash(&input).as_bytes());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Counts flushes so the sync modes can be observed
+#[derive(Default)]
+struct CountingStore {
+    inner: MemoryNodeStore,
+    flushes: usize,
+}
+
+impl NodeStore for CountingStore {
+    fn get(&self, index: usize) -> std::io::Result<Option<Output>> {
+        self.inner.get(index)
+    }
+
+    fn write_batch(&mut self, nodes: &[(usize, Output)]) -> std::io::Result<()> {
+        self.inner.write_batch(nodes)
+    }
+
+    fn leaf_count(&self) -> std::io::Result<Option<usize>> {
+        self.inner.leaf_count()
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> std::io::Result<()> {
+        self.inner.set_leaf_count(leaf_count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_sync_modes() {
+    let input = vec![1u8; 4 * CHUNK_LEN];
+    let mut tree = StoredMerkleTree::create(CountingStore::default(), process_input_to_chunks(&input)).unwrap();
+    let update = [(1, chunk_output(1, &[2; CHUNK_LEN]))];
+
+    tree.update_leaves(&update).unwrap();
+    assert_eq!(tree.store().flushes, 0);
+    tree.set_sync_mode(SyncMode::OnCommit);
+    tree.update_leaves(&update).unwrap();
+    tree.update_leaves(&update).unwrap();
+    assert_eq!(tree.store().flushes, 2);
+    tree.set_sync_mode(SyncMode::Periodic(Duration::from_secs(3600)));
+    tree.update_leaves(&update).unwrap();
+    assert_eq!(tree.store().flushes, 2);
+
+    let durable = tree.flush().unwrap();
+    assert_eq!(tree.store().flushes, 3);
+    assert_eq!(durable, tree.root_hash().unwrap());
+}
@@ -0,0 +1,28 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::chunk_store::{rebuild, store_input};
+use merkle_tree::object_store::{IndexedObjectChunks, MemoryObjectStore, ObjectChunkStore};
+
+#[test]
+fn test_chunks_by_cv_in_object_store() {
+    let input: Vec<u8> = (0..6 * CHUNK_LEN + 50).map(|i| (i % 233) as u8).collect();
+    let mut store = ObjectChunkStore::new(MemoryObjectStore::new(), "chunks/");
+    let tree = store_input(&mut store, &input).unwrap();
+    assert_eq!(store.object_store().len(), 7);
+
+    let mut out = Vec::new();
+    rebuild(&tree, &store, &mut out).unwrap();
+    assert_eq!(out, input);
+}
+
+#[test]
+fn test_tree_updates_from_indexed_remote_chunks() {
+    let mut input: Vec<u8> = (0..6 * CHUNK_LEN + 50).map(|i| (i % 233) as u8).collect();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut remote = IndexedObjectChunks::upload(MemoryObjectStore::new(), "file/", &input).unwrap();
+
+    input[3 * CHUNK_LEN + 1] ^= 0xFF;
+    remote.put_chunk(3, &input[3 * CHUNK_LEN..4 * CHUNK_LEN]).unwrap();
+    let changed = tree.update_from_provider(&remote, (3 * CHUNK_LEN as u64)..(3 * CHUNK_LEN as u64 + 2)).unwrap();
+    assert_eq!(changed, vec![3]);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&input).as_bytes());
+}
@@ -0,0 +1,54 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::oplog::{Operation, OperationLog};
+
+#[test]
+fn test_replay_reproduces_recorded_tree() {
+    let mut input = vec![3u8; 6 * CHUNK_LEN + 100];
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    tree.begin_recording();
+    tree.insert_leaf(2, chunk_output(2, &[1; CHUNK_LEN])).unwrap();
+    tree.bulk_insert_leaves([0, 5].into_iter(), [chunk_output(0, &[2]), chunk_output(5, &[4])].into_iter())
+        .unwrap();
+    input[3 * CHUNK_LEN] = 9;
+    input.extend_from_slice(&[5; 3 * CHUNK_LEN]);
+    tree.update_bytes(&input, 3 * CHUNK_LEN as u64..3 * CHUNK_LEN as u64 + 1);
+
+    let log = tree.end_recording().unwrap();
+    assert!(matches!(log.operations()[0], Operation::Rebuild(ref leaves) if leaves.len() == 7));
+    assert_eq!(log.operations()[1], Operation::SetLeaf { index: 2, leaf: chunk_output(2, &[1; CHUNK_LEN]) });
+    assert!(matches!(log.operations().last(), Some(Operation::Rebuild(leaves)) if leaves.len() == 10));
+    assert!(tree.recording().is_none());
+
+    let replayed: BinaryMerkleTree = log.replay().unwrap();
+    assert_eq!(replayed.root_hash(), tree.root_hash());
+    assert_eq!(replayed.num_leaves(), tree.num_leaves());
+}
+
+#[test]
+fn test_log_round_trips_and_pinpoints_divergence() {
+    let leaves = process_input_to_chunks(&[8u8; 4 * CHUNK_LEN]);
+    let mut a: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(leaves.clone());
+    let mut b: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(leaves);
+    a.begin_recording();
+    b.begin_recording();
+    for (index, byte) in [(1, 1u8), (3, 2), (0, 3)] {
+        a.insert_leaf(index, chunk_output(index as u64, &[byte])).unwrap();
+        // The second service applies the same updates, but misnumbers one chunk
+        let counter = if index == 3 { 4 } else { index as u64 };
+        b.insert_leaf(index, chunk_output(counter, &[byte])).unwrap();
+    }
+
+    let mut bytes = Vec::new();
+    a.recording().unwrap().write_to(&mut bytes).unwrap();
+    let log_a = OperationLog::read_from(&bytes[..]).unwrap();
+    assert_eq!(&log_a, a.recording().unwrap());
+
+    let mut roots_a = Vec::new();
+    let replayed_a: BinaryMerkleTree = log_a.replay_with(|_, tree| roots_a.push(tree.root_hash())).unwrap();
+    let mut roots_b = Vec::new();
+    let _: BinaryMerkleTree = b.recording().unwrap().replay_with(|_, tree| roots_b.push(tree.root_hash())).unwrap();
+    assert_eq!(replayed_a.root_hash(), a.root_hash());
+    assert_eq!(roots_a.iter().zip(&roots_b).position(|(x, y)| x != y), Some(2));
+
+    assert_eq!(OperationLog::read_from(&bytes[..bytes.len() - 1]).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+}
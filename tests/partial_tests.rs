@@ -0,0 +1,37 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::error::Error;
+use merkle_tree::partial::PartialTree;
+
+#[test]
+fn test_partial_tree_serves_proofs_for_ingested_leaves() {
+    let input: Vec<u8> = (0..23 * CHUNK_LEN + 9).map(|i| (i % 253) as u8).collect();
+    let leaves = process_input_to_chunks(&input);
+    let tree = BinaryMerkleTree::new_from_leaves(leaves.clone());
+    let root = tree.root_hash();
+
+    let mut partial = PartialTree::new(root, tree.num_leaves());
+    partial.ingest(&tree.prove_range(5..6).unwrap(), &leaves[5..6]).unwrap();
+    partial.ingest(&tree.prove_range(17..24).unwrap(), &leaves[17..24]).unwrap();
+    assert_eq!(partial.known_leaves(), 8);
+    assert_eq!(partial.leaf(5), Some(&leaves[5]));
+    assert_eq!(partial.leaf(6), None);
+
+    // Proofs from the partial tree are the ones the full tree would give
+    for range in [5..6, 17..24, 20..22, 23..24] {
+        assert_eq!(partial.prove_range(range.clone()), tree.prove_range(range));
+    }
+    assert_eq!(partial.prove_range(4..6), None);
+}
+
+#[test]
+fn test_partial_tree_rejects_bad_proofs() {
+    let leaves = process_input_to_chunks(&[1; 9 * CHUNK_LEN]);
+    let tree = BinaryMerkleTree::new_from_leaves(leaves.clone());
+    let mut partial = PartialTree::new(tree.root_hash(), tree.num_leaves());
+
+    let proof = tree.prove_range(2..4).unwrap();
+    assert_eq!(partial.ingest(&proof, &leaves[3..5]), Err(Error::InvalidProof));
+    assert_eq!(partial.ingest(&proof, &leaves[2..3]), Err(Error::InvalidProof));
+    assert_eq!(partial.known_leaves(), 0);
+    assert_eq!(partial.ingest(&proof, &leaves[2..4]), Ok(()));
+}
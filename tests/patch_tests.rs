@@ -0,0 +1,127 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::error::Error;
+use merkle_tree::patch::{FilePatch, MerklePatch};
+
+fn inputs() -> (Vec<u8>, Vec<u8>) {
+    let old: Vec<u8> = (0..10 * CHUNK_LEN + 300).map(|i| (i % 253) as u8).collect();
+    let mut new = old.clone();
+    new[CHUNK_LEN + 5] ^= 1;
+    new[7 * CHUNK_LEN] ^= 1;
+    new.truncate(10 * CHUNK_LEN + 12);
+    (old, new)
+}
+
+#[test]
+fn test_patch_round_trips_and_applies() {
+    let (old, new) = inputs();
+    let patch = MerklePatch::between(&old, &new).unwrap();
+    let indices: Vec<usize> = patch.chunks.iter().map(|(index, _)| *index).collect();
+    assert_eq!(indices, [1, 7, 10]);
+    let patch = MerklePatch::decode(&patch.encode()).unwrap();
+
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&old));
+    tree.apply_patch(&patch).unwrap();
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&new).as_bytes());
+    // Already applied: the old root no longer matches
+    assert_eq!(tree.apply_patch(&patch), Err(Error::InvalidProof));
+    assert!(MerklePatch::between(&old, &old[..5 * CHUNK_LEN]).is_none());
+}
+
+#[test]
+fn test_untrusted_patch_is_rolled_back() {
+    let (old, new) = inputs();
+    let mut patch = MerklePatch::between(&old, &new).unwrap();
+    patch.chunks[1].1[3] ^= 1;
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&old));
+    let original = tree.root_hash();
+    assert_eq!(tree.apply_patch(&patch), Err(Error::InvalidProof));
+    assert_eq!(tree.root_hash(), original);
+    assert!(tree.journal().is_none());
+
+    // A short chunk in the middle of the input is rejected before it is hashed
+    patch.chunks[1].1.pop();
+    assert_eq!(tree.apply_patch(&patch), Err(Error::InvalidProof));
+    patch.chunks[1].1.push(0);
+    patch.chunks.push((11, vec![0]));
+    assert!(matches!(tree.apply_patch(&patch), Err(Error::IndexOutOfRange { index: 11, .. })));
+    assert_eq!(tree.root_hash(), original);
+}
+
+#[test]
+fn test_patch_nests_in_the_callers_journal() {
+    let (old, new) = inputs();
+    let patch = MerklePatch::between(&old, &new).unwrap();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&old));
+    let original = tree.root_hash();
+
+    tree.begin_journal();
+    let mut bad = patch.clone();
+    bad.chunks[0].1[0] ^= 1;
+    assert_eq!(tree.apply_patch(&bad), Err(Error::InvalidProof));
+    assert_eq!(tree.journal().unwrap().depth(), 0);
+    assert_eq!(tree.apply_patch(&patch), Ok(()));
+    assert!(!tree.journal().unwrap().is_empty());
+
+    // The caller can still undo the applied patch
+    assert!(tree.rollback_journal());
+    assert_eq!(tree.root_hash(), original);
+    assert!(tree.journal().is_none());
+}
+
+fn tree_of(input: &[u8]) -> BinaryMerkleTree {
+    BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input))
+}
+
+#[test]
+fn test_file_patch_upgrades_data_and_tree() {
+    let (old, _) = inputs();
+    let mut grown = old.clone();
+    grown[3 * CHUNK_LEN] ^= 1;
+    grown.extend((0..5 * CHUNK_LEN).map(|i| (i % 7) as u8));
+    let mut shrunk = old[..4 * CHUNK_LEN + 1].to_vec();
+    shrunk[CHUNK_LEN] ^= 1;
+
+    for new in [grown, shrunk, old.clone()] {
+        let patch = FilePatch::between(&tree_of(&old), &tree_of(&new), &new);
+        let patch = FilePatch::decode(&patch.encode()).unwrap();
+        let mut data = old.clone();
+        let mut tree = tree_of(&old);
+        patch.apply(&mut data, &mut tree).unwrap();
+        assert_eq!(data, new);
+        assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&new).as_bytes());
+    }
+}
+
+#[test]
+fn test_file_patch_only_carries_changed_chunks() {
+    let (old, mut new) = inputs();
+    new.extend_from_slice(&[1; 2 * CHUNK_LEN]);
+    let patch = FilePatch::between(&tree_of(&old), &tree_of(&new), &new);
+    let indices: Vec<usize> = patch.chunks.iter().map(|(index, _)| *index).collect();
+    assert_eq!(indices, [1, 7, 10, 11, 12]);
+    assert_eq!(patch.new_len, new.len() as u64);
+}
+
+#[test]
+fn test_file_patch_rejects_tampering_without_changes() {
+    let (old, new) = inputs();
+    let patch = FilePatch::between(&tree_of(&old), &tree_of(&new), &new);
+
+    let mut tampered = patch.clone();
+    tampered.chunks[0].1[0] ^= 1;
+    let mut data = old.clone();
+    let mut tree = tree_of(&old);
+    assert_eq!(tampered.apply(&mut data, &mut tree), Err(Error::InvalidProof));
+    assert_eq!(data, old);
+    assert_eq!(tree.root_hash(), tree_of(&old).root_hash());
+
+    // Dropping a chunk past A's end, or applying to the wrong version
+    let mut grown = old.clone();
+    grown.extend_from_slice(&[3; CHUNK_LEN]);
+    let mut truncated = FilePatch::between(&tree_of(&old), &tree_of(&grown), &grown);
+    truncated.chunks.pop();
+    assert_eq!(truncated.apply(&mut data, &mut tree), Err(Error::InvalidProof));
+    let mut other = tree_of(&new);
+    assert_eq!(patch.apply(&mut new.clone(), &mut other), Err(Error::InvalidProof));
+    assert!(FilePatch::decode(&patch.encode()[..40]).is_none());
+}
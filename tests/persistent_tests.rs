@@ -0,0 +1,55 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::error::Error;
+use merkle_tree::persistent::{PersistentMerkleTree, TreeHistory};
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_persistent_updates_keep_old_versions() {
+    let mut input = random_input(11 * CHUNK_LEN + 7);
+    let v0 = PersistentMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let v0_root = v0.root_hash();
+    assert_eq!(v0_root, BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input)).root_hash());
+
+    input[6 * CHUNK_LEN] ^= 1;
+    let v1 = v0.insert_leaf(6, chunk_output(6, &input[6 * CHUNK_LEN..7 * CHUNK_LEN])).unwrap();
+
+    assert_eq!(v0.root_hash(), v0_root);
+    assert_eq!(v1.root_hash(), BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input)).root_hash());
+    assert_eq!(v1.get_leaf(5), v0.get_leaf(5));
+    assert_ne!(v1.get_leaf(6), v0.get_leaf(6));
+    assert!(v1.get_leaf(12).is_none());
+    assert!(!v1.shares_root_with(&v0));
+}
+
+#[test]
+fn test_persistent_insert_leaf_out_of_range() {
+    let v0 = PersistentMerkleTree::new_from_leaves(process_input_to_chunks(&[0; 3 * CHUNK_LEN]));
+    assert_eq!(
+        v0.insert_leaf(3, chunk_output(3, b"x")).unwrap_err(),
+        Error::IndexOutOfRange { index: 3, leaf_count: 3 }
+    );
+    assert_eq!(
+        PersistentMerkleTree::new_from_leaves(Vec::new()).insert_leaf(0, chunk_output(0, b"x")).unwrap_err(),
+        Error::IndexOutOfRange { index: 0, leaf_count: 0 }
+    );
+}
+
+#[test]
+fn test_history_retains_last_versions() {
+    let input = random_input(4 * CHUNK_LEN);
+    let mut tree = PersistentMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut history = TreeHistory::new(3);
+    for i in 0..5u8 {
+        tree = tree.insert_leaf(0, chunk_output(0, &[i; 10])).unwrap();
+        assert_eq!(history.commit(tree.clone()), i as u64);
+    }
+    assert!(history.get(1).is_none());
+    assert_eq!(history.get(2).unwrap().get_leaf(0), Some(chunk_output(0, &[2; 10])));
+    assert_eq!(history.roots().len(), 3);
+    assert_eq!(history.latest().unwrap().root_hash(), tree.root_hash());
+}
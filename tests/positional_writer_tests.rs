@@ -0,0 +1,105 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, HashMode, CHUNK_LEN};
+use merkle_tree::hash::Hash;
+use merkle_tree::io::{PositionalWriter, TreeUpdate};
+use rand::Rng;
+use std::io::{Cursor, Write};
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn root_of(input: &[u8]) -> Hash {
+    BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input)).root_hash()
+}
+
+#[test]
+fn test_random_writes_keep_root_current() {
+    let mut rng = rand::thread_rng();
+    for update in [TreeUpdate::EachWrite, TreeUpdate::OnFlush] {
+        let mut expected = random_input(20 * CHUNK_LEN + 300);
+        let mut writer = PositionalWriter::new(Cursor::new(expected.clone()), update).unwrap();
+        for _ in 0..30 {
+            let offset = rng.gen_range(0..expected.len());
+            let data = random_input(rng.gen_range(1..3 * CHUNK_LEN).min(expected.len() - offset));
+            writer.write_at(offset as u64, &data).unwrap();
+            expected[offset..offset + data.len()].copy_from_slice(&data);
+            assert_eq!(writer.root().unwrap(), root_of(&expected));
+        }
+        let (file, tree) = writer.finish().unwrap();
+        assert_eq!(file.into_inner(), expected);
+        assert_eq!(tree.root_hash(), root_of(&expected));
+    }
+}
+
+#[test]
+fn test_on_flush_defers_rehashing() {
+    let input = random_input(4 * CHUNK_LEN);
+    let mut writer = PositionalWriter::new(Cursor::new(input.clone()), TreeUpdate::OnFlush).unwrap();
+    writer.write_at(100, b"changed").unwrap();
+    assert_eq!(writer.tree().root_hash(), root_of(&input));
+    writer.flush().unwrap();
+    assert_ne!(writer.tree().root_hash(), root_of(&input));
+}
+
+#[test]
+fn test_writes_past_the_end_grow_the_file() {
+    let input = random_input(CHUNK_LEN + 10);
+    let mut writer = PositionalWriter::new(Cursor::new(input.clone()), TreeUpdate::EachWrite).unwrap();
+
+    // Leaves a zero-filled gap, crossing a power of two in chunk count
+    writer.write_at(5 * CHUNK_LEN as u64 + 7, &[1, 2, 3]).unwrap();
+    let mut expected = input.clone();
+    expected.resize(5 * CHUNK_LEN + 7, 0);
+    expected.extend_from_slice(&[1, 2, 3]);
+    assert_eq!(writer.len(), expected.len() as u64);
+    assert_eq!(writer.root().unwrap(), root_of(&expected));
+
+    writer.write_all(b"appended").unwrap();
+    expected.extend_from_slice(b"appended");
+    assert_eq!(writer.root().unwrap(), root_of(&expected));
+    assert_eq!(writer.tree().num_leaves(), 6);
+}
+
+#[test]
+fn test_empty_file() {
+    let mut writer = PositionalWriter::new(Cursor::new(Vec::new()), TreeUpdate::OnFlush).unwrap();
+    assert!(writer.is_empty());
+    assert_eq!(writer.root().unwrap(), root_of(&[]));
+    writer.write_at(0, b"hello").unwrap();
+    assert_eq!(writer.root().unwrap(), root_of(b"hello"));
+}
+
+#[test]
+fn test_backing_file() {
+    let path = std::env::temp_dir().join(format!("merkle_tree_positional_{}", std::process::id()));
+    let input = random_input(8 * CHUNK_LEN);
+    std::fs::write(&path, &input).unwrap();
+    let file = std::fs::OpenOptions::new().read(true).write(true).open(&path).unwrap();
+
+    let mut writer = PositionalWriter::new(file, TreeUpdate::EachWrite).unwrap();
+    writer.write_at(3 * CHUNK_LEN as u64 - 2, &[9; 4]).unwrap();
+    let root = writer.root().unwrap();
+    drop(writer);
+
+    let contents = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(root, root_of(&contents));
+    assert_eq!(&contents[3 * CHUNK_LEN - 2..3 * CHUNK_LEN + 2], &[9; 4]);
+}
+
+#[test]
+fn test_keyed_writer_root_matches_keyed_hash() {
+    let key = [1u8; 32];
+    let mut expected = random_input(3 * CHUNK_LEN + 5);
+    let mode = HashMode::keyed(&key);
+    let mut writer = PositionalWriter::with_mode(Cursor::new(expected.clone()), TreeUpdate::EachWrite, mode).unwrap();
+    assert_eq!(writer.root().unwrap().as_bytes(), blake3::keyed_hash(&key, &expected).as_bytes());
+
+    writer.write_at(CHUNK_LEN as u64 + 10, &[0xaa; 50]).unwrap();
+    expected[CHUNK_LEN + 10..CHUNK_LEN + 60].fill(0xaa);
+    writer.write_at(5 * CHUNK_LEN as u64, &[7; 3]).unwrap();
+    expected.resize(5 * CHUNK_LEN, 0);
+    expected.extend_from_slice(&[7; 3]);
+    assert_eq!(writer.root().unwrap().as_bytes(), blake3::keyed_hash(&key, &expected).as_bytes());
+}
@@ -0,0 +1,173 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, HashMode, CHUNK_LEN, OUT_LEN};
+use merkle_tree::hash::Hash;
+use merkle_tree::proof::{verify_range_with_mode, RangeProof};
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_range_proofs_verify_for_all_ranges() {
+    // Odd chunk counts exercise the promoted right edge of the tree
+    for &len in &[1, CHUNK_LEN, 3 * CHUNK_LEN + 5, 7 * CHUNK_LEN, 8 * CHUNK_LEN + 1] {
+        let input = random_input(len);
+        let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+        let root = tree.root_hash();
+        let total = tree.num_leaves();
+        for start in 0..total {
+            for end in start + 1..=total {
+                let proof = tree.prove_range(start..end).unwrap();
+                let data = &input[start * CHUNK_LEN..std::cmp::min(end * CHUNK_LEN, len)];
+                assert!(proof.verify(&root, data), "len {} range {}..{}", len, start, end);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_range_proof_rejects_tampering() {
+    let mut input = random_input(5 * CHUNK_LEN + 100);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let root = tree.root_hash();
+    let proof = tree.prove_range(1..3).unwrap();
+
+    input[CHUNK_LEN + 1] ^= 1;
+    assert!(!proof.verify(&root, &input[CHUNK_LEN..3 * CHUNK_LEN]));
+    input[CHUNK_LEN + 1] ^= 1;
+
+    // Truncated data and extra proof nodes are rejected
+    assert!(!proof.verify(&root, &input[CHUNK_LEN..3 * CHUNK_LEN - 1]));
+    let mut padded = proof.clone();
+    padded.nodes.push([0; 8]);
+    assert!(!padded.verify(&root, &input[CHUNK_LEN..3 * CHUNK_LEN]));
+
+    assert!(tree.prove_range(2..2).is_none());
+    assert!(tree.prove_range(5..7).is_none());
+}
+
+#[test]
+fn test_range_proof_encoding_round_trip() {
+    let input = random_input(9 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let proof = tree.prove_range(4..6).unwrap();
+    let decoded = RangeProof::decode(&proof.encode()).unwrap();
+    assert_eq!(decoded, proof);
+    assert!(RangeProof::decode(&proof.encode()[..20]).is_none());
+}
+
+#[test]
+fn test_tree_file_round_trip() {
+    let input = random_input(6 * CHUNK_LEN + 3);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut encoded = Vec::new();
+    tree.write_to(&mut encoded).unwrap();
+    let decoded = BinaryMerkleTree::read_from(encoded.as_slice()).unwrap();
+    assert_eq!(decoded.num_leaves(), tree.num_leaves());
+    assert_eq!(decoded.root_hash(), tree.root_hash());
+    assert!(BinaryMerkleTree::read_from(&encoded[..10]).is_err());
+}
+
+#[test]
+fn test_verify_byte_ranges() {
+    use merkle_tree::proof::verify_range;
+
+    let len = 5 * CHUNK_LEN + 300;
+    let input = random_input(len);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let root = tree.root_hash();
+
+    for (start, end) in [(0, 1), (10, 20), (1000, 1100), (CHUNK_LEN, 2 * CHUNK_LEN), (500, len), (len - 1, len)] {
+        let range = start as u64..end as u64;
+        let proof = tree.prove_bytes(&input, range.clone()).unwrap();
+        assert!(verify_range(&root, range.clone(), &input[start..end], &proof), "{}..{}", start, end);
+        assert!(proof.prefix.len() < CHUNK_LEN && proof.suffix.len() < CHUNK_LEN);
+
+        let mut tampered = input[start..end].to_vec();
+        tampered[0] ^= 1;
+        assert!(!verify_range(&root, range, &tampered, &proof));
+    }
+
+    // The proof only covers the range it was made for
+    let proof = tree.prove_bytes(&input, 10..20).unwrap();
+    assert!(!verify_range(&root, 11..21, &input[11..21], &proof));
+    assert!(tree.prove_bytes(&input, 20..20).is_none());
+    assert!(tree.prove_bytes(&input, 0..len as u64 + 1).is_none());
+}
+
+#[test]
+fn test_generate_proofs_matches_single_proofs() {
+    let input = random_input(29 * CHUNK_LEN + 3);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let root = tree.root_hash();
+    let indices: Vec<usize> = vec![0, 29, 7, 8, 7, 15, 16, 28];
+
+    let proofs = tree.generate_proofs(&indices).unwrap();
+    assert_eq!(proofs.len(), indices.len());
+    for (&leaf, proof) in indices.iter().zip(&proofs) {
+        assert_eq!(proof, &tree.prove_range(leaf..leaf + 1).unwrap());
+        let data = &input[leaf * CHUNK_LEN..std::cmp::min((leaf + 1) * CHUNK_LEN, input.len())];
+        assert!(proof.verify(&root, data));
+    }
+    assert!(tree.generate_proofs(&[30]).is_none());
+}
+
+#[test]
+fn test_hash_ct_eq() {
+    let input = random_input(3 * CHUNK_LEN);
+    let root = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input)).root_hash();
+    assert!(root.ct_eq(&Hash::from_bytes(*root.as_bytes())));
+
+    let mut forged = *root.as_bytes();
+    forged[OUT_LEN - 1] ^= 1;
+    assert!(!root.ct_eq(&Hash::from_bytes(forged)));
+}
+
+#[test]
+fn test_patch_proof_from_journal() {
+    let input = random_input(21 * CHUNK_LEN + 40);
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut proof = tree.prove_range(6..9).unwrap();
+
+    tree.begin_journal();
+    let mut updated = input.clone();
+    updated[2 * CHUNK_LEN] ^= 1;
+    updated[15 * CHUNK_LEN + 3] ^= 1;
+    updated[7 * CHUNK_LEN] ^= 1; // inside the proven range
+    tree.update_bytes(&updated, 0..updated.len() as u64);
+    let changed = tree.journal().unwrap().node_indices();
+
+    assert_eq!(tree.patch_proof(&mut proof, &changed), Some(2));
+    assert_eq!(proof, tree.prove_range(6..9).unwrap());
+    assert!(proof.verify(&tree.root_hash(), &updated[6 * CHUNK_LEN..9 * CHUNK_LEN]));
+
+    let mut other_shape = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input[..CHUNK_LEN * 10]))
+        .prove_range(6..9)
+        .unwrap();
+    assert_eq!(tree.patch_proof(&mut other_shape, &changed), None);
+}
+
+#[test]
+fn test_keyed_and_derive_key_proofs_verify_with_their_mode() {
+    let key = [9u8; 32];
+    let context = "merkle_tree proof test";
+    let input = random_input(7 * CHUNK_LEN + 100);
+    let expected = [
+        (HashMode::keyed(&key), Hash::from_bytes(*blake3::keyed_hash(&key, &input).as_bytes())),
+        (HashMode::derive_key(context), Hash::from_bytes(blake3::derive_key(context, &input))),
+    ];
+    for (mode, root) in expected {
+        let (tree, _) = BinaryMerkleTree::from_reader_with_mode(&input[..], mode).unwrap();
+        assert_eq!(tree.root_hash(), root);
+
+        let proof = tree.prove_range(2..5).unwrap();
+        let data = &input[2 * CHUNK_LEN..5 * CHUNK_LEN];
+        assert_eq!(proof.compute_root_with_mode(data, &mode), Some(root));
+        assert!(proof.verify_with_mode(&root, data, &mode));
+        assert!(!proof.verify(&root, data));
+
+        let proof = tree.prove_bytes(&input, 3000..7200).unwrap();
+        assert!(verify_range_with_mode(&root, 3000..7200, &input[3000..7200], &proof, &mode));
+    }
+}
@@ -0,0 +1,73 @@
+use merkle_tree::binary_merkle_tree::{
+    chunk_output, process_input_to_chunks, BinaryMerkleTree, UnbalancedMerkleTree, CHUNK_LEN,
+};
+use proptest::prelude::*;
+
+// Up to 40 chunks, enough for several levels of promoted right edges
+fn input() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), 0..40 * CHUNK_LEN)
+}
+
+// Byte writes as (position, value), with positions wrapped to the input length
+fn writes() -> impl Strategy<Value = Vec<(usize, u8)>> {
+    prop::collection::vec((any::<usize>(), any::<u8>()), 0..32)
+}
+
+proptest! {
+    #[test]
+    fn incremental_updates_match_rebuild(mut input in input(), writes in writes()) {
+        let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+        for (position, value) in writes {
+            let position = position % input.len().max(1);
+            if input.is_empty() {
+                break;
+            }
+            input[position] = value;
+            let chunk = position / CHUNK_LEN;
+            let end = ((chunk + 1) * CHUNK_LEN).min(input.len());
+            tree.insert_leaf(chunk, chunk_output(chunk as u64, &input[chunk * CHUNK_LEN..end])).unwrap();
+        }
+        let rebuilt: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+        prop_assert_eq!(tree.root_node(), rebuilt.root_node());
+        prop_assert_eq!(*tree.root_hash().as_bytes(), *blake3::hash(&input).as_bytes());
+    }
+
+    #[test]
+    fn bulk_insert_matches_repeated_insert(input in input(), writes in writes()) {
+        let leaves = process_input_to_chunks(&input);
+        let mut updates: Vec<(usize, _)> = writes
+            .iter()
+            .map(|&(position, value)| {
+                let index = position % leaves.len();
+                (index, chunk_output(index as u64, &[value; 7]))
+            })
+            .collect();
+        updates.sort_by_key(|&(index, _)| index);
+        updates.dedup_by_key(|&mut (index, _)| index);
+
+        let mut bulk: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(leaves.clone());
+        bulk.bulk_insert_leaves(updates.iter().map(|(i, _)| *i), updates.iter().map(|(_, leaf)| *leaf))
+            .unwrap();
+        let mut repeated: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(leaves);
+        for (index, leaf) in &updates {
+            repeated.insert_leaf(*index, *leaf).unwrap();
+        }
+        prop_assert_eq!(bulk.root_node(), repeated.root_node());
+    }
+
+    #[test]
+    fn unbalanced_and_balanced_roots_agree(input in input(), writes in writes()) {
+        let leaves = process_input_to_chunks(&input);
+        let mut balanced = BinaryMerkleTree::new_from_leaves(leaves.clone());
+        let mut unbalanced = UnbalancedMerkleTree::new_from_leaves(leaves.clone());
+        prop_assert_eq!(balanced.root_hash(), unbalanced.root_hash());
+
+        for (position, value) in writes {
+            let index = position % leaves.len();
+            let leaf = chunk_output(index as u64, &[value; 100]);
+            balanced.insert_leaf(index, leaf).unwrap();
+            unbalanced.insert_leaf(index, leaf);
+        }
+        prop_assert_eq!(balanced.root_hash(), unbalanced.root_hash());
+    }
+}
@@ -0,0 +1,53 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::protocol::{read_request, read_response, respond, write_request, write_response, Request, Response};
+use rand::Rng;
+
+#[test]
+fn test_messages_round_trip_over_a_stream() {
+    let requests = vec![Request::GetRoot, Request::GetNodes(2..6), Request::GetChunks(vec![0, 7, 3])];
+    let mut stream = Vec::new();
+    for request in &requests {
+        write_request(&mut stream, request).unwrap();
+    }
+    let mut reader = stream.as_slice();
+    for request in &requests {
+        assert_eq!(&read_request(&mut reader).unwrap(), request);
+    }
+    assert!(reader.is_empty());
+}
+
+#[test]
+fn test_respond_serves_a_tree() {
+    let mut rng = rand::thread_rng();
+    let data: Vec<u8> = (0..5 * CHUNK_LEN + 10).map(|_| rng.gen()).collect();
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&data));
+
+    let mut stream = Vec::new();
+    for request in [Request::GetRoot, Request::GetNodes(1..3), Request::GetChunks(vec![5]), Request::GetChunks(vec![6])] {
+        write_response(&mut stream, &respond(&tree, &data, &request)).unwrap();
+    }
+    let mut reader = stream.as_slice();
+    assert_eq!(
+        read_response(&mut reader).unwrap(),
+        Response::Root { leaf_count: 6, root: tree.root_hash() }
+    );
+    assert_eq!(
+        read_response(&mut reader).unwrap(),
+        Response::Nodes { first: 1, cvs: vec![tree.node_cv(1).unwrap(), tree.node_cv(2).unwrap()] }
+    );
+    assert_eq!(read_response(&mut reader).unwrap(), Response::Chunks(vec![(5, data[5 * CHUNK_LEN..].to_vec())]));
+    assert!(matches!(read_response(&mut reader).unwrap(), Response::Error(_)));
+}
+
+#[test]
+fn test_decode_rejects_malformed_frames() {
+    let mut frame = Request::GetChunks(vec![1, 2]).encode();
+    // Claim a million indices without sending them
+    frame[6..14].copy_from_slice(&1_000_000u64.to_le_bytes());
+    assert!(Request::decode(&frame).is_err());
+
+    let mut frame = Request::GetRoot.encode();
+    frame[4] = 99;
+    assert!(Request::decode(&frame).is_err());
+    assert!(Response::decode(&[1, 0, 0, 0]).is_err());
+}
@@ -0,0 +1,51 @@
+use std::io::Write;
+
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::provider::{ChunkProvider, FileChunkProvider};
+
+#[test]
+fn test_update_from_file_provider() {
+    let mut content: Vec<u8> = (0..9 * CHUNK_LEN + 77).map(|i| (i % 251) as u8).collect();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&content));
+
+    let path = std::env::temp_dir().join(format!("provider_test_{}", std::process::id()));
+    content[4 * CHUNK_LEN + 3] ^= 1;
+    content.extend_from_slice(&[5; CHUNK_LEN]);
+    std::fs::File::create(&path).unwrap().write_all(&content).unwrap();
+    let provider = FileChunkProvider::open(&path).unwrap();
+
+    let changed = tree.update_from_provider(&provider, (4 * CHUNK_LEN as u64)..(4 * CHUNK_LEN as u64 + 4)).unwrap();
+    assert_eq!(changed, vec![4, 9, 10]);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&content).as_bytes());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_refresh_from_memory_provider() {
+    let mut content = vec![1u8; 6 * CHUNK_LEN];
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&content));
+    content[2 * CHUNK_LEN] = 2;
+
+    assert_eq!(tree.refresh_from_provider(&content).unwrap(), vec![2]);
+    assert_eq!(tree.root_hash().as_bytes(), blake3::hash(&content).as_bytes());
+    assert_eq!(content[..].read_chunk(5).unwrap().len(), CHUNK_LEN);
+}
+
+struct ShortProvider;
+
+impl ChunkProvider for ShortProvider {
+    fn len(&self) -> std::io::Result<u64> {
+        Ok(2 * CHUNK_LEN as u64)
+    }
+
+    fn read_chunk(&self, _index: usize) -> std::io::Result<Vec<u8>> {
+        Ok(vec![0; 10])
+    }
+}
+
+#[test]
+fn test_short_chunk_is_an_error() {
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[0; 2 * CHUNK_LEN]));
+    let err = tree.refresh_from_provider(&ShortProvider).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
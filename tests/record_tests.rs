@@ -0,0 +1,34 @@
+use merkle_tree::binary_merkle_tree::BinaryMerkleTree;
+use merkle_tree::record::record_leaf;
+
+fn rows() -> Vec<String> {
+    (0..9).map(|i| format!("id={},name=user{},balance={}", i, i, i * 100)).collect()
+}
+
+#[test]
+fn test_record_tree_updates_match_rebuild() {
+    let mut rows = rows();
+    let mut tree = BinaryMerkleTree::from_records(&rows);
+    assert_eq!(tree.num_leaves(), 9);
+
+    rows[4] = "id=4,name=user4,balance=0".to_string();
+    tree.update_record(4, rows[4].as_bytes()).unwrap();
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::from_records(&rows).root_hash());
+
+    // Records commit to their position
+    assert_ne!(record_leaf(0, b"row"), record_leaf(1, b"row"));
+    // Even when a record is identical to its empty-input chunk, the trees differ
+    assert_ne!(BinaryMerkleTree::from_records([b""]).root_hash(), BinaryMerkleTree::new_from_leaves(vec![]).root_hash());
+}
+
+#[test]
+fn test_record_range_proofs() {
+    let rows = rows();
+    let tree = BinaryMerkleTree::from_records(&rows);
+    let root = tree.root_hash();
+
+    let proof = tree.prove_range(3..6).unwrap();
+    assert!(proof.verify_records(&root, &rows[3..6]));
+    assert!(!proof.verify_records(&root, &rows[2..5]));
+    assert!(!proof.verify_records(&root, &rows[3..5]));
+}
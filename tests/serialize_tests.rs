@@ -0,0 +1,86 @@
+use std::io::ErrorKind;
+
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, Output, CHUNK_LEN};
+use merkle_tree::serialize::{decode_output, encode_output, migrate_tree_file, OUTPUT_ENCODED_LEN, TREE_FORMAT_VERSION};
+
+// Distinct bytes in every field, so a field written in the wrong order or
+// with the host's byte order shows up as a mismatch
+fn sample_output() -> Output {
+    Output {
+        input_chaining_value: std::array::from_fn(|i| 0x0302_0100 + 0x0404_0404 * i as u32),
+        block_words: std::array::from_fn(|i| 0xA3A2_A1A0 + 0x0404_0404 * i as u32),
+        counter: 0x0807_0605_0403_0201,
+        block_len: 0x1413_1211,
+        flags: 0x2423_2221,
+    }
+}
+
+#[test]
+fn test_output_layout_is_little_endian() {
+    let mut bytes = Vec::new();
+    encode_output(&sample_output(), &mut bytes);
+    assert_eq!(bytes.len(), OUTPUT_ENCODED_LEN);
+
+    let mut expected: Vec<u8> = (0..32).collect();
+    expected.extend((0..64).map(|i| 0xA0 + i as u8));
+    expected.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    expected.extend_from_slice(&[0x11, 0x12, 0x13, 0x14]);
+    expected.extend_from_slice(&[0x21, 0x22, 0x23, 0x24]);
+    assert_eq!(bytes, expected);
+    assert_eq!(decode_output(bytes[..].try_into().unwrap()), sample_output());
+}
+
+#[test]
+fn test_tree_file_layout() {
+    let tree = BinaryMerkleTree::new_from_leaves(vec![sample_output(), sample_output()]);
+    let mut file = Vec::new();
+    tree.write_to(&mut file).unwrap();
+    assert_eq!(&file[..6], b"B3MT\x03\x00");
+    assert_eq!(&file[6..14], &[2, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(&file[14..46], tree.root_hash().as_bytes());
+    assert_eq!(&file[46..54], &blake3::hash(&file[..46]).as_bytes()[..8]);
+    assert_eq!(file.len(), 54 + 2 * OUTPUT_ENCODED_LEN);
+    assert_eq!(file[54], 0x00);
+    assert_eq!(file[54 + 96], 0x01);
+}
+
+// A version 1 file: magic, version, leaf count, then the leaves
+fn version_1_file(tree: &BinaryMerkleTree) -> Vec<u8> {
+    let mut file = b"B3MT\x01".to_vec();
+    file.extend_from_slice(&(tree.num_leaves() as u64).to_le_bytes());
+    for (_, leaf) in tree.iter_leaves() {
+        encode_output(leaf, &mut file);
+    }
+    file
+}
+
+#[test]
+fn test_corrupted_tree_files_are_rejected() {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[7; 3 * CHUNK_LEN]));
+    let mut file = Vec::new();
+    tree.write_to(&mut file).unwrap();
+
+    for (offset, message) in [(10, "checksum"), (20, "checksum"), (50, "checksum"), (54 + 40, "root")] {
+        let mut corrupted = file.clone();
+        corrupted[offset] ^= 1;
+        let error = BinaryMerkleTree::read_from(&corrupted[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "offset {}", offset);
+        assert!(error.to_string().contains(message), "offset {}: {}", offset, error);
+    }
+    assert_eq!(BinaryMerkleTree::read_from(&file[..file.len() - 1]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_old_tree_files_load_and_migrate() {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[9; 5 * CHUNK_LEN + 1]));
+    let old = version_1_file(&tree);
+    assert_eq!(BinaryMerkleTree::read_from(&old[..]).unwrap().root_hash(), tree.root_hash());
+
+    let mut migrated = Vec::new();
+    assert_eq!(migrate_tree_file(&old[..], &mut migrated).unwrap(), 1);
+    assert_eq!(migrated[4], TREE_FORMAT_VERSION);
+    let mut current = Vec::new();
+    tree.write_to(&mut current).unwrap();
+    assert_eq!(migrated, current);
+    assert_eq!(migrate_tree_file(&current[..], &mut Vec::new()).unwrap(), TREE_FORMAT_VERSION);
+}
@@ -0,0 +1,43 @@
+#![cfg(feature = "arc-swap")]
+
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::shared::SharedMerkleTree;
+use std::sync::Arc;
+
+#[test]
+fn test_readers_keep_their_version() {
+    let shared = SharedMerkleTree::new(BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[0; 4 * CHUNK_LEN])));
+    let before = shared.load();
+    let root_before = before.root_hash();
+
+    let root_after = shared.update(|tree| {
+        tree.insert_leaf(1, chunk_output(1, b"new")).unwrap();
+        tree.root_hash()
+    });
+    assert_eq!(before.root_hash(), root_before);
+    assert_eq!(shared.load().root_hash(), root_after);
+    assert_ne!(root_before, root_after);
+}
+
+#[test]
+fn test_concurrent_readers_see_consistent_versions() {
+    let leaves = process_input_to_chunks(&[0; 8 * CHUNK_LEN]);
+    let shared = Arc::new(SharedMerkleTree::new(BinaryMerkleTree::new_from_leaves(leaves)));
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let shared = &shared;
+            scope.spawn(move || {
+                for _ in 0..200 {
+                    // A loaded version's root always matches its own leaves
+                    let version = shared.load();
+                    let leaves = version.iter_leaves().map(|(_, leaf)| *leaf).collect();
+                    assert_eq!(BinaryMerkleTree::new_from_leaves(leaves).root_hash(), version.root_hash());
+                }
+            });
+        }
+        for i in 0..100u64 {
+            shared.update(|tree| tree.insert_leaf((i % 8) as usize, chunk_output(i % 8, &i.to_le_bytes())).unwrap());
+        }
+    });
+}
@@ -0,0 +1,58 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::error::Error;
+use merkle_tree::slice::{decode_slice, HEADER_LEN};
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_slices_decode_for_many_ranges() {
+    for &len in &[0, 1, CHUNK_LEN, 5 * CHUNK_LEN + 7, 16 * CHUNK_LEN] {
+        let input = random_input(len);
+        let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+        let root = tree.root_hash();
+        let len = len as u64;
+        for range in [0..len, 0..1, len / 2..len / 2 + 3000, len.saturating_sub(1)..len + 10, len + 5..len + 6] {
+            let slice = tree.extract_slice(&input, range.clone()).unwrap();
+            let decoded = decode_slice(&root, &slice, range.clone()).unwrap();
+            let start = range.start.min(len) as usize;
+            let end = range.end.min(len) as usize;
+            assert_eq!(decoded, &input[start..end], "len {} range {:?}", len, range);
+        }
+    }
+}
+
+#[test]
+fn test_slice_is_smaller_than_input() {
+    let input = random_input(64 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let slice = tree.extract_slice(&input, 10 * CHUNK_LEN as u64..11 * CHUNK_LEN as u64).unwrap();
+    // One chunk plus one parent per level
+    assert_eq!(slice.len(), HEADER_LEN + CHUNK_LEN + 6 * 64);
+}
+
+#[test]
+fn test_slice_rejects_tampering() {
+    let input = random_input(9 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let root = tree.root_hash();
+    let range = 3000..5000;
+    let slice = tree.extract_slice(&input, range.clone()).unwrap();
+
+    for position in [HEADER_LEN, HEADER_LEN + 40, slice.len() - 1] {
+        let mut tampered = slice.clone();
+        tampered[position] ^= 1;
+        assert!(matches!(decode_slice(&root, &tampered, range.clone()), Err(Error::HashMismatch { .. })));
+    }
+    assert_eq!(decode_slice(&root, &slice[..slice.len() - 1], range.clone()), Err(Error::UnexpectedEnd));
+
+    // A wrong length in the header changes the shape of the right edge, which a
+    // slice reaching the end of the input always verifies
+    let range = 8000..input.len() as u64;
+    let mut wrong_len = tree.extract_slice(&input, range.clone()).unwrap();
+    wrong_len[0] ^= 1;
+    assert!(decode_slice(&root, &wrong_len, range).is_err());
+}
@@ -0,0 +1,59 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use rand::Rng;
+
+#[test]
+fn test_snapshot_is_unaffected_by_later_writes() {
+    let mut rng = rand::thread_rng();
+    let mut input: Vec<u8> = (0..8 * CHUNK_LEN).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+
+    let snapshot = tree.snapshot();
+    // Shares the node array until the tree is written
+    assert!(snapshot.shares_nodes_with(&tree));
+    let root_before = snapshot.root_hash();
+    let proof_before = snapshot.prove_range(2..3).unwrap();
+
+    input[2 * CHUNK_LEN] ^= 1;
+    tree.insert_leaf(2, chunk_output(2, &input[2 * CHUNK_LEN..3 * CHUNK_LEN])).unwrap();
+    assert!(!snapshot.shares_nodes_with(&tree));
+
+    assert_eq!(snapshot.root_hash(), root_before);
+    assert_eq!(snapshot.prove_range(2..3).unwrap(), proof_before);
+    assert_ne!(tree.root_hash(), root_before);
+    assert_eq!(snapshot.diff(&tree), vec![2]);
+}
+
+#[test]
+fn test_snapshot_leaves_out_journal_and_recording() {
+    let input = vec![4u8; 4 * CHUNK_LEN];
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    tree.begin_recording();
+    tree.begin_journal();
+    tree.insert_leaf(1, chunk_output(1, b"recorded")).unwrap();
+
+    let snapshot = tree.snapshot();
+    assert!(snapshot.journal().is_none());
+    assert!(snapshot.recording().is_none());
+    assert_eq!(snapshot.root_hash(), tree.root_hash());
+    assert!(!tree.recording().unwrap().is_empty());
+    assert!(!tree.journal().unwrap().is_empty());
+
+    // The writable tree made from a snapshot starts out with neither
+    let mut copy = snapshot.into_tree();
+    copy.insert_leaf(2, chunk_output(2, b"unrecorded")).unwrap();
+    assert!(copy.recording().is_none());
+    assert!(!copy.rollback_journal());
+}
+
+#[test]
+fn test_snapshot_does_not_copy_block_cvs() {
+    let input = vec![6u8; 16 * CHUNK_LEN];
+    let mut tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    tree.cache_block_cvs(&input).unwrap();
+
+    let snapshot = tree.snapshot();
+    assert!(!snapshot.has_block_cvs());
+    assert_eq!(snapshot.memory_usage().cached_cv_bytes, 0);
+    assert!(tree.has_block_cvs());
+    assert!(!snapshot.into_tree().has_block_cvs());
+}
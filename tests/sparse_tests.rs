@@ -0,0 +1,43 @@
+use merkle_tree::sparse::SparseMerkleTree;
+
+#[test]
+fn test_sparse_root_depends_only_on_contents() {
+    let mut a = SparseMerkleTree::new();
+    let mut b = SparseMerkleTree::new();
+    let empty_root = a.root_hash();
+
+    a.insert(b"alice", b"10".to_vec());
+    a.insert(b"bob", b"20".to_vec());
+    b.insert(b"bob", b"20".to_vec());
+    b.insert(b"alice", b"10".to_vec());
+    assert_eq!(a.root_hash(), b.root_hash());
+    assert_eq!(a.get(b"alice"), Some(&b"10"[..]));
+
+    assert_eq!(a.insert(b"alice", b"11".to_vec()), Some(b"10".to_vec()));
+    assert_ne!(a.root_hash(), b.root_hash());
+
+    a.remove(b"alice");
+    a.remove(b"bob");
+    assert!(a.is_empty());
+    assert_eq!(a.root_hash(), empty_root);
+}
+
+#[test]
+fn test_sparse_membership_and_non_membership_proofs() {
+    let mut tree = SparseMerkleTree::new();
+    for i in 0..50u32 {
+        tree.insert(format!("key {}", i).as_bytes(), i.to_le_bytes().to_vec());
+    }
+    let root = tree.root_hash();
+
+    let proof = tree.prove(b"key 7");
+    assert!(proof.verify(&root, b"key 7", Some(&7u32.to_le_bytes())));
+    assert!(!proof.verify(&root, b"key 7", Some(&8u32.to_le_bytes())));
+    assert!(!proof.verify(&root, b"key 7", None));
+
+    let absent = tree.prove(b"missing");
+    assert!(absent.verify(&root, b"missing", None));
+    assert!(!absent.verify(&root, b"missing", Some(b"anything")));
+    // Sparse proofs only carry the non-empty siblings
+    assert!(absent.siblings.len() < 20);
+}
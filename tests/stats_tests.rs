@@ -0,0 +1,55 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::grouped::GroupedMerkleTree;
+
+#[test]
+fn test_stats_for_partial_tree() {
+    let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![0u8; 5 * CHUNK_LEN]));
+    let stats = tree.stats();
+    assert_eq!(stats.depth, 3);
+    assert_eq!(stats.leaf_count, 5);
+    assert_eq!(stats.interior_nodes, 7);
+    assert_eq!(stats.populated_leaves, 5);
+    assert_eq!(stats.empty_leaves, 3);
+    assert!(stats.heap_bytes >= 16 * std::mem::size_of::<merkle_tree::binary_merkle_tree::Output>());
+    // A 5 chunk BLAKE3 tree has 4 real parents
+    assert_eq!(stats.parent_hashes, 4);
+
+    tree.insert_leaf(0, chunk_output(0, b"x")).unwrap();
+    assert_eq!(tree.stats().parent_hashes, 4 + 3);
+}
+
+#[test]
+fn test_stats_for_single_leaf() {
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(b"tiny"));
+    let stats = tree.stats();
+    assert_eq!(stats.depth, 0);
+    assert_eq!(stats.interior_nodes, 0);
+    assert_eq!(stats.parent_hashes, 0);
+}
+
+#[test]
+fn test_memory_usage_counts_nodes_and_journal() {
+    let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vec![0u8; 5 * CHUNK_LEN]));
+    let usage = tree.memory_usage();
+    assert!(usage.node_bytes >= 16 * std::mem::size_of::<merkle_tree::binary_merkle_tree::Output>());
+    assert_eq!(usage.index_bytes, 0);
+    assert_eq!(usage.cached_cv_bytes, 0);
+    assert_eq!(usage.total(), usage.inline_bytes + usage.node_bytes);
+
+    tree.begin_journal();
+    tree.insert_leaf(0, chunk_output(0, b"x")).unwrap();
+    let journaled = tree.memory_usage();
+    assert!(journaled.index_bytes > 0);
+    assert_eq!(journaled.node_bytes, usage.node_bytes);
+}
+
+#[test]
+fn test_grouped_memory_usage_is_smaller() {
+    let input = vec![7u8; 64 * CHUNK_LEN];
+    let grouped = GroupedMerkleTree::from_bytes(&input, 4);
+    let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let usage = grouped.memory_usage();
+    assert_eq!(usage.node_bytes, 8 * std::mem::size_of::<merkle_tree::binary_merkle_tree::Output>());
+    assert_eq!(usage.inline_bytes, std::mem::size_of::<GroupedMerkleTree>());
+    assert!(usage.total() < tree.memory_usage().total());
+}
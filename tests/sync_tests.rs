@@ -0,0 +1,41 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::sync::{plan_sync, TreeSummary};
+use rand::Rng;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn tree_of(input: &[u8]) -> BinaryMerkleTree {
+    BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input))
+}
+
+#[test]
+fn test_plan_sync_coalesces_ranges() {
+    let local = random_input(20 * CHUNK_LEN);
+    let mut remote = local.clone();
+    for chunk in [3, 4, 5, 9, 19] {
+        remote[chunk * CHUNK_LEN] ^= 1;
+    }
+    remote.extend(random_input(CHUNK_LEN + 4));
+
+    // The summary travels over the wire
+    let summary = TreeSummary::decode(&TreeSummary::from_tree(&tree_of(&remote)).encode()).unwrap();
+    let plan = plan_sync(&tree_of(&local), &summary);
+    assert_eq!(plan.fetch, vec![3..6, 9..10, 19..22]);
+    assert_eq!(plan.remote_leaf_count, 22);
+    assert_eq!(plan.chunks_to_fetch(), 7);
+    assert_eq!(plan.byte_ranges(remote.len() as u64).last().unwrap().end, remote.len() as u64);
+}
+
+#[test]
+fn test_plan_sync_shrinking_remote() {
+    let local = random_input(8 * CHUNK_LEN);
+    let remote = &local[..5 * CHUNK_LEN];
+    let plan = plan_sync(&tree_of(&local), &TreeSummary::from_tree(&tree_of(remote)));
+    assert!(plan.is_empty());
+    assert_eq!(plan.remote_leaf_count, 5);
+
+    assert!(TreeSummary::decode(b"B3NS\x01garbage").is_none());
+}
@@ -0,0 +1,70 @@
+//! Checks `Blake3Hasher` and the tree root against the BLAKE3 test vectors,
+//! in all three modes and with extended output.
+//!
+//! `test_vectors/test_vectors.json` follows the layout and parameters of the
+//! upstream `test_vectors/test_vectors.json` in the BLAKE3 repository: the
+//! same input lengths, key, context string and 131-byte outputs. The outputs
+//! were produced with the `blake3` crate, which upstream checks against that
+//! file.
+
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, Blake3Hasher, OUT_LEN};
+use serde_json::Value;
+
+fn vectors() -> Value {
+    serde_json::from_str(include_str!("test_vectors/test_vectors.json")).unwrap()
+}
+
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn vector_input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn hasher_output(mut hasher: Blake3Hasher, input: &[u8], len: usize) -> Vec<u8> {
+    hasher.update(input);
+    let mut out = vec![0; len];
+    hasher.finalize(&mut out);
+    out
+}
+
+#[test]
+fn test_hasher_matches_test_vectors() {
+    let vectors = vectors();
+    let key: [u8; 32] = vectors["key"].as_str().unwrap().as_bytes().try_into().unwrap();
+    let context = vectors["context_string"].as_str().unwrap();
+
+    for case in vectors["cases"].as_array().unwrap() {
+        let input_len = case["input_len"].as_u64().unwrap() as usize;
+        let input = vector_input(input_len);
+        for mode in ["hash", "keyed_hash", "derive_key"] {
+            let new_hasher = || match mode {
+                "hash" => Blake3Hasher::new(),
+                "keyed_hash" => Blake3Hasher::new_keyed(&key),
+                _ => Blake3Hasher::new_derive_key(context),
+            };
+            let expected = unhex(case[mode].as_str().unwrap());
+            assert_eq!(hasher_output(new_hasher(), &input, expected.len()), expected, "{mode}, input_len {input_len}");
+            // The default-length output is a prefix of the extended one
+            assert_eq!(hasher_output(new_hasher(), &input, OUT_LEN), expected[..OUT_LEN], "{mode}, input_len {input_len}");
+        }
+    }
+}
+
+#[test]
+fn test_tree_root_matches_test_vectors() {
+    for case in vectors()["cases"].as_array().unwrap() {
+        let input_len = case["input_len"].as_u64().unwrap() as usize;
+        let expected = unhex(case["hash"].as_str().unwrap());
+        let tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&vector_input(input_len)));
+
+        let mut extended = vec![0; expected.len()];
+        tree.root().root_output_bytes(&mut extended);
+        assert_eq!(extended, expected, "input_len {input_len}");
+        assert_eq!(tree.root_hash().as_bytes()[..], expected[..OUT_LEN], "input_len {input_len}");
+    }
+}
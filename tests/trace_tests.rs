@@ -0,0 +1,59 @@
+#![cfg(feature = "trace")]
+
+use merkle_tree::binary_merkle_tree::{chunk_output, Blake3Hasher, UnbalancedMerkleTree};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+// Counts spans by name and all events
+#[derive(Default)]
+struct Counter {
+    spans: std::sync::Mutex<Vec<&'static str>>,
+    events: AtomicUsize,
+}
+
+struct CountingSubscriber(Arc<Counter>);
+
+impl Subscriber for CountingSubscriber {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut spans = self.0.spans.lock().unwrap();
+        spans.push(span.metadata().name());
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {
+        self.0.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn test_hashing_emits_spans_and_events() {
+    let counter = Arc::new(Counter::default());
+    tracing::subscriber::with_default(CountingSubscriber(counter.clone()), || {
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&[3u8; 3000]);
+        let mut out = [0; 32];
+        hasher.finalize(&mut out);
+        assert_eq!(out, *blake3::hash(&[3u8; 3000]).as_bytes());
+
+        let mut tree = UnbalancedMerkleTree::new_from_leaves(vec![chunk_output(0, &[1])]);
+        tree.insert_leaf(1, chunk_output(1, &[2]));
+    });
+    let spans = counter.spans.lock().unwrap();
+    assert!(spans.contains(&"finalize"));
+    assert!(spans.contains(&"insert_leaf"));
+    assert!(counter.events.load(Ordering::Relaxed) > 0);
+}
@@ -0,0 +1,50 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::error::Error;
+use merkle_tree::transition::{verify_transition, LeafTransition};
+
+fn transition(tree: &BinaryMerkleTree, index: usize, new_chunk: Vec<u8>) -> LeafTransition {
+    LeafTransition {
+        proof: tree.prove_range(index..index + 1).unwrap(),
+        old_leaf: *tree.get_leaf(index).unwrap(),
+        new_chunk,
+    }
+}
+
+#[test]
+fn test_transition_root_matches_full_rehash() {
+    let old: Vec<u8> = (0..11 * CHUNK_LEN + 40).map(|i| (i % 241) as u8).collect();
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&old));
+    let mut new = old.clone();
+    // Leaves 4 and 5 are siblings, so each proof holds the other's stale leaf
+    for index in [4, 5, 9] {
+        new[index * CHUNK_LEN + 1] ^= 0xff;
+    }
+    new.truncate(11 * CHUNK_LEN + 3);
+    let transitions: Vec<LeafTransition> = [9, 4, 11, 5]
+        .into_iter()
+        .map(|i| transition(&tree, i, new[i * CHUNK_LEN..new.len().min((i + 1) * CHUNK_LEN)].to_vec()))
+        .collect();
+    let new_root = verify_transition(&tree.root_hash(), &transitions).unwrap();
+    assert_eq!(new_root.as_bytes(), blake3::hash(&new).as_bytes());
+
+    let single = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(b"tiny"));
+    let root = verify_transition(&single.root_hash(), &[transition(&single, 0, b"other".to_vec())]).unwrap();
+    assert_eq!(root.as_bytes(), blake3::hash(b"other").as_bytes());
+}
+
+#[test]
+fn test_transition_rejects_bad_witnesses() {
+    let old = vec![6u8; 8 * CHUNK_LEN];
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&old));
+    let root = tree.root_hash();
+    let good = transition(&tree, 2, vec![1; CHUNK_LEN]);
+
+    let mut wrong_leaf = good.clone();
+    wrong_leaf.old_leaf = *tree.get_leaf(3).unwrap();
+    assert_eq!(verify_transition(&root, &[wrong_leaf]), Err(Error::InvalidProof));
+
+    let mut short = good.clone();
+    short.new_chunk.pop();
+    assert_eq!(verify_transition(&root, &[short]), Err(Error::InvalidProof));
+    assert_eq!(verify_transition(&root, &[good.clone(), good]), Err(Error::InvalidProof));
+}
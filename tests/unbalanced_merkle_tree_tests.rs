@@ -1,4 +1,4 @@
-use merkle_tree::binary_merkle_tree::{UnbalancedMerkleTree, Output, process_input_to_chunks, Blake3Hasher, CHUNK_LEN, IV, ChunkState};
+use merkle_tree::binary_merkle_tree::{UnbalancedMerkleTree, process_input_to_chunks, Blake3Hasher, CHUNK_LEN, IV, ChunkState};
 
 #[test]
 fn test_unbalanced_tree_creation() {
@@ -35,6 +35,30 @@ fn test_unbalanced_tree_creation() {
         root.chaining_value(), blake3_chaining_value);
 }
 
+#[test]
+fn test_unbalanced_tree_grows_past_its_capacity() {
+    let input: Vec<u8> = (0..6 * CHUNK_LEN + 10).map(|i| (i % 251) as u8).collect();
+    let chunks = process_input_to_chunks(&input);
+    let expected = blake3::hash(&input);
+    let root_bytes = |tree: &UnbalancedMerkleTree| {
+        let mut root = [0; 32];
+        tree.root().root_output_bytes(&mut root);
+        root
+    };
+
+    // Going from 3 leaves to 7 reallocates the node array, moving the leaves
+    let mut tree = UnbalancedMerkleTree::new_from_leaves(chunks[..3].to_vec());
+    for (leaf_index, chunk) in chunks.iter().enumerate().skip(3) {
+        tree.insert_leaf(leaf_index, *chunk);
+    }
+    assert_eq!(tree.num_leaves(), 7);
+    assert_eq!(&root_bytes(&tree), expected.as_bytes());
+
+    let mut bulk = UnbalancedMerkleTree::new_from_leaves(chunks[..3].to_vec());
+    bulk.bulk_insert_leaves(3..7, chunks[3..].iter().copied()).unwrap();
+    assert_eq!(&root_bytes(&bulk), expected.as_bytes());
+}
+
 #[test]
 fn test_unbalanced_tree_insert() {
     println!("\n=== Starting unbalanced tree insert test ===\n");
@@ -95,4 +119,38 @@ fn test_unbalanced_tree_insert() {
     assert_eq!(root_cv, blake3_chaining_value,
         "Root chaining value does not match BLAKE3 hash");
     println!("\n=== Test completed successfully ===");
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_unbalanced_bulk_insert_rejects_unsorted_indices() {
+    use merkle_tree::error::Error;
+
+    let input = vec![1u8; 5 * CHUNK_LEN];
+    let mut tree = UnbalancedMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let leaves = process_input_to_chunks(&input[..2 * CHUNK_LEN]);
+    assert_eq!(
+        tree.bulk_insert_leaves([3, 3].into_iter(), leaves.into_iter()),
+        Err(Error::UnsortedIndices { position: 1, previous: 3, index: 3 })
+    );
+}
+
+#[test]
+fn test_unbalanced_bulk_insert_unordered() {
+    let input = vec![2u8; 5 * CHUNK_LEN];
+    let mut unordered = UnbalancedMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let mut sorted = unordered.clone();
+    let leaves = process_input_to_chunks(&[4u8; 3 * CHUNK_LEN]);
+
+    unordered.bulk_insert_leaves_unordered([(4, leaves[0]), (0, leaves[1]), (4, leaves[2])]).unwrap();
+    sorted.bulk_insert_leaves([0, 4].into_iter(), [leaves[1], leaves[2]].into_iter()).unwrap();
+    assert_eq!(unordered.root_hash(), sorted.root_hash());
+}
+
+#[test]
+fn test_unbalanced_empty_tree_hashes_like_empty_input() {
+    let empty = UnbalancedMerkleTree::new_from_leaves(Vec::new());
+    assert_eq!(empty.num_leaves(), 0);
+    assert_eq!(empty.root_hash().as_bytes(), blake3::hash(b"").as_bytes());
+    let from_input = UnbalancedMerkleTree::new_from_leaves(process_input_to_chunks(&[]));
+    assert_eq!(from_input.root_hash(), empty.root_hash());
+}
@@ -0,0 +1,118 @@
+use std::io;
+
+use merkle_tree::binary_merkle_tree::{chunk_output, process_input_to_chunks, BinaryMerkleTree, Output, CHUNK_LEN};
+use merkle_tree::node_store::{MemoryNodeStore, NodeStore, StoredMerkleTree};
+
+fn input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 241) as u8).collect()
+}
+
+#[test]
+fn test_valid_trees_have_no_inconsistent_nodes() {
+    for len in [0, CHUNK_LEN, 5 * CHUNK_LEN + 1, 64 * CHUNK_LEN] {
+        let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input(len)));
+        assert!(tree.validate().is_empty(), "length {}", len);
+        let last = tree.num_leaves() - 1;
+        tree.insert_leaf(last, chunk_output(last as u64, b"new")).unwrap();
+        assert!(tree.validate().is_empty(), "length {}", len);
+    }
+}
+
+#[test]
+fn test_stored_tree_reports_corrupted_nodes() {
+    let leaves = process_input_to_chunks(&input(11 * CHUNK_LEN));
+    let tree = StoredMerkleTree::create(MemoryNodeStore::new(), leaves).unwrap();
+    assert!(tree.validate().unwrap().is_empty());
+
+    // A bad interior node disagrees with its children, and its parent with it.
+    // A bad leaf shows up at its parent.
+    let mut store = tree.into_store();
+    let bogus = chunk_output(0, b"bogus");
+    store.write_batch(&[(5, bogus), (16 + 9, bogus)]).unwrap();
+    let tree = StoredMerkleTree::open(store).unwrap();
+    assert_eq!(tree.validate().unwrap(), vec![2, 5, 12]);
+}
+
+// Forgets one node, like a backend that lost a write
+struct LossyStore {
+    inner: MemoryNodeStore,
+    lost: usize,
+}
+
+impl NodeStore for LossyStore {
+    fn get(&self, index: usize) -> io::Result<Option<Output>> {
+        if index == self.lost {
+            return Ok(None);
+        }
+        self.inner.get(index)
+    }
+
+    fn write_batch(&mut self, nodes: &[(usize, Output)]) -> io::Result<()> {
+        self.inner.write_batch(nodes)
+    }
+
+    fn leaf_count(&self) -> io::Result<Option<usize>> {
+        self.inner.leaf_count()
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> io::Result<()> {
+        self.inner.set_leaf_count(leaf_count)
+    }
+}
+
+#[test]
+fn test_stored_tree_reports_missing_nodes() {
+    let leaves = process_input_to_chunks(&input(6 * CHUNK_LEN));
+    let store = LossyStore { inner: MemoryNodeStore::new(), lost: 6 };
+    let tree = StoredMerkleTree::create(store, leaves).unwrap();
+    assert_eq!(tree.validate().unwrap(), vec![6]);
+}
+
+#[test]
+fn test_repair_rebuilds_interior_nodes_from_leaves() {
+    // Placeholder interior nodes disagree with the leaves until repaired
+    let content = input(7 * CHUNK_LEN + 3);
+    let expected: BinaryMerkleTree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&content));
+    let mut tree: BinaryMerkleTree = BinaryMerkleTree::new_empty(8);
+    for (index, leaf) in process_input_to_chunks(&content).into_iter().enumerate() {
+        tree.insert_leaf(index, leaf).unwrap();
+    }
+    assert!(tree.validate().is_empty());
+    assert!(tree.repair().is_empty());
+
+    let mut placeholders: BinaryMerkleTree = BinaryMerkleTree::new_empty(8);
+    assert!(!placeholders.validate().is_empty());
+    let changed = placeholders.repair_from_provider(&content).unwrap();
+    assert!(changed.contains(&1) && changed.contains(&15));
+    assert!(placeholders.validate().is_empty());
+    assert_eq!(placeholders.root_hash(), expected.root_hash());
+    assert!(placeholders.repair_from_provider(&content[..CHUNK_LEN]).is_err());
+}
+
+#[test]
+fn test_stored_repair_fixes_corrupted_and_missing_nodes() {
+    let content = input(11 * CHUNK_LEN);
+    let tree = StoredMerkleTree::create(MemoryNodeStore::new(), process_input_to_chunks(&content)).unwrap();
+    let root = tree.root_hash().unwrap();
+
+    let mut store = tree.into_store();
+    let bogus = chunk_output(0, b"bogus");
+    store.write_batch(&[(5, bogus)]).unwrap();
+    let mut tree = StoredMerkleTree::open(store).unwrap();
+    // Node 2 was only flagged for disagreeing with its bad child
+    assert_eq!(tree.repair().unwrap(), vec![5]);
+    assert!(tree.validate().unwrap().is_empty());
+    assert_eq!(tree.root_hash().unwrap(), root);
+
+    // A corrupted leaf is rebuilt upon by `repair`, restored from the chunks
+    let mut store = tree.into_store();
+    store.write_batch(&[(16 + 9, bogus)]).unwrap();
+    let mut tree = StoredMerkleTree::open(store).unwrap();
+    tree.repair_from_provider(&content).unwrap();
+    assert!(tree.validate().unwrap().is_empty());
+    assert_eq!(tree.root_hash().unwrap(), root);
+
+    let lossy = LossyStore { inner: MemoryNodeStore::new(), lost: 16 + 3 };
+    let mut tree = StoredMerkleTree::create(lossy, process_input_to_chunks(&content)).unwrap();
+    assert!(tree.repair().is_err());
+}
@@ -0,0 +1,100 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::hash::Hash;
+use merkle_tree::io::VerifiedReader;
+use rand::Rng;
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn tree_of(input: &[u8]) -> BinaryMerkleTree {
+    BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input))
+}
+
+#[test]
+fn test_reads_genuine_data() {
+    for len in [0, 10, CHUNK_LEN, 6 * CHUNK_LEN + 42] {
+        let input = random_input(len);
+        let tree = tree_of(&input);
+        let root = tree.root_hash();
+        let mut reader = VerifiedReader::new(Cursor::new(input.clone()), tree, root).unwrap();
+        assert_eq!(reader.len(), len as u64);
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, input);
+    }
+}
+
+#[test]
+fn test_random_access() {
+    let input = random_input(9 * CHUNK_LEN + 100);
+    let tree = tree_of(&input);
+    let root = tree.root_hash();
+    let mut reader = VerifiedReader::new(Cursor::new(input.clone()), tree, root).unwrap();
+
+    let mut buf = [0u8; 3000];
+    reader.seek(SeekFrom::Start(2 * CHUNK_LEN as u64 + 500)).unwrap();
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &input[2 * CHUNK_LEN + 500..2 * CHUNK_LEN + 3500]);
+
+    let mut tail = Vec::new();
+    reader.seek(SeekFrom::End(-150)).unwrap();
+    reader.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, &input[input.len() - 150..]);
+
+    assert_eq!(reader.seek(SeekFrom::Current(10)).unwrap(), input.len() as u64 + 10);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(reader.seek(SeekFrom::Current(-(input.len() as i64) - 11)).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_rejects_corrupt_chunk() {
+    let mut input = random_input(4 * CHUNK_LEN);
+    let tree = tree_of(&input);
+    let root = tree.root_hash();
+    input[CHUNK_LEN * 2 + 7] ^= 1;
+    let mut reader = VerifiedReader::new(Cursor::new(input), tree, root).unwrap();
+
+    let mut buf = vec![0u8; CHUNK_LEN];
+    reader.read_exact(&mut buf).unwrap();
+    reader.seek(SeekFrom::Start(2 * CHUNK_LEN as u64 + 100)).unwrap();
+    assert_eq!(reader.read(&mut buf).unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_lru_skips_hot_chunks_until_evicted() {
+    let input = random_input(4 * CHUNK_LEN);
+    let tree = tree_of(&input);
+    let root = tree.root_hash();
+    let mut reader = VerifiedReader::with_capacity(Cursor::new(input), tree, root, 2).unwrap();
+    let mut buf = [0u8; 16];
+    reader.read_exact(&mut buf).unwrap();
+
+    // Chunk 0 is in the LRU, so a change behind the reader's back goes unseen
+    reader.get_mut().get_mut()[5] ^= 1;
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    reader.read_exact(&mut buf).unwrap();
+
+    // Reading chunks 1 and 2 evicts chunk 0, which then fails verification
+    for chunk in [1, 2] {
+        reader.seek(SeekFrom::Start((chunk * CHUNK_LEN) as u64)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+    }
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    assert_eq!(reader.read(&mut buf).unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_rejects_untrusted_tree() {
+    let input = random_input(3 * CHUNK_LEN);
+    let tree = tree_of(&input);
+    let other = Hash::from_bytes([7; 32]);
+    let err = VerifiedReader::new(Cursor::new(input.clone()), tree.clone(), other).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let root = tree.root_hash();
+    let err = VerifiedReader::new(Cursor::new(input[..CHUNK_LEN].to_vec()), tree, root).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
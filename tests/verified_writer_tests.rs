@@ -0,0 +1,111 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::encoding::encode_combined;
+use merkle_tree::io::VerifiedWriter;
+use rand::Rng;
+use std::io::{ErrorKind, Write};
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn root_and_cvs(input: &[u8]) -> (merkle_tree::hash::Hash, Vec<[u32; 8]>) {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(input));
+    let cvs = (0..tree.num_leaves()).map(|i| tree.leaf_cv(i).unwrap()).collect();
+    (tree.root_hash(), cvs)
+}
+
+#[test]
+fn test_verified_writer_accepts_genuine_data() {
+    for len in [0, 10, CHUNK_LEN, 6 * CHUNK_LEN + 42] {
+        let input = random_input(len);
+        let (root, cvs) = root_and_cvs(&input);
+        let mut writer = VerifiedWriter::with_outboard(Vec::new(), root, len as u64, cvs).unwrap();
+        for piece in input.chunks(700) {
+            writer.write_all(piece).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), input);
+    }
+}
+
+#[test]
+fn test_verified_writer_stops_at_first_bad_chunk() {
+    let input = random_input(5 * CHUNK_LEN);
+    let (root, cvs) = root_and_cvs(&input);
+    let mut corrupted = input.clone();
+    corrupted[2 * CHUNK_LEN + 5] ^= 1;
+
+    let mut writer = VerifiedWriter::with_outboard(Vec::new(), root, input.len() as u64, cvs).unwrap();
+    let error = writer.write_all(&corrupted).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    // Only the two chunks before the corruption reached the inner writer
+    assert_eq!(writer.get_ref(), &input[..2 * CHUNK_LEN]);
+    assert!(writer.write(&[0]).is_err());
+    assert!(writer.finish().is_err());
+}
+
+#[test]
+fn test_verified_writer_rejects_bad_outboard_and_short_data() {
+    let input = random_input(3 * CHUNK_LEN);
+    let (root, mut cvs) = root_and_cvs(&input);
+    assert!(VerifiedWriter::with_outboard(Vec::new(), root, input.len() as u64, cvs[..2].to_vec()).is_err());
+
+    let mut writer = VerifiedWriter::with_outboard(Vec::new(), root, input.len() as u64, cvs.clone()).unwrap();
+    writer.write_all(&input[..CHUNK_LEN]).unwrap();
+    assert_eq!(writer.finish().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+    cvs[1][0] ^= 1;
+    assert!(VerifiedWriter::with_outboard(Vec::new(), root, input.len() as u64, cvs).is_err());
+}
+
+#[test]
+fn test_verified_writer_streams_combined_encoding() {
+    for len in [0, 10, CHUNK_LEN, 6 * CHUNK_LEN + 42] {
+        let input = random_input(len);
+        let (encoded, root) = encode_combined(&input);
+        let mut writer = VerifiedWriter::new(Vec::new(), root, len as u64);
+        for piece in encoded.chunks(700) {
+            writer.write_all(piece).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), input);
+    }
+}
+
+#[test]
+fn test_verified_writer_passes_on_chunks_as_they_arrive() {
+    let input = random_input(4 * CHUNK_LEN);
+    let (encoded, root) = encode_combined(&input);
+    let mut writer = VerifiedWriter::new(Vec::new(), root, input.len() as u64);
+    // Header, the root parent and the left parent come before the first chunk
+    let first_chunk_end = 8 + 2 * 64 + CHUNK_LEN;
+    writer.write_all(&encoded[..first_chunk_end - 1]).unwrap();
+    assert_eq!(writer.bytes_verified(), 0);
+    writer.write_all(&encoded[first_chunk_end - 1..first_chunk_end]).unwrap();
+    assert_eq!(writer.get_ref(), &input[..CHUNK_LEN]);
+    assert_eq!(writer.finish().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_verified_writer_rejects_bad_combined_encoding() {
+    let input = random_input(4 * CHUNK_LEN);
+    let (encoded, root) = encode_combined(&input);
+
+    // A flipped bit in the right child of the root parent fails as soon as
+    // that parent is complete
+    let mut corrupted = encoded.clone();
+    corrupted[8 + 40] ^= 1;
+    let mut writer = VerifiedWriter::new(Vec::new(), root, input.len() as u64);
+    assert_eq!(writer.write_all(&corrupted).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert!(writer.get_ref().is_empty());
+
+    // A flipped bit in the third chunk lets the first two through
+    let mut corrupted = encoded.clone();
+    corrupted[encoded.len() - CHUNK_LEN - 5] ^= 1;
+    let mut writer = VerifiedWriter::new(Vec::new(), root, input.len() as u64);
+    assert_eq!(writer.write_all(&corrupted).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(writer.get_ref(), &input[..2 * CHUNK_LEN]);
+    assert!(writer.write(&[0]).is_err());
+
+    let mut writer = VerifiedWriter::new(Vec::new(), root, input.len() as u64 - 1);
+    assert_eq!(writer.write_all(&encoded).unwrap_err().kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,207 @@
+use merkle_tree::binary_merkle_tree::BinaryMerkleTree;
+use merkle_tree::node_index::NodeIndex;
+use merkle_tree::verify::{verify_file_root, verify_file_root_with_threads, Mismatches, VerifiedMarks, VerifyCursor};
+use rand::Rng;
+use std::path::PathBuf;
+
+fn temp_file_with(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("merkle_tree_verify_{}_{}", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_intact_file_verifies() {
+    // Spans several read buffers per thread and ends in a partial chunk
+    let input = random_input(300 * 1024 + 77);
+    let path = temp_file_with("intact", &input);
+    let (tree, _) = BinaryMerkleTree::from_file(&path).unwrap();
+
+    for threads in [1, 3, 8] {
+        assert!(tree.verify_file_with_threads(&path, Mismatches::All, threads).unwrap().is_empty());
+        assert!(verify_file_root_with_threads(&path, &tree.root_hash(), threads).unwrap());
+    }
+    assert!(verify_file_root(&path, &tree.root_hash()).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_reports_corrupted_ranges() {
+    let mut input = random_input(64 * 1024 + 100);
+    let (tree, _) = BinaryMerkleTree::from_reader(&input[..]).unwrap();
+    input[5 * 1024 + 3] ^= 1;
+    input[6 * 1024] ^= 1;
+    input[40 * 1024 + 1023] ^= 1;
+    input[64 * 1024 + 99] ^= 1;
+    let path = temp_file_with("ranges", &input);
+
+    let all = tree.verify_file_with_threads(&path, Mismatches::All, 4).unwrap();
+    assert_eq!(all, vec![5 * 1024..7 * 1024, 40 * 1024..41 * 1024, 64 * 1024..64 * 1024 + 100]);
+    for threads in [1, 4, 16] {
+        let first = tree.verify_file_with_threads(&path, Mismatches::First, threads).unwrap();
+        assert_eq!(first, vec![5 * 1024..6 * 1024]);
+    }
+    assert!(!verify_file_root(&path, &tree.root_hash()).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_length_changes_are_mismatches() {
+    let input = random_input(10 * 1024);
+    let (tree, _) = BinaryMerkleTree::from_reader(&input[..]).unwrap();
+
+    let path = temp_file_with("shorter", &input[..8 * 1024 + 10]);
+    assert_eq!(tree.verify_file(&path, Mismatches::All).unwrap(), vec![8 * 1024..10 * 1024]);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut longer = input.clone();
+    longer.extend_from_slice(&[7; 1500]);
+    let path = temp_file_with("longer", &longer);
+    assert_eq!(tree.verify_file(&path, Mismatches::All).unwrap(), vec![10 * 1024..10 * 1024 + 1500]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_empty_file() {
+    let path = temp_file_with("empty", &[]);
+    let (tree, _) = BinaryMerkleTree::from_file(&path).unwrap();
+    assert!(tree.verify_file(&path, Mismatches::First).unwrap().is_empty());
+    assert!(verify_file_root(&path, &tree.root_hash()).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_missing_file() {
+    let tree = BinaryMerkleTree::new_from_leaves(vec![]);
+    let missing = std::env::temp_dir().join("merkle_tree_verify_does_not_exist");
+    assert!(tree.verify_file(&missing, Mismatches::All).is_err());
+}
+
+#[test]
+fn test_cursor_resumes_after_interruption() {
+    let input = random_input(37 * 1024 + 5);
+    let path = temp_file_with("cursor", &input);
+    let (tree, _) = BinaryMerkleTree::from_file(&path).unwrap();
+
+    let mut cursor = VerifyCursor::new(&tree);
+    assert_eq!(cursor.step(&tree, &path, 10, 2).unwrap(), 10);
+    assert!(!cursor.is_complete());
+    assert_eq!(cursor.passed_subtrees(), vec![NodeIndex::new(64 / 8).unwrap(), NodeIndex::new(64 / 2 + 4).unwrap()]);
+
+    // Simulate a restart by round-tripping the cursor through bytes
+    let mut saved = Vec::new();
+    cursor.write_to(&mut saved).unwrap();
+    let mut cursor = VerifyCursor::read_from(&saved[..]).unwrap();
+    while !cursor.is_complete() {
+        assert!(cursor.step(&tree, &path, 7, 3).unwrap() > 0);
+    }
+    assert_eq!(cursor.chunks_checked(), 38);
+    assert!(cursor.failed_chunks().is_empty());
+    assert_eq!(cursor.passed_subtrees(), vec![NodeIndex::ROOT]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cursor_records_failed_chunks() {
+    let mut input = random_input(20 * 1024);
+    let (tree, _) = BinaryMerkleTree::from_reader(&input[..]).unwrap();
+    input[3 * 1024] ^= 1;
+    input[4 * 1024] ^= 1;
+    input[17 * 1024 + 9] ^= 1;
+    let path = temp_file_with("cursor_failed", &input);
+
+    let mut cursor = VerifyCursor::new(&tree);
+    while !cursor.is_complete() {
+        cursor.step(&tree, &path, 4, 2).unwrap();
+    }
+    assert_eq!(cursor.failed_chunks(), &[3..5, 17..18]);
+    // Only chunks 8..16 passed, as the parent of two four-chunk subtrees
+    assert_eq!(cursor.passed_subtrees(), vec![NodeIndex::new(5).unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cursor_rejects_other_tree() {
+    let input = random_input(4 * 1024);
+    let path = temp_file_with("cursor_other", &input);
+    let (tree, _) = BinaryMerkleTree::from_file(&path).unwrap();
+    let (other, _) = BinaryMerkleTree::from_reader(&input[..3 * 1024]).unwrap();
+
+    let mut cursor = VerifyCursor::new(&other);
+    let error = cursor.step(&tree, &path, 4, 1).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(VerifyCursor::read_from(&b"B3XX"[..]).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_marked_subtrees_are_skipped() {
+    let mut input = random_input(16 * 1024);
+    let path = temp_file_with("marks", &input);
+    let (tree, _) = BinaryMerkleTree::from_file(&path).unwrap();
+    let mut marks = VerifiedMarks::new();
+    // Leaves 0..8
+    marks.mark(&tree, NodeIndex::new(2).unwrap(), 1);
+
+    // Corruption under the mark goes unnoticed; elsewhere it is still found
+    input[2 * 1024] ^= 1;
+    input[12 * 1024] ^= 1;
+    std::fs::write(&path, &input).unwrap();
+    assert_eq!(tree.verify_file_skipping(&path, Mismatches::All, &marks).unwrap(), vec![12 * 1024..13 * 1024]);
+    assert_eq!(tree.verify_file(&path, Mismatches::First).unwrap(), vec![2 * 1024..3 * 1024]);
+
+    marks.expire_before(2);
+    assert!(marks.is_empty());
+    assert_eq!(tree.verify_file_skipping(&path, Mismatches::First, &marks).unwrap(), vec![2 * 1024..3 * 1024]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_marks_lapse_when_the_subtree_changes() {
+    let input = random_input(8 * 1024);
+    let (mut tree, _) = BinaryMerkleTree::from_reader(&input[..]).unwrap();
+    let mut marks = VerifiedMarks::new();
+    let left = NodeIndex::new(2).unwrap();
+    let right = NodeIndex::new(3).unwrap();
+    marks.mark(&tree, left, 7);
+    marks.mark(&tree, right, 9);
+    assert_eq!(marks.get(&tree, left).unwrap().generation, 7);
+
+    let mut changed = input.clone();
+    changed[6 * 1024] ^= 1;
+    tree.update_bytes(&changed, 6 * 1024..6 * 1024 + 1);
+    assert_eq!(marks.get(&tree, left).unwrap().generation, 7);
+    assert!(marks.get(&tree, right).is_none());
+
+    let mut saved = Vec::new();
+    marks.write_to(&mut saved).unwrap();
+    assert_eq!(VerifiedMarks::read_from(&saved[..]).unwrap(), marks);
+}
+
+#[test]
+fn test_refresh_skips_marked_chunks() {
+    let mut input = random_input(8 * 1024 + 10);
+    let (mut tree, _) = BinaryMerkleTree::from_reader(&input[..]).unwrap();
+    let mut marks = VerifiedMarks::new();
+    // Leaves 0..4, and leaf 8 alone
+    marks.mark(&tree, NodeIndex::new(4).unwrap(), 1);
+    marks.mark(&tree, NodeIndex::leaf(&tree, 8).unwrap(), 1);
+
+    input[1024] ^= 1;
+    input[5 * 1024] ^= 1;
+    input[8 * 1024 + 3] ^= 1;
+    assert_eq!(tree.refresh_from_provider_skipping(&input, &marks).unwrap(), vec![5]);
+    assert_eq!(tree.refresh_from_provider(&input).unwrap(), vec![1, 8]);
+}
+
+#[test]
+#[should_panic(expected = "covers no leaves")]
+fn test_marking_an_empty_subtree_panics() {
+    let (tree, _) = BinaryMerkleTree::from_reader(&[0u8; 3 * 1024][..]).unwrap();
+    VerifiedMarks::new().mark(&tree, NodeIndex::new(7).unwrap(), 0);
+}
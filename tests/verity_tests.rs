@@ -0,0 +1,179 @@
+#![cfg(feature = "sha2")]
+
+use merkle_tree::verity::{
+    build_fsverity_tree, fsverity_tree_from_file, verity_tree_from_file, write_verity_tree, FsVerityParams, VerityParams,
+};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::io::ErrorKind;
+
+fn random_input(len: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn salted(salt: &[u8], block: &[u8]) -> [u8; 32] {
+    Sha256::new().chain_update(salt).chain_update(block).finalize().into()
+}
+
+#[test]
+fn test_single_block() {
+    let input = random_input(4096);
+    let params = VerityParams { superblock: false, salt: b"pepper".to_vec(), ..VerityParams::default() };
+    let mut hash_device = Vec::new();
+    let tree = write_verity_tree(&input[..], &params, &mut hash_device).unwrap();
+
+    let mut expected_block = salted(b"pepper", &input).to_vec();
+    expected_block.resize(4096, 0);
+    assert_eq!(hash_device, expected_block);
+    assert_eq!(tree.root_hash, salted(b"pepper", &expected_block));
+    assert_eq!((tree.data_blocks, tree.hash_blocks), (1, 1));
+    assert_eq!(tree.root_hash_hex().len(), 64);
+}
+
+#[test]
+fn test_levels_are_written_top_down() {
+    // 16 digests per 512-byte hash block: 40 data blocks need three level-0
+    // blocks and one level-1 block
+    let input = random_input(40 * 512 - 100);
+    let params = VerityParams {
+        data_block_size: 512,
+        hash_block_size: 512,
+        superblock: false,
+        ..VerityParams::default()
+    };
+    let mut hash_device = Vec::new();
+    let tree = write_verity_tree(&input[..], &params, &mut hash_device).unwrap();
+    assert_eq!((tree.data_blocks, tree.hash_blocks), (40, 4));
+    assert_eq!(hash_device.len(), 4 * 512);
+
+    let mut padded = input.clone();
+    padded.resize(40 * 512, 0);
+    let mut level0: Vec<u8> = padded.chunks(512).flat_map(|block| salted(&[], block)).collect();
+    level0.resize(3 * 512, 0);
+    let mut level1: Vec<u8> = level0.chunks(512).flat_map(|block| salted(&[], block)).collect();
+    level1.resize(512, 0);
+    assert_eq!(&hash_device[..512], &level1[..]);
+    assert_eq!(&hash_device[512..], &level0[..]);
+    assert_eq!(tree.root_hash, salted(&[], &level1));
+}
+
+#[test]
+fn test_superblock() {
+    let input = random_input(3 * 4096);
+    let params = VerityParams { salt: vec![0xab; 32], uuid: [7; 16], ..VerityParams::default() };
+    let mut hash_device = Vec::new();
+    write_verity_tree(&input[..], &params, &mut hash_device).unwrap();
+    assert_eq!(hash_device.len(), 2 * 4096);
+
+    let superblock = &hash_device[..512];
+    assert_eq!(&superblock[..8], b"verity\0\0");
+    assert_eq!(u32::from_le_bytes(superblock[8..12].try_into().unwrap()), 1);
+    assert_eq!(u32::from_le_bytes(superblock[12..16].try_into().unwrap()), 1);
+    assert_eq!(&superblock[16..32], &[7; 16]);
+    assert_eq!(&superblock[32..38], b"sha256");
+    assert_eq!(u32::from_le_bytes(superblock[64..68].try_into().unwrap()), 4096);
+    assert_eq!(u32::from_le_bytes(superblock[68..72].try_into().unwrap()), 4096);
+    assert_eq!(u64::from_le_bytes(superblock[72..80].try_into().unwrap()), 3);
+    assert_eq!(u16::from_le_bytes(superblock[80..82].try_into().unwrap()), 32);
+    assert_eq!(&superblock[88..120], &[0xab; 32]);
+    assert!(hash_device[120..4096].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn test_from_file_matches_reader() {
+    let input = random_input(10 * 4096 + 1);
+    let path = std::env::temp_dir().join(format!("merkle_tree_verity_{}", std::process::id()));
+    std::fs::write(&path, &input).unwrap();
+    let params = VerityParams::default();
+
+    let mut from_file = Vec::new();
+    let file_tree = verity_tree_from_file(&path, &params, &mut from_file).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut from_reader = Vec::new();
+    let reader_tree = write_verity_tree(&input[..], &params, &mut from_reader).unwrap();
+    assert_eq!(file_tree, reader_tree);
+    assert_eq!(from_file, from_reader);
+}
+
+#[test]
+fn test_rejects_invalid_params() {
+    let input = [0u8; 4096];
+    for params in [
+        VerityParams { data_block_size: 1000, ..VerityParams::default() },
+        VerityParams { hash_block_size: 256, ..VerityParams::default() },
+        VerityParams { salt: vec![0; 257], ..VerityParams::default() },
+    ] {
+        let err = write_verity_tree(&input[..], &params, Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn test_fsverity_empty_file_digest() {
+    // `fsverity digest` of an empty file
+    let tree = build_fsverity_tree(&[][..], &FsVerityParams::default()).unwrap();
+    assert_eq!(tree.root_hash, [0; 32]);
+    assert!(tree.tree.is_empty());
+    assert_eq!(
+        tree.digest_string(),
+        "sha256:3d248ca542a24fc62d1c43b916eae5016878e2533c88238480b26128a1f1af95"
+    );
+}
+
+#[test]
+fn test_fsverity_single_block_has_no_tree() {
+    let input = random_input(100);
+    let tree = build_fsverity_tree(&input[..], &FsVerityParams::default()).unwrap();
+    let mut block = input.clone();
+    block.resize(4096, 0);
+    assert_eq!(tree.root_hash, salted(&[], &block));
+    assert!(tree.tree.is_empty());
+    assert_eq!(tree.data_size, 100);
+}
+
+#[test]
+fn test_fsverity_levels_and_salt() {
+    // 128 digests per 4096-byte block: 130 data blocks need two level-0
+    // blocks and one level-1 block
+    let input = random_input(129 * 4096 + 5);
+    let params = FsVerityParams { salt: b"salt".to_vec(), ..FsVerityParams::default() };
+    let tree = build_fsverity_tree(&input[..], &params).unwrap();
+
+    // The salt is zero-padded to SHA-256's 64-byte block
+    let mut salt = b"salt".to_vec();
+    salt.resize(64, 0);
+    let mut padded = input.clone();
+    padded.resize(130 * 4096, 0);
+    let mut level0: Vec<u8> = padded.chunks(4096).flat_map(|block| salted(&salt, block)).collect();
+    level0.resize(2 * 4096, 0);
+    let mut level1: Vec<u8> = level0.chunks(4096).flat_map(|block| salted(&salt, block)).collect();
+    level1.resize(4096, 0);
+    assert_eq!(tree.tree.len(), 3 * 4096);
+    assert_eq!(&tree.tree[..4096], &level1[..]);
+    assert_eq!(&tree.tree[4096..], &level0[..]);
+    assert_eq!(tree.root_hash, salted(&salt, &level1));
+
+    let descriptor = tree.descriptor();
+    assert_eq!(&descriptor[..4], &[1, 1, 12, 4]);
+    assert_eq!(u64::from_le_bytes(descriptor[8..16].try_into().unwrap()), input.len() as u64);
+    assert_eq!(&descriptor[16..48], &tree.root_hash);
+    assert_eq!(&descriptor[80..84], b"salt");
+    assert_eq!(tree.digest(), <[u8; 32]>::from(Sha256::digest(descriptor)));
+
+    let path = std::env::temp_dir().join(format!("merkle_tree_fsverity_{}", std::process::id()));
+    std::fs::write(&path, &input).unwrap();
+    assert_eq!(fsverity_tree_from_file(&path, &params).unwrap(), tree);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_fsverity_rejects_invalid_params() {
+    for params in [
+        FsVerityParams { block_size: 512, ..FsVerityParams::default() },
+        FsVerityParams { block_size: 5000, ..FsVerityParams::default() },
+        FsVerityParams { salt: vec![0; 33], ..FsVerityParams::default() },
+    ] {
+        assert_eq!(build_fsverity_tree(&[1u8][..], &params).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
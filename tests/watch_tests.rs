@@ -0,0 +1,47 @@
+#![cfg(feature = "notify")]
+
+use merkle_tree::binary_merkle_tree::Blake3Hasher;
+use merkle_tree::watch::WatchedMerkleFile;
+use rand::Rng;
+use std::time::Duration;
+
+fn blake3_chaining_value(input: &[u8]) -> [u32; 8] {
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(input);
+    let mut hash = [0; 32];
+    hasher.finalize(&mut hash);
+
+    let mut chaining_value = [0u32; 8];
+    for i in 0..8 {
+        chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
+    }
+    chaining_value
+}
+
+#[test]
+fn test_watched_file_emits_new_root() {
+    let mut rng = rand::thread_rng();
+    let mut input: Vec<u8> = (0..16 * 1024).map(|_| rng.gen()).collect();
+    let path = std::env::temp_dir().join(format!("merkle_tree_watch_{}", std::process::id()));
+    std::fs::write(&path, &input).unwrap();
+
+    let watched = WatchedMerkleFile::watch(&path).unwrap();
+    assert_eq!(watched.root().chaining_value(), blake3_chaining_value(&input));
+
+    input[5 * 1024 + 3] ^= 0xFF;
+    std::fs::write(&path, &input).unwrap();
+
+    // Writes can surface as several events; wait for the one carrying the final contents
+    let expected = blake3_chaining_value(&input);
+    let mut saw_expected_root = false;
+    while let Ok(update) = watched.updates().recv_timeout(Duration::from_secs(5)) {
+        assert!(!update.changed_chunks.is_empty());
+        if update.root.chaining_value() == expected {
+            assert!(update.changed_chunks.contains(&5));
+            saw_expected_root = true;
+            break;
+        }
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert!(saw_expected_root, "no root update for the modified file");
+}
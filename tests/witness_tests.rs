@@ -0,0 +1,46 @@
+use merkle_tree::binary_merkle_tree::{process_input_to_chunks, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::witness::Side;
+
+#[test]
+fn test_witness_reproduces_root_for_every_leaf() {
+    let input: Vec<u8> = (0..11 * CHUNK_LEN + 9).map(|i| (i % 239) as u8).collect();
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&input));
+    let root = tree.root_hash();
+    for leaf_index in 0..tree.num_leaves() {
+        let witness = tree.path_witness(leaf_index).unwrap();
+        assert!(witness.verify(&root), "leaf {}", leaf_index);
+        assert_eq!(witness.root_hash().as_bytes(), blake3::hash(&input).as_bytes());
+        assert_eq!(witness.steps[0].compression.chaining_value(), tree.get_leaf(leaf_index).unwrap().chaining_value());
+    }
+    // Chunk 11 is the right child of its pair, which is the right half of
+    // chunks 8..12; that subtree is promoted unchanged to be the root's right
+    // child, so it has no step of its own
+    let last = tree.path_witness(11).unwrap();
+    let sides: Vec<_> = last.steps.iter().map(|step| step.path_child).collect();
+    assert_eq!(sides, [None, Some(Side::Right), Some(Side::Right), Some(Side::Right)]);
+    assert!(tree.path_witness(12).is_none());
+}
+
+#[test]
+fn test_tampered_witness_fails() {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[5u8; 4 * CHUNK_LEN]));
+    let root = tree.root_hash();
+    let mut witness = tree.path_witness(2).unwrap();
+    witness.steps[1].compression.block_words[3] ^= 1;
+    assert!(!witness.verify(&root));
+
+    let single = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(b"one chunk"));
+    let witness = single.path_witness(0).unwrap();
+    assert_eq!(witness.steps.len(), 1);
+    assert!(witness.verify(&single.root_hash()));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_witness_exports_as_json() {
+    let tree = BinaryMerkleTree::new_from_leaves(process_input_to_chunks(&[1u8; 3 * CHUNK_LEN]));
+    let json = serde_json::to_value(tree.path_witness(2).unwrap()).unwrap();
+    assert_eq!(json["leaf_index"], 2);
+    assert_eq!(json["steps"][1]["path_child"], "Right");
+    assert_eq!(json["steps"][1]["compression"]["output"].as_array().unwrap().len(), 16);
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "zeroize")]
+
+use merkle_tree::binary_merkle_tree::{Blake3Hasher, ChunkState, IV};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+fn assert_zeroize_on_drop<T: ZeroizeOnDrop>() {}
+
+#[test]
+fn test_zeroize_clears_keyed_hasher() {
+    assert_zeroize_on_drop::<Blake3Hasher>();
+    let key = [0xA5; 32];
+    let mut hasher = Blake3Hasher::new_keyed(&key);
+    hasher.update(&[7; 3000]);
+    hasher.zeroize();
+
+    // Past the magic and version, the whole state is zero: key, chunk state and
+    // the (now empty) CV stack
+    let state = hasher.serialize_state();
+    assert!(state[5..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn test_zeroize_clears_chunk_state() {
+    let mut chunk_state = ChunkState::new(IV, 3, 0);
+    chunk_state.update(b"secret");
+    chunk_state.zeroize();
+    assert_eq!(chunk_state.chaining_value, [0; 8]);
+    assert_eq!(chunk_state.block, [0; 64]);
+    assert!(chunk_state.is_empty());
+}
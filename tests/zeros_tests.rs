@@ -0,0 +1,42 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::zeros::ZeroSubtrees;
+
+#[test]
+fn test_zeroed_tree_matches_tree_of_copies() {
+    let leaf = chunk_output(0, &[0; CHUNK_LEN]);
+    let zeros: ZeroSubtrees = ZeroSubtrees::new(leaf, 6);
+    assert_eq!(zeros.height(), 6);
+    assert!(zeros.node(7).is_none());
+
+    for leaf_count in [1, 2, 3, 8, 13, 33, 64] {
+        let zeroed = BinaryMerkleTree::new_zeroed(leaf_count, &zeros);
+        let built = BinaryMerkleTree::new_from_leaves(vec![leaf; leaf_count]);
+        assert!(zeroed.iter_nodes().eq(built.iter_nodes()), "{} leaves", leaf_count);
+        assert_eq!(zeroed.root_hash(), built.root_hash());
+        // One partial node per level at most
+        assert!(zeroed.parent_hash_count() <= 6, "{} leaves", leaf_count);
+    }
+    assert!(zeros.is_zero(6, &BinaryMerkleTree::new_zeroed(64, &zeros).root_node()));
+}
+
+#[test]
+fn test_zeroed_tree_updates_like_any_other() {
+    let leaf = chunk_output(0, &[0; CHUNK_LEN]);
+    let zeros: ZeroSubtrees = ZeroSubtrees::new(leaf, 4);
+    let mut zeroed = BinaryMerkleTree::new_zeroed(11, &zeros);
+    let mut built = BinaryMerkleTree::new_from_leaves(vec![leaf; 11]);
+    let other = chunk_output(5, b"written");
+    zeroed.insert_leaf(5, other).unwrap();
+    built.insert_leaf(5, other).unwrap();
+    assert_eq!(zeroed.root_hash(), built.root_hash());
+    // The untouched left half is still the table's subtree
+    assert!(zeros.is_zero(2, zeroed.get_node(4).unwrap()));
+    assert!(!zeros.is_zero(2, zeroed.get_node(5).unwrap()));
+}
+
+#[test]
+#[should_panic]
+fn test_zeroed_tree_needs_a_tall_enough_table() {
+    let zeros: ZeroSubtrees = ZeroSubtrees::new(chunk_output(0, &[]), 2);
+    BinaryMerkleTree::new_zeroed(5, &zeros);
+}